@@ -9,10 +9,10 @@ use malen::nalgebra::{Point2, Point3, Vector2};
 use malen::{
     draw::{
         ColPass, ColVertex, Font, Light, LineBatch, OccluderBatch, Quad, ShadowColPass, ShadowMap,
-        TextBatch, TriBatch,
+        TextBatch, TriBatch, WaterEffect,
     },
     golem::depth::{DepthTestFunction, DepthTestMode},
-    Camera, Canvas, Color3, Color4, Error, InputState, Key,
+    AaRect, Camera, Canvas, ClearOptions, Color3, Color4, Error, InputState, Key,
 };
 
 struct Wall {
@@ -36,10 +36,12 @@ struct Game {
     shadow_col_pass: ShadowColPass,
     color_pass: ColPass,
     font: Font,
+    water: WaterEffect,
 
     walls: Vec<Wall>,
     thingies: Vec<Thingy>,
     player_pos: Point2<f32>,
+    time: Duration,
 }
 
 impl Game {
@@ -87,14 +89,17 @@ impl Game {
             shadow_col_pass: ShadowColPass::new(canvas)?,
             color_pass: ColPass::new(canvas)?,
             font,
+            water: WaterEffect::new(canvas)?,
             walls,
             thingies,
             player_pos: Point2::origin(),
+            time: Duration::from_secs(0),
         })
     }
 
     pub fn update(&mut self, dt: Duration, input_state: &InputState) {
         let dt_secs = dt.as_secs_f32();
+        self.time += dt;
 
         let mut player_dir = Vector2::zeros();
         if input_state.key(Key::W) {
@@ -220,12 +225,7 @@ impl Game {
         );
 
         let screen_geom = canvas.screen_geom();
-        let view = Camera {
-            center: self.player_pos,
-            zoom: 1.0,
-            angle: 0.0,
-        }
-        .to_matrix(&screen_geom);
+        let view = Camera::new(self.player_pos).to_matrix(&screen_geom);
 
         self.shadow_map
             .build(
@@ -236,7 +236,7 @@ impl Game {
             .draw_occluders(&self.occluder_batch.draw_unit())?
             .finish()?;
 
-        canvas.clear(Color4::new(0.0, 0.0, 0.0, 1.0));
+        canvas.begin_frame(ClearOptions::default());
 
         self.shadow_col_pass.draw(
             &(screen_geom.orthographic_projection() * view),
@@ -264,12 +264,30 @@ impl Game {
         )?;
         canvas.golem_ctx().set_depth_test_mode(None);
 
+        // Reflect the walls and thingies in a pool of water.
+        let transform = screen_geom.orthographic_projection() * view;
+        let color_pass = &mut self.color_pass;
+        let tri_plain_batch = &mut self.tri_plain_batch;
+        self.water.render_reflection(canvas, |_| {
+            color_pass.draw(&transform, &tri_plain_batch.draw_unit())
+        })?;
+        self.water.draw(
+            canvas,
+            &transform,
+            AaRect::from_top_left(Point2::new(-600.0, 200.0), Vector2::new(1200.0, 400.0)),
+            0.4,
+            Color4::new(0.1, 0.3, 0.6, 0.8),
+            self.time,
+        )?;
+
         self.font.draw(
             canvas,
             &screen_geom.orthographic_projection(),
             &self.text_batch.draw_unit(),
         )?;
 
+        canvas.end_frame();
+
         Ok(())
     }
 }
@@ -0,0 +1,299 @@
+use web_sys::{
+    AudioBuffer, AudioContext, AudioNode, AudioParam, BiquadFilterNode, BiquadFilterType,
+    ConvolverNode, GainNode,
+};
+
+use crate::{audio::mixer::audio_error, Error};
+
+/// The time constant with which effect parameters approach new values, in
+/// seconds. Changing them abruptly would cause audible clicks.
+const PARAM_SMOOTHING: f64 = 0.05;
+
+/// Identifies a bus of a `Mixer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BusId(pub(crate) usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BusEffect {
+    /// Removes frequencies above `frequency` (in Hz), e.g. to muffle the
+    /// game while paused or under water. `q` controls the resonance at the
+    /// cutoff; `0.7` gives a flat response.
+    Lowpass { frequency: f32, q: f32 },
+
+    /// A simple reverb, e.g. for caves, using a decaying noise impulse
+    /// response of `duration` seconds. Larger `decay` values make the
+    /// reverb die out faster. `mix` is the fraction of reverberated sound in
+    /// the output.
+    Reverb { duration: f32, decay: f32, mix: f32 },
+}
+
+enum EffectNodes {
+    Lowpass(BiquadFilterNode),
+    Reverb {
+        input: GainNode,
+        convolver: ConvolverNode,
+        dry: GainNode,
+        wet: GainNode,
+        output: GainNode,
+    },
+}
+
+impl EffectNodes {
+    fn new(ctx: &AudioContext, effect: &BusEffect) -> Result<Self, Error> {
+        match effect {
+            BusEffect::Lowpass { .. } => {
+                let filter = ctx.create_biquad_filter().map_err(audio_error)?;
+                filter.set_type(BiquadFilterType::Lowpass);
+
+                Ok(EffectNodes::Lowpass(filter))
+            }
+            BusEffect::Reverb { .. } => {
+                let input = ctx.create_gain().map_err(audio_error)?;
+                let convolver = ctx.create_convolver().map_err(audio_error)?;
+                let dry = ctx.create_gain().map_err(audio_error)?;
+                let wet = ctx.create_gain().map_err(audio_error)?;
+                let output = ctx.create_gain().map_err(audio_error)?;
+
+                input.connect_with_audio_node(&dry).map_err(audio_error)?;
+                input
+                    .connect_with_audio_node(&convolver)
+                    .map_err(audio_error)?;
+                convolver
+                    .connect_with_audio_node(&wet)
+                    .map_err(audio_error)?;
+                dry.connect_with_audio_node(&output).map_err(audio_error)?;
+                wet.connect_with_audio_node(&output).map_err(audio_error)?;
+
+                Ok(EffectNodes::Reverb {
+                    input,
+                    convolver,
+                    dry,
+                    wet,
+                    output,
+                })
+            }
+        }
+    }
+
+    fn input(&self) -> &AudioNode {
+        match self {
+            EffectNodes::Lowpass(filter) => filter,
+            EffectNodes::Reverb { input, .. } => input,
+        }
+    }
+
+    fn output(&self) -> &AudioNode {
+        match self {
+            EffectNodes::Lowpass(filter) => filter,
+            EffectNodes::Reverb { output, .. } => output,
+        }
+    }
+
+    /// Applies the parameters of `effect`, which must be of the same kind as
+    /// the effect that the nodes have been created for. `old` are the
+    /// parameters that have been applied before, if any.
+    fn apply(
+        &self,
+        ctx: &AudioContext,
+        effect: &BusEffect,
+        old: Option<&BusEffect>,
+    ) -> Result<(), Error> {
+        // New nodes start out with the parameters applied immediately.
+        let set = |param: AudioParam, value: f32| {
+            if old.is_some() {
+                param
+                    .set_target_at_time(value, ctx.current_time(), PARAM_SMOOTHING)
+                    .map(|_| ())
+                    .map_err(audio_error)
+            } else {
+                param.set_value(value);
+                Ok(())
+            }
+        };
+
+        match (self, effect) {
+            (EffectNodes::Lowpass(filter), BusEffect::Lowpass { frequency, q }) => {
+                set(filter.frequency(), *frequency)?;
+                set(filter.q(), *q)?;
+            }
+            (
+                EffectNodes::Reverb {
+                    convolver,
+                    dry,
+                    wet,
+                    ..
+                },
+                BusEffect::Reverb {
+                    duration,
+                    decay,
+                    mix,
+                },
+            ) => {
+                // Generating the impulse response is expensive, so only do it
+                // when its parameters have changed.
+                let regenerate = match old {
+                    Some(BusEffect::Reverb {
+                        duration: old_duration,
+                        decay: old_decay,
+                        ..
+                    }) => old_duration != duration || old_decay != decay,
+                    _ => true,
+                };
+                if regenerate {
+                    let response = reverb_impulse_response(ctx, *duration, *decay)?;
+                    convolver.set_buffer(Some(&response));
+                }
+
+                set(dry.gain(), 1.0 - mix)?;
+                set(wet.gain(), *mix)?;
+            }
+            _ => unreachable!("effect nodes of the wrong kind"),
+        }
+
+        Ok(())
+    }
+
+    fn disconnect(&self) {
+        let _ = self.input().disconnect();
+        let _ = self.output().disconnect();
+    }
+}
+
+fn reverb_impulse_response(
+    ctx: &AudioContext,
+    duration: f32,
+    decay: f32,
+) -> Result<AudioBuffer, Error> {
+    let sample_rate = ctx.sample_rate();
+    let len = ((duration * sample_rate) as u32).max(1);
+    let buffer = ctx
+        .create_buffer(2, len, sample_rate)
+        .map_err(audio_error)?;
+
+    // Independent noise on both channels gives a wide stereo image.
+    for channel in 0..2 {
+        let mut data: Vec<f32> = (0..len)
+            .map(|i| {
+                let t = i as f32 / len as f32;
+                (js_sys::Math::random() as f32 * 2.0 - 1.0) * (1.0 - t).powf(decay)
+            })
+            .collect();
+        buffer
+            .copy_to_channel(&mut data, channel)
+            .map_err(audio_error)?;
+    }
+
+    Ok(buffer)
+}
+
+/// A group of voices, such as music or sound effects, that shares a volume
+/// and a chain of effects.
+pub(crate) struct Bus {
+    input: GainNode,
+    output: GainNode,
+    effects: Vec<(BusEffect, EffectNodes)>,
+}
+
+impl Bus {
+    pub fn new(ctx: &AudioContext, destination: &AudioNode) -> Result<Self, Error> {
+        let input = ctx.create_gain().map_err(audio_error)?;
+        let output = ctx.create_gain().map_err(audio_error)?;
+        input
+            .connect_with_audio_node(&output)
+            .map_err(audio_error)?;
+        output
+            .connect_with_audio_node(destination)
+            .map_err(audio_error)?;
+
+        Ok(Self {
+            input,
+            output,
+            effects: Vec::new(),
+        })
+    }
+
+    /// Returns the node that voices of the bus connect to.
+    pub fn input(&self) -> &AudioNode {
+        &self.input
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.output.gain().set_value(volume);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.output.gain().value()
+    }
+
+    pub fn effects(&self) -> impl Iterator<Item = &BusEffect> {
+        self.effects.iter().map(|(effect, _)| effect)
+    }
+
+    /// Replaces the effect chain.
+    ///
+    /// If the new effects are of the same kinds as the current ones, their
+    /// parameters are changed smoothly, so that e.g. a lowpass filter can be
+    /// swept without clicks. Otherwise, the chain is rebuilt, which cuts off
+    /// reverb tails.
+    pub fn set_effects(&mut self, ctx: &AudioContext, effects: &[BusEffect]) -> Result<(), Error> {
+        let same_kinds =
+            self.effects.len() == effects.len()
+                && self.effects.iter().zip(effects).all(|((old, _), new)| {
+                    std::mem::discriminant(old) == std::mem::discriminant(new)
+                });
+
+        if same_kinds {
+            for ((old, nodes), new) in self.effects.iter_mut().zip(effects) {
+                nodes.apply(ctx, new, Some(old))?;
+                *old = new.clone();
+            }
+
+            return Ok(());
+        }
+
+        // Build the new chain completely before swapping it in, so that the
+        // bus keeps playing through the old chain if anything fails.
+        let mut new_effects: Vec<(BusEffect, EffectNodes)> = Vec::with_capacity(effects.len());
+        for effect in effects {
+            let nodes = EffectNodes::new(ctx, effect)?;
+            nodes.apply(ctx, effect, None)?;
+            if let Some((_, prev)) = new_effects.last() {
+                prev.output()
+                    .connect_with_audio_node(nodes.input())
+                    .map_err(audio_error)?;
+            }
+
+            new_effects.push((effect.clone(), nodes));
+        }
+
+        if let Some((_, last)) = new_effects.last() {
+            last.output()
+                .connect_with_audio_node(&self.output)
+                .map_err(audio_error)?;
+        }
+
+        let _ = self.input.disconnect();
+        let new_head = new_effects
+            .first()
+            .map_or(&*self.output, |(_, nodes)| nodes.input());
+        if let Err(e) = self.input.connect_with_audio_node(new_head) {
+            if let Some((_, last)) = new_effects.last() {
+                let _ = last.output().disconnect();
+            }
+
+            let old_head = self
+                .effects
+                .first()
+                .map_or(&*self.output, |(_, nodes)| nodes.input());
+            let _ = self.input.connect_with_audio_node(old_head);
+
+            return Err(audio_error(e));
+        }
+
+        for (_, nodes) in std::mem::replace(&mut self.effects, new_effects) {
+            nodes.disconnect();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use crate::{audio::Mixer, util};
+
+/// Song positions that differ from the audio clock by more than this many
+/// seconds are reset instead of being corrected gradually, e.g. after the
+/// tab has been in the background.
+const MAX_DRIFT: f64 = 0.1;
+
+/// The fraction of the difference to the audio clock that is corrected in
+/// each update.
+const CORRECTION: f64 = 0.1;
+
+/// Maps audio time to the beats of a song, for rhythm games.
+///
+/// The audio clock is the only reliable source for the position in a song,
+/// but it is coarse, running ahead of what is heard, and drifts against the
+/// time of the main loop. `Conductor` advances a song position with the
+/// frame time for smooth animation, and continuously pulls it towards the
+/// position that is being heard according to the audio clock and the
+/// latency estimates of the `Mixer`.
+#[derive(Debug, Clone)]
+pub struct Conductor {
+    bpm: f64,
+    start_time: f64,
+    offset: f64,
+
+    song_time: f64,
+    last_audio_time: f64,
+    since_audio_time: f64,
+    last_update_ms: f64,
+}
+
+impl Conductor {
+    /// Creates a conductor for a song with a constant tempo of `bpm` that
+    /// starts at the audio context time `start_time`, which usually is the
+    /// time passed to `Mixer::play_at`.
+    pub fn new(bpm: f64, start_time: f64) -> Self {
+        assert!(bpm > 0.0);
+
+        Self {
+            bpm,
+            start_time,
+            offset: 0.0,
+            song_time: 0.0,
+            last_audio_time: 0.0,
+            since_audio_time: 0.0,
+            last_update_ms: util::now_ms(),
+        }
+    }
+
+    /// Sets the time of the first beat in the song file, in seconds.
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn bpm(&self) -> f64 {
+        self.bpm
+    }
+
+    /// Returns the duration of one beat in seconds.
+    pub fn beat_duration(&self) -> f64 {
+        60.0 / self.bpm
+    }
+
+    /// Advances the song position by the frame time `dt` and corrects it
+    /// towards the audio clock. Should be called once per frame.
+    pub fn update(&mut self, mixer: &Mixer, dt: Duration) {
+        let dt = dt.as_secs_f64();
+        let audio_time = mixer.current_time();
+
+        // Between steps of the audio clock, extrapolate it with the frame
+        // time.
+        if audio_time != self.last_audio_time {
+            self.last_audio_time = audio_time;
+            self.since_audio_time = 0.0;
+        } else {
+            self.since_audio_time += dt;
+        }
+
+        let target = audio_time + self.since_audio_time - mixer.latency() - self.start_time;
+
+        if mixer.is_running() {
+            self.song_time += dt;
+        }
+
+        let error = target - self.song_time;
+        if error.abs() > MAX_DRIFT || !mixer.is_running() {
+            self.song_time = target;
+        } else {
+            self.song_time += error * CORRECTION;
+        }
+
+        self.last_update_ms = util::now_ms();
+    }
+
+    /// Returns the position in the song that is being heard, in seconds.
+    /// This is negative before the song has started.
+    pub fn song_time(&self) -> f64 {
+        self.song_time
+    }
+
+    /// Returns the position in the song at the time `time_ms`, which is
+    /// given by the high-resolution clock of the browser, e.g. by
+    /// `Canvas::pop_timed_event`. This allows judging the timing of inputs
+    /// more precisely than by the frame in which they are handled.
+    pub fn song_time_at(&self, time_ms: f64) -> f64 {
+        self.song_time + (time_ms - self.last_update_ms) / 1000.0
+    }
+
+    /// Returns the beat that is being heard, counting from zero at the first
+    /// beat of the song. The fractional part is the progress within the
+    /// beat.
+    pub fn beat(&self) -> f64 {
+        self.beat_at_song_time(self.song_time)
+    }
+
+    /// Returns the beat at the song position `song_time`.
+    pub fn beat_at_song_time(&self, song_time: f64) -> f64 {
+        (song_time - self.offset) / self.beat_duration()
+    }
+
+    /// Returns the audio context time at which `beat` is played, e.g. for
+    /// scheduling sounds on the beat with `Mixer::play_at`.
+    pub fn audio_time_of_beat(&self, beat: f64) -> f64 {
+        self.start_time + self.offset + beat * self.beat_duration()
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    AudioBuffer, AudioBufferSourceNode, AudioContext, AudioContextState, AudioNode, GainNode,
+    Response,
+};
+
+use crate::{
+    audio::{bus::Bus, BusEffect, BusId},
+    Error,
+};
+
+/// The duration over which stolen voices are faded out, in seconds. Cutting
+/// them off immediately would cause audible clicks.
+const STEAL_FADE_OUT: f64 = 0.02;
+
+/// How a sound is played by the mixer.
+#[derive(Debug, Clone)]
+pub struct SoundConfig {
+    pub volume: f32,
+
+    /// Each time the sound is played, its volume is reduced by a random
+    /// fraction of up to this much, e.g. `0.2` for volumes between 80% and
+    /// 100% of `volume`.
+    pub volume_variation: f32,
+
+    /// Each time the sound is played, its pitch is shifted randomly by up to
+    /// this many semitones in either direction. This also changes the speed
+    /// of the sound.
+    pub pitch_variation: f32,
+
+    /// The maximal number of voices of the sound that play at the same time.
+    /// When the sound is played once more, the oldest voice is faded out
+    /// quickly. This keeps rapid-fire effects such as gunshots from piling
+    /// up into noise.
+    pub max_voices: Option<usize>,
+
+    /// The bus that the sound is played through, or `None` to play it
+    /// directly through the master volume.
+    pub bus: Option<BusId>,
+}
+
+impl Default for SoundConfig {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            volume_variation: 0.0,
+            pitch_variation: 0.0,
+            max_voices: None,
+            bus: None,
+        }
+    }
+}
+
+/// Decoded audio data, which can be played any number of times.
+///
+/// Clones of a sound share their voices, so the voice limit of the
+/// `SoundConfig` applies to all of them together.
+#[derive(Debug, Clone)]
+pub struct Sound {
+    buffer: AudioBuffer,
+    config: SoundConfig,
+    voices: Rc<RefCell<Vec<Voice>>>,
+}
+
+impl Sound {
+    /// Returns the length of the sound in seconds.
+    pub fn duration(&self) -> f64 {
+        self.buffer.duration()
+    }
+
+    pub fn config(&self) -> &SoundConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SoundConfig) {
+        self.config = config;
+    }
+
+    pub fn with_config(mut self, config: SoundConfig) -> Self {
+        self.config = config;
+        self
+    }
+}
+
+/// One playback of a sound.
+#[derive(Debug, Clone)]
+pub struct Voice {
+    source: AudioBufferSourceNode,
+    gain: GainNode,
+
+    /// The audio context time at which the voice ends, which is updated when
+    /// it is stopped.
+    end_time: Rc<Cell<f64>>,
+}
+
+impl Voice {
+    /// Stops playing. Has no effect if the voice has already ended.
+    pub fn stop(&self) {
+        // Older browsers throw when stopping a source that has ended.
+        let _ = self.source.stop();
+
+        let now = self.source.context().current_time();
+        self.end_time.set(self.end_time.get().min(now));
+    }
+
+    /// Fades the voice out over `duration` seconds and then stops it.
+    pub fn fade_out(&self, duration: f64) {
+        let now = self.source.context().current_time();
+        let gain = self.gain.gain();
+
+        // Anchor the ramp at the current value, so that it does not jump.
+        let _ = gain.cancel_scheduled_values(now);
+        let _ = gain.set_value_at_time(gain.value(), now);
+        let _ = gain.linear_ramp_to_value_at_time(0.0, now + duration);
+        let _ = self.source.stop_with_when(now + duration);
+
+        self.end_time.set(self.end_time.get().min(now + duration));
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.gain.gain().set_value(volume);
+    }
+
+    /// Sets the playback rate, which changes both speed and pitch.
+    pub fn set_playback_rate(&self, rate: f32) {
+        self.source.playback_rate().set_value(rate);
+    }
+}
+
+/// Plays sounds through a WebAudio context.
+///
+/// Browsers only allow audio to start in response to a user gesture, so the
+/// context may start out suspended. Call `resume` when handling the first
+/// key press or click.
+///
+/// Times are given in seconds on the clock of the audio context, which is
+/// advanced by the audio hardware. It is not synchronized with the time
+/// passed by the main loop, and the two drift apart over time; see
+/// `Conductor` for following the audio clock in the game.
+pub struct Mixer {
+    ctx: AudioContext,
+    master: GainNode,
+    buses: Vec<Bus>,
+}
+
+impl Mixer {
+    pub fn new() -> Result<Self, Error> {
+        let ctx = AudioContext::new().map_err(audio_error)?;
+        let master = ctx.create_gain().map_err(audio_error)?;
+        master
+            .connect_with_audio_node(&ctx.destination())
+            .map_err(audio_error)?;
+
+        Ok(Self {
+            ctx,
+            master,
+            buses: Vec::new(),
+        })
+    }
+
+    pub fn context(&self) -> &AudioContext {
+        &self.ctx
+    }
+
+    /// Resumes the audio context if it has been suspended by the browser.
+    pub fn resume(&self) {
+        if !self.is_running() {
+            let _ = self.ctx.resume();
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.ctx.state() == AudioContextState::Running
+    }
+
+    /// Returns the current time of the audio context. This is the time up to
+    /// which audio has been passed to the audio hardware, so it runs ahead
+    /// of what can be heard by `latency`.
+    ///
+    /// The time advances in steps of an audio buffer, which are several
+    /// milliseconds long, so it is not smooth from frame to frame.
+    pub fn current_time(&self) -> f64 {
+        self.ctx.current_time()
+    }
+
+    /// Returns the latency that the audio context adds when passing audio to
+    /// the operating system, in seconds.
+    pub fn base_latency(&self) -> f64 {
+        self.ctx.base_latency()
+    }
+
+    /// Returns the estimated latency between the operating system receiving
+    /// audio and the audio being heard, in seconds. Not all browsers provide
+    /// this.
+    pub fn output_latency(&self) -> Option<f64> {
+        js_sys::Reflect::get(&self.ctx, &JsValue::from_str("outputLatency"))
+            .ok()
+            .and_then(|latency| latency.as_f64())
+    }
+
+    /// Returns the total estimated latency between `current_time` and audio
+    /// being heard, in seconds.
+    pub fn latency(&self) -> f64 {
+        self.base_latency() + self.output_latency().unwrap_or(0.0)
+    }
+
+    /// Returns the time of the audio that is being heard right now.
+    pub fn audible_time(&self) -> f64 {
+        self.current_time() - self.latency()
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.master.gain().set_value(volume);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.master.gain().value()
+    }
+
+    /// Creates a bus, which groups sounds such as music or sound effects so
+    /// that they share a volume and a chain of effects. Sounds are assigned
+    /// to buses through `SoundConfig::bus`.
+    pub fn create_bus(&mut self) -> Result<BusId, Error> {
+        self.buses.push(Bus::new(&self.ctx, &self.master)?);
+
+        Ok(BusId(self.buses.len() - 1))
+    }
+
+    pub fn set_bus_volume(&self, bus: BusId, volume: f32) {
+        self.buses[bus.0].set_volume(volume);
+    }
+
+    pub fn bus_volume(&self, bus: BusId) -> f32 {
+        self.buses[bus.0].volume()
+    }
+
+    /// Replaces the effects of `bus`, which are applied in order, e.g. to
+    /// muffle the music while the game is paused.
+    ///
+    /// If the effects are of the same kinds as before, only their
+    /// parameters change, smoothly and without interrupting the sound. To
+    /// toggle an effect without clicks, keep it in the chain and change its
+    /// parameters instead, e.g. by setting the frequency of a lowpass filter
+    /// to 20000 Hz, or the mix of a reverb to zero.
+    pub fn set_bus_effects(&mut self, bus: BusId, effects: &[BusEffect]) -> Result<(), Error> {
+        self.buses[bus.0].set_effects(&self.ctx, effects)
+    }
+
+    pub fn bus_effects(&self, bus: BusId) -> impl Iterator<Item = &BusEffect> {
+        self.buses[bus.0].effects()
+    }
+
+    /// Fetches and decodes the sound file at `url`. All formats that the
+    /// browser supports can be used.
+    pub async fn load(&self, url: &str) -> Result<Sound, Error> {
+        let window = web_sys::window().ok_or(Error::NoWindow)?;
+
+        let response: Response = JsFuture::from(window.fetch_with_str(url))
+            .await
+            .map_err(audio_error)?
+            .dyn_into()
+            .map_err(audio_error)?;
+        if !response.ok() {
+            return Err(Error::Audio(format!(
+                "failed to load `{}`: HTTP status {} {}",
+                url,
+                response.status(),
+                response.status_text()
+            )));
+        }
+
+        let data = JsFuture::from(response.array_buffer().map_err(audio_error)?)
+            .await
+            .map_err(audio_error)?
+            .dyn_into()
+            .map_err(audio_error)?;
+        let buffer = JsFuture::from(self.ctx.decode_audio_data(&data).map_err(audio_error)?)
+            .await
+            .map_err(audio_error)?
+            .dyn_into()
+            .map_err(audio_error)?;
+
+        Ok(Sound {
+            buffer,
+            config: SoundConfig::default(),
+            voices: Rc::new(RefCell::new(Vec::new())),
+        })
+    }
+
+    /// Returns the number of voices of `sound` that are playing or scheduled
+    /// to play.
+    pub fn num_voices(&self, sound: &Sound) -> usize {
+        let now = self.ctx.current_time();
+
+        sound
+            .voices
+            .borrow()
+            .iter()
+            .filter(|voice| voice.end_time.get() > now)
+            .count()
+    }
+
+    /// Starts playing `sound` immediately.
+    pub fn play(&self, sound: &Sound) -> Result<Voice, Error> {
+        self.play_at(sound, 0.0)
+    }
+
+    /// Starts playing `sound` at the audio context time `when`. Times in the
+    /// past start the sound immediately.
+    ///
+    /// Scheduling sounds ahead of time, e.g. a song a few hundred
+    /// milliseconds after `current_time`, makes them start exactly on time,
+    /// independently of the frame rate.
+    ///
+    /// The volume and pitch are varied according to the sound's
+    /// `SoundConfig`. If the sound already plays with the maximal number of
+    /// voices, its oldest voice is stolen.
+    pub fn play_at(&self, sound: &Sound, when: f64) -> Result<Voice, Error> {
+        let config = &sound.config;
+        let now = self.ctx.current_time();
+
+        {
+            let mut voices = sound.voices.borrow_mut();
+            voices.retain(|voice| voice.end_time.get() > now);
+
+            if let Some(max_voices) = config.max_voices {
+                // There always is room for the new voice.
+                let num_stolen = (voices.len() + 1).saturating_sub(max_voices.max(1));
+                for voice in voices.drain(..num_stolen) {
+                    voice.fade_out(STEAL_FADE_OUT);
+                }
+            }
+        }
+
+        let volume =
+            config.volume * (1.0 - config.volume_variation * js_sys::Math::random() as f32);
+        let semitones = config.pitch_variation * (js_sys::Math::random() as f32 * 2.0 - 1.0);
+        let rate = 2.0f32.powf(semitones / 12.0);
+
+        let source = self.ctx.create_buffer_source().map_err(audio_error)?;
+        source.set_buffer(Some(&sound.buffer));
+        source.playback_rate().set_value(rate);
+
+        let gain = self.ctx.create_gain().map_err(audio_error)?;
+        gain.gain().set_value(volume);
+        source.connect_with_audio_node(&gain).map_err(audio_error)?;
+        let destination: &AudioNode = match config.bus {
+            Some(bus) => self.buses[bus.0].input(),
+            None => &self.master,
+        };
+        gain.connect_with_audio_node(destination)
+            .map_err(audio_error)?;
+
+        source.start_with_when(when).map_err(audio_error)?;
+
+        let voice = Voice {
+            source,
+            gain,
+            end_time: Rc::new(Cell::new(when.max(now) + sound.duration() / rate as f64)),
+        };
+        sound.voices.borrow_mut().push(voice.clone());
+
+        Ok(voice)
+    }
+}
+
+pub(crate) fn audio_error(e: JsValue) -> Error {
+    Error::Audio(e.as_string().unwrap_or_else(|| format!("{:?}", e)))
+}
@@ -0,0 +1,9 @@
+//! Playing sounds with WebAudio.
+
+mod bus;
+mod conductor;
+mod mixer;
+
+pub use bus::{BusEffect, BusId};
+pub use conductor::Conductor;
+pub use mixer::{Mixer, Sound, SoundConfig, Voice};
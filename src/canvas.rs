@@ -1,23 +1,96 @@
+use std::cell::{Cell, RefCell, RefMut};
+
 use wasm_bindgen::JsCast;
 use web_sys::{HtmlCanvasElement, WebGlRenderingContext};
 
-use golem::{glow, GolemError, Texture};
+use golem::{blend::BlendMode, glow, GolemError, Texture};
 use nalgebra::{Point2, Vector2};
 
 use crate::input::EventHandlers;
 use crate::{
-    draw::{TexColPass, TexColVertex, TriBatch},
-    util, AaRect, Color4, Error, Event, InputState, ScreenGeom,
+    draw::{
+        Batch, BlendScope, ColPass, ColVertex, Geometry, RenderTarget, ScissorScope, ShaderCache,
+        TargetScope, TexColPass, TexColVertex, TriBatch,
+    },
+    gpu_memory, util, AaRect, Camera, Color4, Error, Event, GpuMemoryReport, InputState,
+    Orientation, RedrawTrigger, ScreenGeom,
 };
 
+/// The number of frames for which we check the window size after a resize or
+/// orientation change event.
+const WINDOW_CHECKS_AFTER_RESIZE: usize = 30;
+
+/// The buffers that are cleared by `Canvas::begin_frame`, and the values
+/// that they are cleared to. Buffers that are `None` keep their contents.
+#[derive(Debug, Clone, Copy)]
+pub struct ClearOptions {
+    pub color: Option<Color4>,
+    pub depth: Option<f32>,
+    pub stencil: Option<i32>,
+}
+
+impl Default for ClearOptions {
+    /// Clears the color to opaque black, the depth to the far plane, and the
+    /// stencil to zero.
+    fn default() -> Self {
+        Self {
+            color: Some(Color4::new(0.0, 0.0, 0.0, 1.0)),
+            depth: Some(1.0),
+            stencil: Some(0),
+        }
+    }
+}
+
+impl ClearOptions {
+    /// Clears all buffers like `default`, but with the given color.
+    pub fn with_color(color: Color4) -> Self {
+        Self {
+            color: Some(color),
+            ..Self::default()
+        }
+    }
+}
+
 pub struct Canvas {
     canvas: HtmlCanvasElement,
+    webgl_ctx: WebGlRenderingContext,
     golem_ctx: golem::Context,
     event_handlers: EventHandlers,
     input_state: InputState,
+    redraw: RedrawTrigger,
+
+    /// The screen geometry as of the last resize, used for detecting changes.
+    last_screen_geom: Cell<Option<ScreenGeom>>,
+
+    /// If true, the canvas is resized to fill the window whenever the window
+    /// is resized.
+    fill_window: bool,
+
+    orientation: Orientation,
+
+    /// The number of times that we will check the window for size and
+    /// orientation changes after the browser told us that something changed.
+    pending_window_checks: usize,
+
+    shader_cache: RefCell<ShaderCache>,
+
+    /// Scissor rects in logical pixels. Each rect is already intersected
+    /// with the ones below it.
+    scissor_stack: Vec<AaRect>,
+
+    /// True between `begin_frame` and `end_frame`.
+    in_frame: bool,
+
+    /// The write masks from before `begin_frame` enabled them for clearing,
+    /// which are restored by `end_frame`.
+    saved_masks: Option<WriteMasks>,
 
     debug_tex_batch: Option<TriBatch<TexColVertex>>,
     debug_tex_pass: Option<TexColPass>,
+
+    /// Passes for `draw_batch` and `draw_tex_batch`, created on first use.
+    col_pass: Option<ColPass>,
+    tex_col_pass: Option<TexColPass>,
 }
 
 impl Canvas {
@@ -45,7 +118,8 @@ impl Canvas {
 
     #[cfg(target_arch = "wasm32")]
     pub fn from_element(canvas: HtmlCanvasElement) -> Result<Self, Error> {
-        let event_handlers = EventHandlers::new(canvas.clone())?;
+        let redraw = RedrawTrigger::default();
+        let event_handlers = EventHandlers::new(canvas.clone(), redraw.clone())?;
         let input_state = InputState::default();
 
         let webgl_ctx = canvas
@@ -54,19 +128,33 @@ impl Canvas {
             .ok_or(Error::InitializeWebGl)?
             .dyn_into::<WebGlRenderingContext>()
             .map_err(|_| Error::InitializeWebGl)?;
-        let glow_ctx = glow::Context::from_webgl1_context(webgl_ctx);
+        let glow_ctx = glow::Context::from_webgl1_context(webgl_ctx.clone());
         let golem_ctx = golem::Context::from_glow(glow_ctx)?;
 
         // Make the canvas focusable.
         canvas.set_attribute("tabIndex", "1").unwrap();
 
+        set_touch_gestures_suppressed(&canvas, true);
+
         let mut canvas = Self {
             canvas,
+            webgl_ctx,
             golem_ctx,
             event_handlers,
             input_state,
+            redraw,
+            last_screen_geom: Cell::new(None),
+            fill_window: false,
+            orientation: Self::window_orientation(),
+            pending_window_checks: 0,
+            shader_cache: RefCell::new(ShaderCache::new()),
+            scissor_stack: Vec::new(),
+            in_frame: false,
+            saved_masks: None,
             debug_tex_batch: None,
             debug_tex_pass: None,
+            col_pass: None,
+            tex_col_pass: None,
         };
 
         // Make sure that the canvas size is correct for the screen's DPI.
@@ -83,13 +171,75 @@ impl Canvas {
         &self.golem_ctx
     }
 
+    /// Returns the underlying WebGL context.
+    ///
+    /// This is needed for functionality that `golem` does not cover, such as
+    /// querying extensions. Be careful when changing GL state through this,
+    /// since `golem` caches some of the state.
+    pub fn webgl_ctx(&self) -> &WebGlRenderingContext {
+        &self.webgl_ctx
+    }
+
+    #[cfg(feature = "xr")]
+    pub(crate) fn event_queue(&self) -> crate::input::EventQueue {
+        self.event_handlers.queue()
+    }
+
+    /// Returns the cache of shader programs that are shared between the
+    /// passes created for this canvas.
+    pub fn shader_cache(&self) -> RefMut<ShaderCache> {
+        self.shader_cache.borrow_mut()
+    }
+
     pub fn input_state(&self) -> &InputState {
         &self.input_state
     }
 
+    /// Sets the dead zone of gamepad axes, see
+    /// `InputState::set_gamepad_dead_zone`.
+    pub fn set_gamepad_dead_zone(&mut self, dead_zone: f32) {
+        self.input_state.set_gamepad_dead_zone(dead_zone);
+    }
+
+    /// Requests that the next frame is drawn when running
+    /// `main_loop_on_demand`.
+    pub fn request_redraw(&self) {
+        self.redraw.request();
+    }
+
+    /// Returns the trigger that is set by `request_redraw` and by incoming
+    /// input events. This should be passed to `main_loop_on_demand`.
+    pub fn redraw_trigger(&self) -> RedrawTrigger {
+        self.redraw.clone()
+    }
+
+    /// Resizes the canvas to the given size in logical (CSS) pixels, and
+    /// resets the viewport to cover the whole canvas.
+    ///
+    /// If this changes the screen geometry, an `Event::WindowResized` is
+    /// emitted.
     pub fn resize(&self, logical_size: Vector2<u32>) {
         util::set_canvas_size(&self.canvas, logical_size);
-        self.set_viewport(Point2::origin(), logical_size);
+
+        let screen_geom = self.screen_geom();
+        self.set_viewport(Point2::origin(), screen_geom.size);
+
+        if self.last_screen_geom.get() != Some(screen_geom) {
+            self.last_screen_geom.set(Some(screen_geom));
+            self.event_handlers
+                .push_event(Event::WindowResized(screen_geom));
+        }
+    }
+
+    /// If enabled, the canvas is resized to fill the browser window
+    /// immediately and then whenever the window is resized, so that there is
+    /// no need to call `resize_full` every frame.
+    pub fn set_fill_window(&mut self, fill_window: bool) {
+        self.fill_window = fill_window;
+
+        if fill_window {
+            self.resize_full();
+        }
     }
 
     pub fn screen_geom(&self) -> ScreenGeom {
@@ -99,27 +249,253 @@ impl Canvas {
         }
     }
 
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
     pub fn pop_event(&mut self) -> Option<Event> {
-        if let Some(event) = self.event_handlers.pop_event() {
+        self.pop_timed_event().map(|(event, _)| event)
+    }
+
+    /// Like `pop_event`, but also returns the time at which the event
+    /// occurred, in milliseconds.
+    ///
+    /// The time is taken from the browser's high-resolution clock, which is
+    /// the same clock that the timestamps of `requestAnimationFrame` and
+    /// `performance.now()` use. This allows ordering and interpolating the
+    /// inputs that arrive within a single frame, e.g. when running a
+    /// fixed-timestep simulation with multiple steps per frame. Events
+    /// generated by the canvas itself, such as `WindowResized`, are stamped
+    /// when they are generated.
+    pub fn pop_timed_event(&mut self) -> Option<(Event, f64)> {
+        crate::profile_scope!("input");
+
+        if self.event_handlers.take_window_resized() {
+            // Mobile Safari reports stale window sizes for a while after the
+            // device has been rotated, so we keep checking for a number of
+            // frames. Redundant checks are cheap, since events are only
+            // emitted on actual changes.
+            self.pending_window_checks = WINDOW_CHECKS_AFTER_RESIZE;
+            self.check_window();
+        }
+
+        let mut event = self.event_handlers.pop_event();
+
+        if event.is_none() && self.pending_window_checks > 0 {
+            // All events of this frame have been handled, so this runs about
+            // once per frame.
+            self.pending_window_checks -= 1;
+            self.check_window();
+            event = self.event_handlers.pop_event();
+        }
+
+        if let Some((event, time_ms)) = event {
             self.on_event(&event);
 
-            Some(event)
+            Some((event, time_ms))
         } else {
+            // Like the window checks, this runs about once per frame.
+            self.input_state.poll_gamepads();
+
             None
         }
     }
 
+    /// Pushes an event to the end of the event queue, e.g. to emulate input.
+    /// Once popped, it is handled like any other event, so e.g. emulated key
+    /// presses also show up in the `InputState`.
+    pub fn push_event(&self, event: Event) {
+        self.event_handlers.push_event(event);
+    }
+
+    /// Sets whether the browser's context menu is suppressed when
+    /// right-clicking the canvas. This is enabled by default, so that the
+    /// right mouse button can be used by the application.
+    pub fn set_suppress_context_menu(&mut self, suppress: bool) {
+        self.event_handlers.set_suppress_context_menu(suppress);
+    }
+
+    /// Sets whether the browser's default touch gestures, such as scrolling,
+    /// double-tap zoom, pull-to-refresh and the long-press callout, are
+    /// suppressed on the canvas. This is enabled by default.
+    ///
+    /// Note that if touch gestures are not suppressed, the browser will
+    /// usually cancel touch pointers as soon as they start moving.
+    pub fn set_suppress_touch_gestures(&mut self, suppress: bool) {
+        set_touch_gestures_suppressed(&self.canvas, suppress);
+    }
+
+    /// Sets the text that is put into the clipboard when the user copies or
+    /// cuts while the canvas has focus, e.g. the selection of a text field.
+    ///
+    /// Browsers only allow writing to the clipboard while handling the copy
+    /// event, which happens before the next frame, so the text has to be set
+    /// ahead of time.
+    pub fn set_copy_text(&mut self, text: Option<String>) {
+        self.event_handlers.set_copy_text(text);
+    }
+
     pub fn clear(&self, color: Color4) {
         self.golem_ctx
             .set_clear_color(color.r, color.g, color.b, color.a);
         self.golem_ctx.clear();
     }
 
+    /// Starts drawing a frame onto the canvas, clearing the buffers given by
+    /// `options` across the whole canvas.
+    ///
+    /// Depth and stencil are cleared even if writing to them has been
+    /// disabled, so that depth tests of this frame do not compare against
+    /// the previous one. The write masks are enabled for the frame, and
+    /// restored by `end_frame`.
+    ///
+    /// If the previous frame has not been ended, e.g. because drawing
+    /// returned early with an error, it is ended here with a warning,
+    /// dropping any scissor rects that are still pushed.
+    pub fn begin_frame(&mut self, options: ClearOptions) {
+        if self.in_frame {
+            log::warn!("begin_frame called without end_frame, ending the previous frame");
+
+            self.scissor_stack.clear();
+            self.end_frame();
+        }
+        self.in_frame = true;
+
+        let gl = &self.webgl_ctx;
+        self.saved_masks = Some(WriteMasks::get(gl));
+        let mut mask = 0;
+
+        if let Some(color) = options.color {
+            gl.color_mask(true, true, true, true);
+            gl.clear_color(color.r, color.g, color.b, color.a);
+            mask |= WebGlRenderingContext::COLOR_BUFFER_BIT;
+        }
+        if let Some(depth) = options.depth {
+            gl.depth_mask(true);
+            gl.clear_depth(depth);
+            mask |= WebGlRenderingContext::DEPTH_BUFFER_BIT;
+        }
+        if let Some(stencil) = options.stencil {
+            gl.stencil_mask(!0);
+            gl.clear_stencil(stencil);
+            mask |= WebGlRenderingContext::STENCIL_BUFFER_BIT;
+        }
+
+        self.set_viewport(Point2::origin(), self.screen_geom().size);
+        if mask != 0 {
+            gl.disable(WebGlRenderingContext::SCISSOR_TEST);
+            gl.clear(mask);
+            self.apply_scissor();
+        }
+    }
+
+    /// Finishes the frame that has been started with `begin_frame`,
+    /// restoring the write masks from before the frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no frame has been started, or if a scissor rect is still
+    /// pushed, which usually means that a `pop_scissor` is missing.
+    pub fn end_frame(&mut self) {
+        assert!(self.in_frame, "end_frame called without begin_frame");
+        assert!(
+            self.scissor_stack.is_empty(),
+            "end_frame called with {} scissor rects still pushed",
+            self.scissor_stack.len(),
+        );
+
+        if let Some(masks) = self.saved_masks.take() {
+            masks.set(&self.webgl_ctx);
+        }
+        self.apply_scissor();
+
+        self.in_frame = false;
+    }
+
+    pub fn is_in_frame(&self) -> bool {
+        self.in_frame
+    }
+
     pub fn set_viewport(&self, lower_left: Point2<u32>, size: Vector2<u32>) {
         self.golem_ctx
             .set_viewport(lower_left.x, lower_left.y, size.x, size.y);
     }
 
+    /// Calls `f` with all drawing going into `target` instead of the canvas.
+    ///
+    /// The viewport covers the whole target while `f` runs, and scissor
+    /// rects of the canvas are disabled, since they are given in screen
+    /// coordinates. Afterwards, the previous render target and viewport are
+    /// restored, so calls can be nested.
+    pub fn with_target<R>(&mut self, target: &RenderTarget, f: impl FnOnce(&mut Canvas) -> R) -> R {
+        let scissor_stack = std::mem::take(&mut self.scissor_stack);
+        self.apply_scissor();
+
+        let result = {
+            let _target = TargetScope::new(self, target);
+            f(self)
+        };
+
+        self.scissor_stack = scissor_stack;
+        self.apply_scissor();
+
+        result
+    }
+
+    /// Restricts drawing to `rect`, given in logical pixels, until the
+    /// matching call to `pop_scissor`.
+    ///
+    /// Scissor rects nest: the effective rect is the intersection with all
+    /// rects that are currently on the stack.
+    pub fn push_scissor(&mut self, rect: &AaRect) {
+        let rect = match self.scissor_stack.last() {
+            Some(top) => top.intersection(rect),
+            None => *rect,
+        };
+
+        self.scissor_stack.push(rect);
+        self.apply_scissor();
+    }
+
+    pub fn pop_scissor(&mut self) {
+        assert!(
+            self.scissor_stack.pop().is_some(),
+            "pop_scissor called without matching push_scissor"
+        );
+        self.apply_scissor();
+    }
+
+    /// Restricts drawing to `rect` until the returned scope is dropped.
+    pub fn scissor_scope(&mut self, rect: &AaRect) -> ScissorScope {
+        ScissorScope::new(self, rect)
+    }
+
+    pub fn scissor(&self) -> Option<&AaRect> {
+        self.scissor_stack.last()
+    }
+
+    fn apply_scissor(&self) {
+        let gl = &self.webgl_ctx;
+
+        if let Some(rect) = self.scissor_stack.last() {
+            let screen = self.screen_geom();
+            let ratio = screen.device_pixel_ratio as f32;
+            let (min, max) = (rect.min(), rect.max());
+
+            // GL's scissor rect is in physical pixels, with the origin at the
+            // bottom left.
+            gl.enable(WebGlRenderingContext::SCISSOR_TEST);
+            gl.scissor(
+                (min.x * ratio).floor() as i32,
+                (screen.size.y as f32 - max.y * ratio).floor() as i32,
+                (rect.size.x * ratio).ceil() as i32,
+                (rect.size.y * ratio).ceil() as i32,
+            );
+        } else {
+            gl.disable(WebGlRenderingContext::SCISSOR_TEST);
+        }
+    }
+
     pub fn resize_full(&self) {
         // A collection of anti-patterns [1] recommends using
         // clientWidth/clientHeight and CSS for resizing. I have not been able
@@ -176,6 +552,80 @@ impl Canvas {
         Ok(())
     }
 
+    /// Uploads `batch` and draws it as seen through `camera`, using the
+    /// built-in `ColPass` with alpha blending.
+    ///
+    /// This covers the common case of drawing colored shapes in world space
+    /// without setting up passes and transforms by hand. For anything more
+    /// specific, use the passes directly.
+    pub fn draw_batch<G>(&mut self, batch: &mut Batch<G>, camera: &Camera) -> Result<(), Error>
+    where
+        G: Geometry<Vertex = ColVertex>,
+    {
+        if self.col_pass.is_none() {
+            self.col_pass = Some(ColPass::new(self)?);
+        }
+
+        let screen = self.screen_geom();
+        let transform = camera.projection_view(&screen);
+
+        let _blend = BlendScope::new(&self.golem_ctx, BlendMode::default());
+        self.col_pass
+            .as_mut()
+            .unwrap()
+            .draw(&transform, &batch.draw_unit())
+    }
+
+    /// Like `draw_batch`, but for textured batches, which are drawn with the
+    /// built-in `TexColPass`.
+    pub fn draw_tex_batch(
+        &mut self,
+        batch: &mut TriBatch<TexColVertex>,
+        texture: &Texture,
+        camera: &Camera,
+    ) -> Result<(), Error> {
+        if self.tex_col_pass.is_none() {
+            self.tex_col_pass = Some(TexColPass::new(self)?);
+        }
+
+        let screen = self.screen_geom();
+        let transform = camera.projection_view(&screen);
+
+        let _blend = BlendScope::new(&self.golem_ctx, BlendMode::default());
+        self.tex_col_pass
+            .as_mut()
+            .unwrap()
+            .draw(&transform, texture, &batch.draw_unit())
+    }
+
+    /// Returns an estimate of the GPU memory that is used by the resources
+    /// that `malen` keeps track of.
+    ///
+    /// Note that the report covers all canvases, since resources are tracked
+    /// per thread.
+    pub fn gpu_memory_report(&self) -> GpuMemoryReport {
+        gpu_memory::gpu_memory_report()
+    }
+
+    fn window_orientation() -> Orientation {
+        util::window_inner_size().map_or(Orientation::Landscape, |(width, height)| {
+            Orientation::from_size(width, height)
+        })
+    }
+
+    fn check_window(&mut self) {
+        if self.fill_window {
+            self.resize_full();
+        }
+
+        let orientation = Self::window_orientation();
+        if orientation != self.orientation {
+            self.orientation = orientation;
+            self.event_handlers
+                .push_event(Event::OrientationChanged(orientation));
+        }
+    }
+
     fn on_event(&mut self, event: &Event) {
         self.input_state.on_event(event);
     }
@@ -186,3 +636,62 @@ impl From<GolemError> for Error {
         Error::Golem(e)
     }
 }
+
+fn set_touch_gestures_suppressed(canvas: &HtmlCanvasElement, suppress: bool) {
+    let (touch_action, overscroll_behavior, user_select, touch_callout) = if suppress {
+        ("none", "none", "none", "none")
+    } else {
+        ("auto", "auto", "auto", "default")
+    };
+
+    util::set_canvas_style_property(canvas, "touch-action", touch_action);
+    util::set_canvas_style_property(canvas, "overscroll-behavior", overscroll_behavior);
+    util::set_canvas_style_property(canvas, "user-select", user_select);
+    util::set_canvas_style_property(canvas, "-webkit-user-select", user_select);
+    util::set_canvas_style_property(canvas, "-webkit-touch-callout", touch_callout);
+}
+
+/// The write masks of the color, depth and stencil buffers.
+#[derive(Debug, Clone, Copy)]
+struct WriteMasks {
+    color: [bool; 4],
+    depth: bool,
+    stencil: u32,
+}
+
+impl WriteMasks {
+    /// Reads the current masks, assuming that writing is enabled for values
+    /// that cannot be read.
+    fn get(gl: &WebGlRenderingContext) -> Self {
+        let param = |name| gl.get_parameter(name).ok();
+
+        let color = param(WebGlRenderingContext::COLOR_WRITEMASK)
+            .map(|value| js_sys::Array::from(&value))
+            .map_or([true; 4], |array| {
+                let mut color = [true; 4];
+                for (i, c) in color.iter_mut().enumerate() {
+                    *c = array.get(i as u32).as_bool().unwrap_or(true);
+                }
+                color
+            });
+        let depth = param(WebGlRenderingContext::DEPTH_WRITEMASK)
+            .and_then(|value| value.as_bool())
+            .unwrap_or(true);
+        let stencil = param(WebGlRenderingContext::STENCIL_WRITEMASK)
+            .and_then(|value| value.as_f64())
+            .map_or(!0, |value| value as u32);
+
+        Self {
+            color,
+            depth,
+            stencil,
+        }
+    }
+
+    fn set(&self, gl: &WebGlRenderingContext) {
+        let [r, g, b, a] = self.color;
+        gl.color_mask(r, g, b, a);
+        gl.depth_mask(self.depth);
+        gl.stencil_mask(self.stencil);
+    }
+}
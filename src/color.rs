@@ -1,4 +1,5 @@
 #[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
 pub struct Color3 {
     pub r: f32,
     pub g: f32,
@@ -6,6 +7,7 @@ pub struct Color3 {
 }
 
 #[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
 pub struct Color4 {
     pub r: f32,
     pub g: f32,
@@ -0,0 +1,200 @@
+//! Autotiling, which selects the edge and corner tiles of a terrain
+//! automatically.
+//!
+//! A `TerrainLayer` stores which tiles of a map belong to the terrain, e.g.
+//! water or grass. Each terrain tile is given the frame that matches which of
+//! its neighbors also belong to the terrain. Neighbors are taken along the
+//! axes of the map (see `TileLayout::neighbor`), with north being the
+//! negative Y direction.
+
+use nalgebra::{Point2, Vector2};
+
+use crate::draw::Tilemap;
+
+/// The neighbors of a tile in the order of the bits of an 8-bit mask:
+/// north, northeast, east, southeast, south, southwest, west, northwest.
+const NEIGHBORS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutotileMode {
+    /// Only the four edge neighbors are considered, giving 16 variants. The
+    /// variant is a mask of the neighbors that belong to the terrain, with
+    /// north = 1, east = 2, south = 4 and west = 8.
+    Edges16,
+
+    /// Edge and corner neighbors are considered, giving 47 variants. A
+    /// corner is only considered if both adjacent edges belong to the
+    /// terrain. The variants are the 47 possible masks, with bits in the
+    /// order of north = 1, northeast = 2, east = 4, and so on clockwise,
+    /// sorted by ascending mask.
+    Blob47,
+}
+
+impl AutotileMode {
+    pub fn num_variants(self) -> usize {
+        match self {
+            AutotileMode::Edges16 => 16,
+            AutotileMode::Blob47 => 47,
+        }
+    }
+}
+
+/// Returns the mask of the neighbors in a 47-blob tileset, dropping corners
+/// whose adjacent edges do not both belong to the terrain.
+fn reduce_blob_mask(mask: u8) -> u8 {
+    let mut reduced = mask & 0b0101_0101;
+
+    for corner in (1..8).step_by(2) {
+        let prev = 1 << (corner - 1);
+        let next = 1 << ((corner + 1) % 8);
+
+        if mask & (1 << corner) != 0 && mask & prev != 0 && mask & next != 0 {
+            reduced |= 1 << corner;
+        }
+    }
+
+    reduced
+}
+
+/// Marks which tiles of a map belong to a terrain, and updates the tiles of
+/// the map whenever the terrain changes.
+#[derive(Debug, Clone)]
+pub struct TerrainLayer {
+    mode: AutotileMode,
+    frames: Vec<usize>,
+
+    /// Maps 8-bit neighbor masks to variants.
+    variants: [u8; 256],
+
+    size: Vector2<u32>,
+    cells: Vec<bool>,
+}
+
+impl TerrainLayer {
+    /// Creates an empty terrain layer for maps of `size` tiles.
+    ///
+    /// `frames` gives the frame of each variant of `mode`, in the order that
+    /// is described in `AutotileMode`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of frames does not match the number of variants
+    /// of `mode`.
+    pub fn new(mode: AutotileMode, frames: Vec<usize>, size: Vector2<u32>) -> Self {
+        assert_eq!(frames.len(), mode.num_variants());
+
+        let mut variants = [0; 256];
+        match mode {
+            AutotileMode::Edges16 => {
+                for (mask, variant) in variants.iter_mut().enumerate() {
+                    let mask = mask as u8;
+                    // Keep the bits of the edges, i.e. every other bit.
+                    *variant =
+                        (mask & 1) | ((mask >> 1) & 2) | ((mask >> 2) & 4) | ((mask >> 3) & 8);
+                }
+            }
+            AutotileMode::Blob47 => {
+                let mut reduced_masks: Vec<u8> = (0..=255).map(reduce_blob_mask).collect();
+                reduced_masks.sort_unstable();
+                reduced_masks.dedup();
+                assert_eq!(reduced_masks.len(), 47);
+
+                for (mask, variant) in variants.iter_mut().enumerate() {
+                    let reduced = reduce_blob_mask(mask as u8);
+                    *variant = reduced_masks.binary_search(&reduced).unwrap() as u8;
+                }
+            }
+        }
+
+        Self {
+            mode,
+            frames,
+            variants,
+            size,
+            cells: vec![false; size.x as usize * size.y as usize],
+        }
+    }
+
+    pub fn mode(&self) -> AutotileMode {
+        self.mode
+    }
+
+    pub fn is_terrain(&self, tile: Point2<i32>) -> bool {
+        self.index(tile).map_or(false, |index| self.cells[index])
+    }
+
+    /// Marks tiles as belonging to the terrain without updating a map, e.g.
+    /// while loading. Call `apply` afterwards.
+    pub fn set_terrain(&mut self, tile: Point2<i32>, terrain: bool) {
+        if let Some(index) = self.index(tile) {
+            self.cells[index] = terrain;
+        }
+    }
+
+    /// Sets the frames of all tiles of `map` that belong to the terrain.
+    pub fn apply(&self, map: &mut Tilemap) {
+        for y in 0..self.size.y as i32 {
+            for x in 0..self.size.x as i32 {
+                self.update_tile(map, Point2::new(x, y));
+            }
+        }
+    }
+
+    /// Changes whether `tile` belongs to the terrain, e.g. when the map is
+    /// edited at runtime, and updates the frames of the tile and its
+    /// neighbors in `map`.
+    ///
+    /// Tiles that are removed from the terrain are cleared in `map`.
+    pub fn edit(&mut self, map: &mut Tilemap, tile: Point2<i32>, terrain: bool) {
+        self.set_terrain(tile, terrain);
+
+        if !terrain {
+            map.set(tile, None);
+        }
+        self.update_tile(map, tile);
+
+        let layout = *map.layout();
+        for (dx, dy) in NEIGHBORS.iter() {
+            self.update_tile(map, layout.neighbor(tile, Vector2::new(*dx, *dy)));
+        }
+    }
+
+    fn update_tile(&self, map: &mut Tilemap, tile: Point2<i32>) {
+        if !self.is_terrain(tile) {
+            return;
+        }
+
+        let layout = *map.layout();
+        let mask = NEIGHBORS
+            .iter()
+            .enumerate()
+            .filter(|(_, (dx, dy))| self.is_terrain(layout.neighbor(tile, Vector2::new(*dx, *dy))))
+            .fold(0u8, |mask, (bit, _)| mask | (1 << bit));
+
+        map.set(
+            tile,
+            Some(self.frames[self.variants[mask as usize] as usize]),
+        );
+    }
+
+    fn index(&self, tile: Point2<i32>) -> Option<usize> {
+        if tile.x >= 0
+            && tile.y >= 0
+            && (tile.x as u32) < self.size.x
+            && (tile.y as u32) < self.size.y
+        {
+            Some(tile.y as usize * self.size.x as usize + tile.x as usize)
+        } else {
+            None
+        }
+    }
+}
@@ -1,11 +1,14 @@
 use std::marker::PhantomData;
 
-use golem::{ElementBuffer, GeometryMode, ShaderProgram, VertexBuffer};
-use nalgebra::Point3;
+use golem::ShaderProgram;
+use nalgebra::{Point2, Point3};
 
 use crate::{
-    draw::{ColVertex, Geometry, Line, Quad, TexColVertex, TexVertex, Triangle, Vertex},
-    AaRect, Canvas, Color4, Error,
+    draw::{
+        simd, ColVertex, ElementBuffer, FlatVertex, Geometry, GeometryMode, Line, Quad,
+        TexColVertex, TexVertex, Triangle, Vertex, VertexBuffer,
+    },
+    AaRect, Canvas, Color4, Error, GpuAllocation, GpuResourceKind, YAxis,
 };
 
 pub struct DrawUnit<'a, V> {
@@ -43,14 +46,29 @@ impl<'a, V> DrawUnit<'a, V> {
         // safety.
         Ok(unsafe {
             shader.draw(
-                self.vertices,
-                self.elements,
-                self.first_element..self.num_elements,
-                self.geometry_mode,
+                self.vertices.golem(),
+                self.elements.golem(),
+                self.first_element..self.first_element + self.num_elements,
+                self.geometry_mode.into(),
             )
         }?)
     }
 
+    /// Returns a unit that only draws `num_elements` elements of this unit,
+    /// starting at `first_element`.
+    pub fn sub_unit(&self, first_element: usize, num_elements: usize) -> Self {
+        assert!(first_element + num_elements <= self.num_elements);
+
+        Self {
+            vertices: self.vertices,
+            elements: self.elements,
+            first_element: self.first_element + first_element,
+            num_elements,
+            geometry_mode: self.geometry_mode,
+            _phantom: PhantomData,
+        }
+    }
+
     pub fn vertices(&self) -> &'a VertexBuffer {
         self.vertices
     }
@@ -72,19 +90,186 @@ impl<'a, V> DrawUnit<'a, V> {
     }
 }
 
+/// A range of consecutively pushed elements that share the same sort key.
+#[derive(Debug, Clone, Copy)]
+struct SortGroup {
+    key: f32,
+    first_element: usize,
+}
+
+/// Determines when a batch releases memory that it reserved for more
+/// primitives than it currently holds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShrinkPolicy {
+    /// Memory is only released by `Batch::shrink_to_fit`.
+    Never,
+
+    /// Memory is released by `Batch::apply_shrink_policy` once the batch has
+    /// used less than a quarter of it for the given number of consecutive
+    /// frames.
+    Auto { frames: u32 },
+}
+
+impl Default for ShrinkPolicy {
+    fn default() -> Self {
+        ShrinkPolicy::Never
+    }
+}
+
+/// Memory usage of a `Batch`, see `Batch::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatchStats {
+    pub num_vertices: usize,
+    pub num_elements: usize,
+
+    /// The largest number of vertices that the batch has held since it was
+    /// created, or since the peaks were last reset.
+    pub peak_vertices: usize,
+
+    /// The largest number of elements that the batch has held since it was
+    /// created, or since the peaks were last reset.
+    pub peak_elements: usize,
+
+    /// Bytes reserved on the CPU side for vertices and elements.
+    pub cpu_bytes: usize,
+
+    /// Bytes that have been uploaded to the GPU buffers at most since they
+    /// were created. The driver may reserve more.
+    pub gpu_bytes: usize,
+}
+
+/// The largest number of floats and elements seen over some time span.
+#[derive(Debug, Clone, Copy, Default)]
+struct Peak {
+    floats: usize,
+    vertices: usize,
+    elements: usize,
+}
+
+impl Peak {
+    fn max(self, other: Peak) -> Peak {
+        Peak {
+            floats: self.floats.max(other.floats),
+            vertices: self.vertices.max(other.vertices),
+            elements: self.elements.max(other.elements),
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.floats * std::mem::size_of::<f32>() + self.elements * std::mem::size_of::<u32>()
+    }
+}
+
 #[derive(Default)]
 struct Scratch {
     vertices: Vec<f32>,
     elements: Vec<u32>,
     num_vertices: usize,
     dirty: bool,
+
+    /// The peak since creation or `Batch::reset_peaks`.
+    peak: Peak,
+
+    /// The peak since the last call to `Batch::apply_shrink_policy`.
+    frame_peak: Peak,
+
+    /// The peak over the frames that have used little of the reserved
+    /// memory, which is what the batch shrinks to.
+    window_peak: Peak,
+    frames_below_threshold: u32,
+
+    /// The largest data that has been uploaded to the GPU buffers, which
+    /// never shrink by themselves.
+    gpu_peak: Peak,
+
+    /// Sort groups in the order in which they were started. If this is
+    /// empty, elements are drawn in the order in which they were pushed.
+    sort_groups: Vec<SortGroup>,
+
+    /// Elements after sorting by key. Only used if there are sort groups.
+    sorted_elements: Vec<u32>,
 }
 
-pub struct Batch<G: Geometry> {
-    scratch: Scratch,
+impl Scratch {
+    fn current(&self) -> Peak {
+        Peak {
+            floats: self.vertices.len(),
+            vertices: self.num_vertices,
+            elements: self.elements.len(),
+        }
+    }
+
+    fn note_peaks(&mut self) {
+        let current = self.current();
+        self.peak = self.peak.max(current);
+        self.frame_peak = self.frame_peak.max(current);
+    }
+
+    fn cpu_bytes(&self) -> usize {
+        self.vertices.capacity() * std::mem::size_of::<f32>()
+            + (self.elements.capacity() + self.sorted_elements.capacity())
+                * std::mem::size_of::<u32>()
+    }
+
+    fn sort_elements(&mut self) {
+        self.sorted_elements.clear();
+
+        // Elements that were pushed before the first call to `set_sort_key`
+        // implicitly have a key of zero.
+        let mut groups: Vec<(f32, usize, usize)> = Vec::with_capacity(self.sort_groups.len() + 1);
+        let mut prev = SortGroup {
+            key: 0.0,
+            first_element: 0,
+        };
+        for group in self.sort_groups.iter().chain(std::iter::once(&SortGroup {
+            key: 0.0,
+            first_element: self.elements.len(),
+        })) {
+            if group.first_element > prev.first_element {
+                groups.push((prev.key, prev.first_element, group.first_element));
+            }
+            prev = *group;
+        }
+
+        // `sort_by` is stable, so primitives with equal keys keep the order
+        // in which they were pushed.
+        groups.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (_, start, end) in groups {
+            self.sorted_elements
+                .extend_from_slice(&self.elements[start..end]);
+        }
+    }
+}
 
+/// GPU buffers that hold one upload of a batch.
+struct BufferSet {
     vertices: VertexBuffer,
     elements: ElementBuffer,
+}
+
+impl BufferSet {
+    fn new(ctx: &golem::Context) -> Result<Self, Error> {
+        Ok(Self {
+            vertices: VertexBuffer::new_golem(ctx)?,
+            elements: ElementBuffer::new_golem(ctx)?,
+        })
+    }
+}
+
+pub struct Batch<G: Geometry> {
+    scratch: Scratch,
+
+    /// The batch uploads into the next set each time, so that an upload does
+    /// not need to wait for the GPU to finish drawing from the previous one.
+    buffer_sets: Vec<BufferSet>,
+    current_set: usize,
+
+    vertices_allocation: GpuAllocation,
+    elements_allocation: GpuAllocation,
+
+    shrink_policy: ShrinkPolicy,
+    y_axis: YAxis,
 
     _phantom: PhantomData<G>,
 }
@@ -96,8 +281,20 @@ impl<G: Geometry> Batch<G> {
     pub fn new_golem(ctx: &golem::Context) -> Result<Self, Error> {
         Ok(Self {
             scratch: Scratch::default(),
-            vertices: VertexBuffer::new(ctx)?,
-            elements: ElementBuffer::new(ctx)?,
+            buffer_sets: vec![BufferSet::new(ctx)?],
+            current_set: 0,
+            vertices_allocation: GpuAllocation::new(
+                GpuResourceKind::VertexBuffer,
+                std::any::type_name::<G>(),
+                0,
+            ),
+            elements_allocation: GpuAllocation::new(
+                GpuResourceKind::ElementBuffer,
+                std::any::type_name::<G>(),
+                0,
+            ),
+            shrink_policy: ShrinkPolicy::Never,
+            y_axis: YAxis::Down,
             _phantom: PhantomData,
         })
     }
@@ -106,16 +303,52 @@ impl<G: Geometry> Batch<G> {
         Self::new_golem(ctx.golem_ctx())
     }
 
+    /// Returns the vertex buffer that the batch has been uploaded to last.
     pub fn vertices(&self) -> &VertexBuffer {
-        &self.vertices
+        &self.buffer_sets[self.current_set].vertices
     }
 
+    /// Returns the element buffer that the batch has been uploaded to last.
     pub fn elements(&self) -> &ElementBuffer {
-        &self.elements
+        &self.buffer_sets[self.current_set].elements
+    }
+
+    pub fn num_buffer_sets(&self) -> usize {
+        self.buffer_sets.len()
+    }
+
+    /// Sets the number of GPU buffer sets that the batch rotates through,
+    /// which is one by default.
+    ///
+    /// Batches that are rewritten and uploaded every frame, such as
+    /// particles, can use two or three sets. Each upload then goes to a
+    /// buffer that the GPU is not drawing from anymore, so that the driver
+    /// does not need to wait for earlier draw calls, at the cost of
+    /// reserving the GPU memory multiple times.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is not in `1..=3`.
+    pub fn set_num_buffer_sets(&mut self, ctx: &Canvas, count: usize) -> Result<(), Error> {
+        assert!(
+            (1..=3).contains(&count),
+            "number of buffer sets must be between 1 and 3"
+        );
+
+        while self.buffer_sets.len() < count {
+            self.buffer_sets.push(BufferSet::new(ctx.golem_ctx())?);
+        }
+        self.buffer_sets.truncate(count);
+
+        self.current_set = 0;
+        self.scratch.dirty = true;
+        self.update_allocations();
+
+        Ok(())
     }
 
     pub fn num_vertices(&self) -> usize {
-        self.scratch.vertices.len()
+        self.scratch.num_vertices
     }
 
     pub fn next_index(&self) -> u32 {
@@ -123,7 +356,7 @@ impl<G: Geometry> Batch<G> {
     }
 
     pub fn num_elements(&self) -> usize {
-        self.scratch.num_vertices
+        self.scratch.elements.len()
     }
 
     pub fn push_element(&mut self, element: u32) {
@@ -139,24 +372,198 @@ impl<G: Geometry> Batch<G> {
         self.scratch.dirty = true;
     }
 
+    /// Sets the sort key for all primitives that are pushed after this call.
+    ///
+    /// Once a sort key has been set, the batch's primitives are stably
+    /// sorted by ascending key when the batch is uploaded in `draw_unit`.
+    /// Primitives pushed before the first call have a key of zero. This
+    /// allows drawing transparent primitives back-to-front (painter's
+    /// algorithm) without maintaining one batch per depth.
+    ///
+    /// Note that only whole primitives should be pushed between calls to
+    /// this method, since sorting is done on element ranges.
+    pub fn set_sort_key(&mut self, key: f32) {
+        let first_element = self.scratch.elements.len();
+
+        match self.scratch.sort_groups.last_mut() {
+            Some(last) if last.first_element == first_element => {
+                // No elements have been pushed with the previous key.
+                last.key = key;
+            }
+            _ => {
+                self.scratch
+                    .sort_groups
+                    .push(SortGroup { key, first_element });
+            }
+        }
+
+        self.scratch.dirty = true;
+    }
+
+    /// Sets the sort key for a sprite in a top-down view, given the Y
+    /// coordinate `baseline_y` at which the sprite touches the ground.
+    ///
+    /// Since Y grows downwards, sprites further down are drawn later, so that
+    /// characters walk in front of props whose baseline is above theirs and
+    /// behind props whose baseline is below. `bias` shifts the key of
+    /// individual sprites, e.g. to keep a rug below the characters standing
+    /// on it. See `set_sort_key`.
+    pub fn set_y_sort_key(&mut self, baseline_y: f32, bias: f32) {
+        self.set_sort_key(baseline_y + bias);
+    }
+
+    /// Returns the current and peak memory usage of the batch, e.g. for
+    /// finding batches that pin memory after a one-time burst.
+    pub fn stats(&self) -> BatchStats {
+        let current = self.scratch.current();
+        let peak = self.scratch.peak.max(current);
+
+        BatchStats {
+            num_vertices: current.vertices,
+            num_elements: current.elements,
+            peak_vertices: peak.vertices,
+            peak_elements: peak.elements,
+            cpu_bytes: self.scratch.cpu_bytes(),
+            gpu_bytes: self.buffer_sets.len() * self.scratch.gpu_peak.bytes(),
+        }
+    }
+
+    /// Resets the peaks that are reported by `stats` to the current
+    /// contents of the batch.
+    pub fn reset_peaks(&mut self) {
+        self.scratch.peak = self.scratch.current();
+    }
+
+    pub fn shrink_policy(&self) -> ShrinkPolicy {
+        self.shrink_policy
+    }
+
+    pub fn set_shrink_policy(&mut self, policy: ShrinkPolicy) {
+        self.shrink_policy = policy;
+        self.scratch.window_peak = Peak::default();
+        self.scratch.frames_below_threshold = 0;
+    }
+
+    /// Releases all memory that is not needed for the current contents of
+    /// the batch, on the CPU as well as on the GPU.
+    ///
+    /// The GPU buffers are recreated, so the contents are uploaded again on
+    /// the next draw.
+    pub fn shrink_to_fit(&mut self, ctx: &Canvas) -> Result<(), Error> {
+        let current = self.scratch.current();
+        self.shrink_to(ctx, current)
+    }
+
+    /// Applies the batch's `ShrinkPolicy`. This should be called once per
+    /// frame, e.g. after drawing. Returns whether memory was released.
+    ///
+    /// With `ShrinkPolicy::Auto`, the batch shrinks to the largest size that
+    /// it needed during the frames that used less than a quarter of the
+    /// reserved memory, so that a batch whose size varies from frame to
+    /// frame is not reallocated all the time.
+    pub fn apply_shrink_policy(&mut self, ctx: &Canvas) -> Result<bool, Error> {
+        self.scratch.note_peaks();
+        let frame_peak = std::mem::take(&mut self.scratch.frame_peak);
+
+        let frames = match self.shrink_policy {
+            ShrinkPolicy::Never => return Ok(false),
+            ShrinkPolicy::Auto { frames } => frames,
+        };
+
+        let reserved_bytes = self.scratch.cpu_bytes().max(self.scratch.gpu_peak.bytes());
+        if frame_peak.bytes() * 4 >= reserved_bytes {
+            self.scratch.window_peak = Peak::default();
+            self.scratch.frames_below_threshold = 0;
+            return Ok(false);
+        }
+
+        self.scratch.window_peak = self.scratch.window_peak.max(frame_peak);
+        self.scratch.frames_below_threshold += 1;
+        if self.scratch.frames_below_threshold < frames {
+            return Ok(false);
+        }
+
+        let target = self.scratch.window_peak.max(self.scratch.current());
+        self.shrink_to(ctx, target)?;
+
+        Ok(true)
+    }
+
+    fn shrink_to(&mut self, ctx: &Canvas, target: Peak) -> Result<(), Error> {
+        self.scratch.vertices.shrink_to(target.floats);
+        self.scratch.elements.shrink_to(target.elements);
+        self.scratch.sorted_elements.shrink_to(target.elements);
+        self.scratch.sort_groups.shrink_to_fit();
+
+        for set in self.buffer_sets.iter_mut() {
+            *set = BufferSet::new(ctx.golem_ctx())?;
+        }
+        self.scratch.gpu_peak = Peak::default();
+        self.update_allocations();
+
+        // The peaks reported by `stats` are kept, since they only reset in
+        // `reset_peaks`.
+        self.scratch.window_peak = Peak::default();
+        self.scratch.frames_below_threshold = 0;
+        self.scratch.dirty = true;
+
+        Ok(())
+    }
+
+    pub fn y_axis(&self) -> YAxis {
+        self.y_axis
+    }
+
+    /// Sets the direction of the Y axis of the space that the batch is drawn
+    /// in, which should be the `Camera::y_axis` of the camera that it is
+    /// drawn with. Text, sprite sheet frames and tilemaps that are pushed
+    /// into the batch are flipped accordingly to stay upright, while the
+    /// plain `push_*` methods are not affected.
+    ///
+    /// This is kept when the batch is cleared.
+    pub fn set_y_axis(&mut self, y_axis: YAxis) {
+        self.y_axis = y_axis;
+    }
+
     pub fn clear(&mut self) {
+        self.scratch.note_peaks();
         self.scratch.vertices.clear();
         self.scratch.elements.clear();
+        self.scratch.sort_groups.clear();
         self.scratch.num_vertices = 0;
         self.scratch.dirty = true;
     }
 
     pub fn draw_unit(&mut self) -> DrawUnit<'_, G::Vertex> {
         if self.scratch.dirty {
-            self.vertices.set_data(&self.scratch.vertices);
-            self.elements.set_data(&self.scratch.elements);
+            crate::profile_scope!("upload");
+
+            self.current_set = (self.current_set + 1) % self.buffer_sets.len();
+            let set = &mut self.buffer_sets[self.current_set];
+
+            set.vertices.set_data(&self.scratch.vertices);
+
+            if self.scratch.sort_groups.is_empty() {
+                set.elements.set_data(&self.scratch.elements);
+            } else {
+                self.scratch.sort_elements();
+                set.elements.set_data(&self.scratch.sorted_elements);
+            }
+
+            self.scratch.note_peaks();
+            let current = self.scratch.current();
+            self.scratch.gpu_peak = self.scratch.gpu_peak.max(current);
+            self.update_allocations();
+
             self.scratch.dirty = false;
         }
 
+        let set = &self.buffer_sets[self.current_set];
+
         unsafe {
             DrawUnit::from_buffers_unchecked(
-                &self.vertices,
-                &self.elements,
+                &set.vertices,
+                &set.elements,
                 0,
                 self.scratch.elements.len(),
                 G::mode(),
@@ -167,6 +574,66 @@ impl<G: Geometry> Batch<G> {
     pub fn draw(&mut self, shader: &ShaderProgram) -> Result<(), Error> {
         self.draw_unit().draw(shader)
     }
+
+    fn update_allocations(&self) {
+        let num_sets = self.buffer_sets.len();
+
+        self.vertices_allocation
+            .set_bytes(num_sets * self.scratch.gpu_peak.floats * std::mem::size_of::<f32>());
+        self.elements_allocation
+            .set_bytes(num_sets * self.scratch.gpu_peak.elements * std::mem::size_of::<u32>());
+    }
+}
+
+impl<G: Geometry> Batch<G>
+where
+    G::Vertex: FlatVertex,
+{
+    /// Appends `count` zeroed vertices and returns them for writing in place,
+    /// which avoids writing vertices field by field with `push_vertex`. The
+    /// index of the first new vertex is `next_index` before the call.
+    ///
+    /// Elements referring to the new vertices still need to be pushed.
+    pub fn push_vertices_in_place(&mut self, count: usize) -> &mut [G::Vertex] {
+        let start = self.scratch.vertices.len();
+        let floats = count * floats_per_vertex::<G::Vertex>();
+
+        self.scratch.vertices.resize(start + floats, 0.0);
+        self.scratch.num_vertices += count;
+        self.scratch.dirty = true;
+
+        as_vertices_mut(&mut self.scratch.vertices[start..])
+    }
+
+    /// Returns all vertices of the batch for modifying them in place, e.g.
+    /// for moving particles without pushing them again every frame. The
+    /// batch is uploaded again on the next draw.
+    pub fn vertices_mut(&mut self) -> &mut [G::Vertex] {
+        self.scratch.dirty = true;
+
+        as_vertices_mut(&mut self.scratch.vertices)
+    }
+}
+
+fn floats_per_vertex<V: FlatVertex>() -> usize {
+    assert!(std::mem::size_of::<V>() % std::mem::size_of::<f32>() == 0);
+
+    std::mem::size_of::<V>() / std::mem::size_of::<f32>()
+}
+
+fn as_vertices_mut<V: FlatVertex>(floats: &mut [f32]) -> &mut [V] {
+    let floats_per_vertex = floats_per_vertex::<V>();
+    assert!(floats.len() % floats_per_vertex == 0);
+
+    // Safety: `FlatVertex` guarantees that `V` consists of exactly
+    // `floats_per_vertex` floats, so it has the size and alignment of that
+    // many floats, and any float values are valid.
+    unsafe {
+        std::slice::from_raw_parts_mut(
+            floats.as_mut_ptr() as *mut V,
+            floats.len() / floats_per_vertex,
+        )
+    }
 }
 
 impl<V: Vertex> Batch<Triangle<V>> {
@@ -184,13 +651,66 @@ impl<V: Vertex> Batch<Triangle<V>> {
 }
 
 impl Batch<Triangle<ColVertex>> {
+    /// Pushes a single-colored triangle. Use `push_triangle` to push a
+    /// triangle from arbitrary vertices.
+    pub fn push_colored_triangle(
+        &mut self,
+        a: Point2<f32>,
+        b: Point2<f32>,
+        c: Point2<f32>,
+        z: f32,
+        color: Color4,
+    ) {
+        let vertex = |p: Point2<f32>| ColVertex {
+            world_pos: Point3::new(p.x, p.y, z),
+            color,
+        };
+
+        self.push_triangle(&vertex(a), &vertex(b), &vertex(c));
+    }
+
+    /// Pushes a convex polygon, triangulated as a fan around its first
+    /// point.
+    ///
+    /// The polygon is given by its points in order (either clockwise or
+    /// counterclockwise). Polygons with less than three points are ignored.
+    pub fn push_convex_polygon(&mut self, points: &[Point2<f32>], z: f32, color: Color4) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let first_idx = self.next_index();
+
+        for point in points {
+            self.push_vertex(&ColVertex {
+                world_pos: Point3::new(point.x, point.y, z),
+                color,
+            });
+        }
+
+        for i in 1..points.len() as u32 - 1 {
+            self.scratch
+                .elements
+                .extend_from_slice(&[first_idx, first_idx + i, first_idx + i + 1]);
+        }
+    }
+
     pub fn push_quad(&mut self, quad: &Quad, z: f32, color: Color4) {
+        self.push_quad_gradient(quad, z, [color; 4]);
+    }
+
+    /// Pushes a quad with one color per corner, which are interpolated
+    /// across the quad, e.g. for sky gradients or fades.
+    ///
+    /// The colors are given in the same order as `quad.corners`, which for
+    /// axis-aligned quads is top left, bottom left, bottom right, top right.
+    pub fn push_quad_gradient(&mut self, quad: &Quad, z: f32, colors: [Color4; 4]) {
         let first_idx = self.next_index();
 
-        for corner in &quad.corners {
+        for (corner, color) in quad.corners.iter().zip(colors.iter()) {
             self.push_vertex(&ColVertex {
                 world_pos: Point3::new(corner.x, corner.y, z),
-                color,
+                color: *color,
             });
         }
 
@@ -198,9 +718,44 @@ impl Batch<Triangle<ColVertex>> {
             .elements
             .extend_from_slice(&Quad::triangle_indices(first_idx));
     }
+
+    /// Pushes the quad only if it intersects `visible_rect`, which usually
+    /// is given by `Camera::visible_rect`. Returns whether the quad was
+    /// pushed.
+    pub fn push_quad_if_visible(
+        &mut self,
+        visible_rect: &AaRect,
+        quad: &Quad,
+        z: f32,
+        color: Color4,
+    ) -> bool {
+        let visible = visible_rect.intersects(&quad.bounding_rect());
+        if visible {
+            self.push_quad(quad, z, color);
+        }
+
+        visible
+    }
 }
 
 impl Batch<Line<ColVertex>> {
+    pub fn push_line(&mut self, a: Point2<f32>, b: Point2<f32>, z: f32, color: Color4) {
+        let first_idx = self.next_index();
+
+        self.push_vertex(&ColVertex {
+            world_pos: Point3::new(a.x, a.y, z),
+            color,
+        });
+        self.push_vertex(&ColVertex {
+            world_pos: Point3::new(b.x, b.y, z),
+            color,
+        });
+
+        self.scratch
+            .elements
+            .extend_from_slice(&[first_idx + 0, first_idx + 1]);
+    }
+
     pub fn push_quad_outline(&mut self, quad: &Quad, z: f32, color: Color4) {
         let first_idx = self.next_index();
 
@@ -226,13 +781,19 @@ impl Batch<Line<ColVertex>> {
 
 impl TriBatch<TexVertex> {
     pub fn push_quad(&mut self, quad: &Quad, z: f32, uv_rect: AaRect) {
+        self.push_quad_with_uv_quad(quad, z, &uv_rect.into());
+    }
+
+    /// Pushes a quad whose texture coordinates are given by the corners of
+    /// `uv_quad`, which need not be axis-aligned. See
+    /// `TriBatch<TexColVertex>::push_quad_with_uv_quad`.
+    pub fn push_quad_with_uv_quad(&mut self, quad: &Quad, z: f32, uv_quad: &Quad) {
         let first_idx = self.next_index();
 
-        for corner_idx in 0..4 {
+        for (corner, tex_coords) in quad.corners.iter().zip(uv_quad.corners.iter()) {
             self.push_vertex(&TexVertex {
-                world_pos: Point3::new(quad.corners[corner_idx].x, quad.corners[corner_idx].y, z),
-                tex_coords: uv_rect.center
-                    + Quad::corners()[corner_idx].component_mul(&uv_rect.size),
+                world_pos: Point3::new(corner.x, corner.y, z),
+                tex_coords: *tex_coords,
             })
         }
 
@@ -244,19 +805,51 @@ impl TriBatch<TexVertex> {
 
 impl TriBatch<TexColVertex> {
     pub fn push_quad(&mut self, quad: &Quad, z: f32, uv_rect: AaRect, color: Color4) {
+        self.push_quad_with_uv_quad(quad, z, &uv_rect.into(), color);
+    }
+
+    /// Pushes a quad whose texture coordinates are given by the corners of
+    /// `uv_quad`, which need not be axis-aligned.
+    ///
+    /// Each corner of `quad` is mapped to the corner of `uv_quad` with the
+    /// same index. This is needed e.g. for atlas entries that the packer has
+    /// stored rotated by 90 degrees, see `Quad::rotate_corners`.
+    pub fn push_quad_with_uv_quad(&mut self, quad: &Quad, z: f32, uv_quad: &Quad, color: Color4) {
         let first_idx = self.next_index();
 
-        for corner_idx in 0..4 {
-            self.push_vertex(&TexColVertex {
-                world_pos: Point3::new(quad.corners[corner_idx].x, quad.corners[corner_idx].y, z),
-                tex_coords: uv_rect.center
-                    + Quad::corners()[corner_idx].component_mul(&uv_rect.size),
-                color,
-            })
-        }
+        // This is the same as pushing the four `TexColVertex`s, but it is the
+        // hottest path when drawing many sprites or much text.
+        simd::write_tex_col_quad(
+            &mut self.scratch.vertices,
+            &quad.corners,
+            z,
+            &uv_quad.corners,
+            color,
+        );
+        self.scratch.num_vertices += 4;
+        self.scratch.dirty = true;
 
         self.scratch
             .elements
             .extend_from_slice(&Quad::triangle_indices(first_idx));
     }
+
+    /// Pushes the quad only if it intersects `visible_rect`, which usually
+    /// is given by `Camera::visible_rect`. Returns whether the quad was
+    /// pushed.
+    pub fn push_quad_if_visible(
+        &mut self,
+        visible_rect: &AaRect,
+        quad: &Quad,
+        z: f32,
+        uv_rect: AaRect,
+        color: Color4,
+    ) -> bool {
+        let visible = visible_rect.intersects(&quad.bounding_rect());
+        if visible {
+            self.push_quad(quad, z, uv_rect, color);
+        }
+
+        visible
+    }
 }
@@ -0,0 +1,52 @@
+//! GPU buffers for vertices and elements.
+//!
+//! These wrap the buffers of `golem`, so that code using `Batch` and
+//! `DrawUnit` does not depend on golem's types.
+
+use crate::{Canvas, Error};
+
+/// A buffer of vertex data, given as floats.
+pub struct VertexBuffer(golem::VertexBuffer);
+
+impl VertexBuffer {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        Self::new_golem(ctx.golem_ctx())
+    }
+
+    pub fn new_golem(ctx: &golem::Context) -> Result<Self, Error> {
+        Ok(Self(golem::VertexBuffer::new(ctx)?))
+    }
+
+    /// Replaces the contents of the buffer with `data`.
+    pub fn set_data(&mut self, data: &[f32]) {
+        self.0.set_data(data);
+    }
+
+    /// Gives access to the underlying buffer of `golem`.
+    pub fn golem(&self) -> &golem::VertexBuffer {
+        &self.0
+    }
+}
+
+/// A buffer of indices into a `VertexBuffer`.
+pub struct ElementBuffer(golem::ElementBuffer);
+
+impl ElementBuffer {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        Self::new_golem(ctx.golem_ctx())
+    }
+
+    pub fn new_golem(ctx: &golem::Context) -> Result<Self, Error> {
+        Ok(Self(golem::ElementBuffer::new(ctx)?))
+    }
+
+    /// Replaces the contents of the buffer with `data`.
+    pub fn set_data(&mut self, data: &[u32]) {
+        self.0.set_data(data);
+    }
+
+    /// Gives access to the underlying buffer of `golem`.
+    pub fn golem(&self) -> &golem::ElementBuffer {
+        &self.0
+    }
+}
@@ -0,0 +1,261 @@
+//! Capturing intermediate render targets for debugging.
+//!
+//! Targets such as light maps, bloom levels or ID buffers are usually
+//! overwritten or returned to a pool within the frame, so `TargetCapture`
+//! copies them under a name when they are captured. The copies can then be
+//! shown in a grid on top of the frame, or saved as PNG images.
+//!
+//! Within a render graph pass, an input can be captured with
+//! `capture.capture(ctx.canvas(), "bloom", ctx.input(0))`.
+
+use golem::{Texture, TextureFilter};
+use nalgebra::{Point2, Vector2};
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{
+    CanvasRenderingContext2d, HtmlCanvasElement, HtmlElement, ImageData, WebGlRenderingContext,
+};
+
+use crate::{
+    draw::{CompositePass, RenderTarget, TargetScope, TexColPass, TexColVertex, TriBatch},
+    AaRect, Canvas, Color4, Error,
+};
+
+struct Captured {
+    name: String,
+    target: RenderTarget,
+}
+
+pub struct TargetCapture {
+    enabled: bool,
+    captures: Vec<Captured>,
+
+    copy_pass: CompositePass,
+    grid_batch: TriBatch<TexColVertex>,
+    grid_pass: TexColPass,
+}
+
+impl TargetCapture {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let mut copy_pass = CompositePass::new(ctx)?;
+        copy_pass.set_dithering(false);
+
+        Ok(Self {
+            enabled: true,
+            captures: Vec::new(),
+            copy_pass,
+            grid_batch: TriBatch::new(ctx)?,
+            grid_pass: TexColPass::new(ctx)?,
+        })
+    }
+
+    /// Enables or disables capturing. While disabled, `capture` does
+    /// nothing, so that the calls can stay in place in release builds.
+    /// Enabled by default.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the names of the captured targets, in the order in which
+    /// `draw_grid` shows them (row by row).
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.captures.iter().map(|captured| captured.name.as_str())
+    }
+
+    /// Removes all captures and frees their surfaces.
+    pub fn clear(&mut self) {
+        self.captures.clear();
+    }
+
+    /// Copies `tex` under `name`, replacing an earlier capture with the same
+    /// name.
+    pub fn capture(&mut self, ctx: &Canvas, name: &str, tex: &Texture) -> Result<(), Error> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let size = Vector2::new(tex.width(), tex.height());
+        let index = match self.captures.iter().position(|c| c.name == name) {
+            Some(index) if self.captures[index].target.size() == size => index,
+            Some(index) => {
+                self.captures[index] = Self::new_captured(ctx, name, size)?;
+                index
+            }
+            None => {
+                self.captures.push(Self::new_captured(ctx, name, size)?);
+                self.captures.len() - 1
+            }
+        };
+
+        let _target = TargetScope::new(ctx, &self.captures[index].target);
+        self.copy_pass.draw(tex)
+    }
+
+    /// Draws all captures in a grid over the whole screen, each one scaled
+    /// to fit into its cell.
+    pub fn draw_grid(&mut self, ctx: &Canvas) -> Result<(), Error> {
+        if self.captures.is_empty() {
+            return Ok(());
+        }
+
+        let screen = ctx.screen_geom();
+        let num_cols = (self.captures.len() as f32).sqrt().ceil() as usize;
+        let num_rows = (self.captures.len() + num_cols - 1) / num_cols;
+        let cell_size = Vector2::new(
+            screen.logical_size().x / num_cols as f32,
+            screen.logical_size().y / num_rows as f32,
+        );
+
+        for (i, captured) in self.captures.iter().enumerate() {
+            let cell_min = Point2::new(
+                (i % num_cols) as f32 * cell_size.x,
+                (i / num_cols) as f32 * cell_size.y,
+            );
+            let scale = (cell_size.x / captured.target.size().x as f32)
+                .min(cell_size.y / captured.target.size().y as f32);
+            let size = Vector2::new(
+                captured.target.size().x as f32 * scale,
+                captured.target.size().y as f32 * scale,
+            );
+
+            self.grid_batch.clear();
+            self.grid_batch.push_quad(
+                &AaRect::from_top_left(cell_min + (cell_size - size) / 2.0, size).into(),
+                0.0,
+                AaRect::zero_to_one(),
+                Color4::new(1.0, 1.0, 1.0, 1.0),
+            );
+
+            // Safety: the surface is only bound while copying in `capture`.
+            let texture = unsafe { captured.target.surface().borrow_texture() }
+                .expect("capture surface has no texture");
+            self.grid_pass.draw(
+                &screen.orthographic_projection(),
+                texture,
+                &self.grid_batch.draw_unit(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the capture `name` as a PNG image and returns it as a data
+    /// URL, or `None` if there is no such capture.
+    ///
+    /// The rows are stored in the same order in which `draw_grid` shows
+    /// them.
+    pub fn to_png_data_url(&self, ctx: &Canvas, name: &str) -> Result<Option<String>, Error> {
+        let captured = match self.captures.iter().find(|c| c.name == name) {
+            Some(captured) => captured,
+            None => return Ok(None),
+        };
+        let (width, height) = (captured.target.size().x, captured.target.size().y);
+
+        let data = {
+            let _target = TargetScope::new(ctx, &captured.target);
+            read_pixels(ctx, captured.target.size())?
+        };
+
+        let canvas: HtmlCanvasElement = create_element("canvas")?;
+        canvas.set_width(width);
+        canvas.set_height(height);
+
+        let canvas_ctx: CanvasRenderingContext2d = canvas
+            .get_context("2d")
+            .map_err(dom_error)?
+            .ok_or_else(|| Error::Dom("could not get 2d context".into()))?
+            .dyn_into()
+            .map_err(|_| Error::Dom("could not get 2d context".into()))?;
+        let image_data =
+            ImageData::new_with_u8_clamped_array_and_sh(Clamped(&data[..]), width, height)
+                .map_err(dom_error)?;
+        canvas_ctx
+            .put_image_data(&image_data, 0.0, 0.0)
+            .map_err(dom_error)?;
+
+        canvas
+            .to_data_url_with_type("image/png")
+            .map(Some)
+            .map_err(dom_error)
+    }
+
+    /// Saves the capture `name` as a PNG file through the browser's
+    /// download dialog. Does nothing if there is no such capture.
+    pub fn download_png(&self, ctx: &Canvas, name: &str) -> Result<(), Error> {
+        if let Some(url) = self.to_png_data_url(ctx, name)? {
+            download_url(&url, &format!("{}.png", name))?;
+        }
+
+        Ok(())
+    }
+
+    fn new_captured(ctx: &Canvas, name: &str, size: Vector2<u32>) -> Result<Captured, Error> {
+        Ok(Captured {
+            name: name.to_string(),
+            target: RenderTarget::with_filters(
+                ctx,
+                size,
+                TextureFilter::Nearest,
+                TextureFilter::Linear,
+                format!("capture {}", name),
+            )?,
+        })
+    }
+}
+
+/// Reads the RGBA pixels of the bound target, with the rows from bottom to
+/// top, as WebGL returns them.
+pub(super) fn read_pixels(ctx: &Canvas, size: Vector2<u32>) -> Result<Vec<u8>, Error> {
+    let mut data = vec![0; size.x as usize * size.y as usize * 4];
+    ctx.webgl_ctx()
+        .read_pixels_with_opt_u8_array(
+            0,
+            0,
+            size.x as i32,
+            size.y as i32,
+            WebGlRenderingContext::RGBA,
+            WebGlRenderingContext::UNSIGNED_BYTE,
+            Some(&mut data),
+        )
+        .map_err(dom_error)?;
+
+    Ok(data)
+}
+
+/// Reverses the order of the rows of RGBA pixels that are `width` wide.
+pub(super) fn flip_rows(data: &[u8], width: u32) -> Vec<u8> {
+    data.chunks_exact(width as usize * 4)
+        .rev()
+        .flatten()
+        .copied()
+        .collect()
+}
+
+/// Saves the contents of `url` as `name` through the browser's download
+/// dialog.
+pub(super) fn download_url(url: &str, name: &str) -> Result<(), Error> {
+    let link: HtmlElement = create_element("a")?;
+    link.set_attribute("href", url).map_err(dom_error)?;
+    link.set_attribute("download", name).map_err(dom_error)?;
+    link.click();
+
+    Ok(())
+}
+
+pub(super) fn dom_error(e: JsValue) -> Error {
+    Error::Dom(e.as_string().unwrap_or_else(|| format!("{:?}", e)))
+}
+
+pub(super) fn create_element<T: JsCast>(tag: &str) -> Result<T, Error> {
+    web_sys::window()
+        .ok_or(Error::NoWindow)?
+        .document()
+        .ok_or(Error::NoDocument)?
+        .create_element(tag)
+        .map_err(dom_error)?
+        .dyn_into()
+        .map_err(|_| Error::Dom(format!("could not create `{}` element", tag)))
+}
@@ -0,0 +1,164 @@
+//! Static geometry that is split into spatial chunks, so that only the
+//! chunks that intersect the visible area are drawn.
+//!
+//! This is meant for geometry that is too large to draw as a whole every
+//! frame, but does not change, such as big vector maps or baked levels. The
+//! triangles are sorted into a grid by their centroid once, and each chunk
+//! keeps its own batch together with the bounding rect of its triangles.
+
+use std::collections::HashMap;
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::{DrawUnit, TriBatch, WorldVertex},
+    AaRect, Canvas, Error,
+};
+
+struct MeshChunk<V: WorldVertex> {
+    coords: Point2<i32>,
+
+    /// The bounding rect of all triangles of the chunk, which may extend
+    /// beyond the chunk's grid cell.
+    bounds: AaRect,
+
+    batch: TriBatch<V>,
+}
+
+/// Triangles that are split into square chunks of `chunk_size` world units.
+pub struct ChunkedMesh<V: WorldVertex> {
+    chunk_size: f32,
+    chunks: Vec<MeshChunk<V>>,
+}
+
+impl<V: WorldVertex> ChunkedMesh<V> {
+    /// Sorts `triangles` into chunks and creates a batch for each non-empty
+    /// chunk.
+    ///
+    /// Within a chunk, triangles are drawn in the order in which they are
+    /// given, but the chunks themselves are drawn in the order of their
+    /// coordinates. Overlapping triangles in different chunks should thus
+    /// be told apart by their Z coordinate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is not positive.
+    pub fn new(
+        ctx: &Canvas,
+        chunk_size: f32,
+        triangles: impl IntoIterator<Item = [V; 3]>,
+    ) -> Result<Self, Error> {
+        assert!(chunk_size > 0.0, "chunk size must be positive");
+
+        let mut cells: HashMap<Point2<i32>, Vec<[V; 3]>> = HashMap::new();
+        for triangle in triangles {
+            let centroid = Point2::from(
+                triangle
+                    .iter()
+                    .map(|vertex| vertex.world_pos().xy().coords)
+                    .sum::<Vector2<f32>>()
+                    / 3.0,
+            );
+            let coords = Point2::new(
+                (centroid.x / chunk_size).floor() as i32,
+                (centroid.y / chunk_size).floor() as i32,
+            );
+
+            cells.entry(coords).or_insert_with(Vec::new).push(triangle);
+        }
+
+        let mut cells: Vec<(Point2<i32>, Vec<[V; 3]>)> = cells.into_iter().collect();
+        cells.sort_by_key(|(coords, _)| (coords.y, coords.x));
+
+        let mut chunks = Vec::with_capacity(cells.len());
+        for (coords, triangles) in cells {
+            let points: Vec<Point2<f32>> = triangles
+                .iter()
+                .flat_map(|triangle| triangle.iter().map(|vertex| vertex.world_pos().xy()))
+                .collect();
+            let bounds = AaRect::bounding(&points);
+
+            let mut batch = TriBatch::new(ctx)?;
+            for [a, b, c] in triangles.iter() {
+                batch.push_triangle(a, b, c);
+            }
+
+            chunks.push(MeshChunk {
+                coords,
+                bounds,
+                batch,
+            });
+        }
+
+        Ok(Self { chunk_size, chunks })
+    }
+
+    pub fn chunk_size(&self) -> f32 {
+        self.chunk_size
+    }
+
+    /// Returns the number of non-empty chunks.
+    pub fn num_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn num_triangles(&self) -> usize {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.batch.num_elements() / 3)
+            .sum()
+    }
+
+    /// Returns the bounding rect of all triangles, or `None` if the mesh is
+    /// empty.
+    pub fn bounds(&self) -> Option<AaRect> {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.bounds)
+            .fold(None, |bounds, chunk_bounds| {
+                Some(match bounds {
+                    Some(bounds) => AaRect::bounding(&[
+                        bounds.min(),
+                        bounds.max(),
+                        chunk_bounds.min(),
+                        chunk_bounds.max(),
+                    ]),
+                    None => chunk_bounds,
+                })
+            })
+    }
+
+    /// Returns the coordinates and bounding rects of the chunks that
+    /// intersect `visible_rect`, e.g. for debug drawing.
+    pub fn visible_chunks<'a>(
+        &'a self,
+        visible_rect: &'a AaRect,
+    ) -> impl Iterator<Item = (Point2<i32>, AaRect)> + 'a {
+        self.chunks
+            .iter()
+            .filter(move |chunk| visible_rect.intersects(&chunk.bounds))
+            .map(|chunk| (chunk.coords, chunk.bounds))
+    }
+
+    /// Calls `draw` with the draw unit of every chunk that intersects
+    /// `visible_rect`, which usually is given by `Camera::visible_rect`.
+    /// Returns the number of chunks that were drawn.
+    ///
+    /// The batch of a chunk is uploaded the first time it is drawn.
+    pub fn draw(
+        &mut self,
+        visible_rect: &AaRect,
+        mut draw: impl FnMut(&DrawUnit<V>) -> Result<(), Error>,
+    ) -> Result<usize, Error> {
+        let mut num_drawn = 0;
+
+        for chunk in self.chunks.iter_mut() {
+            if visible_rect.intersects(&chunk.bounds) {
+                draw(&chunk.batch.draw_unit())?;
+                num_drawn += 1;
+            }
+        }
+
+        Ok(num_drawn)
+    }
+}
@@ -0,0 +1,344 @@
+//! Streaming of effectively unbounded tilemaps in chunks.
+//!
+//! The world is divided into chunks of equal size, each of which is a
+//! `Tilemap` with its own batch. As the camera moves, `ChunkedTilemap::update`
+//! requests the chunks around the visible area from a user callback, which
+//! can e.g. generate them procedurally or return them once they have been
+//! fetched from a server. Chunks that are far away are evicted, releasing
+//! both the tiles and the GPU buffers of their batch.
+
+use std::collections::HashMap;
+
+use golem::blend::BlendMode;
+use nalgebra::{Matrix3, Point2, Vector2};
+
+use crate::{
+    draw::{
+        BlendScope, SpriteSheet, TexColPass, TexColVertex, TileLayout, TileProjection, Tilemap,
+        TriBatch,
+    },
+    AaRect, Canvas, Color4, Error,
+};
+
+#[derive(Debug, Clone)]
+pub struct ChunkConfig {
+    /// The number of tiles per chunk. For staggered maps, the number of rows
+    /// needs to be even, so that the rows of all chunks line up.
+    pub chunk_size: Vector2<u32>,
+
+    /// Chunks are requested up to this many chunks beyond the visible area,
+    /// so that they are ready before they become visible.
+    pub load_margin: u32,
+
+    /// Chunks are evicted once they are more than this many chunks beyond
+    /// the visible area. Keeping this larger than `load_margin` prevents
+    /// chunks from being evicted and requested again when the camera moves
+    /// back and forth.
+    pub evict_margin: u32,
+
+    /// Limits the number of chunks that are requested in one update, to
+    /// avoid frame time spikes when many chunks become visible at once.
+    pub max_requests_per_update: usize,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: Vector2::new(32, 32),
+            load_margin: 1,
+            evict_margin: 2,
+            max_requests_per_update: 4,
+        }
+    }
+}
+
+struct Chunk {
+    map: Tilemap,
+    batch: TriBatch<TexColVertex>,
+    dirty: bool,
+}
+
+/// A tilemap that is loaded in chunks around the camera.
+///
+/// Chunk coordinates are given in units of chunks, so the chunk at
+/// `(1, 0)` contains the tiles from `chunk_size.x` to `2 * chunk_size.x - 1`
+/// in X direction. All chunks share the layout of the `ChunkedTilemap`.
+pub struct ChunkedTilemap {
+    layout: TileLayout,
+    config: ChunkConfig,
+    chunks: HashMap<Point2<i32>, Chunk>,
+
+    request: Box<dyn FnMut(Point2<i32>) -> Option<Tilemap>>,
+    on_evict: Option<Box<dyn FnMut(Point2<i32>, Tilemap)>>,
+
+    pass: TexColPass,
+}
+
+impl ChunkedTilemap {
+    /// Creates an empty chunked tilemap.
+    ///
+    /// `request` is called with the coordinates of chunks that are needed.
+    /// It should return a map of `chunk_size` tiles, or `None` if the chunk
+    /// is not available yet, in which case it is requested again in the next
+    /// update.
+    pub fn new(
+        ctx: &Canvas,
+        layout: TileLayout,
+        config: ChunkConfig,
+        request: impl FnMut(Point2<i32>) -> Option<Tilemap> + 'static,
+    ) -> Result<Self, Error> {
+        assert!(config.chunk_size.x > 0 && config.chunk_size.y > 0);
+        assert!(
+            layout.projection != TileProjection::Staggered || config.chunk_size.y % 2 == 0,
+            "staggered chunks need an even number of rows",
+        );
+
+        Ok(Self {
+            layout,
+            config,
+            chunks: HashMap::new(),
+            request: Box::new(request),
+            on_evict: None,
+            pass: TexColPass::new(ctx)?,
+        })
+    }
+
+    /// Sets a callback that is called for every chunk that is evicted, e.g.
+    /// to save chunks that have been edited.
+    pub fn with_on_evict(mut self, on_evict: impl FnMut(Point2<i32>, Tilemap) + 'static) -> Self {
+        self.on_evict = Some(Box::new(on_evict));
+        self
+    }
+
+    pub fn layout(&self) -> &TileLayout {
+        &self.layout
+    }
+
+    pub fn config(&self) -> &ChunkConfig {
+        &self.config
+    }
+
+    pub fn num_loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_loaded(&self, chunk: Point2<i32>) -> bool {
+        self.chunks.contains_key(&chunk)
+    }
+
+    pub fn chunk(&self, chunk: Point2<i32>) -> Option<&Tilemap> {
+        self.chunks.get(&chunk).map(|chunk| &chunk.map)
+    }
+
+    /// Returns a loaded chunk for editing. Its batch is rebuilt in the next
+    /// call to `draw`.
+    pub fn chunk_mut(&mut self, chunk: Point2<i32>) -> Option<&mut Tilemap> {
+        self.chunks.get_mut(&chunk).map(|chunk| {
+            chunk.dirty = true;
+            &mut chunk.map
+        })
+    }
+
+    /// Splits the coordinates of a tile into the coordinates of its chunk
+    /// and its coordinates within the chunk.
+    pub fn split_tile(&self, tile: Point2<i32>) -> (Point2<i32>, Point2<i32>) {
+        let size = self.chunk_size();
+
+        (
+            Point2::new(tile.x.div_euclid(size.x), tile.y.div_euclid(size.y)),
+            Point2::new(tile.x.rem_euclid(size.x), tile.y.rem_euclid(size.y)),
+        )
+    }
+
+    /// Returns the frame of a tile, given in world tile coordinates, if its
+    /// chunk is loaded.
+    pub fn get(&self, tile: Point2<i32>) -> Option<usize> {
+        let (chunk, local) = self.split_tile(tile);
+
+        self.chunk(chunk).and_then(|map| map.get(local))
+    }
+
+    /// Sets the frame of a tile, given in world tile coordinates. Returns
+    /// `false` if its chunk is not loaded.
+    pub fn set(&mut self, tile: Point2<i32>, frame: Option<usize>) -> bool {
+        let (chunk, local) = self.split_tile(tile);
+
+        if let Some(map) = self.chunk_mut(chunk) {
+            map.set(local, frame);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Requests missing chunks around `visible_rect`, which usually is given
+    /// by `Camera::visible_rect`, and evicts chunks that are far away from
+    /// it.
+    ///
+    /// Chunks are requested in the order of their distance to the center of
+    /// the visible area. Returns an error if a requested map does not have
+    /// `chunk_size` tiles.
+    pub fn update(&mut self, ctx: &Canvas, visible_rect: &AaRect) -> Result<(), Error> {
+        let (min, max) = self.chunk_range(visible_rect);
+        let load_margin = self.config.load_margin as i32;
+        let evict_margin = self.config.evict_margin.max(self.config.load_margin) as i32;
+
+        let evicted: Vec<Point2<i32>> = self
+            .chunks
+            .keys()
+            .filter(|chunk| {
+                chunk.x < min.x - evict_margin
+                    || chunk.y < min.y - evict_margin
+                    || chunk.x > max.x + evict_margin
+                    || chunk.y > max.y + evict_margin
+            })
+            .copied()
+            .collect();
+        for coords in evicted {
+            if let Some(chunk) = self.chunks.remove(&coords) {
+                if let Some(on_evict) = self.on_evict.as_mut() {
+                    on_evict(coords, chunk.map);
+                }
+            }
+        }
+
+        let center = Point2::new((min.x + max.x) / 2, (min.y + max.y) / 2);
+        let mut missing = Vec::new();
+        for y in min.y - load_margin..=max.y + load_margin {
+            for x in min.x - load_margin..=max.x + load_margin {
+                let coords = Point2::new(x, y);
+                if !self.chunks.contains_key(&coords) {
+                    missing.push(coords);
+                }
+            }
+        }
+        missing.sort_by_key(|coords| (coords.x - center.x).abs() + (coords.y - center.y).abs());
+
+        for coords in missing
+            .into_iter()
+            .take(self.config.max_requests_per_update)
+        {
+            if let Some(map) = (self.request)(coords) {
+                if map.size() != self.config.chunk_size {
+                    return Err(Error::Tilemap(format!(
+                        "chunk {:?} has size {}x{}, expected {}x{}",
+                        coords,
+                        map.size().x,
+                        map.size().y,
+                        self.config.chunk_size.x,
+                        self.config.chunk_size.y,
+                    )));
+                }
+
+                self.chunks.insert(
+                    coords,
+                    Chunk {
+                        map,
+                        batch: TriBatch::new(ctx)?,
+                        dirty: true,
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Draws the loaded chunks that intersect `visible_rect`, with the tiles
+    /// being frames of `sheet`.
+    ///
+    /// The batch of a chunk is only rebuilt when the chunk has changed.
+    /// Chunks are drawn in the order of their depth, but tiles that extend
+    /// into a neighboring chunk may be overdrawn by it.
+    pub fn draw(
+        &mut self,
+        ctx: &Canvas,
+        transform: &Matrix3<f32>,
+        sheet: &SpriteSheet,
+        visible_rect: &AaRect,
+    ) -> Result<(), Error> {
+        let (min, max) = self.chunk_range(visible_rect);
+        let layout = self.layout;
+        let chunk_size = self.chunk_size();
+
+        let mut visible: Vec<Point2<i32>> = self
+            .chunks
+            .keys()
+            .filter(|chunk| {
+                chunk.x >= min.x && chunk.y >= min.y && chunk.x <= max.x && chunk.y <= max.y
+            })
+            .copied()
+            .collect();
+        visible.sort_by(|a, b| {
+            let key = |chunk: &Point2<i32>| {
+                layout.depth_key(Point2::new(chunk.x * chunk_size.x, chunk.y * chunk_size.y))
+            };
+            key(a).total_cmp(&key(b))
+        });
+
+        let _blend = BlendScope::new(ctx.golem_ctx(), BlendMode::default());
+
+        for coords in visible {
+            let chunk = self.chunks.get_mut(&coords).unwrap();
+
+            if chunk.dirty {
+                let origin = Point2::new(coords.x * chunk_size.x, coords.y * chunk_size.y);
+                rebuild_chunk_batch(&layout, origin, sheet, chunk);
+            }
+
+            self.pass
+                .draw(transform, &sheet.atlas().texture, &chunk.batch.draw_unit())?;
+        }
+
+        Ok(())
+    }
+
+    fn chunk_size(&self) -> Vector2<i32> {
+        Vector2::new(
+            self.config.chunk_size.x as i32,
+            self.config.chunk_size.y as i32,
+        )
+    }
+
+    /// Returns the smallest and largest coordinates of chunks that may
+    /// intersect `rect`.
+    fn chunk_range(&self, rect: &AaRect) -> (Point2<i32>, Point2<i32>) {
+        let (min_tile, max_tile) = self.layout.tile_range(rect);
+
+        (self.split_tile(min_tile).0, self.split_tile(max_tile).0)
+    }
+}
+
+fn rebuild_chunk_batch(
+    layout: &TileLayout,
+    origin: Point2<i32>,
+    sheet: &SpriteSheet,
+    chunk: &mut Chunk,
+) {
+    let size = chunk.map.size();
+    let mut tiles = Vec::new();
+    for y in 0..size.y as i32 {
+        for x in 0..size.x as i32 {
+            if let Some(frame) = chunk.map.get(Point2::new(x, y)) {
+                tiles.push((origin + Vector2::new(x, y), frame));
+            }
+        }
+    }
+
+    // `sort_by` is stable, so tiles at the same depth stay in row order.
+    tiles.sort_by(|(a, _), (b, _)| layout.depth_key(*a).total_cmp(&layout.depth_key(*b)));
+
+    chunk.batch.clear();
+    for (tile, frame) in tiles {
+        chunk.batch.push_sprite(
+            sheet,
+            frame,
+            layout.tile_center(tile),
+            Vector2::new(1.0, 1.0),
+            0.0,
+            Color4::new(1.0, 1.0, 1.0, 1.0),
+        );
+    }
+
+    chunk.dirty = false;
+}
@@ -0,0 +1,233 @@
+//! The final composite pass, which copies a rendered frame to the screen
+//! while applying the adjustments of a video settings menu.
+//!
+//! For checking the readability of a game for colorblind players, the pass
+//! can also simulate color vision deficiencies.
+
+use golem::{
+    Attribute, AttributeType, Dimension, NumberType, ShaderDescription, Texture, Uniform,
+    UniformType, UniformValue,
+};
+use nalgebra::{Matrix3, Point2, Vector2};
+
+use crate::{
+    draw::{SharedShader, TexVertex, TriBatch, Vertex, DITHER_GLSL},
+    geom::matrix3_to_flat_array,
+    AaRect, Canvas, Error,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ColorAdjustments {
+    /// Factor that colors are multiplied with.
+    pub exposure: f32,
+
+    /// Offset that is added to colors.
+    pub brightness: f32,
+
+    /// Values larger than one increase the contrast around mid-gray, values
+    /// smaller than one decrease it.
+    pub contrast: f32,
+
+    /// Zero gives grayscale, values larger than one oversaturate.
+    pub saturation: f32,
+
+    /// Values larger than one brighten the midtones, values smaller than one
+    /// darken them.
+    pub gamma: f32,
+}
+
+impl Default for ColorAdjustments {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// A color vision deficiency to simulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBlindness {
+    /// Normal color vision.
+    None,
+
+    /// Missing red cones.
+    Protanopia,
+
+    /// Missing green cones.
+    Deuteranopia,
+
+    /// Missing blue cones.
+    Tritanopia,
+}
+
+impl Default for ColorBlindness {
+    fn default() -> Self {
+        ColorBlindness::None
+    }
+}
+
+impl ColorBlindness {
+    /// Returns the matrix that maps linear RGB colors to the colors that are
+    /// perceived, following Machado et al., "A Physiologically-based Model
+    /// for Simulation of Color Vision Deficiency" (2009), at full severity.
+    pub fn to_matrix(self) -> Matrix3<f32> {
+        let rows = match self {
+            ColorBlindness::None => return Matrix3::identity(),
+            ColorBlindness::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorBlindness::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorBlindness::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        };
+
+        Matrix3::from_fn(|i, j| rows[i][j])
+    }
+}
+
+pub struct CompositePass {
+    shader: SharedShader,
+    batch: TriBatch<TexVertex>,
+    adjustments: ColorAdjustments,
+    dithering: bool,
+    color_blindness: ColorBlindness,
+}
+
+impl CompositePass {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let fragment_shader = format!(
+            "{}{}",
+            DITHER_GLSL,
+            r#"
+            void main() {
+                vec4 color = texture(tex, v_tex_coords);
+
+                vec3 rgb = color.rgb * exposure + brightness;
+                rgb = (rgb - 0.5) * contrast + 0.5;
+                float luma = dot(rgb, vec3(0.2126, 0.7152, 0.0722));
+                rgb = mix(vec3(luma), rgb, saturation);
+                rgb = pow(max(rgb, vec3(0.0)), vec3(1.0 / gamma));
+
+                // The simulation works on linear colors.
+                rgb = pow(rgb, vec3(2.2));
+                rgb = clamp(color_blindness * rgb, 0.0, 1.0);
+                rgb = pow(rgb, vec3(1.0 / 2.2));
+
+                gl_FragColor = vec4(rgb + dither_strength * dither_offset(), color.a);
+            }
+            "#
+        );
+
+        let shader = ctx.shader_cache().get_or_compile(
+            ctx.golem_ctx(),
+            "CompositePass",
+            ShaderDescription {
+                vertex_input: &TexVertex::attributes(),
+                fragment_input: &[Attribute::new(
+                    "v_tex_coords",
+                    AttributeType::Vector(Dimension::D2),
+                )],
+                uniforms: &[
+                    Uniform::new("tex", UniformType::Sampler2D),
+                    Uniform::new("exposure", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("brightness", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("contrast", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("saturation", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("gamma", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("dither_strength", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("color_blindness", UniformType::Matrix(Dimension::D3)),
+                ],
+                vertex_shader: r#"
+                void main() {
+                    gl_Position = vec4(a_world_pos.xy, 0.0, 1.0);
+                    v_tex_coords = a_tex_coords;
+                }
+                "#,
+                fragment_shader: &fragment_shader,
+            },
+            &[],
+        )?;
+
+        // A quad that covers the whole viewport in normalized device
+        // coordinates.
+        let mut batch = TriBatch::new(ctx)?;
+        batch.push_quad(
+            &AaRect::from_top_left(Point2::new(-1.0, -1.0), Vector2::new(2.0, 2.0)).into(),
+            0.0,
+            AaRect::zero_to_one(),
+        );
+
+        Ok(Self {
+            shader,
+            batch,
+            adjustments: ColorAdjustments::default(),
+            dithering: true,
+            color_blindness: ColorBlindness::None,
+        })
+    }
+
+    pub fn adjustments(&self) -> &ColorAdjustments {
+        &self.adjustments
+    }
+
+    /// Changes the adjustments, e.g. when the player moves a slider in the
+    /// video settings.
+    pub fn set_adjustments(&mut self, adjustments: ColorAdjustments) {
+        self.adjustments = adjustments;
+    }
+
+    /// Enables ordered dithering of the output, which is on by default since
+    /// the composite is the last step before quantization.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
+    }
+
+    pub fn color_blindness(&self) -> ColorBlindness {
+        self.color_blindness
+    }
+
+    /// Simulates a color vision deficiency in the output. This is meant for
+    /// debugging, e.g. bound to a key in development builds.
+    pub fn set_color_blindness(&mut self, color_blindness: ColorBlindness) {
+        self.color_blindness = color_blindness;
+    }
+
+    /// Draws `tex`, which usually holds the rendered frame, over the whole
+    /// viewport.
+    pub fn draw(&mut self, tex: &Texture) -> Result<(), Error> {
+        tex.set_active(std::num::NonZeroU32::new(1).unwrap());
+
+        let adjustments = &self.adjustments;
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform("tex", UniformValue::Int(1))?;
+        shader.set_uniform("exposure", UniformValue::Float(adjustments.exposure))?;
+        shader.set_uniform("brightness", UniformValue::Float(adjustments.brightness))?;
+        shader.set_uniform("contrast", UniformValue::Float(adjustments.contrast))?;
+        shader.set_uniform("saturation", UniformValue::Float(adjustments.saturation))?;
+        shader.set_uniform("gamma", UniformValue::Float(adjustments.gamma))?;
+        shader.set_uniform(
+            "dither_strength",
+            UniformValue::Float(if self.dithering { 1.0 } else { 0.0 }),
+        )?;
+        shader.set_uniform(
+            "color_blindness",
+            UniformValue::Matrix3(matrix3_to_flat_array(&self.color_blindness.to_matrix())),
+        )?;
+
+        self.batch.draw_unit().draw(&shader)
+    }
+}
@@ -0,0 +1,270 @@
+//! Passes with user-supplied shaders.
+//!
+//! `CustomPass` compiles GLSL for any vertex type through the shader cache of
+//! the `Canvas`, and checks the values of uniforms against the types that
+//! have been declared in the builder, so that mistakes show up as errors
+//! instead of silently black output.
+
+use std::{marker::PhantomData, num::NonZeroU32, rc::Rc};
+
+use golem::{
+    Attribute, AttributeType, Dimension, NumberType, ShaderDescription, Texture, Uniform,
+    UniformType, UniformValue,
+};
+use nalgebra::Matrix3;
+
+use crate::{
+    draw::{shader_cache::set_uniform_if_used, Batch, DrawUnit, Geometry, SharedShader, Vertex},
+    geom::matrix3_to_flat_array,
+    Canvas, Error,
+};
+
+/// Describes a `CustomPass` for vertices of type `V`.
+///
+/// The vertex shader receives the attributes of `V`, and the uniform
+/// `mat_projection_view`, which is declared automatically and set to the
+/// transform that is given to `CustomPass::draw`. The fragment shader
+/// receives the varyings that have been declared with `varying`. Neither
+/// shader should declare these inputs itself.
+pub struct CustomPassBuilder<V> {
+    name: String,
+    varyings: Vec<Attribute>,
+    uniforms: Vec<(&'static str, UniformType)>,
+    defines: Vec<(String, String)>,
+    vertex_shader: String,
+    fragment_shader: String,
+    _phantom: PhantomData<V>,
+}
+
+impl<V: Vertex> CustomPassBuilder<V> {
+    /// Starts describing a pass. `name` needs to be unique among the passes
+    /// of the application, since it identifies the program in the shader
+    /// cache.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            varyings: Vec::new(),
+            uniforms: Vec::new(),
+            defines: Vec::new(),
+            vertex_shader: String::new(),
+            fragment_shader: String::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Declares a value that is passed from the vertex to the fragment
+    /// shader.
+    pub fn varying(mut self, name: &'static str, ty: AttributeType) -> Self {
+        self.varyings.push(Attribute::new(name, ty));
+        self
+    }
+
+    /// Declares a uniform, whose value needs to be set with
+    /// `CustomPass::set_uniform`, or `CustomPass::set_texture` for samplers,
+    /// before drawing.
+    pub fn uniform(mut self, name: &'static str, ty: UniformType) -> Self {
+        self.uniforms.push((name, ty));
+        self
+    }
+
+    /// Prepends `#define name value` to both shaders.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn vertex_shader(mut self, source: &str) -> Self {
+        self.vertex_shader = source.to_string();
+        self
+    }
+
+    pub fn fragment_shader(mut self, source: &str) -> Self {
+        self.fragment_shader = source.to_string();
+        self
+    }
+
+    /// Compiles the shaders, or takes the program from the cache if a pass
+    /// with the same name, sources and defines has been built before.
+    pub fn build(self, ctx: &Canvas) -> Result<CustomPass<V>, Error> {
+        if let Some((name, _)) = self
+            .uniforms
+            .iter()
+            .find(|(name, _)| *name == "mat_projection_view")
+        {
+            return Err(Error::Shader(format!(
+                "uniform `{}` is declared automatically",
+                name
+            )));
+        }
+
+        let mut uniforms = vec![Uniform::new(
+            "mat_projection_view",
+            UniformType::Matrix(Dimension::D3),
+        )];
+        uniforms.extend(
+            self.uniforms
+                .iter()
+                .map(|(name, ty)| Uniform::new(name, ty.clone())),
+        );
+
+        let defines: Vec<(&str, &str)> = self
+            .defines
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+
+        let shader = ctx.shader_cache().get_or_compile(
+            ctx.golem_ctx(),
+            &self.name,
+            ShaderDescription {
+                vertex_input: &V::attributes(),
+                fragment_input: &self.varyings,
+                uniforms: &uniforms,
+                vertex_shader: &self.vertex_shader,
+                fragment_shader: &self.fragment_shader,
+            },
+            &defines,
+        )?;
+
+        Ok(CustomPass {
+            shader,
+            uniforms: self
+                .uniforms
+                .into_iter()
+                .map(|(name, ty)| (name, ty, None))
+                .collect(),
+            _phantom: PhantomData,
+        })
+    }
+}
+
+enum Value {
+    Uniform(UniformValue),
+    Texture(Rc<Texture>),
+}
+
+/// A pass with user-supplied shaders for vertices of type `V`. See
+/// `CustomPassBuilder`.
+pub struct CustomPass<V> {
+    shader: SharedShader,
+    uniforms: Vec<(&'static str, UniformType, Option<Value>)>,
+    _phantom: PhantomData<V>,
+}
+
+impl<V: Vertex> CustomPass<V> {
+    pub fn builder(name: &str) -> CustomPassBuilder<V> {
+        CustomPassBuilder::new(name)
+    }
+
+    /// Sets the value of a uniform, which is kept until it is set again.
+    ///
+    /// Fails if no uniform called `name` has been declared, or if `value`
+    /// does not match its type.
+    pub fn set_uniform(&mut self, name: &str, value: UniformValue) -> Result<(), Error> {
+        let (_, ty, slot) = self.uniform_mut(name)?;
+        if !value_matches_type(&value, ty) {
+            return Err(Error::Shader(format!(
+                "value {:?} does not match the type {:?} of uniform `{}`",
+                value, ty, name
+            )));
+        }
+
+        *slot = Some(Value::Uniform(value));
+        Ok(())
+    }
+
+    /// Sets the texture that is sampled by a `Sampler2D` uniform.
+    pub fn set_texture(&mut self, name: &str, texture: Rc<Texture>) -> Result<(), Error> {
+        let (_, ty, slot) = self.uniform_mut(name)?;
+        if !matches!(ty, UniformType::Sampler2D) {
+            return Err(Error::Shader(format!(
+                "uniform `{}` is not a sampler",
+                name
+            )));
+        }
+
+        *slot = Some(Value::Texture(texture));
+        Ok(())
+    }
+
+    /// Draws `draw_unit`, transforming it by `transform`. Fails if a
+    /// declared uniform has not been set.
+    ///
+    /// Uniforms that the shader does not use, including
+    /// `mat_projection_view`, may be removed by the GLSL compiler; setting
+    /// them is skipped.
+    pub fn draw(&mut self, transform: &Matrix3<f32>, draw_unit: &DrawUnit<V>) -> Result<(), Error> {
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        set_uniform_if_used(
+            &mut shader,
+            "mat_projection_view",
+            UniformValue::Matrix3(matrix3_to_flat_array(transform)),
+        )?;
+
+        // Like the built-in passes, textures are bound starting at unit 1.
+        let mut next_unit = 1;
+        for (name, _, value) in &self.uniforms {
+            match value {
+                Some(Value::Uniform(value)) => {
+                    set_uniform_if_used(&mut shader, name, value.clone())?
+                }
+                Some(Value::Texture(texture)) => {
+                    texture.set_active(NonZeroU32::new(next_unit).unwrap());
+                    set_uniform_if_used(&mut shader, name, UniformValue::Int(next_unit as i32))?;
+                    next_unit += 1;
+                }
+                None => {
+                    return Err(Error::Shader(format!(
+                        "uniform `{}` has not been set",
+                        name
+                    )))
+                }
+            }
+        }
+
+        draw_unit.draw(&shader)
+    }
+
+    /// Uploads `batch` and draws it like `draw`.
+    pub fn draw_batch<G>(
+        &mut self,
+        transform: &Matrix3<f32>,
+        batch: &mut Batch<G>,
+    ) -> Result<(), Error>
+    where
+        G: Geometry<Vertex = V>,
+    {
+        self.draw(transform, &batch.draw_unit())
+    }
+
+    fn uniform_mut(
+        &mut self,
+        name: &str,
+    ) -> Result<&mut (&'static str, UniformType, Option<Value>), Error> {
+        self.uniforms
+            .iter_mut()
+            .find(|(declared, _, _)| *declared == name)
+            .ok_or_else(|| Error::Shader(format!("uniform `{}` has not been declared", name)))
+    }
+}
+
+fn value_matches_type(value: &UniformValue, ty: &UniformType) -> bool {
+    use Dimension::*;
+    use NumberType::*;
+
+    match (value, ty) {
+        (UniformValue::Int(_), UniformType::Scalar(Int))
+        | (UniformValue::Float(_), UniformType::Scalar(Float))
+        | (UniformValue::Vector2(_), UniformType::Vector(Float, D2))
+        | (UniformValue::Vector3(_), UniformType::Vector(Float, D3))
+        | (UniformValue::Vector4(_), UniformType::Vector(Float, D4))
+        | (UniformValue::IVector2(_), UniformType::Vector(Int, D2))
+        | (UniformValue::IVector3(_), UniformType::Vector(Int, D3))
+        | (UniformValue::IVector4(_), UniformType::Vector(Int, D4))
+        | (UniformValue::Matrix2(_), UniformType::Matrix(D2))
+        | (UniformValue::Matrix3(_), UniformType::Matrix(D3))
+        | (UniformValue::Matrix4(_), UniformType::Matrix(D4)) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,28 @@
+//! Ordered dithering to hide banding in smooth gradients.
+//!
+//! With 8 bits per channel, large soft gradients such as lighting falloffs
+//! show visible bands. Adding a small offset that follows an 8x8 Bayer
+//! pattern before the color is quantized breaks up the bands.
+
+/// GLSL functions for ordered dithering, to be prepended to fragment shaders.
+///
+/// `dither_offset()` returns an offset in `[-0.5, 0.5] / 255` for the current
+/// fragment, which is to be added to the final color.
+pub const DITHER_GLSL: &str = r#"
+float bayer2(vec2 a) {
+    a = floor(a);
+    return fract(dot(a, vec2(0.5, a.y * 0.75)));
+}
+
+float bayer4(vec2 a) {
+    return bayer2(0.5 * a) * 0.25 + bayer2(a);
+}
+
+float bayer8(vec2 a) {
+    return bayer4(0.5 * a) * 0.25 + bayer2(a);
+}
+
+float dither_offset() {
+    return (bayer8(gl_FragCoord.xy) - 0.5) / 255.0;
+}
+"#;
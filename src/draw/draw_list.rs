@@ -0,0 +1,211 @@
+//! Mixing shapes, text and textured quads in the order in which they are
+//! pushed.
+//!
+//! Shapes, text and textured quads are drawn with different passes, so they
+//! live in different batches. Drawing one batch after the other puts e.g. all
+//! text on top of all panels, which breaks as soon as a panel overlaps the
+//! text of another one. A `DrawList` remembers where the pushes switch
+//! between batches, and draws the batches in runs in that order, switching
+//! passes as needed. Consecutive pushes into the same batch share a single
+//! draw call.
+
+use std::rc::Rc;
+
+use golem::blend::BlendMode;
+use nalgebra::Matrix3;
+
+use crate::{
+    draw::{
+        BlendScope, ColPass, ColVertex, GlyphAtlas, TexColPass, TexColVertex, TextBatch, TextBlend,
+        Texture, TriBatch,
+    },
+    Canvas, Error, YAxis,
+};
+
+enum RunKind {
+    Shapes,
+    Text(TextBlend),
+    Textured(Rc<Texture>),
+}
+
+impl RunKind {
+    fn same_batch(&self, other: &RunKind) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    fn same_pass(&self, other: &RunKind) -> bool {
+        match (self, other) {
+            (RunKind::Text(a), RunKind::Text(b)) => a == b,
+            (RunKind::Textured(a), RunKind::Textured(b)) => Rc::ptr_eq(a, b),
+            _ => self.same_batch(other),
+        }
+    }
+}
+
+struct Run {
+    kind: RunKind,
+    first_element: usize,
+}
+
+/// Batches of shapes, text and textured quads that are drawn interleaved,
+/// in the order in which they have been pushed.
+///
+/// Get the batch to push into with `shapes`, `text` or `textured` right
+/// before pushing, since these calls record the order. Sort keys must not be
+/// used with these batches, since they reorder the elements.
+pub struct DrawList {
+    shapes: TriBatch<ColVertex>,
+    text: TextBatch,
+    textured: TriBatch<TexColVertex>,
+    runs: Vec<Run>,
+
+    atlas: GlyphAtlas,
+    col_pass: ColPass,
+    tex_pass: TexColPass,
+}
+
+impl DrawList {
+    /// Creates a draw list whose text is drawn from `atlas`, which needs to
+    /// be the atlas of the fonts that write into it.
+    pub fn new(ctx: &Canvas, atlas: GlyphAtlas) -> Result<Self, Error> {
+        Ok(Self {
+            shapes: TriBatch::new(ctx)?,
+            text: TriBatch::new(ctx)?,
+            textured: TriBatch::new(ctx)?,
+            runs: Vec::new(),
+            atlas,
+            col_pass: ColPass::new(ctx)?,
+            tex_pass: TexColPass::new(ctx)?,
+        })
+    }
+
+    pub fn atlas(&self) -> &GlyphAtlas {
+        &self.atlas
+    }
+
+    /// Returns the batch for colored shapes.
+    pub fn shapes(&mut self) -> &mut TriBatch<ColVertex> {
+        self.begin_run(RunKind::Shapes);
+        &mut self.shapes
+    }
+
+    /// Returns the batch for text that is drawn with `TextBlend::Additive`.
+    pub fn text(&mut self) -> &mut TextBatch {
+        self.text_with_blend(TextBlend::default())
+    }
+
+    /// Returns the batch for text that is drawn with `blend`.
+    pub fn text_with_blend(&mut self, blend: TextBlend) -> &mut TextBatch {
+        self.begin_run(RunKind::Text(blend));
+        &mut self.text
+    }
+
+    /// Returns the batch for quads that are textured with `texture`.
+    pub fn textured(&mut self, texture: &Rc<Texture>) -> &mut TriBatch<TexColVertex> {
+        self.begin_run(RunKind::Textured(texture.clone()));
+        &mut self.textured
+    }
+
+    /// Sets the Y axis of all batches of the list, see `Batch::set_y_axis`.
+    pub fn set_y_axis(&mut self, y_axis: YAxis) {
+        self.shapes.set_y_axis(y_axis);
+        self.text.set_y_axis(y_axis);
+        self.textured.set_y_axis(y_axis);
+    }
+
+    /// Returns the number of draw calls that `draw` will make.
+    pub fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.shapes.clear();
+        self.text.clear();
+        self.textured.clear();
+        self.runs.clear();
+    }
+
+    /// Draws everything that has been pushed and clears the batches. Shapes
+    /// and textured quads are drawn with alpha blending, and text with the
+    /// `TextBlend` that it was pushed with.
+    pub fn draw(&mut self, ctx: &Canvas, transform: &Matrix3<f32>) -> Result<(), Error> {
+        let num_elements = |kind: &RunKind| match kind {
+            RunKind::Shapes => self.shapes.num_elements(),
+            RunKind::Text(_) => self.text.num_elements(),
+            RunKind::Textured(_) => self.textured.num_elements(),
+        };
+        let ends: Vec<usize> = self
+            .runs
+            .iter()
+            .enumerate()
+            .map(|(i, run)| {
+                self.runs[i + 1..]
+                    .iter()
+                    .find(|next| next.kind.same_batch(&run.kind))
+                    .map_or_else(|| num_elements(&run.kind), |next| next.first_element)
+            })
+            .collect();
+
+        {
+            let shapes = self.shapes.draw_unit();
+            let text = self.text.draw_unit();
+            let textured = self.textured.draw_unit();
+
+            for (run, end) in self.runs.iter().zip(ends) {
+                let count = end - run.first_element;
+                if count == 0 {
+                    continue;
+                }
+
+                match &run.kind {
+                    RunKind::Shapes => {
+                        let _blend = BlendScope::new(ctx.golem_ctx(), BlendMode::default());
+                        self.col_pass
+                            .draw(transform, &shapes.sub_unit(run.first_element, count))?;
+                    }
+                    RunKind::Text(blend) => {
+                        self.atlas.draw_with_blend(
+                            ctx,
+                            transform,
+                            *blend,
+                            &text.sub_unit(run.first_element, count),
+                        )?;
+                    }
+                    RunKind::Textured(texture) => {
+                        let _blend = BlendScope::new(ctx.golem_ctx(), BlendMode::default());
+                        self.tex_pass.draw(
+                            transform,
+                            texture,
+                            &textured.sub_unit(run.first_element, count),
+                        )?;
+                    }
+                }
+            }
+        }
+
+        self.clear();
+
+        Ok(())
+    }
+
+    fn begin_run(&mut self, kind: RunKind) {
+        if self
+            .runs
+            .last()
+            .map_or(false, |last| last.kind.same_pass(&kind))
+        {
+            return;
+        }
+
+        let first_element = match kind {
+            RunKind::Shapes => self.shapes.num_elements(),
+            RunKind::Text(_) => self.text.num_elements(),
+            RunKind::Textured(_) => self.textured.num_elements(),
+        };
+
+        self.runs.push(Run {
+            kind,
+            first_element,
+        });
+    }
+}
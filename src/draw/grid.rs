@@ -0,0 +1,114 @@
+//! A world-space grid for editors and debug views.
+
+use nalgebra::Point2;
+
+use crate::{
+    draw::{ColVertex, LineBatch},
+    Camera, Color4, ScreenGeom,
+};
+
+/// Parameters for drawing an infinite-looking grid in world space.
+///
+/// Minor lines fade out as the camera zooms out. Once they would become too
+/// dense, the grid switches to coarser spacing, so that the number of lines
+/// stays bounded regardless of the zoom level.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    /// Distance between minor lines in world units.
+    pub spacing: f32,
+
+    /// Every `major_every`-th line is a major line.
+    pub major_every: u32,
+
+    pub minor_color: Color4,
+    pub major_color: Color4,
+
+    /// Color for the lines at `x = 0` and `y = 0`. If `None`, the axes are
+    /// drawn like major lines.
+    pub axis_color: Option<Color4>,
+
+    /// Minor lines that are closer than this many pixels on screen are faded
+    /// out completely.
+    pub min_pixel_spacing: f32,
+
+    /// Minor lines that are at least this many pixels apart on screen are
+    /// drawn fully opaque.
+    pub fade_pixel_spacing: f32,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            spacing: 32.0,
+            major_every: 8,
+            minor_color: Color4::new(0.5, 0.5, 0.5, 0.3),
+            major_color: Color4::new(0.5, 0.5, 0.5, 0.7),
+            axis_color: Some(Color4::new(0.9, 0.3, 0.3, 1.0)),
+            min_pixel_spacing: 4.0,
+            fade_pixel_spacing: 16.0,
+        }
+    }
+}
+
+impl Grid {
+    /// Pushes the grid lines that are visible through `camera` into `batch`.
+    ///
+    /// Since the lines may be translucent, the batch should be drawn with
+    /// blending enabled.
+    pub fn push(
+        &self,
+        camera: &Camera,
+        screen: &ScreenGeom,
+        z: f32,
+        batch: &mut LineBatch<ColVertex>,
+    ) {
+        assert!(self.spacing > 0.0);
+        assert!(self.major_every > 1);
+
+        let visible_rect = camera.visible_rect(screen);
+        let (min, max) = (visible_rect.min(), visible_rect.max());
+
+        // Increase the spacing until minor lines are far enough apart on
+        // screen.
+        let zoom = camera.clamped_zoom().abs();
+        let mut spacing = self.spacing;
+        while spacing * zoom < self.min_pixel_spacing {
+            spacing *= self.major_every as f32;
+        }
+
+        let minor_alpha = ((spacing * zoom - self.min_pixel_spacing)
+            / (self.fade_pixel_spacing - self.min_pixel_spacing).max(std::f32::EPSILON))
+        .min(1.0)
+        .max(0.0);
+        let minor_color = Color4::new(
+            self.minor_color.r,
+            self.minor_color.g,
+            self.minor_color.b,
+            self.minor_color.a * minor_alpha,
+        );
+
+        let color = |index: i64| {
+            if index == 0 {
+                self.axis_color.unwrap_or(self.major_color)
+            } else if index % self.major_every as i64 == 0 {
+                self.major_color
+            } else {
+                minor_color
+            }
+        };
+
+        let first_x = (min.x / spacing).floor() as i64;
+        let last_x = (max.x / spacing).ceil() as i64;
+        for i in first_x..=last_x {
+            let x = i as f32 * spacing;
+            batch.push_line(Point2::new(x, min.y), Point2::new(x, max.y), z, color(i));
+        }
+
+        let first_y = (min.y / spacing).floor() as i64;
+        let last_y = (max.y / spacing).ceil() as i64;
+        for i in first_y..=last_y {
+            let y = i as f32 * spacing;
+            batch.push_line(Point2::new(min.x, y), Point2::new(max.x, y), z, color(i));
+        }
+    }
+}
@@ -0,0 +1,496 @@
+//! Drawing many copies of the same geometry with a single draw call.
+//!
+//! `Batch` transforms every vertex on the CPU, so thousands of sprites mean
+//! regenerating and uploading thousands of quads in each frame. With
+//! instancing, the geometry of one copy is uploaded once, and only a few
+//! floats per copy (the instance) are streamed, e.g. a transform, a color and
+//! a UV rect. The vertex shader combines both.
+//!
+//! WebGL 1 supports instancing through the `ANGLE_instanced_arrays`
+//! extension, which is available practically everywhere. golem does not know
+//! about instancing, so the attributes are bound with raw WebGL calls, while
+//! shaders are still compiled with golem. The vertex input of these shaders
+//! needs to be `InstancedBatch::vertex_input`.
+
+use std::marker::PhantomData;
+
+use golem::{
+    Attribute, AttributeType, Dimension, NumberType, ShaderDescription, ShaderProgram, Texture,
+    Uniform, UniformType, UniformValue,
+};
+use js_sys::{Float32Array, Uint16Array};
+use nalgebra::{Matrix3, Point2, Point3, Vector2};
+use wasm_bindgen::JsCast;
+use web_sys::{AngleInstancedArrays, WebGlBuffer, WebGlProgram, WebGlRenderingContext as Gl};
+
+use crate::{
+    draw::{GeometryMode, Quad, SharedShader, TexVertex, TextureRegion, Vertex},
+    geom::matrix3_to_flat_array,
+    Canvas, Color4, Error, GpuAllocation, GpuResourceKind, YAxis,
+};
+
+/// Static geometry of type `V` that is drawn once for each pushed instance of
+/// type `I`.
+///
+/// Instances are pushed like vertices into a `Batch`, and uploaded as a whole
+/// when drawing, so they can be cleared and pushed again in every frame.
+pub struct InstancedBatch<V, I> {
+    gl: Gl,
+    ext: AngleInstancedArrays,
+
+    vertices: WebGlBuffer,
+    elements: WebGlBuffer,
+    instances: WebGlBuffer,
+
+    vertices_allocation: GpuAllocation,
+    elements_allocation: GpuAllocation,
+    instances_allocation: GpuAllocation,
+
+    geometry_mode: GeometryMode,
+    num_elements: usize,
+
+    scratch: Vec<f32>,
+    num_instances: usize,
+    dirty: bool,
+
+    _phantom: PhantomData<(V, I)>,
+}
+
+impl<V: Vertex, I: Vertex> InstancedBatch<V, I> {
+    /// Returns true if the browser supports instancing.
+    pub fn is_supported(ctx: &Canvas) -> bool {
+        matches!(
+            ctx.webgl_ctx().get_extension("ANGLE_instanced_arrays"),
+            Ok(Some(_))
+        )
+    }
+
+    /// Creates a batch that draws `vertices`, connected by `elements`, once
+    /// per instance.
+    pub fn new(
+        ctx: &Canvas,
+        geometry_mode: GeometryMode,
+        vertices: &[V],
+        elements: &[u16],
+    ) -> Result<Self, Error> {
+        let gl = ctx.webgl_ctx().clone();
+        let ext = match gl.get_extension("ANGLE_instanced_arrays") {
+            Ok(Some(ext)) => ext.unchecked_into::<AngleInstancedArrays>(),
+            _ => {
+                return Err(Error::Instancing(
+                    "ANGLE_instanced_arrays is not supported".into(),
+                ))
+            }
+        };
+
+        let create_buffer = || {
+            gl.create_buffer()
+                .ok_or_else(|| Error::Instancing("failed to create buffer".into()))
+        };
+        let label = std::any::type_name::<Self>();
+
+        let mut batch = Self {
+            vertices: create_buffer()?,
+            elements: create_buffer()?,
+            instances: create_buffer()?,
+            gl,
+            ext,
+            vertices_allocation: GpuAllocation::new(GpuResourceKind::VertexBuffer, label, 0),
+            elements_allocation: GpuAllocation::new(GpuResourceKind::ElementBuffer, label, 0),
+            instances_allocation: GpuAllocation::new(GpuResourceKind::VertexBuffer, label, 0),
+            geometry_mode,
+            num_elements: 0,
+            scratch: Vec::new(),
+            num_instances: 0,
+            dirty: false,
+            _phantom: PhantomData,
+        };
+        batch.set_geometry(geometry_mode, vertices, elements);
+
+        Ok(batch)
+    }
+
+    /// Returns the vertex input for shaders that draw this batch: the
+    /// attributes of `V`, followed by the attributes of `I`.
+    ///
+    /// The shaders need to use every attribute, since attributes that are
+    /// optimized away break the layout of the buffers.
+    pub fn vertex_input() -> Vec<Attribute> {
+        let mut attributes = V::attributes();
+        attributes.extend(I::attributes());
+        attributes
+    }
+
+    /// Replaces the geometry that is drawn for each instance.
+    pub fn set_geometry(&mut self, geometry_mode: GeometryMode, vertices: &[V], elements: &[u16]) {
+        assert!(elements
+            .iter()
+            .all(|&element| (element as usize) < vertices.len()));
+
+        let mut data = Vec::new();
+        for vertex in vertices {
+            vertex.write(&mut data);
+        }
+
+        self.gl.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.vertices));
+        upload_f32(&self.gl, Gl::ARRAY_BUFFER, &data, Gl::STATIC_DRAW);
+        self.gl
+            .bind_buffer(Gl::ELEMENT_ARRAY_BUFFER, Some(&self.elements));
+        // Safety: The view is dropped before anything is allocated, so the
+        // wasm memory can not be moved while it is alive.
+        unsafe {
+            self.gl.buffer_data_with_array_buffer_view(
+                Gl::ELEMENT_ARRAY_BUFFER,
+                &Uint16Array::view(elements),
+                Gl::STATIC_DRAW,
+            );
+        }
+
+        self.vertices_allocation
+            .set_bytes(data.len() * std::mem::size_of::<f32>());
+        self.elements_allocation
+            .set_bytes(elements.len() * std::mem::size_of::<u16>());
+
+        self.geometry_mode = geometry_mode;
+        self.num_elements = elements.len();
+    }
+
+    pub fn num_instances(&self) -> usize {
+        self.num_instances
+    }
+
+    pub fn push_instance(&mut self, instance: &I) {
+        instance.write(&mut self.scratch);
+        self.num_instances += 1;
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.scratch.clear();
+        self.num_instances = 0;
+        self.dirty = true;
+    }
+
+    /// Draws the geometry once per instance with `shader`, which needs to be
+    /// bound and have its uniforms set.
+    pub fn draw(&mut self, shader: &ShaderProgram) -> Result<(), Error> {
+        if !shader.is_bound() {
+            return Err(Error::Instancing("the shader is not bound".into()));
+        }
+        if self.num_instances == 0 || self.num_elements == 0 {
+            return Ok(());
+        }
+
+        if self.dirty {
+            crate::profile_scope!("upload");
+
+            self.gl.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.instances));
+            upload_f32(&self.gl, Gl::ARRAY_BUFFER, &self.scratch, Gl::STREAM_DRAW);
+            self.instances_allocation
+                .set_bytes(self.scratch.len() * std::mem::size_of::<f32>());
+
+            self.dirty = false;
+        }
+
+        let num_vertex_attributes = V::attributes().len();
+        let sizes = attribute_sizes(&self.gl, num_vertex_attributes + I::attributes().len())?;
+        let (vertex_sizes, instance_sizes) = sizes.split_at(num_vertex_attributes);
+
+        self.gl.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.vertices));
+        self.bind_attributes(0, vertex_sizes, 0);
+        self.gl.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.instances));
+        self.bind_attributes(num_vertex_attributes, instance_sizes, 1);
+        self.gl
+            .bind_buffer(Gl::ELEMENT_ARRAY_BUFFER, Some(&self.elements));
+
+        self.ext.draw_elements_instanced_angle_with_i32(
+            geometry_mode_to_gl(self.geometry_mode),
+            self.num_elements as i32,
+            Gl::UNSIGNED_SHORT,
+            0,
+            self.num_instances as i32,
+        );
+
+        // golem does not reset divisors, so they would leak into its next
+        // draw call.
+        for location in num_vertex_attributes..sizes.len() {
+            self.ext.vertex_attrib_divisor_angle(location as u32, 0);
+            self.gl.disable_vertex_attrib_array(location as u32);
+        }
+
+        Ok(())
+    }
+
+    fn bind_attributes(&self, first_location: usize, sizes: &[i32], divisor: u32) {
+        let float_size = std::mem::size_of::<f32>() as i32;
+        let stride = sizes.iter().sum::<i32>() * float_size;
+        let mut offset = 0;
+
+        for (i, &size) in sizes.iter().enumerate() {
+            let location = (first_location + i) as u32;
+
+            self.gl.enable_vertex_attrib_array(location);
+            self.gl.vertex_attrib_pointer_with_i32(
+                location,
+                size,
+                Gl::FLOAT,
+                false,
+                stride,
+                offset,
+            );
+            self.ext.vertex_attrib_divisor_angle(location, divisor);
+
+            offset += size * float_size;
+        }
+    }
+}
+
+impl<V, I> Drop for InstancedBatch<V, I> {
+    fn drop(&mut self) {
+        self.gl.delete_buffer(Some(&self.vertices));
+        self.gl.delete_buffer(Some(&self.elements));
+        self.gl.delete_buffer(Some(&self.instances));
+    }
+}
+
+impl InstancedBatch<TexVertex, SpriteInstance> {
+    /// Creates a batch of sprites, drawn as instances of a unit quad. See
+    /// `InstancedSpritePass`.
+    pub fn sprites(ctx: &Canvas) -> Result<Self, Error> {
+        let vertices: Vec<TexVertex> = Quad::corners()
+            .iter()
+            .map(|corner| TexVertex {
+                world_pos: Point3::new(corner.x, corner.y, 0.0),
+                tex_coords: Point2::new(corner.x + 0.5, corner.y + 0.5),
+            })
+            .collect();
+
+        Self::new(ctx, GeometryMode::Triangles, &vertices, &[0, 1, 2, 2, 3, 0])
+    }
+}
+
+/// A textured sprite that is drawn by `InstancedSpritePass`.
+#[derive(Clone)]
+pub struct SpriteInstance {
+    /// The center of the sprite in world coordinates.
+    pub position: Point3<f32>,
+
+    /// The rotation around the center in radians.
+    pub rotation: f32,
+
+    /// The size of the sprite in world coordinates.
+    pub size: Vector2<f32>,
+
+    /// The part of a texture that is shown by the sprite. All sprites of a
+    /// batch need to show regions of the texture that it is drawn with.
+    pub region: TextureRegion,
+
+    /// The color that the texture is multiplied with.
+    pub color: Color4,
+}
+
+impl SpriteInstance {
+    /// Returns a sprite that shows `region` at its size in pixels, scaled
+    /// by `scale`.
+    pub fn new(
+        region: TextureRegion,
+        position: Point3<f32>,
+        rotation: f32,
+        scale: Vector2<f32>,
+        color: Color4,
+    ) -> Self {
+        Self {
+            position,
+            rotation,
+            size: region.size.component_mul(&scale),
+            region,
+            color,
+        }
+    }
+}
+
+impl Vertex for SpriteInstance {
+    fn attributes() -> Vec<Attribute> {
+        vec![
+            Attribute::new("i_position", AttributeType::Vector(Dimension::D3)),
+            Attribute::new("i_rotation", AttributeType::Scalar),
+            Attribute::new("i_size", AttributeType::Vector(Dimension::D2)),
+            Attribute::new("i_uv_rect", AttributeType::Vector(Dimension::D4)),
+            Attribute::new("i_color", AttributeType::Vector(Dimension::D4)),
+        ]
+    }
+
+    fn write(&self, out: &mut Vec<f32>) {
+        let uv_rect = self.region.uv_rect;
+        let uv_min = uv_rect.min();
+
+        out.extend_from_slice(&[
+            self.position.x,
+            self.position.y,
+            self.position.z,
+            self.rotation,
+            self.size.x,
+            self.size.y,
+            uv_min.x,
+            uv_min.y,
+            uv_rect.size.x,
+            uv_rect.size.y,
+        ]);
+        self.color.write(out);
+    }
+}
+
+/// Draws `SpriteInstance`s, transforming the quads on the GPU.
+///
+/// Sprites are mirrored around their center if the Y axis of the transform
+/// points up, so that they stay upright, see `YAxis`.
+pub struct InstancedSpritePass {
+    shader: SharedShader,
+}
+
+impl InstancedSpritePass {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let shader = ctx.shader_cache().get_or_compile(
+            ctx.golem_ctx(),
+            "InstancedSpritePass",
+            ShaderDescription {
+                vertex_input: &InstancedBatch::<TexVertex, SpriteInstance>::vertex_input(),
+                fragment_input: &[
+                    Attribute::new("v_color", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("v_tex_coords", AttributeType::Vector(Dimension::D2)),
+                ],
+                uniforms: &[
+                    Uniform::new("mat_projection_view", UniformType::Matrix(Dimension::D3)),
+                    Uniform::new("my_tex", UniformType::Sampler2D),
+                    Uniform::new("y_sign", UniformType::Scalar(NumberType::Float)),
+                ],
+                vertex_shader: r#"
+                void main() {
+                    float c = cos(i_rotation);
+                    float s = sin(i_rotation);
+                    vec2 corner = a_world_pos.xy * i_size * vec2(1.0, y_sign);
+                    vec2 world_pos = i_position.xy + vec2(
+                        c * corner.x - s * corner.y,
+                        s * corner.x + c * corner.y
+                    );
+
+                    vec3 p = mat_projection_view * vec3(world_pos, 1.0);
+                    gl_Position = vec4(p.xy, i_position.z * p.z, p.z);
+                    v_color = i_color;
+                    v_tex_coords = i_uv_rect.xy + a_tex_coords * i_uv_rect.zw;
+                }
+                "#,
+                fragment_shader: r#"
+                void main() {
+                    gl_FragColor = v_color * texture(my_tex, v_tex_coords);
+                }
+                "#,
+            },
+            &[],
+        )?;
+
+        Ok(Self { shader })
+    }
+
+    /// Draws the sprites of `batch` with `tex`, which needs to be the
+    /// texture of their regions.
+    pub fn draw(
+        &mut self,
+        transform: &Matrix3<f32>,
+        tex: &Texture,
+        batch: &mut InstancedBatch<TexVertex, SpriteInstance>,
+    ) -> Result<(), Error> {
+        tex.set_active(std::num::NonZeroU32::new(1).unwrap());
+
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform(
+            "mat_projection_view",
+            UniformValue::Matrix3(matrix3_to_flat_array(transform)),
+        )?;
+        shader.set_uniform("my_tex", UniformValue::Int(1))?;
+        shader.set_uniform(
+            "y_sign",
+            UniformValue::Float(YAxis::of_transform(transform).sign()),
+        )?;
+
+        batch.draw(&shader)
+    }
+}
+
+/// Returns the number of floats of the attributes at locations `0..num` of
+/// the currently bound program.
+///
+/// golem binds the location of each attribute to its index in the vertex
+/// input, but it does not expose the attribute types, so they are queried
+/// from the linked program.
+fn attribute_sizes(gl: &Gl, num: usize) -> Result<Vec<i32>, Error> {
+    let program: WebGlProgram = gl
+        .get_parameter(Gl::CURRENT_PROGRAM)
+        .ok()
+        .and_then(|program| program.dyn_into().ok())
+        .ok_or_else(|| Error::Instancing("no program is bound".into()))?;
+
+    let num_active = gl
+        .get_program_parameter(&program, Gl::ACTIVE_ATTRIBUTES)
+        .as_f64()
+        .unwrap_or(0.0) as u32;
+
+    let mut sizes = vec![None; num];
+    for i in 0..num_active {
+        let info = match gl.get_active_attrib(&program, i) {
+            Some(info) => info,
+            None => continue,
+        };
+        let location = gl.get_attrib_location(&program, &info.name());
+        let size = match info.type_() {
+            Gl::FLOAT => 1,
+            Gl::FLOAT_VEC2 => 2,
+            Gl::FLOAT_VEC3 => 3,
+            Gl::FLOAT_VEC4 => 4,
+            _ => {
+                return Err(Error::Instancing(format!(
+                    "attribute `{}` has an unsupported type",
+                    info.name()
+                )))
+            }
+        };
+
+        if let Some(slot) = sizes.get_mut(location as usize) {
+            *slot = Some(size);
+        }
+    }
+
+    sizes
+        .into_iter()
+        .enumerate()
+        .map(|(location, size)| {
+            size.ok_or_else(|| {
+                Error::Instancing(format!(
+                    "the attribute at location {} is not used by the shader",
+                    location
+                ))
+            })
+        })
+        .collect()
+}
+
+fn upload_f32(gl: &Gl, target: u32, data: &[f32], usage: u32) {
+    // Safety: The view is dropped before anything is allocated, so the wasm
+    // memory can not be moved while it is alive.
+    unsafe {
+        gl.buffer_data_with_array_buffer_view(target, &Float32Array::view(data), usage);
+    }
+}
+
+fn geometry_mode_to_gl(mode: GeometryMode) -> u32 {
+    match mode {
+        GeometryMode::Points => Gl::POINTS,
+        GeometryMode::Lines => Gl::LINES,
+        GeometryMode::LineStrip => Gl::LINE_STRIP,
+        GeometryMode::LineLoop => Gl::LINE_LOOP,
+        GeometryMode::TriangleStrip => Gl::TRIANGLE_STRIP,
+        GeometryMode::TriangleFan => Gl::TRIANGLE_FAN,
+        GeometryMode::Triangles => Gl::TRIANGLES,
+    }
+}
@@ -0,0 +1,336 @@
+//! Loading KTX2 textures that contain Basis Universal data.
+//!
+//! Transcoding is done by the official Basis Universal transcoder, which is
+//! built to WebAssembly separately and loaded by the page, e.g.:
+//!
+//! ```js
+//! import BASIS from "./basis_transcoder.js";
+//! BASIS().then((module) => {
+//!     module.initializeBasis();
+//!     // Pass `module` to `Ktx2Transcoder::new`.
+//! });
+//! ```
+//!
+//! `Ktx2Transcoder::load_texture` transcodes to the best compressed format
+//! that the device supports, see `CompressedTextureSupport`, and uploads it
+//! with `compressedTexImage2D`. If no compressed format is supported, or the
+//! image size is not allowed for the format, it falls back to uncompressed
+//! RGBA.
+
+use std::num::NonZeroU32;
+
+use golem::{ColorFormat, TextureFilter, TextureWrap};
+use js_sys::{Array, Function, Reflect, Uint8Array};
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use web_sys::WebGlRenderingContext as Gl;
+
+use crate::{draw::Texture, Canvas, Error};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Target formats of the Basis transcoder, as defined by
+/// `transcoder_texture_format` in `basisu_transcoder.h`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BasisFormat {
+    Etc1Rgb = 0,
+    Etc2Rgba = 1,
+    Bc1Rgb = 2,
+    Bc3Rgba = 3,
+    Bc7Rgba = 6,
+    Pvrtc1Rgb = 8,
+    Pvrtc1Rgba = 9,
+    Astc4x4Rgba = 10,
+    Rgba32 = 13,
+}
+
+impl BasisFormat {
+    /// Returns the internal format of `compressedTexImage2D` for this
+    /// format, or `None` if it is uncompressed.
+    pub fn gl_internal_format(self) -> Option<u32> {
+        match self {
+            BasisFormat::Etc1Rgb => Some(0x8D64), // COMPRESSED_RGB_ETC1_WEBGL
+            BasisFormat::Etc2Rgba => Some(0x9278), // COMPRESSED_RGBA8_ETC2_EAC
+            BasisFormat::Bc1Rgb => Some(0x83F0),  // COMPRESSED_RGB_S3TC_DXT1_EXT
+            BasisFormat::Bc3Rgba => Some(0x83F3), // COMPRESSED_RGBA_S3TC_DXT5_EXT
+            BasisFormat::Bc7Rgba => Some(0x8E8C), // COMPRESSED_RGBA_BPTC_UNORM_EXT
+            BasisFormat::Pvrtc1Rgb => Some(0x8C00), // COMPRESSED_RGB_PVRTC_4BPPV1_IMG
+            BasisFormat::Pvrtc1Rgba => Some(0x8C02), // COMPRESSED_RGBA_PVRTC_4BPPV1_IMG
+            BasisFormat::Astc4x4Rgba => Some(0x93B0), // COMPRESSED_RGBA_ASTC_4x4_KHR
+            BasisFormat::Rgba32 => None,
+        }
+    }
+
+    /// Returns true if WebGL accepts images of the given size in this
+    /// format. PVRTC requires square power-of-two sizes, and the other
+    /// compressed formats consist of 4x4 blocks.
+    pub fn supports_size(self, width: u32, height: u32) -> bool {
+        match self {
+            BasisFormat::Rgba32 => true,
+            BasisFormat::Pvrtc1Rgb | BasisFormat::Pvrtc1Rgba => {
+                width == height && width.is_power_of_two()
+            }
+            _ => width % 4 == 0 && height % 4 == 0,
+        }
+    }
+}
+
+/// Compressed texture formats that the WebGL context supports.
+#[derive(Debug, Clone, Default)]
+pub struct CompressedTextureSupport {
+    pub astc: bool,
+    pub bptc: bool,
+    pub s3tc: bool,
+    pub etc: bool,
+    pub etc1: bool,
+    pub pvrtc: bool,
+}
+
+impl CompressedTextureSupport {
+    pub fn detect(ctx: &Canvas) -> Self {
+        let has = |name: &str| matches!(ctx.webgl_ctx().get_extension(name), Ok(Some(_)));
+
+        Self {
+            astc: has("WEBGL_compressed_texture_astc"),
+            bptc: has("EXT_texture_compression_bptc"),
+            s3tc: has("WEBGL_compressed_texture_s3tc"),
+            etc: has("WEBGL_compressed_texture_etc"),
+            etc1: has("WEBGL_compressed_texture_etc1"),
+            pvrtc: has("WEBGL_compressed_texture_pvrtc"),
+        }
+    }
+
+    /// Returns the best transcoding target for this device, in the order of
+    /// preference that is recommended by the Basis Universal documentation.
+    pub fn preferred_format(&self, has_alpha: bool) -> BasisFormat {
+        if self.astc {
+            BasisFormat::Astc4x4Rgba
+        } else if self.bptc {
+            BasisFormat::Bc7Rgba
+        } else if self.s3tc {
+            if has_alpha {
+                BasisFormat::Bc3Rgba
+            } else {
+                BasisFormat::Bc1Rgb
+            }
+        } else if self.etc {
+            BasisFormat::Etc2Rgba
+        } else if self.etc1 && !has_alpha {
+            BasisFormat::Etc1Rgb
+        } else if self.pvrtc {
+            if has_alpha {
+                BasisFormat::Pvrtc1Rgba
+            } else {
+                BasisFormat::Pvrtc1Rgb
+            }
+        } else {
+            BasisFormat::Rgba32
+        }
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    type Ktx2File;
+
+    #[wasm_bindgen(method, js_name = isValid)]
+    fn is_valid(this: &Ktx2File) -> bool;
+
+    #[wasm_bindgen(method, js_name = getWidth)]
+    fn get_width(this: &Ktx2File) -> u32;
+
+    #[wasm_bindgen(method, js_name = getHeight)]
+    fn get_height(this: &Ktx2File) -> u32;
+
+    #[wasm_bindgen(method, js_name = getHasAlpha)]
+    fn get_has_alpha(this: &Ktx2File) -> bool;
+
+    #[wasm_bindgen(method, js_name = startTranscoding)]
+    fn start_transcoding(this: &Ktx2File) -> bool;
+
+    #[wasm_bindgen(method, js_name = getImageTranscodedSizeInBytes)]
+    fn get_image_transcoded_size_in_bytes(
+        this: &Ktx2File,
+        level: u32,
+        layer: u32,
+        face: u32,
+        format: u32,
+    ) -> u32;
+
+    #[wasm_bindgen(method, js_name = transcodeImage)]
+    fn transcode_image(
+        this: &Ktx2File,
+        dst: &Uint8Array,
+        level: u32,
+        layer: u32,
+        face: u32,
+        format: u32,
+        get_alpha_for_opaque_formats: u32,
+        channel0: i32,
+        channel1: i32,
+    ) -> bool;
+
+    #[wasm_bindgen(method)]
+    fn close(this: &Ktx2File);
+
+    #[wasm_bindgen(method)]
+    fn delete(this: &Ktx2File);
+}
+
+/// A transcoded image.
+pub struct TranscodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub has_alpha: bool,
+    pub format: BasisFormat,
+    pub data: Vec<u8>,
+}
+
+/// Wraps the initialized Basis Universal transcoder module.
+pub struct Ktx2Transcoder {
+    ktx2_file_ctor: Function,
+}
+
+impl Ktx2Transcoder {
+    pub fn new(module: &JsValue) -> Result<Self, Error> {
+        let ktx2_file_ctor = Reflect::get(module, &JsValue::from_str("KTX2File"))
+            .ok()
+            .and_then(|ctor| ctor.dyn_into::<Function>().ok())
+            .ok_or_else(|| {
+                Error::Image("Basis module has no KTX2File, was it built with KTX2 support?".into())
+            })?;
+
+        Ok(Self { ktx2_file_ctor })
+    }
+
+    pub fn is_ktx2(data: &[u8]) -> bool {
+        data.starts_with(&KTX2_IDENTIFIER)
+    }
+
+    /// Transcodes the base level of a KTX2 file into the given format.
+    pub fn transcode(&self, data: &[u8], format: BasisFormat) -> Result<TranscodedImage, Error> {
+        let file = self.open(data)?;
+        let result = Self::transcode_file(&file, format);
+
+        file.close();
+        file.delete();
+
+        result
+    }
+
+    /// Returns the width, height and whether the KTX2 file has alpha,
+    /// without transcoding it.
+    fn header(&self, data: &[u8]) -> Result<(u32, u32, bool), Error> {
+        let file = self.open(data)?;
+
+        let result = if file.is_valid() {
+            Ok((file.get_width(), file.get_height(), file.get_has_alpha()))
+        } else {
+            Err(Error::Image("invalid KTX2 file".into()))
+        };
+
+        file.close();
+        file.delete();
+
+        result
+    }
+
+    fn open(&self, data: &[u8]) -> Result<Ktx2File, Error> {
+        if !Self::is_ktx2(data) {
+            return Err(Error::Image("data is not a KTX2 file".into()));
+        }
+
+        let input = Uint8Array::from(data);
+        let file = Reflect::construct(&self.ktx2_file_ctor, &Array::of1(&input))
+            .map_err(|e| Error::Image(format!("KTX2File construction failed: {:?}", e)))?;
+
+        Ok(file.unchecked_into())
+    }
+
+    /// Transcodes a KTX2 file into the preferred format of the device and
+    /// uploads it as a texture.
+    pub fn load_texture(
+        &self,
+        ctx: &Canvas,
+        support: &CompressedTextureSupport,
+        data: &[u8],
+    ) -> Result<Texture, Error> {
+        let (width, height, has_alpha) = self.header(data)?;
+
+        let mut format = support.preferred_format(has_alpha);
+        if !format.supports_size(width, height) {
+            format = BasisFormat::Rgba32;
+        }
+
+        let image = self.transcode(data, format)?;
+
+        let mut texture = Texture::new(ctx.golem_ctx())?;
+        match format.gl_internal_format() {
+            Some(internal_format) => {
+                // Let golem allocate the storage, so that it knows the size
+                // of the texture, and then replace it with the compressed
+                // data.
+                texture.set_image(None, image.width, image.height, ColorFormat::RGBA);
+                texture.set_active(NonZeroU32::new(1).unwrap());
+
+                let gl = ctx.webgl_ctx();
+                gl.compressed_tex_image_2d_with_array_buffer_view(
+                    Gl::TEXTURE_2D,
+                    0,
+                    internal_format,
+                    image.width as i32,
+                    image.height as i32,
+                    0,
+                    &Uint8Array::from(image.data.as_slice()),
+                );
+
+                let gl_error = gl.get_error();
+                if gl_error != Gl::NO_ERROR {
+                    return Err(Error::Image(format!(
+                        "compressedTexImage2D failed for {:?}: error {:#x}",
+                        format, gl_error,
+                    )));
+                }
+            }
+            None => {
+                texture.set_image(
+                    Some(&image.data),
+                    image.width,
+                    image.height,
+                    ColorFormat::RGBA,
+                );
+            }
+        }
+        texture.set_magnification(TextureFilter::Linear)?;
+        texture.set_minification(TextureFilter::Linear)?;
+        texture.set_wrap_h(TextureWrap::ClampToEdge)?;
+        texture.set_wrap_v(TextureWrap::ClampToEdge)?;
+
+        Ok(texture)
+    }
+
+    fn transcode_file(file: &Ktx2File, format: BasisFormat) -> Result<TranscodedImage, Error> {
+        if !file.is_valid() {
+            return Err(Error::Image("invalid KTX2 file".into()));
+        }
+        if !file.start_transcoding() {
+            return Err(Error::Image("KTX2 startTranscoding failed".into()));
+        }
+
+        let size = file.get_image_transcoded_size_in_bytes(0, 0, 0, format as u32);
+        let output = Uint8Array::new_with_length(size);
+
+        if !file.transcode_image(&output, 0, 0, 0, format as u32, 0, -1, -1) {
+            return Err(Error::Image("KTX2 transcodeImage failed".into()));
+        }
+
+        Ok(TranscodedImage {
+            width: file.get_width(),
+            height: file.get_height(),
+            has_alpha: file.get_has_alpha(),
+            format,
+            data: output.to_vec(),
+        })
+    }
+}
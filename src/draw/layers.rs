@@ -0,0 +1,183 @@
+//! Named layers that are drawn in the order in which they are declared.
+//!
+//! Instead of carefully ordering draw calls by hand, shapes are pushed into
+//! the batches of a layer such as "background", "world", "lights" or "ui".
+//! Each layer has its own clear color, blend preset and camera.
+
+use nalgebra::Matrix3;
+
+use crate::{
+    draw::{BlendPreset, BlendScope, ColPass, ColVertex, LineBatch, TriBatch},
+    Camera, Canvas, Color4, Error, ScreenGeom,
+};
+
+#[derive(Debug, Clone)]
+pub enum LayerCamera {
+    /// Draw in logical screen pixels, e.g. for UI.
+    Screen,
+
+    /// Draw in world space, as seen through the camera.
+    World(Camera),
+}
+
+impl LayerCamera {
+    pub fn to_matrix(&self, screen: &ScreenGeom) -> Matrix3<f32> {
+        match self {
+            LayerCamera::Screen => screen.orthographic_projection(),
+            LayerCamera::World(camera) => camera.projection_view(screen),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayerDesc {
+    /// If set, the canvas is cleared to this color before the layer is
+    /// drawn. This is usually only useful for the first layer, since it
+    /// erases everything drawn by previous layers.
+    pub clear_color: Option<Color4>,
+
+    pub blend: BlendPreset,
+    pub camera: LayerCamera,
+}
+
+impl Default for LayerDesc {
+    fn default() -> Self {
+        Self {
+            clear_color: None,
+            blend: BlendPreset::Alpha,
+            camera: LayerCamera::Screen,
+        }
+    }
+}
+
+pub struct Layer {
+    name: String,
+    desc: LayerDesc,
+    tri_batch: TriBatch<ColVertex>,
+    line_batch: LineBatch<ColVertex>,
+}
+
+impl Layer {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn desc(&self) -> &LayerDesc {
+        &self.desc
+    }
+
+    pub fn desc_mut(&mut self) -> &mut LayerDesc {
+        &mut self.desc
+    }
+
+    /// Sets the camera of the layer, e.g. once per frame for following the
+    /// player.
+    pub fn set_camera(&mut self, camera: LayerCamera) {
+        self.desc.camera = camera;
+    }
+
+    pub fn tri_batch(&mut self) -> &mut TriBatch<ColVertex> {
+        &mut self.tri_batch
+    }
+
+    pub fn line_batch(&mut self) -> &mut LineBatch<ColVertex> {
+        &mut self.line_batch
+    }
+}
+
+pub struct Layers {
+    layers: Vec<Layer>,
+    col_pass: ColPass,
+}
+
+impl Layers {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        Ok(Self {
+            layers: Vec::new(),
+            col_pass: ColPass::new(ctx)?,
+        })
+    }
+
+    /// Declares a new layer, which is drawn after all previously declared
+    /// layers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a layer with the same name already exists.
+    pub fn add(&mut self, ctx: &Canvas, name: &str, desc: LayerDesc) -> Result<(), Error> {
+        assert!(
+            self.get(name).is_none(),
+            "layer `{}` has already been declared",
+            name
+        );
+
+        self.layers.push(Layer {
+            name: name.to_string(),
+            desc,
+            tri_batch: TriBatch::new(ctx)?,
+            line_batch: LineBatch::new(ctx)?,
+        });
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    /// Returns the layer with the given name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no such layer.
+    pub fn layer(&mut self, name: &str) -> &mut Layer {
+        self.get_mut(name)
+            .unwrap_or_else(|| panic!("layer `{}` has not been declared", name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Layer> {
+        self.layers.iter()
+    }
+
+    /// Draws all layers in declaration order and clears their batches.
+    ///
+    /// After the batches of a layer have been drawn, `f` is called with the
+    /// name and the transform of the layer, so that content that does not
+    /// fit into the layer's batches, such as text or textured sprites, can
+    /// be drawn in the right place. Note that `f` needs to set its own blend
+    /// mode, if any.
+    pub fn draw<F>(&mut self, ctx: &Canvas, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&str, &Matrix3<f32>) -> Result<(), Error>,
+    {
+        let screen = ctx.screen_geom();
+
+        for layer in self.layers.iter_mut() {
+            if let Some(color) = layer.desc.clear_color {
+                ctx.clear(color);
+            }
+
+            let transform = layer.desc.camera.to_matrix(&screen);
+
+            {
+                let _blend = BlendScope::preset(ctx, layer.desc.blend);
+
+                self.col_pass
+                    .draw(&transform, &layer.tri_batch.draw_unit())?;
+                self.col_pass
+                    .draw(&transform, &layer.line_batch.draw_unit())?;
+            }
+
+            f(&layer.name, &transform)?;
+
+            layer.tri_batch.clear();
+            layer.line_batch.clear();
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! A minimap that shows a scaled-down view of the world as a HUD element.
+//!
+//! The world shapes are kept in a batch that only needs to be filled when
+//! the map changes, while markers, e.g. for players and objectives, are
+//! pushed every frame. Both are rendered into a texture in `render`, which
+//! `draw` then shows on the screen.
+
+use golem::{blend::BlendMode, TextureFilter};
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::{
+        BlendScope, ColPass, ColVertex, Quad, RenderTarget, TargetScope, TexColPass, TexColVertex,
+        TriBatch,
+    },
+    geom::rect_to_target,
+    AaRect, Canvas, Color4, Error,
+};
+
+#[derive(Debug, Clone)]
+pub struct MinimapMarker {
+    /// The position of the marker in world space.
+    pub pos: Point2<f32>,
+
+    /// The size of the marker in pixels of the minimap, so that markers
+    /// stay visible no matter how large the shown area of the world is.
+    pub size: f32,
+
+    pub color: Color4,
+}
+
+pub struct Minimap {
+    target: RenderTarget,
+
+    world_rect: AaRect,
+    background: Color4,
+
+    world_batch: TriBatch<ColVertex>,
+    marker_batch: TriBatch<ColVertex>,
+    hud_batch: TriBatch<TexColVertex>,
+    col_pass: ColPass,
+    tex_pass: TexColPass,
+}
+
+impl Minimap {
+    /// Creates a minimap with a texture of `size` pixels, which shows the
+    /// area `world_rect` of the world.
+    pub fn new(ctx: &Canvas, size: Vector2<u32>, world_rect: AaRect) -> Result<Self, Error> {
+        Ok(Self {
+            target: RenderTarget::with_filters(
+                ctx,
+                size,
+                TextureFilter::Linear,
+                TextureFilter::Linear,
+                "minimap",
+            )?,
+            world_rect,
+            background: Color4::new(0.0, 0.0, 0.0, 0.6),
+            world_batch: TriBatch::new(ctx)?,
+            marker_batch: TriBatch::new(ctx)?,
+            hud_batch: TriBatch::new(ctx)?,
+            col_pass: ColPass::new(ctx)?,
+            tex_pass: TexColPass::new(ctx)?,
+        })
+    }
+
+    pub fn world_rect(&self) -> AaRect {
+        self.world_rect
+    }
+
+    /// Changes the area of the world that is shown, e.g. to keep the player
+    /// in the center.
+    pub fn set_world_rect(&mut self, world_rect: AaRect) {
+        self.world_rect = world_rect;
+    }
+
+    pub fn set_background(&mut self, background: Color4) {
+        self.background = background;
+    }
+
+    /// Returns the batch holding the world shapes in world space. It is not
+    /// cleared by the minimap, so it only needs to be filled again when the
+    /// world changes.
+    ///
+    /// The Y axis of this batch determines which side of the world is shown
+    /// at the top of the minimap, see `Batch::set_y_axis`.
+    pub fn world_batch(&mut self) -> &mut TriBatch<ColVertex> {
+        &mut self.world_batch
+    }
+
+    /// Adds a marker to be shown until the next call to `render`.
+    pub fn push_marker(&mut self, marker: &MinimapMarker) {
+        let world_size = marker.size * self.world_rect.size.x / self.target.size().x as f32;
+
+        self.marker_batch.push_quad(
+            &Quad::axis_aligned(marker.pos, Vector2::new(world_size, world_size)),
+            0.0,
+            marker.color,
+        );
+    }
+
+    /// Renders the world and the markers into the minimap's texture, and
+    /// clears the markers.
+    pub fn render(&mut self, ctx: &Canvas) -> Result<(), Error> {
+        // The top of the world rect ends up at `v = 0`, which `draw` shows at
+        // the top of the screen rect.
+        let transform = rect_to_target(&self.world_rect, self.world_batch.y_axis());
+
+        {
+            let _target = TargetScope::new(ctx, &self.target);
+            ctx.clear(self.background);
+
+            let _blend = BlendScope::new(ctx.golem_ctx(), BlendMode::default());
+            self.col_pass
+                .draw(&transform, &self.world_batch.draw_unit())?;
+            self.col_pass
+                .draw(&transform, &self.marker_batch.draw_unit())?;
+        }
+
+        self.marker_batch.clear();
+
+        Ok(())
+    }
+
+    /// Draws the minimap's texture into `screen_rect`, given in logical
+    /// pixels.
+    pub fn draw(&mut self, ctx: &Canvas, screen_rect: AaRect) -> Result<(), Error> {
+        self.hud_batch.clear();
+        self.hud_batch.push_quad(
+            &screen_rect.into(),
+            0.0,
+            AaRect::zero_to_one(),
+            Color4::new(1.0, 1.0, 1.0, 1.0),
+        );
+
+        let _blend = BlendScope::new(ctx.golem_ctx(), BlendMode::default());
+
+        let (tex_pass, hud_batch) = (&mut self.tex_pass, &mut self.hud_batch);
+        self.target.with_texture(|texture| {
+            tex_pass.draw(
+                &ctx.screen_geom().orthographic_projection(),
+                texture,
+                &hud_batch.draw_unit(),
+            )
+        })
+    }
+}
@@ -1,16 +1,94 @@
+mod autotile;
 mod batch;
+mod buffer;
+mod capture;
+mod chunked_mesh;
+mod chunks;
+mod composite;
+mod custom_pass;
+mod dither;
+mod draw_list;
+mod grid;
+mod instanced;
+mod ktx2;
+mod layers;
+mod minimap;
 mod pass;
+mod post_process;
 mod primitive;
+mod recording;
+mod render_graph;
+mod render_target;
+mod scope;
+mod shader_cache;
+mod shadow_volume;
+mod shapes;
+mod simd;
+mod sprite_batch;
+mod sprite_sheet;
+mod strip;
 mod text;
+mod texture;
+mod texture_cache;
+mod texture_loader;
+mod texture_region;
+mod tilemap;
+mod transition;
+mod visibility;
+mod water;
 
 pub mod shadow;
 
 pub use golem::Texture;
 
-pub use batch::{Batch, DrawUnit, LineBatch, TriBatch};
-pub use pass::{ColPass, TexColPass};
+pub use autotile::{AutotileMode, TerrainLayer};
+pub use batch::{Batch, BatchStats, DrawUnit, LineBatch, ShrinkPolicy, TriBatch};
+pub use buffer::{ElementBuffer, VertexBuffer};
+pub use capture::TargetCapture;
+pub use chunked_mesh::ChunkedMesh;
+pub use chunks::{ChunkConfig, ChunkedTilemap};
+pub use composite::{ColorAdjustments, ColorBlindness, CompositePass};
+pub use custom_pass::{CustomPass, CustomPassBuilder};
+pub use dither::DITHER_GLSL;
+pub use draw_list::DrawList;
+pub use grid::Grid;
+pub use instanced::{InstancedBatch, InstancedSpritePass, SpriteInstance};
+pub use ktx2::{BasisFormat, CompressedTextureSupport, Ktx2Transcoder, TranscodedImage};
+pub use layers::{Layer, LayerCamera, LayerDesc, Layers};
+pub use minimap::{Minimap, MinimapMarker};
+pub use pass::{ColPass, TexColEffect, TexColPass};
+pub use post_process::{
+    BloomPass, BlurPass, ColorLutPass, PostProcessChain, ScreenPass, ScreenPassContext, ScreenQuad,
+    VignettePass,
+};
 pub use primitive::{
-    ColVertex, Geometry, GeometryMode, Line, Quad, TexColVertex, TexVertex, Triangle, Vertex,
+    ColVertex, FlatVertex, Geometry, GeometryMode, Line, Quad, TexColVertex, TexVertex, Triangle,
+    Vertex, WorldVertex,
+};
+pub use recording::{FrameRecorder, RecordingConfig};
+pub use render_graph::{
+    FrameGraph, PassContext, PassOutput, RenderGraph, TargetDesc, TargetId, TargetSize,
+};
+pub use render_target::RenderTarget;
+pub(crate) use scope::SavedTarget;
+pub use scope::{BlendPreset, BlendScope, ScissorScope, TargetScope};
+pub use shader_cache::{ShaderCache, SharedShader};
+pub use shadow::{Light, Lightmap, OccluderBatch, ShadowColPass, ShadowMap};
+pub use shadow_volume::shadow_volume_quads;
+pub use sprite_batch::{SpriteBatch, SpriteSort};
+pub use sprite_sheet::{SpriteFrame, SpriteSheet};
+pub use strip::smooth_points;
+pub use text::{
+    Font, FontFamily, GlyphAtlas, HorizontalAlign, SdfConfig, SdfEffect, TextAlign, TextBatch,
+    TextBlend, TextLayout, TextMirror, TextShadow, VerticalAlign, WrapStyle,
 };
-pub use shadow::{Light, OccluderBatch, ShadowColPass, ShadowMap};
-pub use text::{Font, TextBatch};
+#[cfg(feature = "vector-text")]
+pub use text::{GlyphMesh, TextMesh, VectorFont};
+pub use texture::TextureExt;
+pub use texture_cache::TextureCache;
+pub use texture_loader::{ImageDecodeOptions, LoadHandle, LoadProgress, LoadState, TextureLoader};
+pub use texture_region::TextureRegion;
+pub use tilemap::{TileLayout, TileProjection, Tilemap};
+pub use transition::{Transition, TransitionEffect};
+pub use visibility::visibility_polygon;
+pub use water::{WaterEffect, WaterParams};
@@ -1,23 +1,26 @@
+use std::{cell::RefCell, rc::Rc};
+
 use golem::{
-    Attribute, AttributeType, Dimension, ShaderDescription, ShaderProgram, Texture, Uniform,
-    UniformType, UniformValue,
+    Attribute, AttributeType, Dimension, NumberType, ShaderDescription, ShaderProgram, Texture,
+    Uniform, UniformType, UniformValue,
 };
-use nalgebra::Matrix3;
+use nalgebra::{Matrix3, Vector2};
 
 use crate::{
-    draw::{ColVertex, DrawUnit, TexColVertex, Vertex},
+    draw::{ColVertex, DrawUnit, SharedShader, TexColVertex, Vertex},
     geom::matrix3_to_flat_array,
-    Canvas, Error,
+    Canvas, Color4, Error,
 };
 
 pub struct ColPass {
-    shader: ShaderProgram,
+    shader: SharedShader,
 }
 
 impl ColPass {
     pub fn new(ctx: &Canvas) -> Result<Self, Error> {
-        let shader = ShaderProgram::new(
+        let shader = ctx.shader_cache().get_or_compile(
             ctx.golem_ctx(),
+            "ColPass",
             ShaderDescription {
                 vertex_input: &ColVertex::attributes(),
                 fragment_input: &[Attribute::new(
@@ -31,7 +34,9 @@ impl ColPass {
                 vertex_shader: r#"
                 void main() {
                     vec3 p = mat_projection_view * vec3(a_world_pos.xy, 1.0);
-                    gl_Position = vec4(p.xy, a_world_pos.z, 1.0);
+                    // For affine transforms, p.z is 1. Perspective
+                    // transforms (see `XrView::plane_transform`) use it as W.
+                    gl_Position = vec4(p.xy, a_world_pos.z * p.z, p.z);
                     v_color = a_color;
                 }
                 "#,
@@ -41,6 +46,7 @@ impl ColPass {
                 }
                 "#,
             },
+            &[],
         )?;
 
         Ok(Self { shader })
@@ -51,55 +57,161 @@ impl ColPass {
         transform: &Matrix3<f32>,
         draw_unit: &DrawUnit<ColVertex>,
     ) -> Result<(), Error> {
-        self.shader.bind();
-        self.shader.set_uniform(
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform(
             "mat_projection_view",
             UniformValue::Matrix3(matrix3_to_flat_array(transform)),
         )?;
 
-        draw_unit.draw(&self.shader)
+        draw_unit.draw(&shader)
+    }
+}
+
+/// Per-draw color adjustments for `TexColPass`.
+#[derive(Debug, Clone, Copy)]
+pub struct TexColEffect {
+    /// Color that every fragment is multiplied with, e.g. for team colors.
+    pub tint: Color4,
+
+    /// Color that is added to every fragment, weighted by its alpha
+    /// component, e.g. for flashing sprites white when they are hit.
+    pub flash: Color4,
+
+    /// Factor that texture coordinates are multiplied with. This allows
+    /// giving texture coordinates in texels, with a scale of one over the
+    /// texture size, so that they stay valid when the texture is resized.
+    pub uv_scale: Vector2<f32>,
+}
+
+impl Default for TexColEffect {
+    fn default() -> Self {
+        Self {
+            tint: Color4::new(1.0, 1.0, 1.0, 1.0),
+            flash: Color4::new(0.0, 0.0, 0.0, 0.0),
+            uv_scale: Vector2::new(1.0, 1.0),
+        }
     }
 }
 
 pub struct TexColPass {
-    shader: ShaderProgram,
+    shader: SharedShader,
+    pixel_grid: Option<Vector2<u32>>,
 }
 
 impl TexColPass {
-    pub fn new_golem(ctx: &golem::Context) -> Result<Self, Error> {
-        let shader = ShaderProgram::new(
-            ctx,
-            ShaderDescription {
-                vertex_input: &TexColVertex::attributes(),
-                fragment_input: &[
-                    Attribute::new("v_color", AttributeType::Vector(Dimension::D4)),
-                    Attribute::new("v_tex_coords", AttributeType::Vector(Dimension::D2)),
-                ],
-                uniforms: &[
-                    Uniform::new("mat_projection_view", UniformType::Matrix(Dimension::D3)),
-                    Uniform::new("my_tex", UniformType::Sampler2D),
-                ],
-                vertex_shader: r#"
+    fn with_description<R>(f: impl FnOnce(ShaderDescription) -> R) -> R {
+        f(ShaderDescription {
+            vertex_input: &TexColVertex::attributes(),
+            fragment_input: &[
+                Attribute::new("v_color", AttributeType::Vector(Dimension::D4)),
+                Attribute::new("v_tex_coords", AttributeType::Vector(Dimension::D2)),
+            ],
+            uniforms: &[
+                Uniform::new("mat_projection_view", UniformType::Matrix(Dimension::D3)),
+                Uniform::new("my_tex", UniformType::Sampler2D),
+                Uniform::new(
+                    "tint",
+                    UniformType::Vector(NumberType::Float, Dimension::D4),
+                ),
+                Uniform::new(
+                    "flash",
+                    UniformType::Vector(NumberType::Float, Dimension::D4),
+                ),
+                Uniform::new(
+                    "pixel_grid",
+                    UniformType::Vector(NumberType::Float, Dimension::D2),
+                ),
+                Uniform::new(
+                    "uv_scale",
+                    UniformType::Vector(NumberType::Float, Dimension::D2),
+                ),
+            ],
+            vertex_shader: r#"
                 void main() {
                     vec3 p = mat_projection_view * vec3(a_world_pos.xy, 1.0);
-                    gl_Position = vec4(p.xy, a_world_pos.z, 1.0);
+                    vec2 ndc = p.xy / p.z;
+                    #ifdef PIXEL_SNAP
+                    // Round to the nearest corner of the virtual pixel grid.
+                    ndc = floor((ndc * 0.5 + 0.5) * pixel_grid + 0.5) / pixel_grid * 2.0 - 1.0;
+                    #endif
+                    // For affine transforms, p.z is 1. Perspective
+                    // transforms (see `XrView::plane_transform`) use it as W.
+                    gl_Position = vec4(ndc * p.z, a_world_pos.z * p.z, p.z);
                     v_color = a_color;
-                    v_tex_coords = a_tex_coords;
+                    v_tex_coords = a_tex_coords * uv_scale;
                 }
                 "#,
-                fragment_shader: r#"
+            fragment_shader: r#"
                 void main() {
-                    gl_FragColor = v_color * texture(my_tex, v_tex_coords);
+                    vec4 color = v_color * texture(my_tex, v_tex_coords) * tint;
+                    gl_FragColor = vec4(
+                        color.rgb + flash.rgb * flash.a * color.a,
+                        color.a
+                    );
                 }
                 "#,
-            },
-        )?;
+        })
+    }
 
-        Ok(Self { shader })
+    /// Creates a pass with its own shader program, bypassing the cache of
+    /// the `Canvas`.
+    pub fn new_golem(ctx: &golem::Context) -> Result<Self, Error> {
+        let shader = Self::with_description(|desc| ShaderProgram::new(ctx, desc))?;
+
+        Ok(Self {
+            shader: Rc::new(RefCell::new(shader)),
+            pixel_grid: None,
+        })
     }
 
     pub fn new(ctx: &Canvas) -> Result<Self, Error> {
-        Self::new_golem(ctx.golem_ctx())
+        let shader = Self::with_description(|desc| {
+            ctx.shader_cache()
+                .get_or_compile(ctx.golem_ctx(), "TexColPass", desc, &[])
+        })?;
+
+        Ok(Self {
+            shader,
+            pixel_grid: None,
+        })
+    }
+
+    /// Creates a pass for pixel-art games that snaps vertices to a virtual
+    /// pixel grid after transforming them.
+    ///
+    /// `pixel_grid` is the virtual resolution, e.g. `320x180` for a game that
+    /// is scaled up to fill the canvas. Snapping prevents sprites from
+    /// shimmering when the camera moves by fractions of a virtual pixel.
+    pub fn new_pixel_snapped(ctx: &Canvas, pixel_grid: Vector2<u32>) -> Result<Self, Error> {
+        let shader = Self::with_description(|desc| {
+            ctx.shader_cache().get_or_compile(
+                ctx.golem_ctx(),
+                "TexColPass",
+                desc,
+                &[("PIXEL_SNAP", "1")],
+            )
+        })?;
+
+        Ok(Self {
+            shader,
+            pixel_grid: Some(pixel_grid),
+        })
+    }
+
+    /// Changes the virtual resolution of a pass that has been created with
+    /// `new_pixel_snapped`, e.g. when the canvas is resized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pass does not snap to a pixel grid.
+    pub fn set_pixel_grid(&mut self, pixel_grid: Vector2<u32>) {
+        assert!(
+            self.pixel_grid.is_some(),
+            "TexColPass was not created with new_pixel_snapped"
+        );
+
+        self.pixel_grid = Some(pixel_grid);
     }
 
     pub fn draw(
@@ -107,17 +219,40 @@ impl TexColPass {
         transform: &Matrix3<f32>,
         tex: &Texture,
         draw_unit: &DrawUnit<TexColVertex>,
+    ) -> Result<(), Error> {
+        self.draw_with_effect(transform, tex, &TexColEffect::default(), draw_unit)
+    }
+
+    pub fn draw_with_effect(
+        &mut self,
+        transform: &Matrix3<f32>,
+        tex: &Texture,
+        effect: &TexColEffect,
+        draw_unit: &DrawUnit<TexColVertex>,
     ) -> Result<(), Error> {
         tex.set_active(std::num::NonZeroU32::new(1).unwrap());
 
-        self.shader.bind();
-        self.shader.set_uniform(
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform(
             "mat_projection_view",
             UniformValue::Matrix3(matrix3_to_flat_array(transform)),
         )?;
-        self.shader.set_uniform("my_tex", UniformValue::Int(1))?;
+        shader.set_uniform("my_tex", UniformValue::Int(1))?;
+        shader.set_uniform("tint", UniformValue::Vector4(effect.tint.into()))?;
+        shader.set_uniform("flash", UniformValue::Vector4(effect.flash.into()))?;
+        shader.set_uniform(
+            "uv_scale",
+            UniformValue::Vector2([effect.uv_scale.x, effect.uv_scale.y]),
+        )?;
+        if let Some(pixel_grid) = self.pixel_grid {
+            shader.set_uniform(
+                "pixel_grid",
+                UniformValue::Vector2([pixel_grid.x as f32, pixel_grid.y as f32]),
+            )?;
+        }
 
-        draw_unit.draw(&self.shader)?;
+        draw_unit.draw(&shader)?;
 
         // FIXME: Unbind texture
 
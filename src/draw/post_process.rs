@@ -0,0 +1,606 @@
+//! Post-processing with a chain of screen-space passes.
+//!
+//! A `PostProcessChain` runs a sequence of `ScreenPass`es over a rendered
+//! frame, e.g. the texture of a `RenderTarget` that the scene has been drawn
+//! into. Each pass reads the output of the previous pass, and the last pass
+//! draws onto the canvas. The intermediate results are ping-ponged between
+//! two targets that are owned by the chain.
+//!
+//! Built-in passes cover Gaussian blur, bloom, vignetting and color grading
+//! with a lookup table. Custom passes implement `ScreenPass`, usually with a
+//! fragment shader compiled by `ScreenQuad::compile_shader`.
+
+use golem::{
+    Attribute, AttributeType, Dimension, NumberType, ShaderDescription, ShaderProgram, Texture,
+    Uniform, UniformType, UniformValue,
+};
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::{RenderTarget, SharedShader, TargetScope, TexVertex, TriBatch, Vertex},
+    AaRect, Canvas, Error,
+};
+
+/// Gives a screen pass access to the canvas and to its input.
+pub struct ScreenPassContext<'a> {
+    canvas: &'a Canvas,
+    input: &'a Texture,
+    source: &'a Texture,
+    output_size: Vector2<u32>,
+}
+
+impl<'a> ScreenPassContext<'a> {
+    pub fn canvas(&self) -> &'a Canvas {
+        self.canvas
+    }
+
+    /// Returns the output of the previous pass, or the input of the chain
+    /// for the first pass.
+    pub fn input(&self) -> &'a Texture {
+        self.input
+    }
+
+    /// Returns the input of the chain, i.e. the unprocessed frame.
+    pub fn source(&self) -> &'a Texture {
+        self.source
+    }
+
+    /// Returns the size of the output in physical pixels. The viewport has
+    /// already been set to cover all of it.
+    pub fn output_size(&self) -> Vector2<u32> {
+        self.output_size
+    }
+}
+
+/// A pass that draws over the whole viewport, reading from its input.
+pub trait ScreenPass {
+    /// Renders intermediate results into the pass's own targets, e.g. for
+    /// passes that need multiple steps. This is called before the output of
+    /// the pass is bound, so the pass may bind its own targets.
+    fn prepare(&mut self, _ctx: &Canvas, _input: &Texture) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Draws the output of the pass over the whole viewport.
+    fn draw(&mut self, ctx: &ScreenPassContext) -> Result<(), Error>;
+}
+
+/// A quad that covers the whole viewport, for drawing screen-space passes.
+pub struct ScreenQuad {
+    batch: TriBatch<TexVertex>,
+}
+
+impl ScreenQuad {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        // The quad is given in normalized device coordinates.
+        let mut batch = TriBatch::new(ctx)?;
+        batch.push_quad(
+            &AaRect::from_top_left(Point2::new(-1.0, -1.0), Vector2::new(2.0, 2.0)).into(),
+            0.0,
+            AaRect::zero_to_one(),
+        );
+
+        Ok(Self { batch })
+    }
+
+    /// Compiles a shader for drawing a `ScreenQuad`.
+    ///
+    /// The fragment shader receives the texture coordinates of the viewport
+    /// in `v_tex_coords`, ranging from zero at the bottom left to one at the
+    /// top right. `uniforms` declares the uniforms that it uses. The shader
+    /// is cached under `name`, which must be unique for each pass.
+    pub fn compile_shader(
+        ctx: &Canvas,
+        name: &str,
+        uniforms: &[Uniform],
+        fragment_shader: &str,
+    ) -> Result<SharedShader, Error> {
+        ctx.shader_cache().get_or_compile(
+            ctx.golem_ctx(),
+            name,
+            ShaderDescription {
+                vertex_input: &TexVertex::attributes(),
+                fragment_input: &[Attribute::new(
+                    "v_tex_coords",
+                    AttributeType::Vector(Dimension::D2),
+                )],
+                uniforms,
+                vertex_shader: r#"
+                void main() {
+                    gl_Position = vec4(a_world_pos.xy, 0.0, 1.0);
+                    v_tex_coords = a_tex_coords;
+                }
+                "#,
+                fragment_shader,
+            },
+            &[],
+        )
+    }
+
+    /// Draws the quad with `shader`, which needs to be bound already.
+    pub fn draw(&mut self, shader: &ShaderProgram) -> Result<(), Error> {
+        self.batch.draw_unit().draw(shader)
+    }
+}
+
+/// Runs screen passes over a frame, drawing the result onto the canvas.
+pub struct PostProcessChain {
+    quad: ScreenQuad,
+    copy_shader: SharedShader,
+    targets: Vec<RenderTarget>,
+}
+
+impl PostProcessChain {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let copy_shader = ScreenQuad::compile_shader(
+            ctx,
+            "PostProcessChain::copy",
+            &[Uniform::new("tex", UniformType::Sampler2D)],
+            r#"
+            void main() {
+                gl_FragColor = texture(tex, v_tex_coords);
+            }
+            "#,
+        )?;
+
+        Ok(Self {
+            quad: ScreenQuad::new(ctx)?,
+            copy_shader,
+            targets: Vec::new(),
+        })
+    }
+
+    /// Runs `passes` in order over `input`, drawing the output of the last
+    /// pass onto the canvas. Without passes, `input` is copied to the
+    /// canvas.
+    ///
+    /// The intermediate targets have the size of the canvas, so `input`
+    /// should have that size as well.
+    pub fn draw(
+        &mut self,
+        ctx: &Canvas,
+        input: &Texture,
+        passes: &mut [&mut dyn ScreenPass],
+    ) -> Result<(), Error> {
+        let screen_size = ctx.screen_geom().size;
+
+        if passes.is_empty() {
+            input.set_active(std::num::NonZeroU32::new(1).unwrap());
+
+            let mut shader = self.copy_shader.borrow_mut();
+            shader.bind();
+            shader.set_uniform("tex", UniformValue::Int(1))?;
+            return self.quad.draw(&shader);
+        }
+
+        if passes.len() > 1 && (self.targets.len() != 2 || self.targets[0].size() != screen_size) {
+            self.targets = vec![
+                RenderTarget::new(ctx, screen_size)?,
+                RenderTarget::new(ctx, screen_size)?,
+            ];
+        }
+
+        let num_passes = passes.len();
+        let mut current: Option<usize> = None;
+
+        for (i, pass) in passes.iter_mut().enumerate() {
+            let output = if i + 1 == num_passes {
+                None
+            } else {
+                Some(if current == Some(0) { 1 } else { 0 })
+            };
+            let targets = &self.targets;
+
+            let mut run = |pass_input: &Texture| {
+                pass.prepare(ctx, pass_input)?;
+
+                let _target = output.map(|index| TargetScope::new(ctx, &targets[index]));
+                pass.draw(&ScreenPassContext {
+                    canvas: ctx,
+                    input: pass_input,
+                    source: input,
+                    output_size: screen_size,
+                })
+            };
+
+            match current {
+                None => run(input)?,
+                Some(index) => self.targets[index].with_texture(run)?,
+            }
+
+            current = output;
+        }
+
+        Ok(())
+    }
+}
+
+/// Resizes `target` to `size`, creating it if needed.
+fn ensure_target<'a>(
+    ctx: &Canvas,
+    target: &'a mut Option<RenderTarget>,
+    size: Vector2<u32>,
+) -> Result<&'a RenderTarget, Error> {
+    if target.as_ref().map_or(true, |target| target.size() != size) {
+        *target = Some(RenderTarget::new(ctx, size)?);
+    }
+
+    Ok(target.as_ref().unwrap())
+}
+
+/// One direction of a separable 9-tap Gaussian blur.
+struct BlurShader {
+    quad: ScreenQuad,
+    shader: SharedShader,
+}
+
+impl BlurShader {
+    fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let shader = ScreenQuad::compile_shader(
+            ctx,
+            "BlurPass",
+            &[
+                Uniform::new("tex", UniformType::Sampler2D),
+                Uniform::new(
+                    "texel_step",
+                    UniformType::Vector(NumberType::Float, Dimension::D2),
+                ),
+            ],
+            r#"
+            void main() {
+                vec4 color = texture(tex, v_tex_coords) * 0.227027;
+                color += texture(tex, v_tex_coords + texel_step) * 0.1945946;
+                color += texture(tex, v_tex_coords - texel_step) * 0.1945946;
+                color += texture(tex, v_tex_coords + 2.0 * texel_step) * 0.1216216;
+                color += texture(tex, v_tex_coords - 2.0 * texel_step) * 0.1216216;
+                color += texture(tex, v_tex_coords + 3.0 * texel_step) * 0.054054;
+                color += texture(tex, v_tex_coords - 3.0 * texel_step) * 0.054054;
+                color += texture(tex, v_tex_coords + 4.0 * texel_step) * 0.016216;
+                color += texture(tex, v_tex_coords - 4.0 * texel_step) * 0.016216;
+                gl_FragColor = color;
+            }
+            "#,
+        )?;
+
+        Ok(Self {
+            quad: ScreenQuad::new(ctx)?,
+            shader,
+        })
+    }
+
+    /// Blurs `input` along `direction` into the currently bound target.
+    /// `radius` is given in pixels of `input`.
+    fn draw(&mut self, input: &Texture, direction: Vector2<f32>, radius: f32) -> Result<(), Error> {
+        // The kernel has four taps to each side.
+        let step = Vector2::new(
+            direction.x * radius / 4.0 / input.width() as f32,
+            direction.y * radius / 4.0 / input.height() as f32,
+        );
+
+        input.set_active(std::num::NonZeroU32::new(1).unwrap());
+
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform("tex", UniformValue::Int(1))?;
+        shader.set_uniform("texel_step", UniformValue::Vector2([step.x, step.y]))?;
+
+        self.quad.draw(&shader)
+    }
+}
+
+/// Blurs the frame with a Gaussian kernel.
+pub struct BlurPass {
+    /// The radius of the blur in pixels.
+    pub radius: f32,
+
+    blur: BlurShader,
+    horizontal: Option<RenderTarget>,
+}
+
+impl BlurPass {
+    pub fn new(ctx: &Canvas, radius: f32) -> Result<Self, Error> {
+        Ok(Self {
+            radius,
+            blur: BlurShader::new(ctx)?,
+            horizontal: None,
+        })
+    }
+}
+
+impl ScreenPass for BlurPass {
+    fn prepare(&mut self, ctx: &Canvas, input: &Texture) -> Result<(), Error> {
+        let size = Vector2::new(input.width(), input.height());
+        let horizontal = ensure_target(ctx, &mut self.horizontal, size)?;
+
+        let _target = TargetScope::new(ctx, horizontal);
+        self.blur.draw(input, Vector2::x(), self.radius)
+    }
+
+    fn draw(&mut self, _: &ScreenPassContext) -> Result<(), Error> {
+        let (blur, radius) = (&mut self.blur, self.radius);
+
+        self.horizontal
+            .as_ref()
+            .unwrap()
+            .with_texture(|horizontal| blur.draw(horizontal, Vector2::y(), radius))
+    }
+}
+
+/// Makes bright parts of the frame bleed into their surroundings.
+///
+/// Colors above a luminance threshold are extracted at half resolution,
+/// blurred, and added back onto the frame.
+pub struct BloomPass {
+    /// The luminance above which colors bloom.
+    pub threshold: f32,
+
+    /// The factor with which the bloom is added.
+    pub intensity: f32,
+
+    /// The radius of the blur in pixels of the half-resolution targets.
+    pub radius: f32,
+
+    quad: ScreenQuad,
+    threshold_shader: SharedShader,
+    composite_shader: SharedShader,
+    blur: BlurShader,
+    bright: Option<RenderTarget>,
+    horizontal: Option<RenderTarget>,
+}
+
+impl BloomPass {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let threshold_shader = ScreenQuad::compile_shader(
+            ctx,
+            "BloomPass::threshold",
+            &[
+                Uniform::new("tex", UniformType::Sampler2D),
+                Uniform::new("threshold", UniformType::Scalar(NumberType::Float)),
+            ],
+            r#"
+            void main() {
+                vec3 color = texture(tex, v_tex_coords).rgb;
+                float luma = dot(color, vec3(0.2126, 0.7152, 0.0722));
+                float excess = max(luma - threshold, 0.0);
+                gl_FragColor = vec4(color * excess / max(luma, 0.0001), 1.0);
+            }
+            "#,
+        )?;
+        let composite_shader = ScreenQuad::compile_shader(
+            ctx,
+            "BloomPass::composite",
+            &[
+                Uniform::new("tex", UniformType::Sampler2D),
+                Uniform::new("bloom", UniformType::Sampler2D),
+                Uniform::new("intensity", UniformType::Scalar(NumberType::Float)),
+            ],
+            r#"
+            void main() {
+                vec4 color = texture(tex, v_tex_coords);
+                vec3 bloom = texture(bloom, v_tex_coords).rgb;
+                gl_FragColor = vec4(color.rgb + intensity * bloom, color.a);
+            }
+            "#,
+        )?;
+
+        Ok(Self {
+            threshold: 0.8,
+            intensity: 1.0,
+            radius: 8.0,
+            quad: ScreenQuad::new(ctx)?,
+            threshold_shader,
+            composite_shader,
+            blur: BlurShader::new(ctx)?,
+            bright: None,
+            horizontal: None,
+        })
+    }
+}
+
+impl ScreenPass for BloomPass {
+    fn prepare(&mut self, ctx: &Canvas, input: &Texture) -> Result<(), Error> {
+        let size = Vector2::new((input.width() / 2).max(1), (input.height() / 2).max(1));
+        let bright = ensure_target(ctx, &mut self.bright, size)?;
+        let horizontal = ensure_target(ctx, &mut self.horizontal, size)?;
+
+        {
+            let _target = TargetScope::new(ctx, bright);
+            input.set_active(std::num::NonZeroU32::new(1).unwrap());
+
+            let mut shader = self.threshold_shader.borrow_mut();
+            shader.bind();
+            shader.set_uniform("tex", UniformValue::Int(1))?;
+            shader.set_uniform("threshold", UniformValue::Float(self.threshold))?;
+            self.quad.draw(&shader)?;
+        }
+
+        let (blur, radius) = (&mut self.blur, self.radius);
+        {
+            let _target = TargetScope::new(ctx, horizontal);
+            bright.with_texture(|bright| blur.draw(bright, Vector2::x(), radius))?;
+        }
+        {
+            let _target = TargetScope::new(ctx, bright);
+            horizontal.with_texture(|horizontal| blur.draw(horizontal, Vector2::y(), radius))?;
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &ScreenPassContext) -> Result<(), Error> {
+        let (quad, shader, intensity) = (&mut self.quad, &self.composite_shader, self.intensity);
+
+        self.bright.as_ref().unwrap().with_texture(|bloom| {
+            ctx.input()
+                .set_active(std::num::NonZeroU32::new(1).unwrap());
+            bloom.set_active(std::num::NonZeroU32::new(2).unwrap());
+
+            let mut shader = shader.borrow_mut();
+            shader.bind();
+            shader.set_uniform("tex", UniformValue::Int(1))?;
+            shader.set_uniform("bloom", UniformValue::Int(2))?;
+            shader.set_uniform("intensity", UniformValue::Float(intensity))?;
+            quad.draw(&shader)
+        })
+    }
+}
+
+/// Darkens the frame towards its corners.
+pub struct VignettePass {
+    /// How much the corners are darkened, from zero (not at all) to one
+    /// (black).
+    pub strength: f32,
+
+    /// The distance from the center at which darkening is complete, where
+    /// one is the distance to the corners.
+    pub radius: f32,
+
+    /// The width of the transition from no darkening to full darkening.
+    pub softness: f32,
+
+    quad: ScreenQuad,
+    shader: SharedShader,
+}
+
+impl VignettePass {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let shader = ScreenQuad::compile_shader(
+            ctx,
+            "VignettePass",
+            &[
+                Uniform::new("tex", UniformType::Sampler2D),
+                Uniform::new("strength", UniformType::Scalar(NumberType::Float)),
+                Uniform::new("radius", UniformType::Scalar(NumberType::Float)),
+                Uniform::new("softness", UniformType::Scalar(NumberType::Float)),
+            ],
+            r#"
+            void main() {
+                vec4 color = texture(tex, v_tex_coords);
+                float dist = length(v_tex_coords - 0.5) * 1.41421356;
+                float lit = smoothstep(radius, radius - softness, dist);
+                gl_FragColor = vec4(color.rgb * mix(1.0 - strength, 1.0, lit), color.a);
+            }
+            "#,
+        )?;
+
+        Ok(Self {
+            strength: 0.5,
+            radius: 1.0,
+            softness: 0.6,
+            quad: ScreenQuad::new(ctx)?,
+            shader,
+        })
+    }
+}
+
+impl ScreenPass for VignettePass {
+    fn draw(&mut self, ctx: &ScreenPassContext) -> Result<(), Error> {
+        ctx.input()
+            .set_active(std::num::NonZeroU32::new(1).unwrap());
+
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform("tex", UniformValue::Int(1))?;
+        shader.set_uniform("strength", UniformValue::Float(self.strength))?;
+        shader.set_uniform("radius", UniformValue::Float(self.radius))?;
+        shader.set_uniform("softness", UniformValue::Float(self.softness))?;
+        self.quad.draw(&shader)
+    }
+}
+
+/// Grades colors with a 3D lookup table.
+///
+/// The table is stored in a 2D texture as a horizontal strip of `size`
+/// slices of `size` by `size` texels each. Within a slice, red increases to
+/// the right and green increases downwards, and blue increases from slice to
+/// slice. This is the layout that most color grading tools export, e.g. a
+/// 256x16 image for a table of size 16. The texture should be linearly
+/// filtered.
+pub struct ColorLutPass {
+    lut: Texture,
+    size: u32,
+
+    /// How much of the grading is applied, from zero to one.
+    pub strength: f32,
+
+    quad: ScreenQuad,
+    shader: SharedShader,
+}
+
+impl ColorLutPass {
+    pub fn new(ctx: &Canvas, lut: Texture) -> Result<Self, Error> {
+        let size = lut.height();
+        if size < 2 || lut.width() != size * size {
+            return Err(Error::Texture(format!(
+                "expected color lookup table of size N*N x N, got {}x{}",
+                lut.width(),
+                lut.height(),
+            )));
+        }
+
+        let shader = ScreenQuad::compile_shader(
+            ctx,
+            "ColorLutPass",
+            &[
+                Uniform::new("tex", UniformType::Sampler2D),
+                Uniform::new("lut", UniformType::Sampler2D),
+                Uniform::new("lut_size", UniformType::Scalar(NumberType::Float)),
+                Uniform::new("strength", UniformType::Scalar(NumberType::Float)),
+            ],
+            r#"
+            vec3 lookup(vec3 color) {
+                // Sample the centers of the texels, and interpolate blue
+                // between the two nearest slices.
+                float blue = color.b * (lut_size - 1.0);
+                float slice = floor(blue);
+                float next_slice = min(slice + 1.0, lut_size - 1.0);
+                float x = (color.r * (lut_size - 1.0) + 0.5) / (lut_size * lut_size);
+                float y = (color.g * (lut_size - 1.0) + 0.5) / lut_size;
+
+                vec3 a = texture(lut, vec2(x + slice / lut_size, y)).rgb;
+                vec3 b = texture(lut, vec2(x + next_slice / lut_size, y)).rgb;
+                return mix(a, b, blue - slice);
+            }
+
+            void main() {
+                vec4 color = texture(tex, v_tex_coords);
+                vec3 graded = lookup(clamp(color.rgb, 0.0, 1.0));
+                gl_FragColor = vec4(mix(color.rgb, graded, strength), color.a);
+            }
+            "#,
+        )?;
+
+        Ok(Self {
+            lut,
+            size,
+            strength: 1.0,
+            quad: ScreenQuad::new(ctx)?,
+            shader,
+        })
+    }
+
+    pub fn lut(&self) -> &Texture {
+        &self.lut
+    }
+
+    /// Returns the number of entries of the table along each axis.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl ScreenPass for ColorLutPass {
+    fn draw(&mut self, ctx: &ScreenPassContext) -> Result<(), Error> {
+        ctx.input()
+            .set_active(std::num::NonZeroU32::new(1).unwrap());
+        self.lut.set_active(std::num::NonZeroU32::new(2).unwrap());
+
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform("tex", UniformValue::Int(1))?;
+        shader.set_uniform("lut", UniformValue::Int(2))?;
+        shader.set_uniform("lut_size", UniformValue::Float(self.size as f32))?;
+        shader.set_uniform("strength", UniformValue::Float(self.strength))?;
+        self.quad.draw(&shader)
+    }
+}
@@ -1,15 +1,56 @@
 use golem::{Attribute, AttributeType, Dimension};
 use nalgebra::{Matrix3, Point2, Point3, Vector2};
 
-use crate::{AaRect, Color4};
+use crate::{draw::simd, AaRect, Color4};
 
-pub use golem::GeometryMode;
+/// How the elements of a batch are assembled into primitives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeometryMode {
+    Points,
+    Lines,
+    LineStrip,
+    LineLoop,
+    TriangleStrip,
+    TriangleFan,
+    Triangles,
+}
+
+impl From<GeometryMode> for golem::GeometryMode {
+    fn from(mode: GeometryMode) -> Self {
+        match mode {
+            GeometryMode::Points => golem::GeometryMode::Points,
+            GeometryMode::Lines => golem::GeometryMode::Lines,
+            GeometryMode::LineStrip => golem::GeometryMode::LineStrip,
+            GeometryMode::LineLoop => golem::GeometryMode::LineLoop,
+            GeometryMode::TriangleStrip => golem::GeometryMode::TriangleStrip,
+            GeometryMode::TriangleFan => golem::GeometryMode::TriangleFan,
+            GeometryMode::Triangles => golem::GeometryMode::Triangles,
+        }
+    }
+}
 
 pub trait Vertex {
     fn attributes() -> Vec<Attribute>;
     fn write(&self, out: &mut Vec<f32>);
 }
 
+/// A vertex whose memory layout is exactly what `Vertex::write` writes, so
+/// that batches can hand out their vertex data as a slice of vertices, see
+/// `Batch::push_vertices_in_place`.
+///
+/// # Safety
+///
+/// Implementors must be `#[repr(C)]` structs that consist only of `f32`s
+/// (possibly nested in other such structs) without padding, and `write` must
+/// write all of them in declaration order.
+pub unsafe trait FlatVertex: Vertex {}
+
+/// A vertex with a position in world coordinates, which allows sorting
+/// geometry into spatial chunks, see `ChunkedMesh`.
+pub trait WorldVertex: Vertex {
+    fn world_pos(&self) -> Point3<f32>;
+}
+
 pub trait Geometry {
     type Vertex: Vertex;
 
@@ -43,6 +84,7 @@ impl<V: Vertex> Geometry for Triangle<V> {
 }
 
 #[derive(Debug, Clone)]
+#[repr(C)]
 pub struct ColVertex {
     /// The vertex position in world coordinates.
     ///
@@ -68,7 +110,19 @@ impl Vertex for ColVertex {
     }
 }
 
+// Safety: nalgebra's points and `Color4` are `#[repr(C)]` structs of `f32`s,
+// and `write` writes the fields in declaration order. The same holds for the
+// other vertex types.
+unsafe impl FlatVertex for ColVertex {}
+
+impl WorldVertex for ColVertex {
+    fn world_pos(&self) -> Point3<f32> {
+        self.world_pos
+    }
+}
+
 #[derive(Debug, Clone)]
+#[repr(C)]
 pub struct TexVertex {
     /// The vertex position in world coordinates.
     pub world_pos: Point3<f32>,
@@ -94,7 +148,16 @@ impl Vertex for TexVertex {
     }
 }
 
+unsafe impl FlatVertex for TexVertex {}
+
+impl WorldVertex for TexVertex {
+    fn world_pos(&self) -> Point3<f32> {
+        self.world_pos
+    }
+}
+
 #[derive(Debug, Clone)]
+#[repr(C)]
 pub struct TexColVertex {
     /// The vertex position in world coordinates.
     pub world_pos: Point3<f32>,
@@ -123,6 +186,14 @@ impl Vertex for TexColVertex {
     }
 }
 
+unsafe impl FlatVertex for TexColVertex {}
+
+impl WorldVertex for TexColVertex {
+    fn world_pos(&self) -> Point3<f32> {
+        self.world_pos
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Quad {
     pub corners: [Point2<f32>; 4],
@@ -142,12 +213,7 @@ impl Quad {
 
     pub fn new(transform: &Matrix3<f32>) -> Self {
         Self {
-            corners: [
-                (transform * Point3::new(-0.5, -0.5, 1.0)).xy(),
-                (transform * Point3::new(-0.5, 0.5, 1.0)).xy(),
-                (transform * Point3::new(0.5, 0.5, 1.0)).xy(),
-                (transform * Point3::new(0.5, -0.5, 1.0)).xy(),
-            ],
+            corners: simd::transform_unit_quad(transform),
         }
     }
 
@@ -166,6 +232,23 @@ impl Quad {
         }
     }
 
+    /// Returns the quad with its corners shifted by `quarter_turns` places.
+    ///
+    /// When used as the UV quad of `push_quad_with_uv_quad`, this rotates the
+    /// texture on the quad by 90 degrees per quarter turn, which undoes the
+    /// rotation of atlas entries that have been stored rotated.
+    pub fn rotate_corners(&self, quarter_turns: usize) -> Quad {
+        let mut corners = self.corners;
+        corners.rotate_left(quarter_turns % 4);
+
+        Quad { corners }
+    }
+
+    /// Returns the smallest axis-aligned rect that contains the quad.
+    pub fn bounding_rect(&self) -> AaRect {
+        AaRect::bounding(&self.corners)
+    }
+
     pub fn triangle_indices(first_idx: u32) -> [u32; 6] {
         [
             first_idx + Self::TRIANGLE_INDICES[0],
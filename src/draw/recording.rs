@@ -0,0 +1,245 @@
+//! Recording short series of frames, e.g. for sharing gameplay loops as GIFs.
+//!
+//! While a recording runs, the game should advance by `FrameRecorder::dt`
+//! instead of the wall clock time, so that the frames are evenly spaced at
+//! the recording's framerate no matter how long reading the pixels back
+//! takes. Frames are captured either from the canvas, right after drawing,
+//! or from a render target, e.g. the output of a `RenderGraph` before UI
+//! has been drawn on top.
+//!
+//! With the `gif` feature, the frames can be encoded as an animated GIF.
+
+use std::time::Duration;
+
+use nalgebra::Vector2;
+
+#[cfg(feature = "gif")]
+use wasm_bindgen::{closure::Closure, JsCast};
+
+#[cfg(feature = "gif")]
+use crate::draw::capture::{dom_error, download_url};
+use crate::{
+    draw::{
+        capture::{flip_rows, read_pixels},
+        RenderTarget, TargetScope,
+    },
+    Canvas, Error,
+};
+
+/// How long to keep the URL of a downloaded GIF alive, in milliseconds.
+#[cfg(feature = "gif")]
+const REVOKE_DELAY_MS: i32 = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct RecordingConfig {
+    /// The number of frames to record.
+    pub num_frames: usize,
+
+    /// The virtual framerate of the recording.
+    pub fps: u32,
+}
+
+impl Default for RecordingConfig {
+    fn default() -> Self {
+        Self {
+            num_frames: 60,
+            fps: 30,
+        }
+    }
+}
+
+/// Records a fixed number of consecutive frames in memory.
+pub struct FrameRecorder {
+    config: RecordingConfig,
+    recording: bool,
+    size: Option<Vector2<u32>>,
+    frames: Vec<Vec<u8>>,
+}
+
+impl FrameRecorder {
+    pub fn new(config: RecordingConfig) -> Self {
+        assert!(config.fps > 0);
+
+        Self {
+            config,
+            recording: false,
+            size: None,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &RecordingConfig {
+        &self.config
+    }
+
+    /// Starts a new recording, discarding the frames of the previous one.
+    pub fn start(&mut self) {
+        self.recording = true;
+        self.size = None;
+        self.frames.clear();
+    }
+
+    /// Stops the recording early, keeping the frames recorded so far.
+    pub fn stop(&mut self) {
+        self.recording = false;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Returns true once a recording has been completed or stopped.
+    pub fn is_finished(&self) -> bool {
+        !self.recording && !self.frames.is_empty()
+    }
+
+    pub fn num_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Returns the size of the recorded frames in pixels.
+    pub fn frame_size(&self) -> Option<Vector2<u32>> {
+        self.size
+    }
+
+    /// Returns the time by which the game should advance in each frame: the
+    /// recording's frame interval while recording, and `wall_dt` otherwise.
+    pub fn dt(&self, wall_dt: Duration) -> Duration {
+        if self.recording {
+            Duration::from_secs_f64(1.0 / self.config.fps as f64)
+        } else {
+            wall_dt
+        }
+    }
+
+    /// Captures what has been drawn onto the canvas in this frame. Needs to
+    /// be called after drawing, and before the frame ends, since the browser
+    /// clears the canvas once it has been presented.
+    pub fn capture_screen(&mut self, ctx: &Canvas) -> Result<(), Error> {
+        if !self.recording {
+            return Ok(());
+        }
+
+        let size = ctx.screen_geom().size;
+        self.read_frame(ctx, size)
+    }
+
+    /// Captures the contents of `target`.
+    pub fn capture_target(&mut self, ctx: &Canvas, target: &RenderTarget) -> Result<(), Error> {
+        if !self.recording {
+            return Ok(());
+        }
+
+        let _target = TargetScope::new(ctx, target);
+        self.read_frame(ctx, target.size())
+    }
+
+    fn read_frame(&mut self, ctx: &Canvas, size: Vector2<u32>) -> Result<(), Error> {
+        if *self.size.get_or_insert(size) != size {
+            self.recording = false;
+            return Err(Error::Recording(format!(
+                "frame size changed from {}x{} to {}x{} while recording",
+                self.size.unwrap().x,
+                self.size.unwrap().y,
+                size.x,
+                size.y,
+            )));
+        }
+
+        let data = read_pixels(ctx, size)?;
+        self.frames.push(flip_rows(&data, size.x));
+
+        if self.frames.len() >= self.config.num_frames {
+            self.recording = false;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the recorded frames as RGBA pixels, with the rows from top to
+    /// bottom.
+    pub fn frames(&self) -> impl Iterator<Item = &[u8]> {
+        self.frames.iter().map(|frame| frame.as_slice())
+    }
+
+    /// Encodes the recorded frames as an endlessly looping GIF.
+    ///
+    /// GIFs are limited to 256 colors per frame, so each frame is quantized.
+    /// `speed` ranges from 1 (best quality) to 30 (fastest); other values
+    /// are an error. Encoding takes a while for larger recordings, so it is
+    /// best done after the recording has finished rather than in between
+    /// frames.
+    #[cfg(feature = "gif")]
+    pub fn encode_gif(&self, speed: i32) -> Result<Vec<u8>, Error> {
+        if !(1..=30).contains(&speed) {
+            return Err(Error::Recording(format!(
+                "GIF encoding speed must be in 1..=30, got {}",
+                speed
+            )));
+        }
+
+        let size = match self.size {
+            Some(size) => size,
+            None => return Err(Error::Recording("no frames have been recorded".into())),
+        };
+        if size.x > u16::MAX as u32 || size.y > u16::MAX as u32 {
+            return Err(Error::Recording("frames are too large for a GIF".into()));
+        }
+
+        let gif_error = |e: gif::EncodingError| Error::Recording(e.to_string());
+
+        let mut data = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut data, size.x as u16, size.y as u16, &[])
+                .map_err(gif_error)?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(gif_error)?;
+
+            // GIF delays are given in hundredths of a second.
+            let delay = (100.0 / self.config.fps as f32).round().max(1.0) as u16;
+
+            for pixels in &self.frames {
+                let mut pixels = pixels.clone();
+                let mut frame =
+                    gif::Frame::from_rgba_speed(size.x as u16, size.y as u16, &mut pixels, speed);
+                frame.delay = delay;
+                encoder.write_frame(&frame).map_err(gif_error)?;
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Encodes the recorded frames as a GIF and saves it as `name` through
+    /// the browser's download dialog.
+    #[cfg(feature = "gif")]
+    pub fn download_gif(&self, name: &str, speed: i32) -> Result<(), Error> {
+        let data = self.encode_gif(speed)?;
+
+        let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(&data[..]));
+        let mut options = web_sys::BlobPropertyBag::new();
+        options.type_("image/gif");
+        let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)
+            .map_err(dom_error)?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(dom_error)?;
+
+        download_url(&url, name)?;
+
+        // Some browsers start the download asynchronously, so revoking the
+        // URL right away could cancel it.
+        let window = web_sys::window().ok_or(Error::NoWindow)?;
+        let revoke = Closure::once_into_js(move || {
+            if let Err(e) = web_sys::Url::revoke_object_url(&url) {
+                log::warn!("Failed to revoke GIF download URL: {:?}", e);
+            }
+        });
+        window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                revoke.unchecked_ref(),
+                REVOKE_DELAY_MS,
+            )
+            .map(|_| ())
+            .map_err(dom_error)
+    }
+}
@@ -0,0 +1,453 @@
+//! A small render graph for organizing passes that render into intermediate
+//! targets, such as lighting and post-processing.
+//!
+//! Every frame, the passes are declared together with the targets that they
+//! read and the target that they write. The graph then runs the passes in
+//! dependency order, skips passes whose output is never used, clears each
+//! target before it is first written, and allocates the transient targets
+//! from a pool that is kept across frames. Once the last reader of a target
+//! has run, its surface goes back to the pool, so that later passes in the
+//! same frame can reuse it.
+
+use golem::TextureFilter;
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::{RenderTarget, TargetScope},
+    Canvas, Color4, Error,
+};
+
+/// Refers to a transient target that has been declared in a `FrameGraph`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TargetId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetSize {
+    /// The size of the canvas in physical pixels.
+    Screen,
+
+    /// The size of the canvas in physical pixels, multiplied by a factor,
+    /// e.g. for rendering bloom at a lower resolution.
+    ScreenScaled(f32),
+
+    Fixed(Vector2<u32>),
+}
+
+impl TargetSize {
+    fn resolve(self, canvas: &Canvas) -> Vector2<u32> {
+        let screen_size = canvas.screen_geom().size;
+
+        let size = match self {
+            TargetSize::Screen => screen_size,
+            TargetSize::ScreenScaled(factor) => Vector2::new(
+                (screen_size.x as f32 * factor).round() as u32,
+                (screen_size.y as f32 * factor).round() as u32,
+            ),
+            TargetSize::Fixed(size) => size,
+        };
+
+        Vector2::new(size.x.max(1), size.y.max(1))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TargetDesc {
+    pub size: TargetSize,
+    pub filter: TextureFilter,
+
+    /// The color that the target is cleared to before it is first written in
+    /// a frame.
+    pub clear_color: Color4,
+}
+
+impl Default for TargetDesc {
+    fn default() -> Self {
+        Self {
+            size: TargetSize::Screen,
+            filter: TextureFilter::Linear,
+            clear_color: Color4::new(0.0, 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassOutput {
+    Canvas,
+    Target(TargetId),
+}
+
+/// Gives a pass access to the canvas and to the textures of its inputs.
+pub struct PassContext<'a> {
+    canvas: &'a Canvas,
+    inputs: Vec<&'a Texture>,
+    output_size: Vector2<u32>,
+}
+
+impl<'a> PassContext<'a> {
+    pub fn canvas(&self) -> &'a Canvas {
+        self.canvas
+    }
+
+    /// Returns the texture of the `index`-th input, in the order in which the
+    /// inputs were given to `FrameGraph::add_pass`.
+    pub fn input(&self, index: usize) -> &'a Texture {
+        self.inputs[index]
+    }
+
+    pub fn num_inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Returns the size of the output in physical pixels. The viewport has
+    /// already been set to cover all of it.
+    pub fn output_size(&self) -> Vector2<u32> {
+        self.output_size
+    }
+}
+
+type PassFn<'a> = Box<dyn FnOnce(&PassContext) -> Result<(), Error> + 'a>;
+
+struct PassNode<'a> {
+    name: String,
+    inputs: Vec<TargetId>,
+    output: PassOutput,
+    run: PassFn<'a>,
+}
+
+struct PooledSurface {
+    size: Vector2<u32>,
+    filter: TextureFilter,
+    target: RenderTarget,
+    in_use: bool,
+    last_used_frame: u64,
+}
+
+/// Keeps the surfaces of transient targets alive between frames.
+///
+/// Surfaces that have not been used in a frame are freed at the end of it,
+/// so that e.g. resizing the canvas does not leave old surfaces behind.
+#[derive(Default)]
+pub struct RenderGraph {
+    pool: Vec<PooledSurface>,
+    frame: u64,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts declaring the passes of a frame.
+    pub fn frame(&mut self) -> FrameGraph {
+        self.frame += 1;
+
+        FrameGraph {
+            graph: self,
+            targets: Vec::new(),
+            passes: Vec::new(),
+            canvas_clear_color: None,
+        }
+    }
+
+    /// Returns the number of surfaces that are currently kept in the pool.
+    pub fn num_pooled_surfaces(&self) -> usize {
+        self.pool.len()
+    }
+
+    fn acquire(
+        &mut self,
+        canvas: &Canvas,
+        size: Vector2<u32>,
+        filter: TextureFilter,
+    ) -> Result<usize, Error> {
+        let frame = self.frame;
+
+        if let Some(index) = self
+            .pool
+            .iter()
+            .position(|entry| !entry.in_use && entry.size == size && entry.filter == filter)
+        {
+            let entry = &mut self.pool[index];
+            entry.in_use = true;
+            entry.last_used_frame = frame;
+
+            return Ok(index);
+        }
+
+        log::info!("Creating render graph target of size {:?}", size);
+
+        self.pool.push(PooledSurface {
+            size,
+            filter,
+            target: RenderTarget::with_filters(
+                canvas,
+                size,
+                filter,
+                filter,
+                "render graph target",
+            )?,
+            in_use: true,
+            last_used_frame: frame,
+        });
+
+        Ok(self.pool.len() - 1)
+    }
+}
+
+/// The passes and targets of a single frame.
+pub struct FrameGraph<'a> {
+    graph: &'a mut RenderGraph,
+    targets: Vec<TargetDesc>,
+    passes: Vec<PassNode<'a>>,
+    canvas_clear_color: Option<Color4>,
+}
+
+impl<'a> FrameGraph<'a> {
+    /// Declares a transient target. Its surface is only allocated if a pass
+    /// that writes to it actually runs.
+    pub fn create_target(&mut self, desc: TargetDesc) -> TargetId {
+        self.targets.push(desc);
+
+        TargetId(self.targets.len() - 1)
+    }
+
+    /// Clears the canvas before the first pass that writes to it. By default,
+    /// the canvas is not cleared.
+    pub fn set_canvas_clear_color(&mut self, color: Option<Color4>) {
+        self.canvas_clear_color = color;
+    }
+
+    /// Declares a pass that reads from `inputs` and writes to `output`.
+    ///
+    /// Passes may be declared in any order. Passes that write to the same
+    /// target run in the order in which they are declared, after which the
+    /// passes that read from the target run.
+    pub fn add_pass(
+        &mut self,
+        name: &str,
+        inputs: &[TargetId],
+        output: PassOutput,
+        run: impl FnOnce(&PassContext) -> Result<(), Error> + 'a,
+    ) {
+        self.passes.push(PassNode {
+            name: name.to_string(),
+            inputs: inputs.to_vec(),
+            output,
+            run: Box::new(run),
+        });
+    }
+
+    /// Schedules and runs the passes.
+    pub fn execute(self, canvas: &Canvas) -> Result<(), Error> {
+        let FrameGraph {
+            graph,
+            targets,
+            passes,
+            canvas_clear_color,
+        } = self;
+
+        let order = schedule(&targets, &passes)?;
+
+        // The position in `order` at which each target is used for the last
+        // time, so that its surface can be returned to the pool afterwards.
+        let mut last_use = vec![None; targets.len()];
+        for (position, &pass_index) in order.iter().enumerate() {
+            let pass = &passes[pass_index];
+            for &TargetId(target) in pass.inputs.iter() {
+                last_use[target] = Some(position);
+            }
+            if let PassOutput::Target(TargetId(target)) = pass.output {
+                last_use[target] = Some(position);
+            }
+        }
+
+        let mut assigned: Vec<Option<usize>> = vec![None; targets.len()];
+        let mut canvas_cleared = false;
+        let mut passes: Vec<Option<PassNode>> = passes.into_iter().map(Some).collect();
+
+        for (position, &pass_index) in order.iter().enumerate() {
+            let pass = passes[pass_index].take().unwrap();
+
+            // Allocate the output before borrowing the pool for the pass.
+            let mut first_write = false;
+            if let PassOutput::Target(TargetId(target)) = pass.output {
+                if assigned[target].is_none() {
+                    let size = targets[target].size.resolve(canvas);
+                    assigned[target] = Some(graph.acquire(canvas, size, targets[target].filter)?);
+                    first_write = true;
+                }
+            }
+
+            {
+                let pool = &graph.pool;
+                let inputs = pass
+                    .inputs
+                    .iter()
+                    .map(|&TargetId(target)| {
+                        // Safety: a pass never reads from its own output, which
+                        // `schedule` checks, so input surfaces are not bound.
+                        unsafe {
+                            pool[assigned[target].unwrap()]
+                                .target
+                                .surface()
+                                .borrow_texture()
+                                .expect("render graph surface has no texture")
+                        }
+                    })
+                    .collect();
+
+                let (_target_scope, output_size) = match pass.output {
+                    PassOutput::Canvas => {
+                        let output_size = canvas.screen_geom().size;
+                        canvas.set_viewport(Point2::origin(), output_size);
+
+                        if let (Some(color), false) = (canvas_clear_color, canvas_cleared) {
+                            canvas.clear(color);
+                        }
+                        canvas_cleared = true;
+
+                        (None, output_size)
+                    }
+                    PassOutput::Target(TargetId(target)) => {
+                        let entry = &pool[assigned[target].unwrap()];
+                        let scope = TargetScope::new(canvas, &entry.target);
+
+                        if first_write {
+                            canvas.clear(targets[target].clear_color);
+                        }
+
+                        (Some(scope), entry.size)
+                    }
+                };
+
+                let context = PassContext {
+                    canvas,
+                    inputs,
+                    output_size,
+                };
+
+                (pass.run)(&context).map_err(|e| {
+                    log::warn!("Render graph pass `{}` failed: {}", pass.name, e);
+                    e
+                })?;
+            }
+
+            for (target, last_use) in last_use.iter().enumerate() {
+                if *last_use == Some(position) {
+                    if let Some(index) = assigned[target] {
+                        graph.pool[index].in_use = false;
+                    }
+                }
+            }
+        }
+
+        let frame = graph.frame;
+        graph.pool.retain(|entry| entry.last_used_frame == frame);
+        for entry in graph.pool.iter_mut() {
+            entry.in_use = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the indices of the passes that need to run, in an order that
+/// respects their dependencies.
+fn schedule(targets: &[TargetDesc], passes: &[PassNode]) -> Result<Vec<usize>, Error> {
+    let mut writers = vec![Vec::new(); targets.len()];
+    for (index, pass) in passes.iter().enumerate() {
+        for &TargetId(target) in pass.inputs.iter() {
+            if pass.output == PassOutput::Target(TargetId(target)) {
+                return Err(Error::RenderGraph(format!(
+                    "pass `{}` reads from its own output",
+                    pass.name
+                )));
+            }
+        }
+        if let PassOutput::Target(TargetId(target)) = pass.output {
+            writers[target].push(index);
+        }
+    }
+
+    // Edges go from each pass to the passes it depends on.
+    let mut dependencies = vec![Vec::new(); passes.len()];
+    for (index, pass) in passes.iter().enumerate() {
+        for &TargetId(target) in pass.inputs.iter() {
+            if writers[target].is_empty() {
+                return Err(Error::RenderGraph(format!(
+                    "pass `{}` reads from a target that no pass writes to",
+                    pass.name
+                )));
+            }
+            dependencies[index].extend(writers[target].iter().copied());
+        }
+        if let PassOutput::Target(TargetId(target)) = pass.output {
+            dependencies[index].extend(
+                writers[target]
+                    .iter()
+                    .copied()
+                    .take_while(|&writer| writer != index),
+            );
+        }
+    }
+
+    // Only passes that contribute to the canvas need to run.
+    let mut live = vec![false; passes.len()];
+    let mut stack: Vec<usize> = passes
+        .iter()
+        .enumerate()
+        .filter(|(_, pass)| pass.output == PassOutput::Canvas)
+        .map(|(index, _)| index)
+        .collect();
+    while let Some(index) = stack.pop() {
+        if !live[index] {
+            live[index] = true;
+            stack.extend(dependencies[index].iter().copied());
+        }
+    }
+
+    // Depth-first topological sort, visiting passes in the order in which
+    // they were declared, so that independent passes keep their order.
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        None,
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        index: usize,
+        passes: &[PassNode],
+        dependencies: &[Vec<usize>],
+        marks: &mut [Mark],
+        order: &mut Vec<usize>,
+    ) -> Result<(), Error> {
+        match marks[index] {
+            Mark::Done => return Ok(()),
+            Mark::Visiting => {
+                return Err(Error::RenderGraph(format!(
+                    "pass `{}` depends on itself",
+                    passes[index].name
+                )))
+            }
+            Mark::None => (),
+        }
+
+        marks[index] = Mark::Visiting;
+        for &dependency in dependencies[index].iter() {
+            visit(dependency, passes, dependencies, marks, order)?;
+        }
+        marks[index] = Mark::Done;
+        order.push(index);
+
+        Ok(())
+    }
+
+    let mut marks = vec![Mark::None; passes.len()];
+    let mut order = Vec::new();
+    for (index, _) in live.iter().enumerate().filter(|(_, live)| **live) {
+        visit(index, passes, &dependencies, &mut marks, &mut order)?;
+    }
+
+    Ok(order)
+}
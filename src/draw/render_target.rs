@@ -0,0 +1,87 @@
+//! Textures that can be rendered into.
+
+use golem::{ColorFormat, Surface, Texture, TextureFilter, TextureWrap};
+use nalgebra::Vector2;
+
+use crate::{Canvas, Error, GpuAllocation, ScreenGeom};
+
+/// A texture-backed framebuffer.
+///
+/// Render into the target with `Canvas::with_target` or a `TargetScope`, and
+/// then draw its texture like any other, e.g. for post-processing or for
+/// showing a scene within the user interface.
+pub struct RenderTarget {
+    surface: Surface,
+    size: Vector2<u32>,
+    _allocation: GpuAllocation,
+}
+
+impl RenderTarget {
+    /// Creates a target with an RGBA texture of `size` pixels, which is
+    /// clamped and linearly filtered.
+    pub fn new(ctx: &Canvas, size: Vector2<u32>) -> Result<Self, Error> {
+        Self::with_filters(
+            ctx,
+            size,
+            TextureFilter::Linear,
+            TextureFilter::Linear,
+            "render target",
+        )
+    }
+
+    /// Creates a target whose texture is clamped and filtered with the
+    /// given filters, and which is listed under `label` in the GPU memory
+    /// books.
+    pub(crate) fn with_filters(
+        ctx: &Canvas,
+        size: Vector2<u32>,
+        magnification: TextureFilter,
+        minification: TextureFilter,
+        label: impl Into<String>,
+    ) -> Result<Self, Error> {
+        let mut texture = Texture::new(ctx.golem_ctx())?;
+        texture.set_image(None, size.x, size.y, ColorFormat::RGBA);
+        texture.set_magnification(magnification)?;
+        texture.set_minification(minification)?;
+        texture.set_wrap_h(TextureWrap::ClampToEdge)?;
+        texture.set_wrap_v(TextureWrap::ClampToEdge)?;
+
+        Ok(Self {
+            surface: Surface::new(ctx.golem_ctx(), texture)?,
+            size,
+            _allocation: GpuAllocation::texture(label, size.x, size.y),
+        })
+    }
+
+    pub fn size(&self) -> Vector2<u32> {
+        self.size
+    }
+
+    /// Returns the geometry of the target, for setting up projections when
+    /// rendering into it, e.g. with `ScreenGeom::orthographic_projection`.
+    pub fn screen_geom(&self) -> ScreenGeom {
+        ScreenGeom {
+            size: self.size,
+            device_pixel_ratio: 1.0,
+        }
+    }
+
+    pub(crate) fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    /// Calls `f` with the texture of the target, e.g. for drawing it.
+    ///
+    /// Must not be called while the target is bound, i.e. from within
+    /// `Canvas::with_target` or a `TargetScope` for the same target, since
+    /// WebGL does not allow sampling from a texture while rendering into it.
+    pub fn with_texture<R>(&self, f: impl FnOnce(&Texture) -> R) -> R {
+        // Safety: the surface is only bound within `Canvas::with_target` and
+        // target scopes, from within which the texture must not be used; see
+        // above.
+        let texture =
+            unsafe { self.surface.borrow_texture() }.expect("render target has no texture");
+
+        f(texture)
+    }
+}
@@ -0,0 +1,227 @@
+//! Guards that set render state on creation and restore it when dropped.
+//!
+//! Setting and resetting state manually is easy to get wrong on error paths:
+//! if a draw call in between fails and we return early with `?`, the state
+//! leaks into whatever is drawn next. The guards here reset state in `Drop`,
+//! so this cannot happen.
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use golem::{
+    blend::{BlendEquation, BlendFactor, BlendFunction, BlendMode, BlendOperation},
+    Surface,
+};
+use js_sys::Int32Array;
+use wasm_bindgen::JsCast;
+use web_sys::{WebGlFramebuffer, WebGlRenderingContext as Gl};
+
+use crate::{draw::RenderTarget, AaRect, Canvas};
+
+/// Common ways of blending colors with what has already been drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendPreset {
+    /// Blending is disabled.
+    Opaque,
+
+    /// Standard alpha blending.
+    Alpha,
+
+    /// Alpha blending for colors that have already been multiplied by their
+    /// alpha, e.g. the output of render targets.
+    PremultipliedAlpha,
+
+    /// Colors are added to what is already there, e.g. for lights and
+    /// particles.
+    Additive,
+
+    /// Colors are multiplied with what is already there, e.g. for darkening
+    /// a scene with a lightmap.
+    Multiply,
+}
+
+impl BlendPreset {
+    pub(crate) fn blend_mode(self) -> Option<BlendMode> {
+        let function = |source, destination| BlendMode {
+            equation: BlendEquation::Same(BlendOperation::Add),
+            function: BlendFunction::Same {
+                source,
+                destination,
+            },
+            ..Default::default()
+        };
+
+        match self {
+            BlendPreset::Opaque => None,
+            BlendPreset::Alpha => Some(BlendMode::default()),
+            BlendPreset::PremultipliedAlpha => {
+                Some(function(BlendFactor::One, BlendFactor::OneMinusSrcAlpha))
+            }
+            BlendPreset::Additive => Some(function(BlendFactor::One, BlendFactor::One)),
+            BlendPreset::Multiply => Some(function(BlendFactor::DstColor, BlendFactor::Zero)),
+        }
+    }
+}
+
+/// Enables blending until dropped, after which blending is disabled again.
+///
+/// Since `golem` does not let us query the current blend mode, nesting blend
+/// scopes does not restore the outer mode.
+#[must_use]
+pub struct BlendScope<'a> {
+    ctx: &'a golem::Context,
+}
+
+impl<'a> BlendScope<'a> {
+    /// Enables blending with a mode of `golem`, for the modes that no
+    /// preset covers.
+    pub(crate) fn new(ctx: &'a golem::Context, mode: BlendMode) -> Self {
+        ctx.set_blend_mode(Some(mode));
+
+        Self { ctx }
+    }
+
+    /// Blends with `preset` until dropped. `BlendPreset::Opaque` disables
+    /// blending.
+    pub fn preset(ctx: &'a Canvas, preset: BlendPreset) -> Self {
+        ctx.golem_ctx().set_blend_mode(preset.blend_mode());
+
+        Self {
+            ctx: ctx.golem_ctx(),
+        }
+    }
+}
+
+impl<'a> Drop for BlendScope<'a> {
+    fn drop(&mut self) {
+        self.ctx.set_blend_mode(None);
+    }
+}
+
+/// Restricts drawing to a rect until dropped; see `Canvas::push_scissor`.
+///
+/// The scope gives access to the canvas, so that drawing can continue while
+/// the scope is alive. Scissor scopes can be nested.
+#[must_use]
+pub struct ScissorScope<'a> {
+    canvas: &'a mut Canvas,
+}
+
+impl<'a> ScissorScope<'a> {
+    /// Restricts drawing to `rect`, given in logical pixels.
+    pub fn new(canvas: &'a mut Canvas, rect: &AaRect) -> Self {
+        canvas.push_scissor(rect);
+
+        Self { canvas }
+    }
+}
+
+impl<'a> Deref for ScissorScope<'a> {
+    type Target = Canvas;
+
+    fn deref(&self) -> &Canvas {
+        self.canvas
+    }
+}
+
+impl<'a> DerefMut for ScissorScope<'a> {
+    fn deref_mut(&mut self) -> &mut Canvas {
+        self.canvas
+    }
+}
+
+impl<'a> Drop for ScissorScope<'a> {
+    fn drop(&mut self) {
+        self.canvas.pop_scissor();
+    }
+}
+
+/// The framebuffer and viewport that are bound at some point, so that they
+/// can be restored after rendering into another target.
+pub(crate) struct SavedTarget {
+    gl: Gl,
+    framebuffer: Option<WebGlFramebuffer>,
+    viewport: [i32; 4],
+}
+
+impl SavedTarget {
+    pub fn save(canvas: &Canvas) -> Self {
+        let gl = canvas.webgl_ctx().clone();
+        let framebuffer = gl
+            .get_parameter(Gl::FRAMEBUFFER_BINDING)
+            .ok()
+            .and_then(|framebuffer| framebuffer.dyn_into().ok());
+
+        // Fall back to the whole canvas if the viewport cannot be queried.
+        let size = canvas.screen_geom().size;
+        let mut viewport = [0, 0, size.x as i32, size.y as i32];
+        if let Some(array) = gl
+            .get_parameter(Gl::VIEWPORT)
+            .ok()
+            .and_then(|array| array.dyn_into::<Int32Array>().ok())
+            .filter(|array| array.length() == 4)
+        {
+            array.copy_to(&mut viewport);
+        }
+
+        Self {
+            gl,
+            framebuffer,
+            viewport,
+        }
+    }
+
+    pub fn restore(&self) {
+        let [x, y, width, height] = self.viewport;
+
+        self.gl
+            .bind_framebuffer(Gl::FRAMEBUFFER, self.framebuffer.as_ref());
+        self.gl.viewport(x, y, width, height);
+    }
+}
+
+/// Renders into a target until dropped, after which the previously bound
+/// target and viewport are restored, so that scopes can be nested.
+#[must_use]
+pub struct TargetScope<'a> {
+    outer: SavedTarget,
+    _surface: PhantomData<&'a Surface>,
+}
+
+impl<'a> TargetScope<'a> {
+    /// Binds `target` and sets the viewport to cover all of it.
+    pub fn new(canvas: &Canvas, target: &'a RenderTarget) -> Self {
+        Self::with_surface(canvas, target.surface())
+    }
+
+    /// Binds `surface` and sets the viewport to cover all of it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the surface has no texture attached.
+    pub(crate) fn with_surface(canvas: &Canvas, surface: &'a Surface) -> Self {
+        let width = surface
+            .width()
+            .expect("TargetScope: surface has no texture");
+        let height = surface
+            .height()
+            .expect("TargetScope: surface has no texture");
+
+        let outer = SavedTarget::save(canvas);
+        surface.bind();
+        canvas.golem_ctx().set_viewport(0, 0, width, height);
+
+        Self {
+            outer,
+            _surface: PhantomData,
+        }
+    }
+}
+
+impl<'a> Drop for TargetScope<'a> {
+    fn drop(&mut self) {
+        self.outer.restore();
+    }
+}
@@ -0,0 +1,152 @@
+//! Caching of compiled shader programs.
+//!
+//! Passes that come in multiple variants (e.g. with lighting on or off) can
+//! request their program from the cache with a set of `#define`s, so that
+//! each variant is compiled and linked only once per `Canvas`, no matter how
+//! many passes use it.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use golem::{GolemError, ShaderDescription, ShaderProgram, UniformValue};
+
+use crate::Error;
+
+/// A shader program that may be shared between multiple passes.
+pub type SharedShader = Rc<RefCell<ShaderProgram>>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ShaderKey {
+    /// Distinguishes programs with the same sources but different inputs.
+    name: String,
+    vertex_shader: String,
+    fragment_shader: String,
+    defines: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+pub struct ShaderCache {
+    programs: HashMap<ShaderKey, SharedShader>,
+    num_compiled: usize,
+}
+
+impl ShaderCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the program for the given description and defines, compiling
+    /// it if it is not in the cache yet.
+    ///
+    /// The cache key consists of `name`, the shader sources and the defines.
+    /// The attributes and uniforms of the description are not part of the
+    /// key, so `name` must be unique for each combination of those, e.g. by
+    /// using the name of the pass.
+    ///
+    /// Each define `(name, value)` is prepended as `#define name value` to
+    /// both the vertex and the fragment shader.
+    pub fn get_or_compile(
+        &mut self,
+        ctx: &golem::Context,
+        name: &str,
+        desc: ShaderDescription,
+        defines: &[(&str, &str)],
+    ) -> Result<SharedShader, Error> {
+        let key = ShaderKey {
+            name: name.to_string(),
+            vertex_shader: desc.vertex_shader.to_string(),
+            fragment_shader: desc.fragment_shader.to_string(),
+            defines: defines
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        };
+
+        if let Some(program) = self.programs.get(&key) {
+            return Ok(program.clone());
+        }
+
+        let prelude: String = defines
+            .iter()
+            .map(|(name, value)| format!("#define {} {}\n", name, value))
+            .collect();
+        let vertex_shader = format!("{}{}", prelude, desc.vertex_shader);
+        let fragment_shader = format!("{}{}", prelude, desc.fragment_shader);
+
+        let program = ShaderProgram::new(
+            ctx,
+            ShaderDescription {
+                vertex_input: desc.vertex_input,
+                fragment_input: desc.fragment_input,
+                uniforms: desc.uniforms,
+                vertex_shader: &vertex_shader,
+                fragment_shader: &fragment_shader,
+            },
+        )?;
+        let program = Rc::new(RefCell::new(program));
+
+        log::debug!(
+            "Compiled shader program `{}` with defines {:?}",
+            name,
+            defines
+        );
+
+        self.num_compiled += 1;
+        self.programs.insert(key, program.clone());
+
+        Ok(program)
+    }
+
+    /// Returns the number of programs in the cache.
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    /// Returns the number of programs that have been compiled by the cache,
+    /// including ones that have been removed since.
+    pub fn num_compiled(&self) -> usize {
+        self.num_compiled
+    }
+
+    /// Removes all programs from the cache. Programs that are still in use by
+    /// passes stay alive until the passes are dropped.
+    pub fn clear(&mut self) {
+        self.programs.clear();
+    }
+}
+
+/// Sets a uniform, ignoring the error if the shader compiler has removed it
+/// because it is unused.
+pub(crate) fn set_uniform_if_used(
+    shader: &mut ShaderProgram,
+    name: &str,
+    value: UniformValue,
+) -> Result<(), Error> {
+    ignore_unused_uniform(shader.set_uniform(name, value))
+}
+
+fn ignore_unused_uniform(result: Result<(), GolemError>) -> Result<(), Error> {
+    match result {
+        Err(GolemError::NoSuchUniform(_)) => Ok(()),
+        result => result.map_err(Error::from),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unused_uniforms_are_ignored() {
+        // This is the error that golem returns for uniforms that have been
+        // optimized out, e.g. a `mat_projection_view` that a screen-space
+        // shader ignores.
+        let unused = Err(GolemError::NoSuchUniform("mat_projection_view".into()));
+
+        assert!(ignore_unused_uniform(unused).is_ok());
+        assert!(ignore_unused_uniform(Ok(())).is_ok());
+    }
+}
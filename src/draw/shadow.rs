@@ -5,16 +5,19 @@
 
 use golem::{
     blend::{BlendEquation, BlendFactor, BlendFunction, BlendMode, BlendOperation},
-    Attribute, AttributeType, ColorFormat, Dimension, GeometryMode, GolemError, NumberType,
-    ShaderDescription, ShaderProgram, Surface, Texture, TextureFilter, TextureWrap, Uniform,
-    UniformType, UniformValue,
+    Attribute, AttributeType, ColorFormat, Dimension, GolemError, NumberType, ShaderDescription,
+    ShaderProgram, Surface, Texture, TextureFilter, TextureWrap, Uniform, UniformType,
+    UniformValue,
 };
 use nalgebra::{Matrix3, Point2, Vector2, Vector3};
 
 use crate::{
-    draw::{Batch, ColVertex, DrawUnit, Geometry, Quad, TriBatch, Vertex},
-    geom::matrix3_to_flat_array,
-    Canvas, Color3, Error,
+    draw::{
+        Batch, BlendScope, ColVertex, DrawUnit, Geometry, GeometryMode, Quad, RenderTarget,
+        SavedTarget, TargetScope, TriBatch, Vertex, DITHER_GLSL,
+    },
+    geom::{matrix3_to_flat_array, rect_to_target},
+    AaRect, Canvas, Color3, Error, GpuAllocation, YAxis,
 };
 
 pub struct LineSegment {
@@ -172,8 +175,10 @@ pub struct ShadowMap {
 
     shadow_map: Surface,
     shadow_map_shader: ShaderProgram,
+    _shadow_map_allocation: GpuAllocation,
 
     light_surface: Surface,
+    light_surface_allocation: GpuAllocation,
     light_surface_shader: ShaderProgram,
     light_area_batch: TriBatch<LightAreaVertex>,
 }
@@ -220,6 +225,14 @@ impl ShadowMap {
         Ok(Surface::new(canvas.golem_ctx(), light_texture)?)
     }
 
+    fn light_surface_allocation(canvas: &Canvas) -> GpuAllocation {
+        GpuAllocation::texture(
+            "shadow light surface",
+            canvas.screen_geom().size.x,
+            canvas.screen_geom().size.y,
+        )
+    }
+
     pub fn new(canvas: &Canvas, resolution: usize, max_num_lights: usize) -> Result<Self, Error> {
         let shadow_map = Self::new_shadow_map(canvas, resolution, max_num_lights)?;
         let light_surface = Self::new_light_surface(canvas)?;
@@ -417,7 +430,13 @@ impl ShadowMap {
             max_num_lights,
             shadow_map,
             shadow_map_shader,
+            _shadow_map_allocation: GpuAllocation::texture(
+                "shadow map",
+                resolution as u32,
+                max_num_lights as u32,
+            ),
             light_surface,
+            light_surface_allocation: Self::light_surface_allocation(canvas),
             light_surface_shader,
             light_area_batch,
         })
@@ -450,18 +469,26 @@ impl ShadowMap {
         transform: &Matrix3<f32>,
         lights: &'a [Light],
     ) -> Result<BuildShadowMap<'a>, Error> {
+        crate::profile_scope!("shadow_map");
+
         if canvas.screen_geom().size.x != self.light_surface.width().unwrap()
             || canvas.screen_geom().size.y != self.light_surface.height().unwrap()
         {
             // Screen surface has been resized, so we also need to recreate
             // the light surface.
             self.light_surface = Self::new_light_surface(canvas)?;
+            self.light_surface_allocation = Self::light_surface_allocation(canvas);
         }
 
         // Already upload the light area data to the GPU. This will be used for
         // rendering the lights in screen space.
         self.fill_light_area_batch(lights);
 
+        // The shadow map is bound directly rather than through a
+        // `TargetScope`, since it stays bound while occluders are drawn.
+        // `BuildShadowMap` restores the outer target instead.
+        let outer = SavedTarget::save(canvas);
+
         // Clear the shadow map to maximal distance, i.e. 1.
         self.shadow_map.bind();
         canvas
@@ -475,12 +502,64 @@ impl ShadowMap {
             canvas,
             lights,
             transform: *transform,
+            outer,
         })
     }
 
     pub fn light_offset(&self, index: usize) -> f32 {
         (index as f32 + 0.5) / self.max_num_lights as f32
     }
+
+    /// Renders `lights`, shadowed by `occluders`, into `lightmap`.
+    ///
+    /// This only needs to be done once for lights and occluders that never
+    /// change, e.g. when a level is loaded. Afterwards, only the dynamic
+    /// lights need to be rendered every frame, and the lightmap is added to
+    /// them by `ShadowColPass::draw_with_lightmap`. As in `build`, there may
+    /// be at most `max_num_lights` lights.
+    pub fn bake(
+        &mut self,
+        canvas: &Canvas,
+        lights: &[Light],
+        occluders: &DrawUnit<LineSegment>,
+        lightmap: &Lightmap,
+    ) -> Result<(), Error> {
+        // The shader samples the lightmap with `v` growing with world Y, so
+        // it is rendered Y-down regardless of the camera.
+        let transform = rect_to_target(&lightmap.bounds, YAxis::Down);
+
+        self.build(canvas, &transform, lights)?
+            .draw_occluders(occluders)?
+            .render_lights(Some(lightmap.target.surface()))
+    }
+}
+
+/// Static lighting that has been baked into a texture covering a rect of the
+/// world. See `ShadowMap::bake`.
+pub struct Lightmap {
+    target: RenderTarget,
+    bounds: AaRect,
+}
+
+impl Lightmap {
+    /// Creates an empty lightmap that covers `bounds` in world space with a
+    /// texture of the given size.
+    pub fn new(canvas: &Canvas, bounds: AaRect, size: Vector2<u32>) -> Result<Self, Error> {
+        Ok(Self {
+            target: RenderTarget::with_filters(
+                canvas,
+                size,
+                TextureFilter::Linear,
+                TextureFilter::Linear,
+                "lightmap",
+            )?,
+            bounds,
+        })
+    }
+
+    pub fn bounds(&self) -> AaRect {
+        self.bounds
+    }
 }
 
 #[must_use]
@@ -489,10 +568,15 @@ pub struct BuildShadowMap<'a> {
     canvas: &'a Canvas,
     lights: &'a [Light],
     transform: Matrix3<f32>,
+
+    /// The target that was bound before the shadow map.
+    outer: SavedTarget,
 }
 
 impl<'a> BuildShadowMap<'a> {
     pub fn draw_occluders(self, draw_unit: &DrawUnit<LineSegment>) -> Result<Self, Error> {
+        crate::profile_scope!("shadow_map");
+
         assert!(draw_unit.geometry_mode() == GeometryMode::Lines);
         assert!(
             self.lights.len() <= self.this.max_num_lights,
@@ -501,14 +585,17 @@ impl<'a> BuildShadowMap<'a> {
             self.this.max_num_lights,
         );
 
-        self.canvas.golem_ctx().set_blend_mode(Some(BlendMode {
-            equation: BlendEquation::Same(BlendOperation::Min),
-            function: BlendFunction::Same {
-                source: BlendFactor::One,
-                destination: BlendFactor::One,
+        let _blend = BlendScope::new(
+            self.canvas.golem_ctx(),
+            BlendMode {
+                equation: BlendEquation::Same(BlendOperation::Min),
+                function: BlendFunction::Same {
+                    source: BlendFactor::One,
+                    destination: BlendFactor::One,
+                },
+                ..Default::default()
             },
-            ..Default::default()
-        }));
+        );
 
         // TODO: We should be able to batch the light draw calls
 
@@ -529,34 +616,40 @@ impl<'a> BuildShadowMap<'a> {
             draw_unit.draw(&self.this.shadow_map_shader)?;
         }
 
-        self.canvas.golem_ctx().set_blend_mode(None);
-
         Ok(self)
     }
 
     pub fn finish(self) -> Result<(), Error> {
+        self.render_lights(None)
+    }
+
+    /// Renders the lights into `target`, or into the light surface if no
+    /// target is given.
+    fn render_lights(self, target: Option<&Surface>) -> Result<(), Error> {
+        crate::profile_scope!("lights");
+
         let golem_ctx = self.canvas.golem_ctx();
 
-        //Surface::unbind(self.ctx.golem_ctx());
-        self.this.light_surface.bind();
+        // Go back to the outer target first, so that the scope below
+        // restores it rather than the shadow map.
+        self.outer.restore();
 
-        golem_ctx.set_viewport(
-            0,
-            0,
-            self.this.light_surface.width().unwrap(),
-            self.this.light_surface.height().unwrap(),
-        );
+        let target = target.unwrap_or(&self.this.light_surface);
+        let _target = TargetScope::with_surface(self.canvas, target);
         golem_ctx.set_clear_color(0.0, 0.0, 0.0, 1.0);
         golem_ctx.clear();
 
-        golem_ctx.set_blend_mode(Some(BlendMode {
-            equation: BlendEquation::Same(BlendOperation::Add),
-            function: BlendFunction::Same {
-                source: BlendFactor::One,
-                destination: BlendFactor::One,
+        let _blend = BlendScope::new(
+            golem_ctx,
+            BlendMode {
+                equation: BlendEquation::Same(BlendOperation::Add),
+                function: BlendFunction::Same {
+                    source: BlendFactor::One,
+                    destination: BlendFactor::One,
+                },
+                ..Default::default()
             },
-            ..Default::default()
-        }));
+        );
 
         unsafe {
             self.this
@@ -585,20 +678,40 @@ impl<'a> BuildShadowMap<'a> {
             .light_area_batch
             .draw(&self.this.light_surface_shader)?;
 
-        self.canvas.golem_ctx().set_blend_mode(None);
-
-        Surface::unbind(self.canvas.golem_ctx());
-
         Ok(())
     }
 }
 
+impl<'a> Drop for BuildShadowMap<'a> {
+    fn drop(&mut self) {
+        self.outer.restore();
+    }
+}
+
 pub struct ShadowColPass {
     shader: ShaderProgram,
+    dithering: bool,
 }
 
 impl ShadowColPass {
     pub fn new(canvas: &Canvas) -> Result<Self, Error> {
+        let fragment_shader = format!(
+            "{}{}",
+            DITHER_GLSL,
+            r#"
+            void main() {
+                vec2 lightmap_coords = (v_world_pos - lightmap_min) / lightmap_size;
+                vec3 light = texture(light_surface, v_tex_coords).rgb
+                    + lightmap_strength * texture(lightmap, lightmap_coords).rgb;
+                vec3 reflect = ambient_light + light * v_color.rgb;
+                gl_FragColor = vec4(
+                    pow(reflect, vec3(1.0/2.2)) + dither_strength * dither_offset(),
+                    v_color.a
+                );
+            }
+            "#
+        );
+
         let shader = ShaderProgram::new(
             canvas.golem_ctx(),
             ShaderDescription {
@@ -606,6 +719,7 @@ impl ShadowColPass {
                 fragment_input: &[
                     Attribute::new("v_tex_coords", AttributeType::Vector(Dimension::D2)),
                     Attribute::new("v_color", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("v_world_pos", AttributeType::Vector(Dimension::D2)),
                 ],
                 uniforms: &[
                     Uniform::new("mat_projection_view", UniformType::Matrix(Dimension::D3)),
@@ -614,6 +728,17 @@ impl ShadowColPass {
                         "ambient_light",
                         UniformType::Vector(NumberType::Float, Dimension::D3),
                     ),
+                    Uniform::new("dither_strength", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("lightmap", UniformType::Sampler2D),
+                    Uniform::new(
+                        "lightmap_min",
+                        UniformType::Vector(NumberType::Float, Dimension::D2),
+                    ),
+                    Uniform::new(
+                        "lightmap_size",
+                        UniformType::Vector(NumberType::Float, Dimension::D2),
+                    ),
+                    Uniform::new("lightmap_strength", UniformType::Scalar(NumberType::Float)),
                 ],
                 vertex_shader: r#"
                 void main() {
@@ -621,21 +746,22 @@ impl ShadowColPass {
                     gl_Position = vec4(p.xy, a_world_pos.z, 1.0);
                     v_color = a_color;
                     v_tex_coords = (gl_Position.xy + vec2(1.0, 1.0)) / 2.0;
+                    v_world_pos = a_world_pos.xy;
                 }
                 "#,
-                fragment_shader: r#"
-                void main() {
-                    vec3 light = texture(light_surface, v_tex_coords).rgb;
-                    vec3 reflect = ambient_light + light * v_color.rgb;
-                    gl_FragColor = vec4(
-                        pow(reflect, vec3(1.0/2.2)),
-                        v_color.a
-                    );
-                }
-                "#,
+                fragment_shader: &fragment_shader,
             },
         )?;
-        Ok(Self { shader })
+        Ok(Self {
+            shader,
+            dithering: false,
+        })
+    }
+
+    /// Enables ordered dithering of the lit colors, which hides banding in
+    /// the falloff of large lights. Disabled by default.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
     }
 
     pub fn draw(
@@ -644,6 +770,36 @@ impl ShadowColPass {
         ambient_light: Color3,
         shadow_map: &ShadowMap,
         draw_unit: &DrawUnit<ColVertex>,
+    ) -> Result<(), Error> {
+        self.draw_impl(transform, ambient_light, shadow_map, None, draw_unit)
+    }
+
+    /// Like `draw`, but adds the static lighting from `lightmap` to the
+    /// dynamic lights in `shadow_map`.
+    pub fn draw_with_lightmap(
+        &mut self,
+        transform: &Matrix3<f32>,
+        ambient_light: Color3,
+        shadow_map: &ShadowMap,
+        lightmap: &Lightmap,
+        draw_unit: &DrawUnit<ColVertex>,
+    ) -> Result<(), Error> {
+        self.draw_impl(
+            transform,
+            ambient_light,
+            shadow_map,
+            Some(lightmap),
+            draw_unit,
+        )
+    }
+
+    fn draw_impl(
+        &mut self,
+        transform: &Matrix3<f32>,
+        ambient_light: Color3,
+        shadow_map: &ShadowMap,
+        lightmap: Option<&Lightmap>,
+        draw_unit: &DrawUnit<ColVertex>,
     ) -> Result<(), Error> {
         unsafe {
             shadow_map
@@ -662,6 +818,39 @@ impl ShadowColPass {
             .set_uniform("ambient_light", UniformValue::Vector3(ambient_light.into()))?;
         self.shader
             .set_uniform("light_surface", UniformValue::Int(1))?;
+        self.shader.set_uniform(
+            "dither_strength",
+            UniformValue::Float(if self.dithering { 1.0 } else { 0.0 }),
+        )?;
+
+        if let Some(lightmap) = lightmap {
+            unsafe {
+                lightmap
+                    .target
+                    .surface()
+                    .borrow_texture()
+                    .unwrap()
+                    .set_active(std::num::NonZeroU32::new(2).unwrap());
+            }
+
+            let min = lightmap.bounds.min();
+            let size = lightmap.bounds.size;
+            self.shader.set_uniform("lightmap", UniformValue::Int(2))?;
+            self.shader
+                .set_uniform("lightmap_min", UniformValue::Vector2([min.x, min.y]))?;
+            self.shader
+                .set_uniform("lightmap_size", UniformValue::Vector2([size.x, size.y]))?;
+            self.shader
+                .set_uniform("lightmap_strength", UniformValue::Float(1.0))?;
+        } else {
+            // The sampler still needs a valid texture unit, and the size
+            // must not be zero, even though the sample is discarded.
+            self.shader.set_uniform("lightmap", UniformValue::Int(1))?;
+            self.shader
+                .set_uniform("lightmap_size", UniformValue::Vector2([1.0, 1.0]))?;
+            self.shader
+                .set_uniform("lightmap_strength", UniformValue::Float(0.0))?;
+        }
 
         draw_unit.draw(&self.shader)
 
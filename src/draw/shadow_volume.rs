@@ -0,0 +1,114 @@
+//! Geometry of hard shadows cast by polygonal occluders.
+//!
+//! For a light at a point, each edge of an occluder that faces the light
+//! casts a shadow volume: the quad between the edge and its projection away
+//! from the light. Drawing these quads on top of the scene darkens what is
+//! hidden from the light. This is cheaper than `ShadowMap` when only a
+//! single light or vision cone is needed, and the quads can also be used
+//! for other purposes, e.g. as a stencil mask.
+
+use nalgebra::Point2;
+
+use crate::{
+    draw::{ColVertex, Quad, TriBatch},
+    Color4,
+};
+
+/// Returns the shadow volume quads cast by the polygon `occluder` for a
+/// light at `light_pos`.
+///
+/// The polygon is given by its points in order, either clockwise or
+/// counterclockwise. Each shadow volume reaches `extent` units beyond the
+/// occluder, which should be at least the radius of the light. Polygons with
+/// less than two points cast no shadow; polygons with two points are treated
+/// as a line segment that casts shadow from both sides.
+pub fn shadow_volume_quads(
+    light_pos: Point2<f32>,
+    occluder: &[Point2<f32>],
+    extent: f32,
+) -> Vec<Quad> {
+    let mut quads = Vec::new();
+    push_shadow_volume_quads(light_pos, occluder, extent, &mut quads);
+
+    quads
+}
+
+fn push_shadow_volume_quads(
+    light_pos: Point2<f32>,
+    occluder: &[Point2<f32>],
+    extent: f32,
+    out: &mut Vec<Quad>,
+) {
+    if occluder.len() < 2 {
+        return;
+    }
+
+    // Twice the signed area, which tells us the winding of the polygon.
+    let winding: f32 = (0..occluder.len())
+        .map(|i| {
+            let a = occluder[i];
+            let b = occluder[(i + 1) % occluder.len()];
+            a.x * b.y - b.x * a.y
+        })
+        .sum();
+
+    let project = |p: Point2<f32>| {
+        let delta = p - light_pos;
+        let norm = delta.norm();
+
+        if norm > 0.0 {
+            Some(p + delta * (extent / norm))
+        } else {
+            None
+        }
+    };
+
+    let num_edges = if occluder.len() == 2 {
+        1
+    } else {
+        occluder.len()
+    };
+
+    for i in 0..num_edges {
+        let a = occluder[i];
+        let b = occluder[(i + 1) % occluder.len()];
+
+        // The edge faces the light if the light is on its outer side. For
+        // line segments, both sides count.
+        let side = (b - a).perp(&(light_pos - a));
+        let faces_light = occluder.len() == 2 || side * winding < 0.0;
+        if !faces_light || side == 0.0 {
+            continue;
+        }
+
+        if let (Some(a_far), Some(b_far)) = (project(a), project(b)) {
+            out.push(Quad {
+                corners: [a, b, b_far, a_far],
+            });
+        }
+    }
+}
+
+impl TriBatch<ColVertex> {
+    /// Pushes the shadow volumes that `occluders` cast for a light at
+    /// `light_pos`. See `shadow_volume_quads`.
+    pub fn push_shadow_volumes<'a>(
+        &mut self,
+        light_pos: Point2<f32>,
+        occluders: impl IntoIterator<Item = &'a [Point2<f32>]>,
+        extent: f32,
+        z: f32,
+        color: Color4,
+    ) {
+        let mut quads = Vec::new();
+
+        for occluder in occluders {
+            quads.clear();
+            push_shadow_volume_quads(light_pos, occluder, extent, &mut quads);
+
+            for quad in quads.iter() {
+                self.push_quad(quad, z, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Tessellation of circles, arcs, rounded rects and polygons into colored
+//! triangles, e.g. for debug drawing and simple UI.
+//!
+//! Curved shapes are approximated with straight segments, whose number is
+//! given by the caller, so that small shapes can use fewer triangles than
+//! large ones.
+
+use std::f32::consts::PI;
+
+use nalgebra::{Point2, Point3, Vector2};
+
+use crate::{
+    draw::{ColVertex, TriBatch},
+    AaRect, Color4,
+};
+
+impl TriBatch<ColVertex> {
+    /// Pushes a filled circle, triangulated as a fan around its center.
+    ///
+    /// At least three segments are used.
+    pub fn push_circle(
+        &mut self,
+        center: Point2<f32>,
+        radius: f32,
+        segments: usize,
+        z: f32,
+        color: Color4,
+    ) {
+        // The last point of the arc equals the first one.
+        let segments = segments.max(3);
+        let points: Vec<Point2<f32>> = arc_points(center, radius, 0.0, 2.0 * PI, segments)
+            .take(segments)
+            .collect();
+
+        self.push_fan(center, &points, z, color);
+    }
+
+    /// Pushes the outline of a circle with the given thickness, centered on
+    /// `radius`.
+    pub fn push_circle_outline(
+        &mut self,
+        center: Point2<f32>,
+        radius: f32,
+        thickness: f32,
+        segments: usize,
+        z: f32,
+        color: Color4,
+    ) {
+        self.push_arc(
+            center,
+            radius,
+            0.0,
+            2.0 * PI,
+            thickness,
+            segments.max(3),
+            z,
+            color,
+        );
+    }
+
+    /// Pushes a part of a circle outline, going counterclockwise from
+    /// `start_angle` to `end_angle`, which are given in radians. The
+    /// outline has the given thickness and is centered on `radius`.
+    ///
+    /// At least one segment is used.
+    #[allow(clippy::too_many_arguments)]
+    pub fn push_arc(
+        &mut self,
+        center: Point2<f32>,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+        thickness: f32,
+        segments: usize,
+        z: f32,
+        color: Color4,
+    ) {
+        let segments = segments.max(1);
+        let inner_radius = (radius - thickness / 2.0).max(0.0);
+        let outer_radius = radius + thickness / 2.0;
+
+        let first_idx = self.next_index();
+
+        let inner = arc_points(center, inner_radius, start_angle, end_angle, segments);
+        let outer = arc_points(center, outer_radius, start_angle, end_angle, segments);
+        for (p, q) in inner.zip(outer) {
+            for point in [p, q].iter() {
+                self.push_vertex(&ColVertex {
+                    world_pos: Point3::new(point.x, point.y, z),
+                    color,
+                });
+            }
+        }
+
+        for i in 0..segments as u32 {
+            let a = first_idx + 2 * i;
+            for element in [a, a + 1, a + 3, a + 3, a + 2, a].iter() {
+                self.push_element(*element);
+            }
+        }
+    }
+
+    /// Pushes a filled rect whose corners are rounded with the given
+    /// radius, using `corner_segments` segments per corner.
+    ///
+    /// The radius is clamped to half of the smaller side of the rect.
+    pub fn push_rounded_rect(
+        &mut self,
+        rect: &AaRect,
+        radius: f32,
+        corner_segments: usize,
+        z: f32,
+        color: Color4,
+    ) {
+        let radius = radius
+            .min(rect.size.x / 2.0)
+            .min(rect.size.y / 2.0)
+            .max(0.0);
+        let inner_half_size = rect.size / 2.0 - Vector2::new(radius, radius);
+        let corner_segments = corner_segments.max(1);
+
+        // Go counterclockwise through the corners, starting at max X and
+        // max Y.
+        let corners = [
+            (Vector2::new(1.0, 1.0), 0.0),
+            (Vector2::new(-1.0, 1.0), 0.5 * PI),
+            (Vector2::new(-1.0, -1.0), PI),
+            (Vector2::new(1.0, -1.0), 1.5 * PI),
+        ];
+        let points: Vec<Point2<f32>> = corners
+            .iter()
+            .flat_map(|(sign, start_angle)| {
+                let corner_center = rect.center + inner_half_size.component_mul(sign);
+
+                arc_points(
+                    corner_center,
+                    radius,
+                    *start_angle,
+                    start_angle + 0.5 * PI,
+                    corner_segments,
+                )
+            })
+            .collect();
+
+        self.push_fan(rect.center, &points, z, color);
+    }
+
+    /// Pushes a filled simple polygon, which may be concave, triangulated by
+    /// ear clipping.
+    ///
+    /// The polygon is given by its points in order (either clockwise or
+    /// counterclockwise) and must not intersect itself. Polygons with less
+    /// than three points are ignored. For convex polygons,
+    /// `push_convex_polygon` is faster.
+    pub fn push_polygon(&mut self, points: &[Point2<f32>], z: f32, color: Color4) {
+        if points.len() < 3 {
+            return;
+        }
+
+        let first_idx = self.next_index();
+
+        for point in points {
+            self.push_vertex(&ColVertex {
+                world_pos: Point3::new(point.x, point.y, z),
+                color,
+            });
+        }
+
+        for [a, b, c] in triangulate(points) {
+            self.push_element(first_idx + a as u32);
+            self.push_element(first_idx + b as u32);
+            self.push_element(first_idx + c as u32);
+        }
+    }
+
+    /// Pushes triangles connecting `center` with consecutive points of the
+    /// closed outline `points`.
+    fn push_fan(&mut self, center: Point2<f32>, points: &[Point2<f32>], z: f32, color: Color4) {
+        let first_idx = self.next_index();
+
+        self.push_vertex(&ColVertex {
+            world_pos: Point3::new(center.x, center.y, z),
+            color,
+        });
+        for point in points {
+            self.push_vertex(&ColVertex {
+                world_pos: Point3::new(point.x, point.y, z),
+                color,
+            });
+        }
+
+        let n = points.len() as u32;
+        for i in 0..n {
+            self.push_element(first_idx);
+            self.push_element(first_idx + 1 + i);
+            self.push_element(first_idx + 1 + (i + 1) % n);
+        }
+    }
+}
+
+/// Returns `segments + 1` points on a circle, going from `start_angle` to
+/// `end_angle`.
+fn arc_points(
+    center: Point2<f32>,
+    radius: f32,
+    start_angle: f32,
+    end_angle: f32,
+    segments: usize,
+) -> impl Iterator<Item = Point2<f32>> {
+    (0..=segments).map(move |i| {
+        let angle = start_angle + (end_angle - start_angle) * i as f32 / segments as f32;
+
+        center + radius * Vector2::new(angle.cos(), angle.sin())
+    })
+}
+
+/// Triangulates a simple polygon by ear clipping, returning indices into
+/// `points`. This takes cubic time in the number of points in the worst
+/// case, so it is meant for polygons with few points.
+fn triangulate(points: &[Point2<f32>]) -> Vec<[usize; 3]> {
+    let signed_area: f32 = (0..points.len())
+        .map(|i| {
+            let (p, q) = (points[i], points[(i + 1) % points.len()]);
+            p.x * q.y - q.x * p.y
+        })
+        .sum();
+    let orientation = if signed_area >= 0.0 { 1.0 } else { -1.0 };
+
+    let cross =
+        |a: Point2<f32>, b: Point2<f32>, c: Point2<f32>| orientation * ((b - a).perp(&(c - a)));
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            );
+
+            // An ear is a convex corner whose triangle contains no other
+            // point of the polygon.
+            cross(points[a], points[b], points[c]) > 0.0
+                && remaining.iter().all(|&j| {
+                    j == a
+                        || j == b
+                        || j == c
+                        || cross(points[a], points[b], points[j]) < 0.0
+                        || cross(points[b], points[c], points[j]) < 0.0
+                        || cross(points[c], points[a], points[j]) < 0.0
+                })
+        });
+
+        // Degenerate polygons, e.g. with collinear or self-intersecting
+        // edges, may have no ear. Clip any corner to make progress.
+        let i = ear.unwrap_or(0);
+
+        triangles.push([
+            remaining[(i + n - 1) % n],
+            remaining[i],
+            remaining[(i + 1) % n],
+        ]);
+        remaining.remove(i);
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+
+    triangles
+}
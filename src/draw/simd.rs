@@ -0,0 +1,116 @@
+//! The hot paths of vertex generation, i.e. transforming quads and writing
+//! their vertices, which dominate the CPU time of drawing many sprites.
+//!
+//! With the `simd` feature, these use the SIMD instructions of wasm32 if the
+//! `simd128` target feature is enabled. Otherwise, the scalar versions are
+//! used, which produce the same output.
+
+use nalgebra::{Matrix3, Point2};
+
+use crate::Color4;
+
+/// The number of floats that a `TexColVertex` is written as.
+const TEX_COL_FLOATS: usize = 9;
+
+/// Returns the corners of the unit quad centered at the origin, transformed
+/// by `m`, in the order of `Quad::corners`.
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+pub fn transform_unit_quad(m: &Matrix3<f32>) -> [Point2<f32>; 4] {
+    use core::arch::wasm32::*;
+
+    let corners_x = f32x4(-0.5, -0.5, 0.5, 0.5);
+    let corners_y = f32x4(-0.5, 0.5, 0.5, -0.5);
+
+    let row = |i: usize| {
+        f32x4_add(
+            f32x4_add(
+                f32x4_mul(f32x4_splat(m[(i, 0)]), corners_x),
+                f32x4_mul(f32x4_splat(m[(i, 1)]), corners_y),
+            ),
+            f32x4_splat(m[(i, 2)]),
+        )
+    };
+    let (xs, ys) = (row(0), row(1));
+
+    [
+        Point2::new(f32x4_extract_lane::<0>(xs), f32x4_extract_lane::<0>(ys)),
+        Point2::new(f32x4_extract_lane::<1>(xs), f32x4_extract_lane::<1>(ys)),
+        Point2::new(f32x4_extract_lane::<2>(xs), f32x4_extract_lane::<2>(ys)),
+        Point2::new(f32x4_extract_lane::<3>(xs), f32x4_extract_lane::<3>(ys)),
+    ]
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128")))]
+pub fn transform_unit_quad(m: &Matrix3<f32>) -> [Point2<f32>; 4] {
+    let corner = |x: f32, y: f32| {
+        Point2::new(
+            m[(0, 0)] * x + m[(0, 1)] * y + m[(0, 2)],
+            m[(1, 0)] * x + m[(1, 1)] * y + m[(1, 2)],
+        )
+    };
+
+    [
+        corner(-0.5, -0.5),
+        corner(-0.5, 0.5),
+        corner(0.5, 0.5),
+        corner(0.5, -0.5),
+    ]
+}
+
+/// Appends the four `TexColVertex`s of a quad to `out`, in the layout of
+/// `TexColVertex::write`.
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+pub fn write_tex_col_quad(
+    out: &mut Vec<f32>,
+    corners: &[Point2<f32>; 4],
+    z: f32,
+    tex_coords: &[Point2<f32>; 4],
+    color: Color4,
+) {
+    use core::arch::wasm32::*;
+
+    let (p, t, c) = (corners, tex_coords, color);
+
+    // Four vertices of nine floats each fill exactly nine vectors.
+    let vectors = [
+        f32x4(p[0].x, p[0].y, z, t[0].x),
+        f32x4(t[0].y, c.r, c.g, c.b),
+        f32x4(c.a, p[1].x, p[1].y, z),
+        f32x4(t[1].x, t[1].y, c.r, c.g),
+        f32x4(c.b, c.a, p[2].x, p[2].y),
+        f32x4(z, t[2].x, t[2].y, c.r),
+        f32x4(c.g, c.b, c.a, p[3].x),
+        f32x4(p[3].y, z, t[3].x, t[3].y),
+        f32x4(c.r, c.g, c.b, c.a),
+    ];
+
+    out.reserve(4 * TEX_COL_FLOATS);
+
+    // Safety: We have reserved space for the 36 floats that are written
+    // here, and `v128_store` does not require alignment.
+    unsafe {
+        let start = out.as_mut_ptr().add(out.len());
+        for (i, vector) in vectors.iter().enumerate() {
+            v128_store(start.add(4 * i) as *mut v128, *vector);
+        }
+        out.set_len(out.len() + 4 * TEX_COL_FLOATS);
+    }
+}
+
+#[cfg(not(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128")))]
+pub fn write_tex_col_quad(
+    out: &mut Vec<f32>,
+    corners: &[Point2<f32>; 4],
+    z: f32,
+    tex_coords: &[Point2<f32>; 4],
+    color: Color4,
+) {
+    let mut floats = [0.0; 4 * TEX_COL_FLOATS];
+
+    for (i, (p, t)) in corners.iter().zip(tex_coords.iter()).enumerate() {
+        floats[i * TEX_COL_FLOATS..(i + 1) * TEX_COL_FLOATS]
+            .copy_from_slice(&[p.x, p.y, z, t.x, t.y, color.r, color.g, color.b, color.a]);
+    }
+
+    out.extend_from_slice(&floats);
+}
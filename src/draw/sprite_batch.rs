@@ -0,0 +1,221 @@
+//! Drawing many textured sprites with per-sprite transforms in one call.
+
+use std::rc::Rc;
+
+use nalgebra::{Matrix3, Point2, Vector2};
+
+use crate::{
+    draw::{Quad, TexColPass, TexColVertex, Texture, TextureRegion, TriBatch},
+    geom::scale_rotate_translate,
+    AaRect, Canvas, Color4, Error, YAxis,
+};
+
+/// The order in which a `SpriteBatch` draws its sprites.
+///
+/// Sorting is stable, so sprites that compare equal are drawn in the order
+/// in which they were pushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteSort {
+    /// Draw sprites in the order in which they were pushed.
+    Submission,
+
+    /// Group sprites by texture, which needs the fewest draw calls, but
+    /// only looks right if sprites with different textures do not overlap
+    /// or use the depth test.
+    Texture,
+
+    /// Draw sprites back to front by their depth, i.e. sprites with a
+    /// greater depth are drawn first, so that transparent sprites blend
+    /// correctly.
+    Depth,
+
+    /// Draw sprites by the Y coordinate of their position, so that in a
+    /// top-down view, sprites that are further down on the screen are drawn
+    /// over the ones above them. Which way is down is given by the Y axis of
+    /// the transform that the batch is drawn with.
+    Y,
+}
+
+impl Default for SpriteSort {
+    fn default() -> Self {
+        SpriteSort::Submission
+    }
+}
+
+struct PendingSprite {
+    texture: Rc<Texture>,
+    transform: Matrix3<f32>,
+    uv_rect: AaRect,
+    position: Point2<f32>,
+    depth: f32,
+    color: Color4,
+}
+
+/// A run of sorted sprites that share a texture, drawn with one call.
+struct DrawGroup {
+    texture: Rc<Texture>,
+    first_element: usize,
+    num_elements: usize,
+}
+
+/// A batch of sprites, which is drawn with one call for every run of
+/// consecutive sprites that share a texture.
+///
+/// The sprites are turned into vertices when they are drawn, so they stay
+/// upright regardless of the Y axis of the transform, see `YAxis`.
+pub struct SpriteBatch {
+    batch: TriBatch<TexColVertex>,
+    pass: TexColPass,
+    sort: SpriteSort,
+
+    sprites: Vec<PendingSprite>,
+    groups: Vec<DrawGroup>,
+
+    /// The Y axis that the batch was last built for, or `None` if the
+    /// sprites or their order have changed since then.
+    built_for: Option<YAxis>,
+}
+
+impl SpriteBatch {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        Ok(Self {
+            batch: TriBatch::new(ctx)?,
+            pass: TexColPass::new(ctx)?,
+            sort: SpriteSort::default(),
+            sprites: Vec::new(),
+            groups: Vec::new(),
+            built_for: None,
+        })
+    }
+
+    pub fn sort(&self) -> SpriteSort {
+        self.sort
+    }
+
+    /// Sets the order in which sprites are drawn, which applies to all
+    /// sprites in the batch, including the ones that have already been
+    /// pushed.
+    pub fn set_sort(&mut self, sort: SpriteSort) {
+        self.sort = sort;
+        self.built_for = None;
+    }
+
+    /// Pushes a sprite that shows `region`, centered at `position`.
+    ///
+    /// The sprite is first scaled by `scale`, relative to the size of the
+    /// region in pixels, and then rotated by `rotation` radians around its
+    /// center. Its texture colors are multiplied by `color`. `depth` is
+    /// used as the Z coordinate of the sprite's vertices and for
+    /// `SpriteSort::Depth`.
+    pub fn push_sprite(
+        &mut self,
+        region: &TextureRegion,
+        position: Point2<f32>,
+        rotation: f32,
+        scale: Vector2<f32>,
+        depth: f32,
+        color: Color4,
+    ) {
+        let transform =
+            scale_rotate_translate(region.size.component_mul(&scale), rotation, position.coords);
+
+        self.sprites.push(PendingSprite {
+            texture: region.texture.clone(),
+            transform,
+            uv_rect: region.uv_rect,
+            position,
+            depth,
+            color,
+        });
+        self.built_for = None;
+    }
+
+    pub fn num_sprites(&self) -> usize {
+        self.sprites.len()
+    }
+
+    /// Returns the number of draw calls that the last `draw` made.
+    pub fn num_draw_calls(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.sprites.clear();
+        self.groups.clear();
+        self.batch.clear();
+        self.built_for = None;
+    }
+
+    /// Draws the sprites with the textures of their regions.
+    pub fn draw(&mut self, transform: &Matrix3<f32>) -> Result<(), Error> {
+        self.build(YAxis::of_transform(transform));
+
+        let draw_unit = self.batch.draw_unit();
+        for group in self.groups.iter() {
+            self.pass.draw(
+                transform,
+                &group.texture,
+                &draw_unit.sub_unit(group.first_element, group.num_elements),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Sorts the pending sprites and pushes them into the batch, if they
+    /// or the Y axis have changed since the last build.
+    fn build(&mut self, y_axis: YAxis) {
+        if self.built_for == Some(y_axis) {
+            return;
+        }
+        self.built_for = Some(y_axis);
+
+        let mut order: Vec<usize> = (0..self.sprites.len()).collect();
+        let sprites = &self.sprites;
+        match self.sort {
+            SpriteSort::Submission => (),
+            SpriteSort::Texture => {
+                order.sort_by_key(|&i| Rc::as_ptr(&sprites[i].texture) as usize);
+            }
+            SpriteSort::Depth => sort_by_f32(&mut order, |i| -sprites[i].depth),
+            SpriteSort::Y => sort_by_f32(&mut order, |i| y_axis.sign() * sprites[i].position.y),
+        }
+
+        self.batch.clear();
+        self.groups.clear();
+
+        for i in order {
+            let sprite = &self.sprites[i];
+
+            let first_element = self.batch.num_elements();
+            let uv_rect = match y_axis {
+                YAxis::Down => sprite.uv_rect,
+                YAxis::Up => sprite.uv_rect.flip_y(),
+            };
+            self.batch.push_quad(
+                &Quad::new(&sprite.transform),
+                sprite.depth,
+                uv_rect,
+                sprite.color,
+            );
+            let num_elements = self.batch.num_elements() - first_element;
+
+            match self.groups.last_mut() {
+                Some(group) if Rc::ptr_eq(&group.texture, &sprite.texture) => {
+                    group.num_elements += num_elements;
+                }
+                _ => self.groups.push(DrawGroup {
+                    texture: sprite.texture.clone(),
+                    first_element,
+                    num_elements,
+                }),
+            }
+        }
+    }
+}
+
+/// Stably sorts `order` by ascending `key`, using the total order of
+/// `f32::total_cmp`, so that NaN keys are sorted to the ends.
+fn sort_by_f32(order: &mut [usize], key: impl Fn(usize) -> f32) {
+    order.sort_by(|&i, &j| key(i).total_cmp(&key(j)));
+}
@@ -0,0 +1,334 @@
+//! Sprite sheets with trimmed frames and pivot points, as exported by
+//! TexturePacker and Aseprite.
+//!
+//! Packers usually trim the transparent border of each frame to save space
+//! in the atlas. The trimmed frames of an animation then have different
+//! sizes and offsets, so drawing them centered at the sprite's position
+//! makes the animation jitter. `SpriteFrame` keeps the information that is
+//! needed to draw each trimmed frame at its position in the untrimmed
+//! source image, relative to the frame's pivot.
+//!
+//! A sheet keeps the `TextureRegion` of its atlas, so the frames can be
+//! drawn without passing the texture separately.
+
+use std::collections::HashMap;
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::{Quad, TexColVertex, TextureRegion, TriBatch},
+    AaRect, Color4,
+};
+
+#[derive(Debug, Clone)]
+pub struct SpriteFrame {
+    pub name: String,
+
+    /// The top left corner of the frame in the atlas, in pixels.
+    pub atlas_pos: Point2<f32>,
+
+    /// The size of the trimmed frame, in pixels. For rotated frames, this is
+    /// the size before rotation, so the frame covers `size.yx()` pixels in
+    /// the atlas.
+    pub size: Vector2<f32>,
+
+    /// Whether the packer has stored the frame rotated by 90 degrees
+    /// clockwise.
+    pub rotated: bool,
+
+    /// The position of the trimmed frame's top left corner in the untrimmed
+    /// source image, in pixels.
+    pub trim_offset: Vector2<f32>,
+
+    /// The size of the untrimmed source image, in pixels.
+    pub source_size: Vector2<f32>,
+
+    /// The point that is placed at the sprite's position, relative to the
+    /// untrimmed source image, where `(0, 0)` is the top left corner and
+    /// `(1, 1)` is the bottom right corner.
+    pub pivot: Point2<f32>,
+}
+
+impl SpriteFrame {
+    /// Returns a frame that covers `rect` of the atlas without trimming,
+    /// with its pivot at the center.
+    pub fn untrimmed(name: impl Into<String>, rect: AaRect) -> Self {
+        Self {
+            name: name.into(),
+            atlas_pos: rect.min(),
+            size: rect.size,
+            rotated: false,
+            trim_offset: Vector2::zeros(),
+            source_size: rect.size,
+            pivot: Point2::new(0.5, 0.5),
+        }
+    }
+
+    /// Returns the quad of the trimmed frame when the pivot is placed at
+    /// `pos` and one pixel of the source image covers `scale` world units.
+    ///
+    /// Negative components of `scale` mirror the sprite around the pivot.
+    pub fn quad(&self, pos: Point2<f32>, scale: Vector2<f32>) -> Quad {
+        let pivot = self.pivot.coords.component_mul(&self.source_size);
+        let top_left = pos + (self.trim_offset - pivot).component_mul(&scale);
+        let size = self.size.component_mul(&scale);
+
+        AaRect::from_top_left(top_left, size).into()
+    }
+
+    /// Returns the part of `atlas` that the frame covers. For rotated
+    /// frames, this is the rotated rect as it is stored in the atlas.
+    pub fn region(&self, atlas: &TextureRegion) -> TextureRegion {
+        let atlas_size = if self.rotated {
+            self.size.yx()
+        } else {
+            self.size
+        };
+
+        atlas.sub_region(AaRect::from_top_left(self.atlas_pos, atlas_size))
+    }
+
+    /// Returns the texture coordinates of the frame's corners in `atlas`,
+    /// in the order of `Quad::corners`.
+    pub fn uv_quad(&self, atlas: &TextureRegion) -> Quad {
+        let uv_quad = Quad::from(self.region(atlas).uv_rect);
+
+        if self.rotated {
+            // The top left corner of a frame that has been rotated clockwise
+            // ends up at the top right of its rect in the atlas.
+            uv_quad.rotate_corners(3)
+        } else {
+            uv_quad
+        }
+    }
+}
+
+/// The frames of an atlas.
+#[derive(Clone)]
+pub struct SpriteSheet {
+    atlas: TextureRegion,
+    frames: Vec<SpriteFrame>,
+    indices: HashMap<String, usize>,
+}
+
+impl SpriteSheet {
+    /// Creates a sheet whose frames are given in pixels of `atlas`, which
+    /// may be a whole texture or a part of a larger one.
+    pub fn new(atlas: TextureRegion, frames: Vec<SpriteFrame>) -> Self {
+        let indices = frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| (frame.name.clone(), index))
+            .collect();
+
+        Self {
+            atlas,
+            frames,
+            indices,
+        }
+    }
+
+    pub fn atlas(&self) -> &TextureRegion {
+        &self.atlas
+    }
+
+    pub fn frames(&self) -> &[SpriteFrame] {
+        &self.frames
+    }
+
+    pub fn frame(&self, index: usize) -> &SpriteFrame {
+        &self.frames[index]
+    }
+
+    pub fn frame_index(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    pub fn frame_by_name(&self, name: &str) -> Option<&SpriteFrame> {
+        self.frame_index(name).map(|index| &self.frames[index])
+    }
+}
+
+#[cfg(feature = "scene")]
+mod json {
+    use std::fmt;
+
+    use nalgebra::{Point2, Vector2};
+    use serde::{
+        de::{MapAccess, Visitor},
+        Deserialize, Deserializer,
+    };
+
+    use super::{SpriteFrame, SpriteSheet};
+    use crate::{draw::TextureRegion, Error};
+
+    #[derive(Deserialize)]
+    struct Rect {
+        #[serde(default)]
+        x: f32,
+        #[serde(default)]
+        y: f32,
+        w: f32,
+        h: f32,
+    }
+
+    #[derive(Deserialize)]
+    struct Pivot {
+        x: f32,
+        y: f32,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Frame {
+        #[serde(default)]
+        filename: Option<String>,
+        frame: Rect,
+        #[serde(default)]
+        rotated: bool,
+        #[serde(default)]
+        sprite_source_size: Option<Rect>,
+        #[serde(default)]
+        source_size: Option<Rect>,
+        #[serde(default)]
+        pivot: Option<Pivot>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Frames {
+        Array(Vec<Frame>),
+        Hash(FrameMap),
+    }
+
+    /// The frames of the hash variant, in the order in which they appear in
+    /// the file, which a `BTreeMap` or `HashMap` would lose.
+    struct FrameMap(Vec<(String, Frame)>);
+
+    impl<'de> Deserialize<'de> for FrameMap {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            struct FrameMapVisitor;
+
+            impl<'de> Visitor<'de> for FrameMapVisitor {
+                type Value = FrameMap;
+
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    formatter.write_str("a map from frame names to frames")
+                }
+
+                fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<FrameMap, A::Error> {
+                    let mut frames = Vec::with_capacity(map.size_hint().unwrap_or(0));
+                    while let Some(entry) = map.next_entry()? {
+                        frames.push(entry);
+                    }
+
+                    Ok(FrameMap(frames))
+                }
+            }
+
+            deserializer.deserialize_map(FrameMapVisitor)
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Meta {
+        size: Rect,
+    }
+
+    #[derive(Deserialize)]
+    struct Sheet {
+        frames: Frames,
+        meta: Meta,
+    }
+
+    impl Frame {
+        fn into_sprite_frame(self, name: String) -> SpriteFrame {
+            let size = Vector2::new(self.frame.w, self.frame.h);
+            let (size, trim_offset) = match self.sprite_source_size {
+                Some(rect) => (Vector2::new(rect.w, rect.h), Vector2::new(rect.x, rect.y)),
+                None => (size, Vector2::zeros()),
+            };
+
+            SpriteFrame {
+                name,
+                atlas_pos: Point2::new(self.frame.x, self.frame.y),
+                size,
+                rotated: self.rotated,
+                trim_offset,
+                source_size: self
+                    .source_size
+                    .map_or(size, |rect| Vector2::new(rect.w, rect.h)),
+                pivot: self
+                    .pivot
+                    .map_or(Point2::new(0.5, 0.5), |pivot| Point2::new(pivot.x, pivot.y)),
+            }
+        }
+    }
+
+    impl SpriteSheet {
+        /// Parses a sprite sheet in the JSON format of TexturePacker, which
+        /// is also exported by Aseprite.
+        ///
+        /// Both the array and the hash variant are supported. In both
+        /// variants, frames keep the order in which they appear in the file.
+        ///
+        /// `atlas` is the region that holds the image of the sheet, whose
+        /// size must match the size given in the file.
+        pub fn from_json(json: &str, atlas: TextureRegion) -> Result<Self, Error> {
+            let sheet: Sheet =
+                serde_json::from_str(json).map_err(|e| Error::SpriteSheet(e.to_string()))?;
+
+            let frames = match sheet.frames {
+                Frames::Array(frames) => frames
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, frame)| {
+                        let name = frame.filename.clone().unwrap_or_else(|| index.to_string());
+                        frame.into_sprite_frame(name)
+                    })
+                    .collect(),
+                Frames::Hash(FrameMap(frames)) => frames
+                    .into_iter()
+                    .map(|(name, frame)| frame.into_sprite_frame(name))
+                    .collect(),
+            };
+
+            let size = Vector2::new(sheet.meta.size.w, sheet.meta.size.h);
+            if size != atlas.size {
+                return Err(Error::SpriteSheet(format!(
+                    "sheet has size {:?}, but its atlas has size {:?}",
+                    size, atlas.size,
+                )));
+            }
+
+            Ok(SpriteSheet::new(atlas, frames))
+        }
+    }
+}
+
+impl TriBatch<TexColVertex> {
+    /// Pushes frame `index` of `sheet`, with the frame's pivot placed at
+    /// `pos`. See `SpriteFrame::quad` for the meaning of `scale`.
+    ///
+    /// If the Y axis of the batch points up, the frame is mirrored around
+    /// its pivot to stay upright.
+    pub fn push_sprite(
+        &mut self,
+        sheet: &SpriteSheet,
+        index: usize,
+        pos: Point2<f32>,
+        scale: Vector2<f32>,
+        z: f32,
+        color: Color4,
+    ) {
+        let frame = sheet.frame(index);
+        let scale = Vector2::new(scale.x, self.y_axis().sign() * scale.y);
+
+        self.push_quad_with_uv_quad(
+            &frame.quad(pos, scale),
+            z,
+            &frame.uv_quad(sheet.atlas()),
+            color,
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Textured strips along a series of connected points, for ropes, chains,
+//! vines, lightning bolts and beams.
+
+use nalgebra::{Point2, Point3, Vector2};
+
+use crate::{
+    draw::{TexColVertex, TriBatch},
+    Color4,
+};
+
+/// Limits how far miter joints can extend at sharp corners, as a multiple
+/// of the half width.
+const MAX_MITER_SCALE: f32 = 4.0;
+
+/// Returns the points of a Catmull-Rom spline through `points`, with
+/// `subdivisions` additional points between each pair of input points.
+///
+/// This smooths the joints of strips whose points are far apart, e.g. the
+/// few simulated points of a rope.
+pub fn smooth_points(points: &[Point2<f32>], subdivisions: usize) -> Vec<Point2<f32>> {
+    if points.len() < 3 || subdivisions == 0 {
+        return points.to_vec();
+    }
+
+    let mut result = Vec::with_capacity((points.len() - 1) * (subdivisions + 1) + 1);
+
+    for i in 0..points.len() - 1 {
+        let p0 = points[i.saturating_sub(1)].coords;
+        let p1 = points[i].coords;
+        let p2 = points[i + 1].coords;
+        let p3 = points[(i + 2).min(points.len() - 1)].coords;
+
+        for step in 0..=subdivisions {
+            let t = step as f32 / (subdivisions + 1) as f32;
+            let t2 = t * t;
+            let t3 = t2 * t;
+
+            let p = 0.5
+                * (2.0 * p1
+                    + (p2 - p0) * t
+                    + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+                    + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3);
+            result.push(Point2::from(p));
+        }
+    }
+
+    result.push(points[points.len() - 1]);
+
+    result
+}
+
+impl TriBatch<TexColVertex> {
+    /// Pushes a strip of the given width along `points`.
+    ///
+    /// The texture runs along the strip: `u` grows by one every `uv_length`
+    /// world units, so the texture should use `TextureWrap::Repeat` in that
+    /// direction, while `v` goes from zero to one across the strip. Joints
+    /// are mitered, so that the strip has no gaps or overlaps at corners.
+    /// For rounder joints, pass the points through `smooth_points` first.
+    pub fn push_strip(
+        &mut self,
+        points: &[Point2<f32>],
+        width: f32,
+        uv_length: f32,
+        z: f32,
+        color: Color4,
+    ) {
+        if points.len() < 2 {
+            return;
+        }
+
+        let half_width = width / 2.0;
+        let first_idx = self.next_index();
+        let mut u = 0.0;
+
+        for i in 0..points.len() {
+            let dir_in = if i > 0 {
+                normalize_or_zero(points[i] - points[i - 1])
+            } else {
+                Vector2::zeros()
+            };
+            let dir_out = if i + 1 < points.len() {
+                normalize_or_zero(points[i + 1] - points[i])
+            } else {
+                Vector2::zeros()
+            };
+
+            // The miter is perpendicular to the average direction, scaled so
+            // that the strip keeps its width on both adjacent segments.
+            let tangent = normalize_or_zero(dir_in + dir_out);
+            let normal = Vector2::new(-tangent.y, tangent.x);
+            let segment_normal = Vector2::new(-dir_out.y, dir_out.x);
+            let cos = if dir_out != Vector2::zeros() {
+                normal.dot(&segment_normal)
+            } else {
+                normal.dot(&Vector2::new(-dir_in.y, dir_in.x))
+            };
+            let miter_scale = if cos > 1.0 / MAX_MITER_SCALE {
+                1.0 / cos
+            } else {
+                MAX_MITER_SCALE
+            };
+            let offset = normal * half_width * miter_scale;
+
+            if i > 0 {
+                u += (points[i] - points[i - 1]).norm() / uv_length;
+            }
+
+            for (side, v) in [(1.0, 0.0), (-1.0, 1.0)].iter() {
+                let p = points[i] + offset * *side;
+                self.push_vertex(&TexColVertex {
+                    world_pos: Point3::new(p.x, p.y, z),
+                    tex_coords: Point2::new(u, *v),
+                    color,
+                });
+            }
+        }
+
+        for i in 0..points.len() as u32 - 1 {
+            let a = first_idx + 2 * i;
+            for element in [a, a + 1, a + 3, a + 3, a + 2, a].iter() {
+                self.push_element(*element);
+            }
+        }
+    }
+}
+
+fn normalize_or_zero(v: Vector2<f32>) -> Vector2<f32> {
+    let norm = v.norm();
+
+    if norm > 0.0 {
+        v / norm
+    } else {
+        Vector2::zeros()
+    }
+}
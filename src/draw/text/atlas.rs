@@ -0,0 +1,507 @@
+//! A glyph atlas that can be shared between multiple fonts.
+//!
+//! The atlas starts small and grows as glyphs are added, up to a maximum
+//! size. Once that is full, glyphs that have not been used recently can be
+//! evicted, see `GlyphAtlas::set_eviction`.
+//!
+//! An atlas created with `GlyphAtlas::new_sdf` stores signed distance fields
+//! instead of coverage, see the `sdf` module.
+
+use std::{
+    cell::{Ref, RefCell},
+    collections::HashMap,
+    rc::Rc,
+};
+
+use fontdue::layout::GlyphRasterConfig;
+use golem::{
+    blend::{BlendEquation, BlendFactor, BlendFunction, BlendMode, BlendOperation},
+    TextureFilter,
+};
+use nalgebra::{Matrix3, Point2};
+
+use crate::{
+    draw::{
+        text::{
+            family::FontFamily,
+            packer::ShelfPacker,
+            sdf::{self, SdfConfig, SdfEffect, SdfPass},
+        },
+        BlendScope, DrawUnit, TexColEffect, TexColPass, TexColVertex, Texture,
+    },
+    AaRect, Canvas, Error,
+};
+
+const ATLAS_WIDTH: usize = 512;
+const ATLAS_HEIGHT: usize = 256;
+
+/// The default size that the atlas may grow to in each dimension, which
+/// all WebGL implementations we know of support.
+const ATLAS_MAX_SIZE: usize = 4096;
+
+/// Identifies a font within a `GlyphAtlas`, so that fonts sharing an atlas
+/// don't get each other's glyphs.
+pub(crate) type FontId = usize;
+
+struct Glyph {
+    /// Texture coordinates in texels.
+    uv_rect: AaRect,
+
+    pos: (usize, usize),
+    size: (usize, usize),
+
+    /// The frame in which the glyph was last written.
+    last_used: u64,
+}
+
+type GlyphKey = (FontId, GlyphRasterConfig, u32);
+
+/// How text from the atlas is blended with what has been drawn before.
+///
+/// This is chosen whenever text is drawn, so that one font can draw e.g.
+/// glowing and shadowed text in the same frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextBlend {
+    /// Adds the text color, which works well for light text on a dark
+    /// background.
+    Additive,
+
+    /// Draws the text over the background, which is needed for text that
+    /// is darker than its background, such as drop shadows.
+    Over,
+}
+
+impl Default for TextBlend {
+    fn default() -> Self {
+        TextBlend::Additive
+    }
+}
+
+struct Inner {
+    packer: ShelfPacker,
+    cache: HashMap<GlyphKey, Glyph>,
+    pass: TexColPass,
+    bitmap_buffer: Vec<u8>,
+    next_font_id: FontId,
+
+    frame: u64,
+    evict_after_frames: Option<u32>,
+
+    /// Set when a glyph did not fit into the atlas.
+    full: bool,
+
+    generation: u64,
+
+    sdf: Option<(SdfConfig, SdfPass)>,
+    sdf_effect: SdfEffect,
+}
+
+/// A texture atlas containing rasterized glyphs.
+///
+/// This is a cheaply clonable handle. Passing the same atlas to multiple
+/// fonts lets them share a single texture and pass, so that text written
+/// with different fonts can be drawn from one `TextBatch` with a single draw
+/// call.
+#[derive(Clone)]
+pub struct GlyphAtlas {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl GlyphAtlas {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        Self::with_size(ctx, ATLAS_WIDTH, ATLAS_HEIGHT)
+    }
+
+    pub fn with_size(ctx: &Canvas, width: usize, height: usize) -> Result<Self, Error> {
+        Self::with_sdf(ctx, width, height, None)
+    }
+
+    /// Creates an atlas that stores glyphs as signed distance fields.
+    ///
+    /// Fonts using this atlas rasterize every glyph once at
+    /// `config.glyph_size`, and the glyphs stay sharp at any scale, which
+    /// suits large text and text in zoomed world space. Small text tends to
+    /// look better in a regular atlas. Outlines and shadows are set with
+    /// `set_sdf_effect`; blurred glyphs are not supported, so the shadows of
+    /// `Font::write_with_shadow` are drawn sharp.
+    pub fn new_sdf(ctx: &Canvas, config: SdfConfig) -> Result<Self, Error> {
+        let pass = SdfPass::new(ctx)?;
+
+        Self::with_sdf(ctx, ATLAS_WIDTH, ATLAS_HEIGHT, Some((config, pass)))
+    }
+
+    fn with_sdf(
+        ctx: &Canvas,
+        width: usize,
+        height: usize,
+        sdf: Option<(SdfConfig, SdfPass)>,
+    ) -> Result<Self, Error> {
+        // Distance fields need to be interpolated for smooth edges.
+        let filter = if sdf.is_some() {
+            TextureFilter::Linear
+        } else {
+            TextureFilter::Nearest
+        };
+        let packer = ShelfPacker::new(ctx, width, height, ATLAS_MAX_SIZE, filter)?;
+        let pass = TexColPass::new(ctx)?;
+
+        Ok(Self {
+            inner: Rc::new(RefCell::new(Inner {
+                packer,
+                cache: HashMap::new(),
+                pass,
+                bitmap_buffer: Vec::new(),
+                next_font_id: 0,
+                frame: 0,
+                evict_after_frames: None,
+                full: false,
+                generation: 0,
+                sdf,
+                sdf_effect: SdfEffect::default(),
+            })),
+        })
+    }
+
+    /// Sets the size that the atlas texture may grow to in each dimension.
+    /// The default is 4096.
+    pub fn set_max_size(&self, max_size: usize) {
+        self.inner.borrow_mut().packer.set_max_size(max_size);
+    }
+
+    /// Returns the current size of the atlas texture.
+    pub fn size(&self) -> (usize, usize) {
+        let inner = self.inner.borrow();
+
+        (inner.packer.width(), inner.packer.height())
+    }
+
+    /// Enables evicting glyphs that have not been written for more than the
+    /// given number of frames, once the atlas has reached its maximum size
+    /// and a glyph does not fit anymore. Frames are counted by `end_frame`.
+    ///
+    /// Without eviction, which is the default, glyphs that do not fit are
+    /// skipped when writing text.
+    pub fn set_eviction(&self, evict_after_frames: Option<u32>) {
+        self.inner.borrow_mut().evict_after_frames = evict_after_frames;
+    }
+
+    /// Returns a number that changes whenever glyphs are moved within the
+    /// atlas by eviction. Text batches that are kept over multiple frames
+    /// need to be written again when it changes; batches that are written
+    /// every frame are not affected.
+    pub fn generation(&self) -> u64 {
+        self.inner.borrow().generation
+    }
+
+    /// Finishes a frame, which should be called once after all text of the
+    /// frame has been drawn. If eviction is enabled and glyphs did not fit
+    /// into the atlas during the frame, stale glyphs are evicted and the
+    /// remaining ones are re-packed, so that the missing glyphs fit when the
+    /// text is written again in the next frame.
+    pub fn end_frame(&self) {
+        let inner = &mut *self.inner.borrow_mut();
+        let frame = inner.frame;
+        inner.frame += 1;
+
+        let evict_after_frames = match inner.evict_after_frames {
+            Some(frames) if inner.full => frames as u64,
+            _ => return,
+        };
+        inner.full = false;
+
+        // Glyphs that were used last are packed first, so that if the
+        // atlas overflows again, the least recently used ones are dropped.
+        let packer = &inner.packer;
+        let mut kept: Vec<(GlyphKey, Glyph, Vec<u8>)> = inner
+            .cache
+            .drain()
+            .filter(|(_, glyph)| frame - glyph.last_used <= evict_after_frames)
+            .map(|(key, glyph)| {
+                let data = packer.read(glyph.pos.0, glyph.pos.1, glyph.size.0, glyph.size.1);
+                (key, glyph, data)
+            })
+            .collect();
+        kept.sort_by_key(|(_, glyph, _)| std::cmp::Reverse(glyph.last_used));
+
+        inner.packer.clear();
+        for (key, glyph, data) in kept {
+            if let Some((pos, uv_rect)) = inner.packer.insert(&data, glyph.size.0, glyph.size.1) {
+                inner.cache.insert(
+                    key,
+                    Glyph {
+                        uv_rect,
+                        pos,
+                        ..glyph
+                    },
+                );
+            }
+        }
+
+        inner.generation += 1;
+    }
+
+    /// Returns the fraction of the atlas that is occupied by cached glyphs,
+    /// in the range `[0..1]`.
+    pub fn usage(&self) -> f32 {
+        self.inner.borrow().packer.usage()
+    }
+
+    /// Returns the fraction of the atlas height that has been claimed by
+    /// shelves of the packer.
+    pub fn shelf_usage(&self) -> f32 {
+        self.inner.borrow().packer.shelf_usage()
+    }
+
+    /// Returns the number of glyphs that are currently cached, summed over
+    /// all fonts using this atlas.
+    pub fn num_cached_glyphs(&self) -> usize {
+        self.inner.borrow().cache.len()
+    }
+
+    /// Returns the configuration of an atlas that stores signed distance
+    /// fields, or `None` for a regular atlas.
+    pub fn sdf_config(&self) -> Option<SdfConfig> {
+        self.inner.borrow().sdf.as_ref().map(|(config, _)| *config)
+    }
+
+    pub fn sdf_effect(&self) -> SdfEffect {
+        self.inner.borrow().sdf_effect
+    }
+
+    /// Sets the outline and shadow of text that is drawn from an SDF atlas.
+    /// This has no effect on regular atlases.
+    pub fn set_sdf_effect(&self, effect: SdfEffect) {
+        self.inner.borrow_mut().sdf_effect = effect;
+    }
+
+    /// Calls `f` with the atlas texture.
+    pub fn with_texture<R>(&self, f: impl FnOnce(&Texture) -> R) -> R {
+        f(self.inner.borrow().packer.texture())
+    }
+
+    /// Borrows the atlas texture. Glyphs cannot be added to the atlas while
+    /// the texture is borrowed.
+    pub(crate) fn texture(&self) -> Ref<'_, Texture> {
+        Ref::map(self.inner.borrow(), |inner| inner.packer.texture())
+    }
+
+    /// Draws text with `TextBlend::Additive`.
+    pub fn draw(
+        &self,
+        ctx: &Canvas,
+        transform: &Matrix3<f32>,
+        draw_unit: &DrawUnit<TexColVertex>,
+    ) -> Result<(), Error> {
+        self.draw_with_blend(ctx, transform, TextBlend::default(), draw_unit)
+    }
+
+    pub fn draw_with_blend(
+        &self,
+        ctx: &Canvas,
+        transform: &Matrix3<f32>,
+        blend: TextBlend,
+        draw_unit: &DrawUnit<TexColVertex>,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.borrow_mut();
+
+        // The glyphs are stored with premultiplied alpha.
+        let destination = match blend {
+            TextBlend::Additive => BlendFactor::One,
+            TextBlend::Over => BlendFactor::OneMinusSrcAlpha,
+        };
+        let _blend = BlendScope::new(
+            ctx.golem_ctx(),
+            BlendMode {
+                equation: BlendEquation::Same(BlendOperation::Add),
+                function: BlendFunction::Same {
+                    source: BlendFactor::One,
+                    destination,
+                },
+                ..Default::default()
+            },
+        );
+
+        if let Some((config, pass)) = inner.sdf.as_mut() {
+            return pass.draw(
+                ctx,
+                transform,
+                inner.packer.texture(),
+                inner.packer.uv_scale(),
+                config,
+                &inner.sdf_effect,
+                draw_unit,
+            );
+        }
+
+        let effect = TexColEffect {
+            uv_scale: inner.packer.uv_scale(),
+            ..TexColEffect::default()
+        };
+
+        inner
+            .pass
+            .draw_with_effect(transform, inner.packer.texture(), &effect, draw_unit)
+    }
+
+    pub fn debug_draw(&self, ctx: &mut Canvas, pos: Point2<f32>) -> Result<(), Error> {
+        self.with_texture(|texture| ctx.debug_tex(pos, texture))
+    }
+
+    pub(crate) fn register_font(&self) -> FontId {
+        let mut inner = self.inner.borrow_mut();
+        let font_id = inner.next_font_id;
+        inner.next_font_id += 1;
+
+        font_id
+    }
+
+    /// Returns the number of pixels by which glyphs with the given blur
+    /// radius are padded on every side.
+    pub(crate) fn glyph_padding(&self, blur_radius: u32) -> u32 {
+        match self.inner.borrow().sdf.as_ref() {
+            Some((config, _)) => config.spread,
+            None => blur_radius,
+        }
+    }
+
+    /// Returns the UV rect of a glyph in texels, rasterizing and inserting
+    /// it into the atlas if it is not cached yet. Returns `None` if the
+    /// glyph does not fit into the atlas.
+    ///
+    /// If `blur_radius` is nonzero, the glyph is blurred, which grows its
+    /// bitmap by `blur_radius` pixels on every side. Blurred variants are
+    /// cached separately from the sharp glyphs. SDF atlases ignore the blur
+    /// radius and pad every glyph by the spread of the field instead.
+    pub(crate) fn glyph_uv_rect(
+        &self,
+        font_id: FontId,
+        family: &FontFamily,
+        key: GlyphRasterConfig,
+        blur_radius: u32,
+    ) -> Option<AaRect> {
+        let inner = &mut *self.inner.borrow_mut();
+        let frame = inner.frame;
+        let sdf_spread = inner.sdf.as_ref().map(|(config, _)| config.spread);
+        let blur_radius = if sdf_spread.is_some() { 0 } else { blur_radius };
+
+        if let Some(glyph) = inner.cache.get_mut(&(font_id, key, blur_radius)) {
+            glyph.last_used = frame;
+            return Some(glyph.uv_rect);
+        }
+
+        let font = &family.fonts()[key.font_index];
+        let (metrics, alpha_bitmap) = font.rasterize(key.c, key.px);
+        let (alpha_bitmap, width, height) = if let Some(spread) = sdf_spread {
+            sdf::distance_field(
+                &alpha_bitmap,
+                metrics.width,
+                metrics.height,
+                spread as usize,
+            )
+        } else if blur_radius > 0 {
+            blur(
+                &alpha_bitmap,
+                metrics.width,
+                metrics.height,
+                blur_radius as usize,
+            )
+        } else {
+            (alpha_bitmap, metrics.width, metrics.height)
+        };
+
+        alpha_to_rgba(&alpha_bitmap, &mut inner.bitmap_buffer);
+
+        match inner
+            .packer
+            .insert(inner.bitmap_buffer.as_slice(), width, height)
+        {
+            Some((pos, uv_rect)) => {
+                inner.cache.insert(
+                    (font_id, key, blur_radius),
+                    Glyph {
+                        uv_rect,
+                        pos,
+                        size: (width, height),
+                        last_used: frame,
+                    },
+                );
+
+                Some(uv_rect)
+            }
+            None => {
+                if !inner.full {
+                    log::warn!("Glyph atlas is full, skipping glyphs");
+                }
+                inner.full = true;
+
+                None
+            }
+        }
+    }
+}
+
+fn alpha_to_rgba(bitmap: &[u8], output: &mut Vec<u8>) {
+    output.clear();
+    for v in bitmap {
+        let v = *v;
+        output.extend_from_slice(&[v, v, v, v]);
+    }
+}
+
+/// Blurs an alpha bitmap with a separable Gaussian kernel, padding it by
+/// `radius` on every side so that the blur is not cut off.
+fn blur(bitmap: &[u8], width: usize, height: usize, radius: usize) -> (Vec<u8>, usize, usize) {
+    let sigma = radius as f32 / 2.0;
+    let kernel: Vec<f32> = (0..=2 * radius)
+        .map(|i| {
+            let x = i as f32 - radius as f32;
+            (-x * x / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let kernel_sum: f32 = kernel.iter().sum();
+
+    let (out_width, out_height) = (width + 2 * radius, height + 2 * radius);
+
+    // Blur the rows into the padded width.
+    let mut rows = vec![0.0; out_width * height];
+    for y in 0..height {
+        for x in 0..out_width {
+            rows[y * out_width + x] = kernel
+                .iter()
+                .enumerate()
+                .filter_map(|(i, weight)| {
+                    let src_x = (x + i).checked_sub(2 * radius)?;
+                    if src_x < width {
+                        Some(weight * bitmap[y * width + src_x] as f32)
+                    } else {
+                        None
+                    }
+                })
+                .sum::<f32>()
+                / kernel_sum;
+        }
+    }
+
+    // Blur the columns into the padded height.
+    let mut output = vec![0; out_width * out_height];
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let value = kernel
+                .iter()
+                .enumerate()
+                .filter_map(|(i, weight)| {
+                    let src_y = (y + i).checked_sub(2 * radius)?;
+                    if src_y < height {
+                        Some(weight * rows[src_y * out_width + x])
+                    } else {
+                        None
+                    }
+                })
+                .sum::<f32>()
+                / kernel_sum;
+            output[y * out_width + x] = value.round().min(255.0) as u8;
+        }
+    }
+
+    (output, out_width, out_height)
+}
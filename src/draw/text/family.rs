@@ -0,0 +1,110 @@
+//! Fonts with per-glyph fallback.
+//!
+//! A single font rarely covers everything that players type or that
+//! translations contain. A `FontFamily` holds an ordered list of fonts, and
+//! takes each character from the first font that has a glyph for it, so that
+//! e.g. a Latin UI font can fall back to a CJK font and a symbol font instead
+//! of drawing empty boxes.
+
+use std::ops::Deref;
+
+use fontdue::FontSettings;
+
+use crate::Error;
+
+pub struct FontFamily {
+    fonts: Vec<fontdue::Font>,
+}
+
+impl FontFamily {
+    /// Creates a family whose primary font is `font`.
+    pub fn new(font: fontdue::Font) -> Self {
+        Self { fonts: vec![font] }
+    }
+
+    /// Loads the primary font of a family. See `fontdue::FontSettings` for
+    /// the meaning of `scale`.
+    pub fn from_bytes<Data>(data: Data, scale: f32) -> Result<Self, Error>
+    where
+        Data: Deref<Target = [u8]>,
+    {
+        Ok(Self::new(load(data, scale)?))
+    }
+
+    /// Appends a font that is used for characters that none of the previous
+    /// fonts have a glyph for.
+    pub fn with_fallback(mut self, font: fontdue::Font) -> Self {
+        self.fonts.push(font);
+        self
+    }
+
+    /// Loads and appends a fallback font, see `with_fallback`.
+    pub fn with_fallback_bytes<Data>(self, data: Data, scale: f32) -> Result<Self, Error>
+    where
+        Data: Deref<Target = [u8]>,
+    {
+        Ok(self.with_fallback(load(data, scale)?))
+    }
+
+    /// Returns the fonts of the family, starting with the primary font.
+    pub fn fonts(&self) -> &[fontdue::Font] {
+        &self.fonts
+    }
+
+    pub fn primary(&self) -> &fontdue::Font {
+        &self.fonts[0]
+    }
+
+    /// Returns the index of the first font that has a glyph for `c`. If no
+    /// font has one, the primary font is used, which then draws its glyph
+    /// for missing characters.
+    pub fn font_index(&self, c: char) -> usize {
+        self.fonts
+            .iter()
+            .position(|font| font.lookup_glyph_index(c) != 0)
+            .unwrap_or(0)
+    }
+
+    /// Returns the font that `c` is drawn with.
+    pub fn font_for(&self, c: char) -> &fontdue::Font {
+        &self.fonts[self.font_index(c)]
+    }
+
+    /// Splits `text` into runs of consecutive characters that are drawn with
+    /// the same font, given by its index.
+    pub fn runs<'a>(&self, text: &'a str) -> Vec<(usize, &'a str)> {
+        let mut runs: Vec<(usize, &'a str)> = Vec::new();
+        let mut run_start = 0;
+        let mut run_font = None;
+
+        for (i, c) in text.char_indices() {
+            let font_index = self.font_index(c);
+            match run_font {
+                Some(index) if index != font_index => {
+                    runs.push((index, &text[run_start..i]));
+                    run_start = i;
+                }
+                _ => (),
+            }
+            run_font = Some(font_index);
+        }
+
+        if let Some(index) = run_font {
+            runs.push((index, &text[run_start..]));
+        }
+
+        runs
+    }
+}
+
+fn load<Data>(data: Data, scale: f32) -> Result<fontdue::Font, Error>
+where
+    Data: Deref<Target = [u8]>,
+{
+    let settings = FontSettings {
+        scale,
+        ..Default::default()
+    };
+
+    fontdue::Font::from_bytes(data, settings).map_err(|msg| Error::Font(msg.into()))
+}
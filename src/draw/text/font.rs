@@ -1,68 +1,223 @@
-use std::{collections::HashMap, ops::Deref};
+use std::{cell::Ref, ops::Deref};
 
-use fontdue::{
-    layout::{CoordinateSystem, GlyphRasterConfig, Layout, LayoutSettings, TextStyle},
-    FontSettings,
+use fontdue::layout::{
+    CoordinateSystem, HorizontalAlign, Layout, LayoutSettings, TextStyle, VerticalAlign, WrapStyle,
 };
-use golem::blend::{BlendEquation, BlendFactor, BlendFunction, BlendMode, BlendOperation};
 use nalgebra::{Matrix3, Point2, Point3, Vector2};
 
 use crate::{
     draw::{
-        text::packer::ShelfPacker, DrawUnit, Quad, TexColPass, TexColVertex, Texture, TriBatch,
+        text::{
+            atlas::{FontId, GlyphAtlas, TextBlend},
+            family::FontFamily,
+            line_break::wrap_lines,
+            mirror::TextMirror,
+        },
+        DrawUnit, Quad, TexColVertex, TriBatch,
     },
-    AaRect, Canvas, Color4, Error,
+    AaRect, Canvas, Color4, Error, Texture, YAxis,
 };
 
 pub type TextBatch = TriBatch<TexColVertex>;
 
-struct Glyph {
-    uv_rect: AaRect,
+/// How `Font::write_layout` arranges text in a box.
+///
+/// Alignment needs the corresponding maximum to be set, since text is
+/// aligned within the box given by `max_width` and `max_height`. Lines are
+/// broken like in `Font::wrap_lines`.
+#[derive(Debug, Clone, Copy)]
+pub struct TextLayout {
+    /// If set, lines are wrapped once they get wider than this.
+    pub max_width: Option<f32>,
+
+    /// If set, the height of the box in which text is aligned vertically.
+    /// Text that does not fit still overflows.
+    pub max_height: Option<f32>,
+
+    pub horizontal_align: HorizontalAlign,
+    pub vertical_align: VerticalAlign,
+    pub wrap_style: WrapStyle,
 }
 
-pub struct Font {
-    font: fontdue::Font,
-    layout: Layout,
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self {
+            max_width: None,
+            max_height: None,
+            horizontal_align: HorizontalAlign::Left,
+            vertical_align: VerticalAlign::Top,
+            wrap_style: WrapStyle::Word,
+        }
+    }
+}
+
+/// The alignment of text in the rect that is given to
+/// `Font::write_wrapped`.
+#[derive(Debug, Clone, Copy)]
+pub struct TextAlign {
+    pub horizontal: HorizontalAlign,
+    pub vertical: VerticalAlign,
+}
+
+impl TextAlign {
+    pub fn new(horizontal: HorizontalAlign, vertical: VerticalAlign) -> Self {
+        Self {
+            horizontal,
+            vertical,
+        }
+    }
+
+    /// Centers text horizontally and vertically, e.g. for buttons.
+    pub fn center() -> Self {
+        Self::new(HorizontalAlign::Center, VerticalAlign::Middle)
+    }
+}
+
+impl Default for TextAlign {
+    fn default() -> Self {
+        Self::new(HorizontalAlign::Left, VerticalAlign::Top)
+    }
+}
+
+/// A blurred copy of text that is written underneath it, for glowing text or
+/// drop shadows.
+///
+/// The blurred glyphs are rasterized once and cached in the atlas next to
+/// the sharp glyphs, so text with a shadow is drawn in the same draw call as
+/// text without.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextShadow {
+    /// The offset of the shadow relative to the text.
+    pub offset: Vector2<f32>,
+
+    /// The blur radius in pixels.
+    pub radius: u32,
 
-    packer: ShelfPacker,
-    cache: HashMap<GlyphRasterConfig, Glyph>,
+    pub color: Color4,
+}
 
-    pass: TexColPass,
+impl TextShadow {
+    /// A glow around the text. Glows look best when drawn with
+    /// `TextBlend::Additive`.
+    pub fn glow(radius: u32, color: Color4) -> Self {
+        Self {
+            offset: Vector2::zeros(),
+            radius,
+            color,
+        }
+    }
 
-    bitmap_buffer: Vec<u8>,
+    /// A shadow that is offset from the text. Dark shadows need to be drawn
+    /// with `TextBlend::Over`, see `Font::draw_with_blend`.
+    pub fn drop(offset: Vector2<f32>, radius: u32, color: Color4) -> Self {
+        Self {
+            offset,
+            radius,
+            color,
+        }
+    }
 }
 
-const ATLAS_WIDTH: usize = 512;
-const ATLAS_HEIGHT: usize = 256;
+/// Text drawing with a `FontFamily`, whose glyphs are cached in a
+/// `GlyphAtlas`.
+pub struct Font {
+    family: FontFamily,
+    layout: Layout,
+
+    atlas: GlyphAtlas,
+    font_id: FontId,
+
+    mirror: Option<TextMirror>,
+    raster_scale: f32,
+}
 
 impl Font {
+    /// Loads a font that uses its own glyph atlas.
     pub fn from_bytes<Data>(ctx: &Canvas, data: Data, scale: f32) -> Result<Self, Error>
     where
         Data: Deref<Target = [u8]>,
     {
-        let settings = FontSettings {
-            scale,
-            ..Default::default()
-        };
+        let atlas = GlyphAtlas::new(ctx)?;
 
-        let font =
-            fontdue::Font::from_bytes(data, settings).map_err(|msg| Error::Font(msg.into()))?;
+        Self::from_bytes_with_atlas(data, scale, atlas)
+    }
 
-        let packer = ShelfPacker::new(ctx, ATLAS_WIDTH, ATLAS_HEIGHT)?;
-        let layout = Layout::new(CoordinateSystem::PositiveYDown);
+    /// Loads a font that caches its glyphs in the given `atlas`, which may be
+    /// shared with other fonts.
+    pub fn from_bytes_with_atlas<Data>(
+        data: Data,
+        scale: f32,
+        atlas: GlyphAtlas,
+    ) -> Result<Self, Error>
+    where
+        Data: Deref<Target = [u8]>,
+    {
+        Ok(Self::from_family_with_atlas(
+            FontFamily::from_bytes(data, scale)?,
+            atlas,
+        ))
+    }
 
-        let pass = TexColPass::new(ctx)?;
+    /// Creates a font that falls back to the other fonts of `family` for
+    /// characters that its primary font lacks, using its own glyph atlas.
+    pub fn from_family(ctx: &Canvas, family: FontFamily) -> Result<Self, Error> {
+        Ok(Self::from_family_with_atlas(family, GlyphAtlas::new(ctx)?))
+    }
 
-        Ok(Font {
-            font,
-            packer,
+    /// Creates a font from `family` that caches its glyphs in the given
+    /// `atlas`, which may be shared with other fonts.
+    ///
+    /// All fonts of the family share one id in the atlas; their glyphs are
+    /// told apart by the font index in the glyph's raster config.
+    pub fn from_family_with_atlas(family: FontFamily, atlas: GlyphAtlas) -> Self {
+        let layout = Layout::new(CoordinateSystem::PositiveYDown);
+        let font_id = atlas.register_font();
+
+        Font {
+            family,
             layout,
-            cache: HashMap::new(),
-            pass,
-            bitmap_buffer: Vec::new(),
-        })
+            atlas,
+            font_id,
+            mirror: None,
+            raster_scale: 1.0,
+        }
+    }
+
+    pub fn family(&self) -> &FontFamily {
+        &self.family
     }
 
+    /// Sets a mirror that receives all text written with this font, making
+    /// it available to screen readers.
+    pub fn set_mirror(&mut self, mirror: Option<TextMirror>) {
+        self.mirror = mirror;
+    }
+
+    pub fn raster_scale(&self) -> f32 {
+        self.raster_scale
+    }
+
+    /// Sets the number of screen pixels that one unit of the space that text
+    /// is written in covers, so that glyphs are rasterized at the size at
+    /// which they end up on the screen. Defaults to 1, which is right for
+    /// text in screen coordinates.
+    ///
+    /// For text in world space, set this to the zoom of the camera, or to
+    /// `geom::transform_scale` of the view matrix, whenever it changes.
+    /// Otherwise, text is magnified from small glyphs and blurs when zooming
+    /// in. The size in pixels is rounded, so that zooming smoothly does not
+    /// cache a new set of glyphs for every frame.
+    pub fn set_raster_scale(&mut self, scale: f32) {
+        assert!(scale > 0.0, "raster scale must be positive");
+
+        self.raster_scale = scale;
+    }
+
+    /// Writes `text` with its top left corner at `pos`.
+    ///
+    /// If the Y axis of `batch` points up, `pos` still is the top left
+    /// corner of the text, and lines grow downwards, i.e. towards smaller Y
+    /// coordinates.
     pub fn write(
         &mut self,
         size: f32,
@@ -71,16 +226,253 @@ impl Font {
         text: &str,
         batch: &mut TextBatch,
     ) {
-        let settings = LayoutSettings {
-            x: pos.x,
-            y: pos.y,
-            max_width: None,
-            ..Default::default()
+        if let Some(mirror) = self.mirror.as_ref() {
+            mirror.write(text);
+        }
+
+        self.push_glyphs(size, pos, Vector2::zeros(), color, 0, text, batch);
+    }
+
+    /// Writes `text` laid out according to `layout`, where `pos` is the top
+    /// left corner of the layout box. Returns the bounding box of the written
+    /// glyphs.
+    pub fn write_layout(
+        &mut self,
+        size: f32,
+        pos: Point3<f32>,
+        color: Color4,
+        layout: &TextLayout,
+        text: &str,
+        batch: &mut TextBatch,
+    ) -> AaRect {
+        if let Some(mirror) = self.mirror.as_ref() {
+            mirror.write(text);
+        }
+
+        // Without a maximum width, lines are only broken where the text
+        // requires it, e.g. at `\n`.
+        let max_width = layout.max_width.unwrap_or(f32::INFINITY);
+        let lines = self.wrap_lines_with_style(size, max_width, layout.wrap_style, text);
+        let align = TextAlign::new(layout.horizontal_align, layout.vertical_align);
+
+        self.push_lines(
+            size,
+            pos,
+            layout.max_width,
+            layout.max_height,
+            align,
+            color,
+            &lines,
+            batch,
+        )
+    }
+
+    /// Writes `text` on top of a blurred copy of itself.
+    pub fn write_with_shadow(
+        &mut self,
+        size: f32,
+        pos: Point3<f32>,
+        color: Color4,
+        shadow: &TextShadow,
+        text: &str,
+        batch: &mut TextBatch,
+    ) {
+        self.push_glyphs(
+            size,
+            pos,
+            shadow.offset,
+            shadow.color,
+            shadow.radius,
+            text,
+            batch,
+        );
+
+        self.write(size, pos, color, text, batch);
+    }
+
+    /// Writes `text` with glyphs that are blurred by `radius` pixels,
+    /// without mirroring it.
+    pub fn write_blurred(
+        &mut self,
+        size: f32,
+        pos: Point3<f32>,
+        color: Color4,
+        radius: u32,
+        text: &str,
+        batch: &mut TextBatch,
+    ) {
+        self.push_glyphs(size, pos, Vector2::zeros(), color, radius, text, batch);
+    }
+
+    /// Writes `text` into `rect`, breaking it into lines that are at most
+    /// as wide as `rect` and aligning the lines in `rect` according to
+    /// `align`, e.g. for dialog boxes and UI labels. See `wrap_lines`.
+    /// Returns the bounding box of the written glyphs.
+    ///
+    /// Text that is higher than `rect` overflows; `wrapped_text_size` can be
+    /// used for checking beforehand.
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_wrapped(
+        &mut self,
+        size: f32,
+        rect: &AaRect,
+        z: f32,
+        align: TextAlign,
+        color: Color4,
+        text: &str,
+        batch: &mut TextBatch,
+    ) -> AaRect {
+        if let Some(mirror) = self.mirror.as_ref() {
+            mirror.write(text);
+        }
+
+        let lines = self.wrap_lines(size, rect.size.x, text);
+        let pos = match batch.y_axis() {
+            YAxis::Down => Point3::new(rect.min().x, rect.min().y, z),
+            YAxis::Up => Point3::new(rect.min().x, rect.max().y, z),
         };
-        self.layout.reset(&settings);
 
-        self.layout
-            .append(&[&self.font], &TextStyle::new(text, size, 0));
+        self.push_lines(
+            size,
+            pos,
+            Some(rect.size.x),
+            Some(rect.size.y),
+            align,
+            color,
+            &lines,
+            batch,
+        )
+    }
+
+    /// Pushes `lines` below each other, aligned within a box whose top left
+    /// corner is at `pos`. If the box has no width or height, text is
+    /// aligned at its left or top. Returns the bounding box of the glyphs.
+    #[allow(clippy::too_many_arguments)]
+    fn push_lines(
+        &mut self,
+        size: f32,
+        pos: Point3<f32>,
+        box_width: Option<f32>,
+        box_height: Option<f32>,
+        align: TextAlign,
+        color: Color4,
+        lines: &[&str],
+        batch: &mut TextBatch,
+    ) -> AaRect {
+        let line_height = self.line_height(size);
+        let text_height = lines.len() as f32 * line_height;
+
+        let top = match (align.vertical, box_height) {
+            (VerticalAlign::Top, _) | (_, None) => 0.0,
+            (VerticalAlign::Middle, Some(height)) => (height - text_height) / 2.0,
+            (VerticalAlign::Bottom, Some(height)) => height - text_height,
+        };
+
+        let mut bounds: Option<AaRect> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let width = *self.caret_offsets(size, line).last().unwrap();
+            let left = match (align.horizontal, box_width) {
+                (HorizontalAlign::Left, _) | (_, None) => 0.0,
+                (HorizontalAlign::Center, Some(box_width)) => (box_width - width) / 2.0,
+                (HorizontalAlign::Right, Some(box_width)) => box_width - width,
+            };
+            let offset = Vector2::new(left, top + i as f32 * line_height);
+
+            let line_bounds = self.push_glyphs(size, pos, offset, color, 0, line, batch);
+            bounds = Some(match bounds {
+                Some(bounds) => AaRect::bounding(&[
+                    bounds.min(),
+                    bounds.max(),
+                    line_bounds.min(),
+                    line_bounds.max(),
+                ]),
+                None => line_bounds,
+            });
+        }
+
+        bounds.unwrap_or_else(|| AaRect::from_top_left(pos.xy(), Vector2::zeros()))
+    }
+
+    /// Breaks `text` into lines that are at most `max_width` wide when
+    /// written at `size`.
+    ///
+    /// Lines are broken at the opportunities given by the Unicode line
+    /// breaking algorithm, so that text in languages without spaces, such as
+    /// Chinese or Japanese, wraps as well. Words that do not fit into a line
+    /// by themselves overflow.
+    pub fn wrap_lines<'a>(&self, size: f32, max_width: f32, text: &'a str) -> Vec<&'a str> {
+        self.wrap_lines_with_style(size, max_width, WrapStyle::Word, text)
+    }
+
+    fn wrap_lines_with_style<'a>(
+        &self,
+        size: f32,
+        max_width: f32,
+        wrap_style: WrapStyle,
+        text: &'a str,
+    ) -> Vec<&'a str> {
+        wrap_lines(text, max_width, wrap_style, |line| {
+            line.chars()
+                .map(|c| self.family.font_for(c).metrics(c, size).advance_width)
+                .sum()
+        })
+    }
+
+    /// Returns the size of the box that `write_wrapped` would fill with
+    /// `text`.
+    pub fn wrapped_text_size(&self, size: f32, max_width: f32, text: &str) -> Vector2<f32> {
+        let lines = self.wrap_lines(size, max_width, text);
+        let width = lines
+            .iter()
+            .map(|line| *self.caret_offsets(size, line).last().unwrap())
+            .fold(0.0, f32::max);
+
+        Vector2::new(width, lines.len() as f32 * self.line_height(size))
+    }
+
+    /// Returns the distance between the baselines of consecutive lines.
+    pub fn line_height(&self, size: f32) -> f32 {
+        self.family
+            .primary()
+            .horizontal_line_metrics(size)
+            .map_or(size, |metrics| metrics.new_line_size)
+    }
+
+    /// Pushes the glyphs of `text`, shifted by `offset`, which is given with
+    /// Y pointing down, regardless of the batch's Y axis. Returns the bounding
+    /// box of the glyphs, without the padding of blurred or SDF glyphs.
+    #[allow(clippy::too_many_arguments)]
+    fn push_glyphs(
+        &mut self,
+        size: f32,
+        pos: Point3<f32>,
+        offset: Vector2<f32>,
+        color: Color4,
+        blur_radius: u32,
+        text: &str,
+        batch: &mut TextBatch,
+    ) -> AaRect {
+        // Glyphs are laid out and rasterized at their size on the screen,
+        // and then scaled back into text space. SDF glyphs scale well, so
+        // they are always rasterized at the same size.
+        let raster_size = match self.atlas.sdf_config() {
+            Some(config) => config.glyph_size.max(1.0),
+            None => (size * self.raster_scale).round().max(1.0),
+        };
+        let scale = size / raster_size;
+        let padding = 2.0 * self.atlas.glyph_padding(blur_radius) as f32;
+
+        self.layout.reset(&LayoutSettings::default());
+        for (font_index, run) in self.family.runs(text) {
+            self.layout.append(
+                self.family.fonts(),
+                &TextStyle::new(run, raster_size, font_index),
+            );
+        }
+
+        let flip = batch.y_axis().sign();
+
+        let mut corners = Vec::new();
 
         for &glyph_pos in self.layout.glyphs() {
             // Ignore empty glyphs (e.g. space).
@@ -88,68 +480,154 @@ impl Font {
                 continue;
             }
 
-            let (font, packer, bitmap_buffer) =
-                (&self.font, &mut self.packer, &mut self.bitmap_buffer);
-
-            let glyph = self.cache.entry(glyph_pos.key).or_insert_with(|| {
-                let (metrics, alpha_bitmap) = font.rasterize(glyph_pos.key.c, size);
-
-                Self::alpha_to_rgba(&alpha_bitmap, bitmap_buffer);
-
-                let uv_rect = packer
-                    .insert(bitmap_buffer.as_slice(), metrics.width, metrics.height)
-                    .unwrap(); // TODO: unwrap in atlas insert
-
-                Glyph { uv_rect }
-            });
+            let uv_rect = match self.atlas.glyph_uv_rect(
+                self.font_id,
+                &self.family,
+                glyph_pos.key,
+                blur_radius,
+            ) {
+                Some(uv_rect) => uv_rect,
+                None => continue,
+            };
 
             let rect_center = Point2::new(
-                glyph_pos.x + glyph_pos.width as f32 / 2.0,
-                glyph_pos.y + glyph_pos.height as f32 / 2.0,
+                pos.x + offset.x + scale * (glyph_pos.x + glyph_pos.width as f32 / 2.0),
+                pos.y + flip * (offset.y + scale * (glyph_pos.y + glyph_pos.height as f32 / 2.0)),
             );
-            let rect_size = Vector2::new(glyph_pos.width as f32, glyph_pos.height as f32);
+            // A negative height mirrors the quad, keeping glyphs upright.
+            let rect_size = scale
+                * Vector2::new(
+                    glyph_pos.width as f32 + padding,
+                    flip * (glyph_pos.height as f32 + padding),
+                );
 
             batch.push_quad(
                 &Quad::axis_aligned(rect_center, rect_size),
                 pos.z,
-                glyph.uv_rect,
+                uv_rect,
                 color,
             );
+
+            let half_size =
+                scale * Vector2::new(glyph_pos.width as f32, glyph_pos.height as f32) / 2.0;
+            corners.push(rect_center - half_size);
+            corners.push(rect_center + half_size);
+        }
+
+        if corners.is_empty() {
+            let corner = Point2::new(pos.x + offset.x, pos.y + flip * offset.y);
+            AaRect::from_top_left(corner, Vector2::zeros())
+        } else {
+            AaRect::bounding(&corners)
         }
     }
 
+    /// Returns the size of the box that `write` would fill with `text`,
+    /// measured from the position that is passed to `write`.
+    pub fn text_size(&mut self, size: f32, text: &str) -> Vector2<f32> {
+        self.layout.reset(&LayoutSettings::default());
+        for (font_index, run) in self.family.runs(text) {
+            self.layout
+                .append(self.family.fonts(), &TextStyle::new(run, size, font_index));
+        }
+
+        self.layout
+            .glyphs()
+            .iter()
+            .fold(Vector2::zeros(), |extent, glyph_pos| {
+                Vector2::new(
+                    extent.x.max(glyph_pos.x + glyph_pos.width as f32),
+                    extent.y.max(glyph_pos.y + glyph_pos.height as f32),
+                )
+            })
+    }
+
+    /// Returns the horizontal caret positions for a single line of `text`,
+    /// relative to the position that is passed to `write`.
+    ///
+    /// The result has one more entry than `text` has characters: entry `i`
+    /// is the position in front of the `i`-th character, and the last entry
+    /// is the position after the last character.
+    pub fn caret_offsets(&self, size: f32, text: &str) -> Vec<f32> {
+        let mut offsets = Vec::with_capacity(text.chars().count() + 1);
+        let mut x = 0.0;
+
+        offsets.push(x);
+        for c in text.chars() {
+            x += self.family.font_for(c).metrics(c, size).advance_width;
+            offsets.push(x);
+        }
+
+        offsets
+    }
+
+    /// Returns the index of the caret position in a single line of `text`
+    /// that is closest to `x`, relative to the position that is passed to
+    /// `write`.
+    pub fn hit_test(&self, size: f32, text: &str, x: f32) -> usize {
+        self.caret_offsets(size, text)
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| (*a - x).abs().total_cmp(&(*b - x).abs()))
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Draws a batch of text with `TextBlend::Additive`.
+    ///
+    /// If the batch contains text written with other fonts, these fonts must
+    /// share this font's atlas.
     pub fn draw(
         &mut self,
         ctx: &Canvas,
         transform: &Matrix3<f32>,
         draw_unit: &DrawUnit<TexColVertex>,
     ) -> Result<(), Error> {
-        ctx.golem_ctx().set_blend_mode(Some(BlendMode {
-            equation: BlendEquation::Same(BlendOperation::Add),
-            function: BlendFunction::Same {
-                source: BlendFactor::One,
-                destination: BlendFactor::One,
-            },
-            ..Default::default()
-        }));
+        self.draw_with_blend(ctx, transform, TextBlend::default(), draw_unit)
+    }
+
+    /// Draws a batch of text, blended with `blend`.
+    pub fn draw_with_blend(
+        &mut self,
+        ctx: &Canvas,
+        transform: &Matrix3<f32>,
+        blend: TextBlend,
+        draw_unit: &DrawUnit<TexColVertex>,
+    ) -> Result<(), Error> {
+        self.atlas.draw_with_blend(ctx, transform, blend, draw_unit)
+    }
 
-        self.pass
-            .draw(transform, self.packer.texture(), draw_unit)?;
+    pub fn atlas(&self) -> &GlyphAtlas {
+        &self.atlas
+    }
 
-        ctx.golem_ctx().set_blend_mode(None);
+    /// Borrows the texture of the font's glyph atlas.
+    ///
+    /// Writing text with any font of the atlas panics while the texture is
+    /// borrowed, since it may need to add glyphs.
+    #[deprecated(note = "use `Font::atlas` and `GlyphAtlas::with_texture` instead")]
+    pub fn texture(&self) -> Ref<'_, Texture> {
+        self.atlas.texture()
+    }
 
-        Ok(())
+    /// Returns the fraction of the glyph atlas that is occupied by cached
+    /// glyphs, in the range `[0..1]`.
+    ///
+    /// Every combination of character and size is cached separately, so
+    /// writing text in many different sizes quickly fills up the atlas.
+    pub fn atlas_usage(&self) -> f32 {
+        self.atlas.usage()
     }
 
-    pub fn texture(&self) -> &Texture {
-        self.packer.texture()
+    /// Returns the number of glyphs that are currently cached in the atlas.
+    pub fn num_cached_glyphs(&self) -> usize {
+        self.atlas.num_cached_glyphs()
     }
 
-    fn alpha_to_rgba(bitmap: &[u8], output: &mut Vec<u8>) {
-        output.clear();
-        for v in bitmap {
-            let v = *v;
-            output.extend_from_slice(&[v, v, v, v]);
-        }
+    /// Draws the glyph atlas texture on the screen, with its top left corner
+    /// at `pos` in screen coordinates.
+    ///
+    /// This is meant for diagnosing which glyphs end up in the cache.
+    pub fn debug_atlas(&self, ctx: &mut Canvas, pos: Point2<f32>) -> Result<(), Error> {
+        self.atlas.debug_draw(ctx, pos)
     }
 }
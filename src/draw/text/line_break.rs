@@ -0,0 +1,76 @@
+//! Breaking text into lines at the opportunities given by the Unicode line
+//! breaking algorithm ([UAX #14](https://www.unicode.org/reports/tr14/)).
+//!
+//! Unlike breaking at spaces, this also breaks between the characters of
+//! scripts that are written without spaces, such as Chinese and Japanese,
+//! and keeps punctuation with the word that it belongs to.
+
+use fontdue::layout::WrapStyle;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+/// Splits `text` into lines that are at most `max_width` wide, as measured
+/// by `width`. Lines are broken at mandatory breaks, e.g. `\n`, and at the
+/// last break opportunity before exceeding `max_width`. Whitespace at the end
+/// of lines is removed.
+///
+/// With `WrapStyle::Letter`, lines may also be broken between any two
+/// characters. Segments without break opportunity that are wider than
+/// `max_width` by themselves are put on a line of their own, where they
+/// overflow.
+pub(crate) fn wrap_lines(
+    text: &str,
+    max_width: f32,
+    wrap_style: WrapStyle,
+    width: impl Fn(&str) -> f32,
+) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+    let mut last_allowed = None;
+
+    for (index, opportunity) in break_opportunities(text, wrap_style) {
+        if width(text[line_start..index].trim_end()) > max_width {
+            if let Some(last_allowed) = last_allowed.take() {
+                lines.push(text[line_start..last_allowed].trim_end());
+                line_start = last_allowed;
+            }
+        }
+
+        match opportunity {
+            BreakOpportunity::Mandatory => {
+                lines.push(text[line_start..index].trim_end());
+                line_start = index;
+                last_allowed = None;
+            }
+            BreakOpportunity::Allowed => {
+                last_allowed = Some(index);
+            }
+        }
+    }
+
+    lines
+}
+
+fn break_opportunities(text: &str, wrap_style: WrapStyle) -> Vec<(usize, BreakOpportunity)> {
+    match wrap_style {
+        WrapStyle::Word => linebreaks(text).collect(),
+        WrapStyle::Letter => {
+            let mandatory: Vec<usize> = linebreaks(text)
+                .filter(|(_, opportunity)| *opportunity == BreakOpportunity::Mandatory)
+                .map(|(index, _)| index)
+                .collect();
+
+            text.char_indices()
+                .skip(1)
+                .map(|(index, _)| index)
+                .chain(std::iter::once(text.len()))
+                .map(|index| {
+                    if mandatory.contains(&index) {
+                        (index, BreakOpportunity::Mandatory)
+                    } else {
+                        (index, BreakOpportunity::Allowed)
+                    }
+                })
+                .collect()
+        }
+    }
+}
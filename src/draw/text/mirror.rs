@@ -0,0 +1,160 @@
+//! Mirroring of rendered text into the DOM for screen readers.
+
+use std::{cell::RefCell, rc::Rc};
+
+use web_sys::Element;
+
+use crate::{Canvas, Error};
+
+/// Style that hides an element visually while keeping it accessible to
+/// screen readers.
+const VISUALLY_HIDDEN_STYLE: &str = "position: absolute; width: 1px; height: 1px; \
+    margin: -1px; padding: 0; border: 0; overflow: hidden; \
+    clip: rect(0 0 0 0); white-space: nowrap;";
+
+struct Line {
+    text: String,
+    element: Element,
+}
+
+struct Inner {
+    document: web_sys::Document,
+    live_region: Element,
+    alert_region: Element,
+
+    /// Lines that are currently in the live region.
+    lines: Vec<Line>,
+
+    /// Lines that have been written since the last `update`.
+    written: Vec<String>,
+}
+
+/// A visually hidden `aria-live` DOM element that receives the text written
+/// with fonts, so that screen readers can announce it.
+///
+/// This is a cheaply clonable handle. Attach it to fonts with
+/// `Font::set_mirror`, and call `update` once per frame after all text has
+/// been written. Only lines that were not on screen in the previous frame
+/// are added to the DOM, so that static text is announced only once.
+#[derive(Clone)]
+pub struct TextMirror {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl TextMirror {
+    /// Creates the hidden DOM elements and inserts them after the canvas.
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let document = web_sys::window()
+            .ok_or(Error::NoWindow)?
+            .document()
+            .ok_or(Error::NoDocument)?;
+
+        let live_region = create_region(&document, "polite")?;
+        let alert_region = create_region(&document, "assertive")?;
+
+        let canvas = ctx.canvas();
+        for region in &[&alert_region, &live_region] {
+            canvas
+                .insert_adjacent_element("afterend", region)
+                .map_err(dom_error)?;
+        }
+
+        Ok(Self {
+            inner: Rc::new(RefCell::new(Inner {
+                document,
+                live_region,
+                alert_region,
+                lines: Vec::new(),
+                written: Vec::new(),
+            })),
+        })
+    }
+
+    /// Records a line of text that is on screen in the current frame. This
+    /// is called by `Font::write`, but it can also be used for text that is
+    /// drawn in other ways.
+    pub fn write(&self, text: &str) {
+        let text = text.trim();
+
+        if !text.is_empty() {
+            self.inner.borrow_mut().written.push(text.to_string());
+        }
+    }
+
+    /// Interrupts the screen reader to announce `text` immediately, e.g. for
+    /// important notifications.
+    pub fn announce(&self, text: &str) {
+        self.inner
+            .borrow()
+            .alert_region
+            .set_text_content(Some(text));
+    }
+
+    /// Synchronizes the DOM with the lines that have been written since the
+    /// last call.
+    pub fn update(&self) -> Result<(), Error> {
+        let mut inner = self.inner.borrow_mut();
+        let inner = &mut *inner;
+        let written = std::mem::replace(&mut inner.written, Vec::new());
+
+        // Match written lines with existing lines, so that lines which stay
+        // on screen keep their element and are not announced again.
+        let mut old_lines: Vec<Option<Line>> = inner.lines.drain(..).map(Some).collect();
+        let mut new_lines = Vec::with_capacity(written.len());
+
+        for text in written {
+            let existing = old_lines
+                .iter_mut()
+                .find(|line| line.as_ref().map_or(false, |line| line.text == text))
+                .and_then(Option::take);
+
+            let line = match existing {
+                Some(line) => line,
+                None => {
+                    let element = inner.document.create_element("p").map_err(dom_error)?;
+                    element.set_text_content(Some(&text));
+                    inner
+                        .live_region
+                        .append_child(&element)
+                        .map_err(dom_error)?;
+
+                    Line { text, element }
+                }
+            };
+
+            new_lines.push(line);
+        }
+
+        for line in old_lines.into_iter().flatten() {
+            line.element.remove();
+        }
+
+        inner.lines = new_lines;
+
+        Ok(())
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.live_region.remove();
+        self.alert_region.remove();
+    }
+}
+
+fn create_region(document: &web_sys::Document, live: &str) -> Result<Element, Error> {
+    let element = document.create_element("div").map_err(dom_error)?;
+
+    element
+        .set_attribute("aria-live", live)
+        .map_err(dom_error)?;
+    element
+        .set_attribute("style", VISUALLY_HIDDEN_STYLE)
+        .map_err(dom_error)?;
+
+    Ok(element)
+}
+
+fn dom_error(e: wasm_bindgen::JsValue) -> Error {
+    Error::Dom(format!("{:?}", e))
+}
@@ -1,4 +1,19 @@
+mod atlas;
+mod family;
 mod font;
+mod line_break;
+mod mirror;
+#[cfg(feature = "vector-text")]
+mod outline;
 mod packer;
+mod sdf;
 
-pub use font::{Font, TextBatch};
+pub use fontdue::layout::{HorizontalAlign, VerticalAlign, WrapStyle};
+
+pub use atlas::{GlyphAtlas, TextBlend};
+pub use family::FontFamily;
+pub use font::{Font, TextAlign, TextBatch, TextLayout, TextShadow};
+pub use mirror::TextMirror;
+#[cfg(feature = "vector-text")]
+pub use outline::{GlyphMesh, TextMesh, VectorFont};
+pub use sdf::{SdfConfig, SdfEffect};
@@ -0,0 +1,316 @@
+//! Text as triangle meshes that are tessellated from glyph outlines.
+//!
+//! Glyphs from the atlas become blurry once they are scaled far beyond the
+//! size at which they have been rasterized. `VectorFont` instead extracts the
+//! outlines of the glyphs and tessellates them into triangles, which stay
+//! sharp under any zoom and whose vertices can be moved individually, e.g.
+//! to extrude or animate huge title text. Tessellated text needs many more
+//! vertices than atlas text, so it is best used for a few large strings.
+
+use std::{collections::HashMap, ops::Range};
+
+use lyon_tessellation::{
+    math::{point, Point},
+    path::{self, Path},
+    BuffersBuilder, FillOptions, FillRule, FillTessellator, FillVertex, VertexBuffers,
+};
+use nalgebra::{Point2, Point3, Vector2};
+
+use crate::{
+    draw::{ColVertex, TriBatch},
+    Color4, Error,
+};
+
+/// The triangles of a single glyph.
+#[derive(Debug, Clone, Default)]
+pub struct GlyphMesh {
+    /// Vertex positions in ems, with Y pointing down, relative to the left
+    /// end of the glyph's baseline.
+    pub positions: Vec<Point2<f32>>,
+
+    /// Triangles, given as three indices into `positions` each.
+    pub indices: Vec<u32>,
+
+    /// The horizontal distance to the next glyph in ems.
+    pub advance: f32,
+}
+
+/// The triangles of a string of text, laid out at a given size.
+#[derive(Debug, Clone, Default)]
+pub struct TextMesh {
+    /// Vertex positions in pixels, relative to the top left corner of the
+    /// text. These can be modified before pushing the mesh.
+    pub positions: Vec<Point2<f32>>,
+
+    /// Triangles, given as three indices into `positions` each.
+    pub indices: Vec<u32>,
+
+    /// The range of vertices in `positions` that belongs to each glyph of
+    /// the text, allowing glyphs to be animated separately. Glyphs without
+    /// outline, such as spaces, are left out.
+    pub glyphs: Vec<(char, Range<usize>)>,
+
+    /// The size of the box that is filled by the text.
+    pub size: Vector2<f32>,
+}
+
+impl TextMesh {
+    /// Pushes the text with its top left corner at `pos`. If the Y axis of
+    /// `batch` points up, the text is mirrored to stay upright.
+    pub fn push(&self, pos: Point3<f32>, color: Color4, batch: &mut TriBatch<ColVertex>) {
+        let flip = batch.y_axis().sign();
+
+        self.push_with(batch, |p| ColVertex {
+            world_pos: Point3::new(pos.x + p.x, pos.y + flip * p.y, pos.z),
+            color,
+        });
+    }
+
+    /// Pushes the text, mapping each vertex position to a vertex with `f`.
+    pub fn push_with(
+        &self,
+        batch: &mut TriBatch<ColVertex>,
+        mut f: impl FnMut(Point2<f32>) -> ColVertex,
+    ) {
+        let first_idx = batch.next_index();
+
+        for &p in &self.positions {
+            batch.push_vertex(&f(p));
+        }
+        for &index in &self.indices {
+            batch.push_element(first_idx + index);
+        }
+    }
+}
+
+/// A font whose glyphs are drawn as triangles instead of textured quads.
+///
+/// Requires the `vector-text` feature. Tessellated glyphs are cached, so
+/// text only needs to be tessellated once per glyph. Kerning is not
+/// applied.
+pub struct VectorFont {
+    data: Vec<u8>,
+    tolerance: f32,
+
+    units_per_em: f32,
+    ascender: f32,
+    line_height: f32,
+
+    glyphs: HashMap<char, GlyphMesh>,
+}
+
+impl VectorFont {
+    /// Loads a TrueType or OpenType font.
+    pub fn from_bytes(data: impl Into<Vec<u8>>) -> Result<Self, Error> {
+        let data = data.into();
+
+        let (units_per_em, ascender, line_height) = {
+            let face = ttf_parser::Face::from_slice(&data, 0)
+                .map_err(|e| Error::Font(format!("failed to parse font: {}", e)))?;
+            let units_per_em = face.units_per_em().unwrap_or(1000) as f32;
+            let ascender = face.ascender() as f32 / units_per_em;
+            // Add in `f32`, since the metrics can overflow `i16` in sum.
+            let line_height = (face.ascender() as f32 - face.descender() as f32
+                + face.line_gap() as f32)
+                / units_per_em;
+
+            (units_per_em, ascender, line_height)
+        };
+
+        Ok(Self {
+            data,
+            tolerance: 0.002,
+            units_per_em,
+            ascender,
+            line_height,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    pub fn tolerance(&self) -> f32 {
+        self.tolerance
+    }
+
+    /// Sets the maximal distance between the curves of the outlines and the
+    /// straight segments that approximate them, in ems. Lower tolerances give
+    /// smoother curves at the cost of more triangles. Clears the glyph cache.
+    pub fn set_tolerance(&mut self, tolerance: f32) {
+        assert!(tolerance > 0.0);
+
+        self.tolerance = tolerance;
+        self.glyphs.clear();
+    }
+
+    /// Returns the height of a line of text in ems.
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    pub fn num_cached_glyphs(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    /// Returns the triangles of the glyph for `c`, tessellating it if it has
+    /// not been cached yet. Characters that are missing in the font give an
+    /// empty mesh.
+    pub fn glyph(&mut self, c: char) -> &GlyphMesh {
+        let data = &self.data;
+        let tolerance = self.tolerance;
+        let units_per_em = self.units_per_em;
+
+        self.glyphs.entry(c).or_insert_with(|| {
+            tessellate_glyph(data, c, units_per_em, tolerance).unwrap_or_else(|e| {
+                log::warn!("Failed to tessellate glyph {:?}: {}", c, e);
+                GlyphMesh::default()
+            })
+        })
+    }
+
+    /// Lays out `text` at `size` pixels per em, starting a new line at each
+    /// `\n`.
+    pub fn text_mesh(&mut self, size: f32, text: &str) -> TextMesh {
+        let line_height = self.line_height * size;
+        let mut pen = Vector2::new(0.0, self.ascender * size);
+        let mut mesh = TextMesh::default();
+
+        for c in text.chars() {
+            if c == '\n' {
+                mesh.size.x = mesh.size.x.max(pen.x);
+                pen = Vector2::new(0.0, pen.y + line_height);
+                continue;
+            }
+
+            let glyph = self.glyph(c);
+            let first_idx = mesh.positions.len();
+
+            mesh.positions
+                .extend(glyph.positions.iter().map(|p| p * size + pen));
+            mesh.indices
+                .extend(glyph.indices.iter().map(|&index| first_idx as u32 + index));
+            if !glyph.positions.is_empty() {
+                mesh.glyphs.push((c, first_idx..mesh.positions.len()));
+            }
+
+            pen.x += glyph.advance * size;
+        }
+
+        mesh.size.x = mesh.size.x.max(pen.x);
+        mesh.size.y = pen.y - self.ascender * size + line_height;
+
+        mesh
+    }
+
+    /// Pushes `text` with its top left corner at `pos`, like `Font::write`.
+    pub fn write(
+        &mut self,
+        size: f32,
+        pos: Point3<f32>,
+        color: Color4,
+        text: &str,
+        batch: &mut TriBatch<ColVertex>,
+    ) {
+        self.text_mesh(size, text).push(pos, color, batch);
+    }
+}
+
+fn tessellate_glyph(
+    data: &[u8],
+    c: char,
+    units_per_em: f32,
+    tolerance: f32,
+) -> Result<GlyphMesh, String> {
+    let face = ttf_parser::Face::from_slice(data, 0).map_err(|e| e.to_string())?;
+
+    let glyph_id = match face.glyph_index(c) {
+        Some(glyph_id) => glyph_id,
+        None => return Ok(GlyphMesh::default()),
+    };
+    let advance = face.glyph_hor_advance(glyph_id).unwrap_or(0) as f32 / units_per_em;
+
+    let mut builder = PathBuilder {
+        builder: Path::builder(),
+        scale: 1.0 / units_per_em,
+        open: false,
+    };
+    if face.outline_glyph(glyph_id, &mut builder).is_none() {
+        // The glyph has no outline, e.g. for spaces.
+        return Ok(GlyphMesh {
+            advance,
+            ..GlyphMesh::default()
+        });
+    }
+    if builder.open {
+        builder.builder.end(true);
+    }
+    let path = builder.builder.build();
+
+    // TrueType outlines are filled with the non-zero rule. Flipping the Y
+    // axis reverses the winding, which does not matter for this rule.
+    let options = FillOptions::tolerance(tolerance).with_fill_rule(FillRule::NonZero);
+    let mut geometry: VertexBuffers<Point, u32> = VertexBuffers::new();
+    FillTessellator::new()
+        .tessellate_path(
+            &path,
+            &options,
+            &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| vertex.position()),
+        )
+        .map_err(|e| format!("{:?}", e))?;
+
+    Ok(GlyphMesh {
+        positions: geometry
+            .vertices
+            .iter()
+            .map(|p| Point2::new(p.x, p.y))
+            .collect(),
+        indices: geometry.indices,
+        advance,
+    })
+}
+
+/// Converts glyph outlines from font units with Y pointing up to paths in
+/// ems with Y pointing down.
+struct PathBuilder {
+    builder: path::Builder,
+    scale: f32,
+    open: bool,
+}
+
+impl PathBuilder {
+    fn point(&self, x: f32, y: f32) -> Point {
+        point(x * self.scale, -y * self.scale)
+    }
+}
+
+impl ttf_parser::OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        if self.open {
+            self.builder.end(true);
+        }
+
+        let p = self.point(x, y);
+        self.builder.begin(p);
+        self.open = true;
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.builder.line_to(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (ctrl, to) = (self.point(x1, y1), self.point(x, y));
+        self.builder.quadratic_bezier_to(ctrl, to);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (ctrl1, ctrl2, to) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.builder.cubic_bezier_to(ctrl1, ctrl2, to);
+    }
+
+    fn close(&mut self) {
+        if self.open {
+            self.builder.end(true);
+            self.open = false;
+        }
+    }
+}
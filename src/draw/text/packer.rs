@@ -1,12 +1,17 @@
 //! Packing cached glyphs into a texture atlas.
 //!
+//! When the atlas is full, the packer doubles the size of its texture until
+//! it reaches the maximum size. Glyphs keep their texel positions when the
+//! texture grows, so their texture coordinates are given in texels, and are
+//! scaled by `ShelfPacker::uv_scale` when drawing.
+//!
 //! Heavily inspired by this:
 //! https://github.com/17cupsofcoffee/tetra/blob/main/src/graphics/text/packer.rs
 
 use golem::{ColorFormat, Texture, TextureFilter};
 use nalgebra::{Point2, Vector2};
 
-use crate::{AaRect, Canvas, Error};
+use crate::{AaRect, Canvas, Error, GpuAllocation};
 
 /// A shelf has a fixed height and grows in width as more glyphs are added.
 #[derive(Clone, Debug)]
@@ -43,21 +48,53 @@ impl Shelf {
 
 pub struct ShelfPacker {
     texture: Texture,
+    _allocation: GpuAllocation,
     shelves: Vec<Shelf>,
     next_y: usize,
+
+    /// A copy of the texture's RGBA data, which is needed for growing the
+    /// texture and for re-packing glyphs, since WebGL cannot read textures
+    /// back directly.
+    pixels: Vec<u8>,
+
+    /// The size that the texture may grow to in each dimension.
+    max_size: usize,
+
+    /// The filter that the texture is sampled with. This is `Nearest` for
+    /// coverage glyphs, which are drawn at their rasterized size, and
+    /// `Linear` for distance fields, which need to be interpolated.
+    filter: TextureFilter,
+
+    /// The number of texels that are occupied by inserted data.
+    used_area: usize,
+
+    /// The number of successful insertions.
+    num_insertions: usize,
 }
 
 impl ShelfPacker {
-    pub fn new(ctx: &Canvas, width: usize, height: usize) -> Result<ShelfPacker, Error> {
+    pub fn new(
+        ctx: &Canvas,
+        width: usize,
+        height: usize,
+        max_size: usize,
+        filter: TextureFilter,
+    ) -> Result<ShelfPacker, Error> {
         let mut texture = Texture::new(ctx.golem_ctx())?;
         texture.set_image(None, width as u32, height as u32, ColorFormat::RGBA);
-        texture.set_magnification(TextureFilter::Nearest)?;
-        texture.set_minification(TextureFilter::Nearest)?;
+        texture.set_magnification(filter)?;
+        texture.set_minification(filter)?;
 
         Ok(ShelfPacker {
             texture,
+            _allocation: GpuAllocation::texture("glyph atlas", width as u32, height as u32),
             shelves: Vec::new(),
             next_y: 0,
+            pixels: vec![0; width * height * 4],
+            max_size: max_size.max(width).max(height),
+            filter,
+            used_area: 0,
+            num_insertions: 0,
         })
     }
 
@@ -65,43 +102,162 @@ impl ShelfPacker {
         &self.texture
     }
 
-    pub fn insert(&mut self, data: &[u8], width: usize, height: usize) -> Option<AaRect> {
+    pub fn width(&self) -> usize {
+        self.texture.width() as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.texture.height() as usize
+    }
+
+    /// Sets the size that the texture may grow to in each dimension. This
+    /// does not shrink the texture if it is larger already.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size.max(self.width()).max(self.height());
+    }
+
+    /// Returns the factor that maps texel coordinates, as returned by
+    /// `insert`, to UV coordinates.
+    pub fn uv_scale(&self) -> Vector2<f32> {
+        Vector2::new(1.0 / self.width() as f32, 1.0 / self.height() as f32)
+    }
+
+    /// Returns a copy of the RGBA data of a rect that has been inserted.
+    pub fn read(&self, x: usize, y: usize, width: usize, height: usize) -> Vec<u8> {
+        let stride = self.width() * 4;
+
+        (y..y + height)
+            .flat_map(|row| {
+                let start = row * stride + x * 4;
+                self.pixels[start..start + width * 4].iter().copied()
+            })
+            .collect()
+    }
+
+    /// Removes all inserted data, keeping the size of the texture.
+    pub fn clear(&mut self) {
+        self.shelves.clear();
+        self.next_y = 0;
+        self.used_area = 0;
+        self.num_insertions = 0;
+    }
+
+    /// Returns the fraction of the texture's area that is occupied by
+    /// inserted data, in the range `[0..1]`.
+    pub fn usage(&self) -> f32 {
+        let total_area = self.texture.width() as usize * self.texture.height() as usize;
+
+        self.used_area as f32 / total_area as f32
+    }
+
+    /// Returns the fraction of the texture's height that has been claimed by
+    /// shelves. Once this reaches one, new shelves can no longer be created,
+    /// even if `usage` is still low.
+    pub fn shelf_usage(&self) -> f32 {
+        self.next_y as f32 / self.texture.height() as f32
+    }
+
+    pub fn num_insertions(&self) -> usize {
+        self.num_insertions
+    }
+
+    /// Inserts RGBA data, growing the texture if needed. Returns the
+    /// position of the data in the texture and its texture coordinates in
+    /// texels, or `None` if the texture is full and cannot grow anymore.
+    pub fn insert(
+        &mut self,
+        data: &[u8],
+        width: usize,
+        height: usize,
+    ) -> Option<((usize, usize), AaRect)> {
         assert!(width > 0 && height > 0);
 
-        let space = self.allocate_space(width, height);
-
-        if let Some((x, y)) = space {
-            self.texture.set_subimage(
-                data,
-                x as u32,
-                y as u32,
-                width as u32,
-                height as u32,
-                ColorFormat::RGBA,
-            );
-
-            // We'll normalize from image coordinates to UV.
-            let tex_width = self.texture().width() as f32;
-            let tex_height = self.texture().height() as f32;
-
-            // Shift by half a pixel, so that the coords are in the center of
-            // the texel.
-            let uv_top_left =
-                Point2::new((x as f32 + 0.5) / tex_width, (y as f32 + 0.5) / tex_height);
-
-            // I think we can think of the size as inclusive, so we need to
-            // subtract one here.
-            let uv_size = Vector2::new(
-                (width - 1) as f32 / tex_width,
-                (height - 1) as f32 / tex_height,
-            );
-
-            let uv_rect = AaRect::from_top_left(uv_top_left, uv_size);
-
-            Some(uv_rect)
+        let (x, y) = loop {
+            if let Some(space) = self.allocate_space(width, height) {
+                break space;
+            }
+            if !self.grow() {
+                return None;
+            }
+        };
+
+        self.texture.set_subimage(
+            data,
+            x as u32,
+            y as u32,
+            width as u32,
+            height as u32,
+            ColorFormat::RGBA,
+        );
+
+        let stride = self.width() * 4;
+        for row in 0..height {
+            let start = (y + row) * stride + x * 4;
+            self.pixels[start..start + width * 4]
+                .copy_from_slice(&data[row * width * 4..(row + 1) * width * 4]);
+        }
+
+        self.used_area += width * height;
+        self.num_insertions += 1;
+
+        // Shift by half a pixel, so that the coords are in the center of
+        // the texel.
+        let uv_top_left = Point2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+        // I think we can think of the size as inclusive, so we need to
+        // subtract one here.
+        let uv_size = Vector2::new((width - 1) as f32, (height - 1) as f32);
+
+        Some(((x, y), AaRect::from_top_left(uv_top_left, uv_size)))
+    }
+
+    /// Doubles the smaller dimension of the texture, keeping the inserted
+    /// data at its position. Returns false if the texture has reached its
+    /// maximum size.
+    fn grow(&mut self) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let (new_width, new_height) = if height < width {
+            (width, height * 2)
         } else {
-            None
+            (width * 2, height)
+        };
+
+        if new_width > self.max_size || new_height > self.max_size {
+            return false;
+        }
+
+        let mut pixels = vec![0; new_width * new_height * 4];
+        for row in 0..height {
+            pixels[row * new_width * 4..(row * new_width + width) * 4]
+                .copy_from_slice(&self.pixels[row * width * 4..(row + 1) * width * 4]);
+        }
+
+        self.texture.set_image(
+            Some(&pixels),
+            new_width as u32,
+            new_height as u32,
+            ColorFormat::RGBA,
+        );
+        self.pixels = pixels;
+        self._allocation =
+            GpuAllocation::texture("glyph atlas", new_width as u32, new_height as u32);
+
+        // Filtering is kept when the image is replaced, but make sure that
+        // the grown texture is sampled like before.
+        if let Err(e) = self
+            .texture
+            .set_magnification(self.filter)
+            .and_then(|()| self.texture.set_minification(self.filter))
+        {
+            log::warn!("Failed to set the filter of the glyph atlas: {:?}", e);
+        }
+
+        // Existing shelves can use the new space to their right.
+        for shelf in self.shelves.iter_mut() {
+            shelf.width = new_width;
         }
+
+        true
     }
 
     fn allocate_space(
@@ -112,6 +268,12 @@ impl ShelfPacker {
         let texture_width = self.texture.width() as usize;
         let texture_height = self.texture.height() as usize;
 
+        if space_width > texture_width {
+            // The space does not fit into any shelf, so the texture needs to
+            // grow first.
+            return None;
+        }
+
         let best_shelf = self
             .shelves
             .iter_mut()
@@ -127,7 +289,7 @@ impl ShelfPacker {
             let position = (best_shelf.next_x, best_shelf.top_y);
             best_shelf.next_x += space_width;
             Some(position)
-        } else if self.next_y + space_height < texture_height {
+        } else if self.next_y + space_height <= texture_height {
             // Create a new shelf
             let position = (0, self.next_y);
 
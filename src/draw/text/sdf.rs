@@ -0,0 +1,273 @@
+//! Text that is rendered from signed distance fields.
+//!
+//! Instead of coverage, an SDF atlas stores for every texel the distance to
+//! the closest edge of the glyph. Glyphs are rasterized once at a fixed size,
+//! and the fragment shader finds the edge by thresholding the interpolated
+//! distance, so text stays sharp when it is scaled up. The distances also
+//! allow drawing outlines and drop shadows in the same pass.
+
+use golem::{
+    Attribute, AttributeType, Dimension, NumberType, ShaderDescription, Texture, Uniform,
+    UniformType, UniformValue,
+};
+use nalgebra::{Matrix3, Vector2};
+
+use crate::{
+    draw::{DrawUnit, SharedShader, TexColVertex, Vertex},
+    geom::{self, matrix3_to_flat_array},
+    Canvas, Color4, Error,
+};
+
+/// How the glyphs of an SDF atlas are generated, see
+/// `GlyphAtlas::new_sdf`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdfConfig {
+    /// The size in pixels at which glyphs are rasterized, regardless of the
+    /// size of the text that is written.
+    pub glyph_size: f32,
+
+    /// The distance in pixels of the rasterized glyphs up to which the
+    /// field is stored. Glyphs are padded by this on every side, and it
+    /// limits the width of outlines and shadows.
+    pub spread: u32,
+}
+
+impl Default for SdfConfig {
+    fn default() -> Self {
+        Self {
+            glyph_size: 48.0,
+            spread: 6,
+        }
+    }
+}
+
+/// Per-draw parameters of text in an SDF atlas.
+///
+/// Widths and offsets are given in pixels of the rasterized glyphs, i.e. at
+/// `SdfConfig::glyph_size`, so they scale with the text. They are clamped
+/// to `SdfConfig::spread`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SdfEffect {
+    /// The approximate size of the text that is drawn, in the units that
+    /// were passed to `Font::write`. Together with the transform, it
+    /// determines how wide the antialiased edge is on the screen.
+    pub text_size: f32,
+
+    pub outline_color: Color4,
+    pub outline_width: f32,
+
+    pub shadow_color: Color4,
+
+    /// The offset of the shadow, with Y pointing down.
+    pub shadow_offset: Vector2<f32>,
+
+    /// The distance over which the shadow fades out.
+    pub shadow_softness: f32,
+}
+
+impl Default for SdfEffect {
+    fn default() -> Self {
+        Self {
+            text_size: 32.0,
+            outline_color: Color4::new(0.0, 0.0, 0.0, 1.0),
+            outline_width: 0.0,
+            shadow_color: Color4::new(0.0, 0.0, 0.0, 0.0),
+            shadow_offset: Vector2::zeros(),
+            shadow_softness: 0.0,
+        }
+    }
+}
+
+pub(crate) struct SdfPass {
+    shader: SharedShader,
+}
+
+impl SdfPass {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let shader = ctx.shader_cache().get_or_compile(
+            ctx.golem_ctx(),
+            "SdfPass",
+            ShaderDescription {
+                vertex_input: &TexColVertex::attributes(),
+                fragment_input: &[
+                    Attribute::new("v_color", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("v_tex_coords", AttributeType::Vector(Dimension::D2)),
+                ],
+                uniforms: &[
+                    Uniform::new("mat_projection_view", UniformType::Matrix(Dimension::D3)),
+                    Uniform::new("my_tex", UniformType::Sampler2D),
+                    Uniform::new(
+                        "uv_scale",
+                        UniformType::Vector(NumberType::Float, Dimension::D2),
+                    ),
+                    Uniform::new("smoothing", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new(
+                        "outline_color",
+                        UniformType::Vector(NumberType::Float, Dimension::D4),
+                    ),
+                    Uniform::new("outline_width", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new(
+                        "shadow_color",
+                        UniformType::Vector(NumberType::Float, Dimension::D4),
+                    ),
+                    Uniform::new(
+                        "shadow_offset",
+                        UniformType::Vector(NumberType::Float, Dimension::D2),
+                    ),
+                    Uniform::new("shadow_softness", UniformType::Scalar(NumberType::Float)),
+                ],
+                vertex_shader: r#"
+                void main() {
+                    vec3 p = mat_projection_view * vec3(a_world_pos.xy, 1.0);
+                    gl_Position = vec4(p.xy, a_world_pos.z * p.z, p.z);
+                    v_color = a_color;
+                    v_tex_coords = a_tex_coords * uv_scale;
+                }
+                "#,
+                fragment_shader: r#"
+                void main() {
+                    // The edge of the glyph is at a distance of 0.5.
+                    float dist = texture(my_tex, v_tex_coords).a;
+                    float fill = smoothstep(0.5 - smoothing, 0.5 + smoothing, dist);
+                    float outlined = smoothstep(
+                        0.5 - outline_width - smoothing,
+                        0.5 - outline_width + smoothing,
+                        dist
+                    );
+                    vec4 color = v_color * fill + outline_color * (outlined - fill);
+
+                    float shadow_dist = texture(my_tex, v_tex_coords - shadow_offset).a;
+                    float shadow = smoothstep(
+                        0.5 - shadow_softness - smoothing,
+                        0.5 + smoothing,
+                        shadow_dist
+                    );
+
+                    // The atlas is blended with premultiplied alpha.
+                    gl_FragColor = color + shadow_color * shadow * (1.0 - color.a);
+                }
+                "#,
+            },
+            &[],
+        )?;
+
+        Ok(Self { shader })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        ctx: &Canvas,
+        transform: &Matrix3<f32>,
+        tex: &Texture,
+        uv_scale: Vector2<f32>,
+        config: &SdfConfig,
+        effect: &SdfEffect,
+        draw_unit: &DrawUnit<TexColVertex>,
+    ) -> Result<(), Error> {
+        // Distances are stored such that `spread` pixels of the rasterized
+        // glyph correspond to 0.5.
+        let spread = config.spread.max(1) as f32;
+        let to_field = |pixels: f32| pixels.min(spread).max(0.0) / (2.0 * spread);
+
+        // Antialias the edge over about one pixel on the screen.
+        let screen_size = ctx.screen_geom().size;
+        let pixels_per_unit = geom::transform_scale(transform)
+            * ((screen_size.x as f32) * (screen_size.y as f32)).sqrt()
+            / 2.0;
+        let screen_pixels_per_glyph_pixel =
+            pixels_per_unit * effect.text_size / config.glyph_size.max(1.0);
+        let smoothing = to_field(0.5 / screen_pixels_per_glyph_pixel.max(f32::EPSILON));
+
+        tex.set_active(std::num::NonZeroU32::new(1).unwrap());
+
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform(
+            "mat_projection_view",
+            UniformValue::Matrix3(matrix3_to_flat_array(transform)),
+        )?;
+        shader.set_uniform("my_tex", UniformValue::Int(1))?;
+        shader.set_uniform("uv_scale", UniformValue::Vector2([uv_scale.x, uv_scale.y]))?;
+        shader.set_uniform("smoothing", UniformValue::Float(smoothing.max(1e-4)))?;
+        shader.set_uniform(
+            "outline_color",
+            UniformValue::Vector4(effect.outline_color.into()),
+        )?;
+        shader.set_uniform(
+            "outline_width",
+            UniformValue::Float(to_field(effect.outline_width)),
+        )?;
+        shader.set_uniform(
+            "shadow_color",
+            UniformValue::Vector4(effect.shadow_color.into()),
+        )?;
+        shader.set_uniform(
+            "shadow_offset",
+            UniformValue::Vector2([
+                effect.shadow_offset.x.min(spread).max(-spread) * uv_scale.x,
+                effect.shadow_offset.y.min(spread).max(-spread) * uv_scale.y,
+            ]),
+        )?;
+        shader.set_uniform(
+            "shadow_softness",
+            UniformValue::Float(to_field(effect.shadow_softness)),
+        )?;
+
+        draw_unit.draw(&shader)
+    }
+}
+
+/// Turns an alpha bitmap into a signed distance field, padding it by
+/// `spread` on every side.
+///
+/// Each output value is 0.5 on the edge of the glyph, grows to 1 at
+/// `spread` pixels inside and shrinks to 0 at `spread` pixels outside.
+pub(crate) fn distance_field(
+    bitmap: &[u8],
+    width: usize,
+    height: usize,
+    spread: usize,
+) -> (Vec<u8>, usize, usize) {
+    let (out_width, out_height) = (width + 2 * spread, height + 2 * spread);
+
+    let inside = |x: isize, y: isize| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < width
+            && (y as usize) < height
+            && bitmap[y as usize * width + x as usize] >= 128
+    };
+
+    let spread_i = spread as isize;
+    let mut output = vec![0; out_width * out_height];
+
+    for out_y in 0..out_height {
+        for out_x in 0..out_width {
+            let (x, y) = (out_x as isize - spread_i, out_y as isize - spread_i);
+            let is_inside = inside(x, y);
+
+            // Search the neighborhood for the closest pixel on the other
+            // side of the edge. Glyphs are rasterized once, so brute force
+            // is fast enough.
+            let mut closest_sq = (spread * spread) as isize;
+            for dy in -spread_i..=spread_i {
+                for dx in -spread_i..=spread_i {
+                    let dist_sq = dx * dx + dy * dy;
+                    if dist_sq < closest_sq && inside(x + dx, y + dy) != is_inside {
+                        closest_sq = dist_sq;
+                    }
+                }
+            }
+
+            // The edge lies halfway between the two pixels.
+            let dist = ((closest_sq as f32).sqrt() - 0.5).max(0.0);
+            let signed = if is_inside { dist } else { -dist };
+            let value = 0.5 + signed / (2.0 * spread.max(1) as f32);
+
+            output[out_y * out_width + out_x] = (value * 255.0).round().min(255.0).max(0.0) as u8;
+        }
+    }
+
+    (output, out_width, out_height)
+}
@@ -0,0 +1,156 @@
+//! Creating textures from images and replacing their contents in place.
+
+use std::{future::Future, num::NonZeroU32, pin::Pin};
+
+use golem::{ColorFormat, Texture};
+use web_sys::{HtmlImageElement, WebGlRenderingContext as Gl};
+
+use crate::{
+    draw::{
+        texture_loader::{load_texture, upload_image, ImageSource},
+        ImageDecodeOptions,
+    },
+    Canvas, Error,
+};
+
+/// Extends golem's `Texture` with operations that are not provided there.
+pub trait TextureExt: Sized {
+    /// Downloads an image, e.g. a PNG or JPEG, and uploads it into a new
+    /// texture once the browser has decoded it.
+    ///
+    /// The texture is clamped and linearly filtered, so that images of any
+    /// size can be used. For loading many images at once, with progress
+    /// reporting, see `TextureLoader`.
+    fn from_image_url<'a>(
+        ctx: &'a Canvas,
+        url: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Error>> + 'a>>;
+
+    /// Uploads the contents of an image element into a new texture.
+    ///
+    /// The image needs to have finished loading, and it must not be
+    /// cross-origin unless it has been loaded with CORS, since the browser
+    /// does not allow reading its pixels otherwise.
+    fn from_image_element(ctx: &Canvas, image: &HtmlImageElement) -> Result<Self, Error>;
+
+    /// Replaces the pixel data of the texture, keeping the same GL handle.
+    ///
+    /// Everything that refers to the texture, e.g. the textures held by
+    /// sprites or the entries of a `TextureCache`, sees the new contents
+    /// without having to be updated. Filtering and wrapping settings are
+    /// kept as well. Since texture coordinates are normalized, they keep
+    /// referring to the same relative region if the size changes.
+    ///
+    /// Changing the size is only allowed if it keeps the texture usable with
+    /// its settings: in WebGL 1, a non-power-of-two texture cannot use
+    /// mipmaps or repeat wrapping.
+    fn replace_contents(
+        &mut self,
+        ctx: &Canvas,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: ColorFormat,
+    ) -> Result<(), Error>;
+}
+
+impl TextureExt for Texture {
+    fn from_image_url<'a>(
+        ctx: &'a Canvas,
+        url: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Self, Error>> + 'a>> {
+        Box::pin(async move { load_texture(ctx, url, &ImageDecodeOptions::default()).await })
+    }
+
+    fn from_image_element(ctx: &Canvas, image: &HtmlImageElement) -> Result<Self, Error> {
+        if !image.complete() || image.natural_width() == 0 {
+            return Err(Error::Image(format!(
+                "image `{}` has not been loaded",
+                image.src()
+            )));
+        }
+
+        upload_image(ctx, ImageSource::Element(image))
+    }
+
+    fn replace_contents(
+        &mut self,
+        ctx: &Canvas,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        format: ColorFormat,
+    ) -> Result<(), Error> {
+        let bytes_per_pixel = match format {
+            ColorFormat::RGB => 3,
+            ColorFormat::RGBA => 4,
+        };
+        let expected_len = width as usize * height as usize * bytes_per_pixel;
+        if data.len() != expected_len {
+            return Err(Error::Texture(format!(
+                "expected {} bytes for {}x{} texture, got {}",
+                expected_len,
+                width,
+                height,
+                data.len(),
+            )));
+        }
+
+        check_size_change(ctx, self, width, height)?;
+
+        self.set_image(Some(data), width, height, format);
+
+        Ok(())
+    }
+}
+
+/// Checks that `texture` stays usable with its settings when its size is
+/// changed to `width` and `height`.
+///
+/// WebGL 1 only allows non-power-of-two textures if they are clamped and do
+/// not use mipmaps, so the texture's wrapping and minification are queried
+/// from GL, since golem does not keep track of them.
+pub(super) fn check_size_change(
+    ctx: &Canvas,
+    texture: &Texture,
+    width: u32,
+    height: u32,
+) -> Result<(), Error> {
+    if width.is_power_of_two() && height.is_power_of_two() {
+        return Ok(());
+    }
+
+    texture.set_active(NonZeroU32::new(1).unwrap());
+
+    let gl = ctx.webgl_ctx();
+    let parameter = |name: u32| {
+        gl.get_tex_parameter(Gl::TEXTURE_2D, name)
+            .as_f64()
+            .map(|value| value as u32)
+    };
+
+    let is_clamped = [Gl::TEXTURE_WRAP_S, Gl::TEXTURE_WRAP_T]
+        .iter()
+        .all(|&name| parameter(name) == Some(Gl::CLAMP_TO_EDGE));
+    let is_mipmapped = !matches!(
+        parameter(Gl::TEXTURE_MIN_FILTER),
+        Some(Gl::NEAREST) | Some(Gl::LINEAR)
+    );
+
+    if !is_clamped || is_mipmapped {
+        return Err(Error::Texture(format!(
+            "cannot resize {}x{} texture to non-power-of-two size {}x{}, since it {}",
+            texture.width(),
+            texture.height(),
+            width,
+            height,
+            if is_mipmapped {
+                "uses mipmaps"
+            } else {
+                "does not clamp to the edge"
+            },
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! A cache of textures that stays within a memory budget.
+
+use std::{collections::HashMap, hash::Hash};
+
+use crate::draw::Texture;
+
+struct Entry {
+    texture: Texture,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// Holds textures up to a budget of bytes, evicting the least recently used
+/// textures once the budget is exceeded.
+///
+/// This is meant for streaming content, such as the chunks of a large map,
+/// where not all textures fit into GPU memory at once. Evicted textures are
+/// passed to the eviction callback, which can e.g. drop them or note that
+/// the corresponding chunk needs to be reloaded.
+pub struct TextureCache<K> {
+    budget_bytes: usize,
+    used_bytes: usize,
+    entries: HashMap<K, Entry>,
+    clock: u64,
+    on_evict: Option<Box<dyn FnMut(K, Texture)>>,
+}
+
+impl<K> TextureCache<K>
+where
+    K: Eq + Hash + Clone,
+{
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            budget_bytes,
+            used_bytes: 0,
+            entries: HashMap::new(),
+            clock: 0,
+            on_evict: None,
+        }
+    }
+
+    /// Sets a callback that is called for every texture that is evicted.
+    pub fn with_on_evict(mut self, on_evict: impl FnMut(K, Texture) + 'static) -> Self {
+        self.on_evict = Some(Box::new(on_evict));
+        self
+    }
+
+    pub fn budget_bytes(&self) -> usize {
+        self.budget_bytes
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Changes the budget, evicting textures if necessary.
+    pub fn set_budget_bytes(&mut self, budget_bytes: usize) {
+        self.budget_bytes = budget_bytes;
+        self.evict_to_budget(None);
+    }
+
+    /// Inserts a texture, evicting least recently used textures until the
+    /// cache fits into the budget again.
+    ///
+    /// The inserted texture itself is never evicted by this call, even if it
+    /// alone exceeds the budget. If there already is a texture for `key`, it
+    /// is replaced and returned without calling the eviction callback.
+    pub fn insert(&mut self, key: K, texture: Texture) -> Option<Texture> {
+        let bytes = texture.width() as usize * texture.height() as usize * 4;
+        self.clock += 1;
+
+        let previous = self.entries.insert(
+            key.clone(),
+            Entry {
+                texture,
+                bytes,
+                last_used: self.clock,
+            },
+        );
+
+        self.used_bytes += bytes;
+        if let Some(previous) = previous.as_ref() {
+            self.used_bytes -= previous.bytes;
+        }
+
+        self.evict_to_budget(Some(&key));
+
+        previous.map(|entry| entry.texture)
+    }
+
+    /// Returns the texture for `key`, marking it as recently used.
+    pub fn get(&mut self, key: &K) -> Option<&Texture> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        self.entries.get_mut(key).map(|entry| {
+            entry.last_used = clock;
+            &entry.texture
+        })
+    }
+
+    /// Returns the texture for `key` without marking it as recently used.
+    pub fn peek(&self, key: &K) -> Option<&Texture> {
+        self.entries.get(key).map(|entry| &entry.texture)
+    }
+
+    /// Removes a texture without calling the eviction callback.
+    pub fn remove(&mut self, key: &K) -> Option<Texture> {
+        self.entries.remove(key).map(|entry| {
+            self.used_bytes -= entry.bytes;
+            entry.texture
+        })
+    }
+
+    fn evict_to_budget(&mut self, keep: Option<&K>) {
+        while self.used_bytes > self.budget_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(key, _)| Some(*key) != keep)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+
+            let key = match oldest {
+                Some(key) => key,
+                None => break,
+            };
+
+            let entry = self.entries.remove(&key).unwrap();
+            self.used_bytes -= entry.bytes;
+
+            if let Some(on_evict) = self.on_evict.as_mut() {
+                on_evict(key, entry.texture);
+            }
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Loading textures from image URLs without blocking the main thread.
+//!
+//! Images are fetched and then decoded by the browser with
+//! [`createImageBitmap`](https://developer.mozilla.org/en-US/docs/Web/API/WindowOrWorkerGlobalScope/createImageBitmap),
+//! which happens off the main thread. Decoded images are uploaded to the GPU
+//! in `TextureLoader::poll`, which should be called once per frame. The
+//! bitmaps are passed to WebGL as they are, so their pixels never pass
+//! through the main thread.
+
+use std::{cell::RefCell, num::NonZeroU32, rc::Rc};
+
+use golem::{ColorFormat, TextureFilter, TextureWrap};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Blob, ColorSpaceConversion, HtmlImageElement, ImageBitmap, ImageBitmapOptions,
+    ImageOrientation, PremultiplyAlpha, Response, WebGlRenderingContext as Gl,
+};
+
+use crate::{
+    draw::{texture::check_size_change, Texture},
+    Canvas, Error,
+};
+
+/// Options for decoding images.
+#[derive(Debug, Clone)]
+pub struct ImageDecodeOptions {
+    /// Multiply the color channels by alpha.
+    pub premultiply_alpha: bool,
+
+    /// Flip the image vertically.
+    pub flip_y: bool,
+
+    /// Apply the color profile embedded in the image. Disable this for images
+    /// that contain data rather than colors, e.g. normal maps.
+    pub convert_color_space: bool,
+}
+
+impl Default for ImageDecodeOptions {
+    fn default() -> Self {
+        Self {
+            premultiply_alpha: false,
+            flip_y: false,
+            convert_color_space: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadState {
+    /// The image is being downloaded.
+    Downloading,
+
+    /// The image has been downloaded and is being decoded by the browser.
+    Decoding,
+
+    /// The image has been decoded and will be uploaded in the next call to
+    /// `TextureLoader::poll`.
+    Decoded,
+
+    /// The texture is ready.
+    Uploaded,
+
+    Failed(String),
+}
+
+impl LoadState {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, LoadState::Uploaded | LoadState::Failed(_))
+    }
+}
+
+/// Progress of loading one image.
+#[derive(Debug, Clone)]
+pub struct LoadProgress {
+    pub url: String,
+    pub state: LoadState,
+
+    /// The size of the downloaded image file, once known.
+    pub num_bytes: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LoadHandle(usize);
+
+struct Asset {
+    progress: LoadProgress,
+
+    /// Incremented whenever loading is restarted, so that results of
+    /// outdated requests can be discarded.
+    generation: usize,
+
+    options: ImageDecodeOptions,
+    bitmap: Option<ImageBitmap>,
+    texture: Option<Texture>,
+}
+
+pub struct TextureLoader {
+    assets: Vec<Rc<RefCell<Asset>>>,
+}
+
+impl TextureLoader {
+    pub fn new() -> Self {
+        Self { assets: Vec::new() }
+    }
+
+    /// Starts loading the image at `url`.
+    pub fn load(&mut self, url: &str, options: ImageDecodeOptions) -> LoadHandle {
+        let asset = Rc::new(RefCell::new(Asset {
+            progress: LoadProgress {
+                url: url.to_string(),
+                state: LoadState::Downloading,
+                num_bytes: None,
+            },
+            generation: 0,
+            options,
+            bitmap: None,
+            texture: None,
+        }));
+
+        start_loading(asset.clone(), url.to_string());
+
+        self.assets.push(asset);
+        LoadHandle(self.assets.len() - 1)
+    }
+
+    /// Starts loading the images again whose URL ends with `path`, e.g.
+    /// because the file has changed on the server. Returns the number of
+    /// images that are reloaded.
+    ///
+    /// Textures that are still kept in the loader get their contents replaced
+    /// in place once the new image has been uploaded, so that their handles
+    /// stay valid. Textures that have already been taken out of the loader
+    /// can be taken again after that.
+    pub fn reload(&mut self, path: &str) -> usize {
+        let mut num_reloaded = 0;
+
+        for asset in &self.assets {
+            let url = {
+                let mut asset = asset.borrow_mut();
+                if !asset.progress.url.ends_with(path) {
+                    continue;
+                }
+
+                asset.generation += 1;
+                asset.bitmap = None;
+                asset.progress.state = LoadState::Downloading;
+                asset.progress.num_bytes = None;
+
+                // Make sure that we don't get the old image from the cache.
+                let separator = if asset.progress.url.contains('?') {
+                    '&'
+                } else {
+                    '?'
+                };
+                format!(
+                    "{}{}reload={}",
+                    asset.progress.url, separator, asset.generation
+                )
+            };
+
+            start_loading(asset.clone(), url);
+            num_reloaded += 1;
+        }
+
+        num_reloaded
+    }
+
+    /// Uploads all images that have been decoded since the last call.
+    ///
+    /// Images that fail to upload are marked as `LoadState::Failed`, without
+    /// stopping the upload of the other images.
+    pub fn poll(&mut self, ctx: &Canvas) -> Result<(), Error> {
+        for asset in &self.assets {
+            let mut asset = asset.borrow_mut();
+
+            if let Some(bitmap) = asset.bitmap.take() {
+                let source = ImageSource::Bitmap(&bitmap);
+
+                // Keep the handle of a reloaded texture valid.
+                let result = if let Some(texture) = asset.texture.as_mut() {
+                    replace_with_image(ctx, texture, source)
+                } else {
+                    upload_image(ctx, source).map(|texture| {
+                        asset.texture = Some(texture);
+                    })
+                };
+                bitmap.close();
+
+                asset.progress.state = match result {
+                    Ok(()) => LoadState::Uploaded,
+                    Err(e) => {
+                        log::warn!("Failed to upload image `{}`: {}", asset.progress.url, e);
+                        LoadState::Failed(e.to_string())
+                    }
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn progress(&self, handle: LoadHandle) -> LoadProgress {
+        self.assets[handle.0].borrow().progress.clone()
+    }
+
+    pub fn all_progress(&self) -> Vec<LoadProgress> {
+        self.assets
+            .iter()
+            .map(|asset| asset.borrow().progress.clone())
+            .collect()
+    }
+
+    /// Returns the fraction of images that have finished loading (either
+    /// successfully or not), in the range `[0..1]`.
+    pub fn fraction_finished(&self) -> f32 {
+        if self.assets.is_empty() {
+            return 1.0;
+        }
+
+        let num_finished = self
+            .assets
+            .iter()
+            .filter(|asset| asset.borrow().progress.state.is_finished())
+            .count();
+
+        num_finished as f32 / self.assets.len() as f32
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.assets
+            .iter()
+            .all(|asset| asset.borrow().progress.state.is_finished())
+    }
+
+    /// Takes the texture out of the loader, once it has been uploaded.
+    pub fn take_texture(&mut self, handle: LoadHandle) -> Option<Texture> {
+        self.assets[handle.0].borrow_mut().texture.take()
+    }
+
+    /// Calls `f` with the texture, if it has been uploaded and not been taken
+    /// out of the loader.
+    ///
+    /// Accessing textures through this instead of taking them means that
+    /// they are replaced transparently when reloaded.
+    pub fn with_texture<R>(&self, handle: LoadHandle, f: impl FnOnce(&Texture) -> R) -> Option<R> {
+        self.assets[handle.0].borrow().texture.as_ref().map(f)
+    }
+}
+
+impl Default for TextureLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn start_loading(asset: Rc<RefCell<Asset>>, url: String) {
+    let (generation, options) = {
+        let asset = asset.borrow();
+        (asset.generation, asset.options.clone())
+    };
+
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = fetch_and_decode(&url, &options, &asset).await;
+
+        let mut asset = asset.borrow_mut();
+        if asset.generation != generation {
+            // Loading has been restarted in the meantime.
+            return;
+        }
+
+        match result {
+            Ok(bitmap) => {
+                asset.bitmap = Some(bitmap);
+                asset.progress.state = LoadState::Decoded;
+            }
+            Err(e) => {
+                let msg = e.as_string().unwrap_or_else(|| format!("{:?}", e));
+                log::warn!("Failed to load image `{}`: {}", url, msg);
+                asset.progress.state = LoadState::Failed(msg);
+            }
+        }
+    });
+}
+
+async fn fetch_and_decode(
+    url: &str,
+    options: &ImageDecodeOptions,
+    asset: &Rc<RefCell<Asset>>,
+) -> Result<ImageBitmap, JsValue> {
+    let blob = fetch_blob(url).await?;
+
+    {
+        let mut asset = asset.borrow_mut();
+        asset.progress.num_bytes = Some(blob.size() as usize);
+        asset.progress.state = LoadState::Decoding;
+    }
+
+    decode_blob(&blob, options).await
+}
+
+/// Loads a texture from `url`, waiting for the download and for the decoding
+/// instead of going through a `TextureLoader`.
+pub(super) async fn load_texture(
+    ctx: &Canvas,
+    url: &str,
+    options: &ImageDecodeOptions,
+) -> Result<Texture, Error> {
+    let blob = fetch_blob(url).await.map_err(js_error)?;
+    let bitmap = decode_blob(&blob, options).await.map_err(js_error)?;
+    let texture = upload_image(ctx, ImageSource::Bitmap(&bitmap));
+    bitmap.close();
+
+    texture
+}
+
+async fn fetch_blob(url: &str) -> Result<Blob, JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+
+    let response: Response = JsFuture::from(window.fetch_with_str(url))
+        .await?
+        .dyn_into()?;
+    if !response.ok() {
+        return Err(JsValue::from_str(&format!(
+            "HTTP status {} {}",
+            response.status(),
+            response.status_text()
+        )));
+    }
+
+    JsFuture::from(response.blob()?).await?.dyn_into()
+}
+
+async fn decode_blob(blob: &Blob, options: &ImageDecodeOptions) -> Result<ImageBitmap, JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+
+    let mut bitmap_options = ImageBitmapOptions::new();
+    bitmap_options.premultiply_alpha(if options.premultiply_alpha {
+        PremultiplyAlpha::Premultiply
+    } else {
+        PremultiplyAlpha::None
+    });
+    bitmap_options.image_orientation(if options.flip_y {
+        ImageOrientation::FlipY
+    } else {
+        ImageOrientation::None
+    });
+    bitmap_options.color_space_conversion(if options.convert_color_space {
+        ColorSpaceConversion::Default
+    } else {
+        ColorSpaceConversion::None
+    });
+
+    let bitmap = JsFuture::from(
+        window.create_image_bitmap_with_blob_and_image_bitmap_options(blob, &bitmap_options)?,
+    )
+    .await?
+    .dyn_into()?;
+
+    Ok(bitmap)
+}
+
+fn js_error(e: JsValue) -> Error {
+    Error::Image(e.as_string().unwrap_or_else(|| format!("{:?}", e)))
+}
+
+/// A decoded image that WebGL can upload directly.
+#[derive(Clone, Copy)]
+pub(super) enum ImageSource<'a> {
+    /// A bitmap from `createImageBitmap`. WebGL ignores its pixel storage
+    /// flags for bitmaps, so alpha premultiplication and flipping are
+    /// applied as given by the `ImageDecodeOptions` that it was decoded
+    /// with.
+    Bitmap(&'a ImageBitmap),
+
+    /// An image element, which is uploaded with straight alpha.
+    Element(&'a HtmlImageElement),
+}
+
+impl<'a> ImageSource<'a> {
+    fn size(self) -> (u32, u32) {
+        match self {
+            ImageSource::Bitmap(bitmap) => (bitmap.width(), bitmap.height()),
+            ImageSource::Element(image) => (image.natural_width(), image.natural_height()),
+        }
+    }
+}
+
+/// Creates a texture with the contents of `source`.
+pub(super) fn upload_image(ctx: &Canvas, source: ImageSource) -> Result<Texture, Error> {
+    let mut texture = Texture::new(ctx.golem_ctx())?;
+    set_image_source(ctx, &mut texture, source)?;
+
+    // WebGL 1 only supports non-power-of-two textures without mipmaps and
+    // with clamping.
+    texture.set_magnification(TextureFilter::Linear)?;
+    texture.set_minification(TextureFilter::Linear)?;
+    texture.set_wrap_h(TextureWrap::ClampToEdge)?;
+    texture.set_wrap_v(TextureWrap::ClampToEdge)?;
+
+    Ok(texture)
+}
+
+/// Replaces the contents of `texture` with `source`, keeping its GL handle
+/// and settings, see `TextureExt::replace_contents`.
+pub(super) fn replace_with_image(
+    ctx: &Canvas,
+    texture: &mut Texture,
+    source: ImageSource,
+) -> Result<(), Error> {
+    let (width, height) = source.size();
+    check_size_change(ctx, texture, width, height)?;
+
+    set_image_source(ctx, texture, source)
+}
+
+fn set_image_source(ctx: &Canvas, texture: &mut Texture, source: ImageSource) -> Result<(), Error> {
+    // Let golem allocate the storage, so that it knows the size of the
+    // texture, and then fill it from the image.
+    let (width, height) = source.size();
+    texture.set_image(None, width, height, ColorFormat::RGBA);
+    texture.set_active(NonZeroU32::new(1).unwrap());
+
+    let gl = ctx.webgl_ctx();
+    match source {
+        ImageSource::Bitmap(bitmap) => gl.tex_image_2d_with_u32_and_u32_and_image_bitmap(
+            Gl::TEXTURE_2D,
+            0,
+            Gl::RGBA as i32,
+            Gl::RGBA,
+            Gl::UNSIGNED_BYTE,
+            bitmap,
+        ),
+        ImageSource::Element(image) => gl.tex_image_2d_with_u32_and_u32_and_image(
+            Gl::TEXTURE_2D,
+            0,
+            Gl::RGBA as i32,
+            Gl::RGBA,
+            Gl::UNSIGNED_BYTE,
+            image,
+        ),
+    }
+    .map_err(js_error)
+}
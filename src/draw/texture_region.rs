@@ -0,0 +1,76 @@
+//! Rectangular regions of textures, e.g. single sprites in an atlas.
+
+use std::rc::Rc;
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{draw::Texture, AaRect};
+
+/// A region of a texture, together with the texture itself.
+///
+/// This is a cheaply clonable handle, so that anything that draws part of a
+/// texture can hold on to the region instead of keeping the texture and the
+/// texture coordinates separately.
+#[derive(Clone)]
+pub struct TextureRegion {
+    pub texture: Rc<Texture>,
+
+    /// The texture coordinates of the region.
+    pub uv_rect: AaRect,
+
+    /// The size of the region in pixels, which is the size at which it is
+    /// drawn with a scale of one.
+    pub size: Vector2<f32>,
+}
+
+impl TextureRegion {
+    /// Returns the region that covers all of `texture`.
+    pub fn whole(texture: Rc<Texture>) -> Self {
+        let size = texture_size(&texture);
+
+        Self {
+            texture,
+            uv_rect: AaRect::zero_to_one(),
+            size,
+        }
+    }
+
+    /// Returns the region of `texture` that is covered by `rect`, given in
+    /// pixels with the origin at the top left corner of the texture.
+    pub fn from_pixels(texture: Rc<Texture>, rect: AaRect) -> Self {
+        let texture_size = texture_size(&texture);
+
+        Self {
+            texture,
+            uv_rect: AaRect::from_top_left(
+                Point2::from(rect.min().coords.component_div(&texture_size)),
+                rect.size.component_div(&texture_size),
+            ),
+            size: rect.size,
+        }
+    }
+
+    /// Returns the part of this region that is covered by `rect`, given in
+    /// pixels relative to the top left corner of this region, e.g. for the
+    /// patches of a nine-slice or for the frames of an animation strip.
+    pub fn sub_region(&self, rect: AaRect) -> Self {
+        let uv_per_pixel = self.uv_rect.size.component_div(&self.size);
+        let uv_min = self.uv_rect.min() + rect.min().coords.component_mul(&uv_per_pixel);
+
+        Self {
+            texture: self.texture.clone(),
+            uv_rect: AaRect::from_top_left(uv_min, rect.size.component_mul(&uv_per_pixel)),
+            size: rect.size,
+        }
+    }
+
+    /// Returns true if both regions refer to the same texture, so that they
+    /// can be drawn in the same batch.
+    pub fn same_texture(&self, other: &TextureRegion) -> bool {
+        Rc::ptr_eq(&self.texture, &other.texture)
+    }
+}
+
+fn texture_size(texture: &Texture) -> Vector2<f32> {
+    Vector2::new(texture.width() as f32, texture.height() as f32)
+}
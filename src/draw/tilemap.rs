@@ -0,0 +1,302 @@
+//! Tilemaps with orthogonal, isometric and staggered projections.
+//!
+//! Tiles are addressed by integer coordinates, and `TileLayout` converts
+//! between these and world coordinates. The tiles themselves are frames of a
+//! `SpriteSheet`, so tiles that are taller than the grid, e.g. walls in an
+//! isometric map, can be placed with the pivot of their frame.
+//!
+//! In the isometric projection, the X axis of the map points down and to
+//! the right on the screen, and the Y axis points down and to the left. In
+//! the staggered projection, the map is drawn row by row, with every odd row
+//! shifted to the right by half a tile, so that the map has a rectangular
+//! outline. Both projections use diamond-shaped tiles of `tile_size`.
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::{Quad, SpriteSheet, TexColVertex, TriBatch},
+    AaRect, Color4, YAxis,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileProjection {
+    Orthogonal,
+    Isometric,
+    Staggered,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TileLayout {
+    pub projection: TileProjection,
+
+    /// The size of a tile in world units. For diamond-shaped tiles, this is
+    /// the size of the diamond's bounding rect, usually twice as wide as it
+    /// is high.
+    pub tile_size: Vector2<f32>,
+}
+
+impl TileLayout {
+    /// Returns the world position of the center of `tile`.
+    pub fn tile_center(&self, tile: Point2<i32>) -> Point2<f32> {
+        let half = self.tile_size / 2.0;
+
+        match self.projection {
+            TileProjection::Orthogonal => Point2::new(
+                (tile.x as f32 + 0.5) * self.tile_size.x,
+                (tile.y as f32 + 0.5) * self.tile_size.y,
+            ),
+            TileProjection::Isometric => Point2::new(
+                (tile.x - tile.y) as f32 * half.x,
+                (tile.x + tile.y) as f32 * half.y,
+            ),
+            TileProjection::Staggered => Point2::new(
+                tile.x as f32 * self.tile_size.x + (tile.y & 1) as f32 * half.x,
+                tile.y as f32 * half.y,
+            ),
+        }
+    }
+
+    /// Returns the tile that contains the world position `pos`, e.g. for
+    /// picking the tile under the cursor. The tile may be outside of the
+    /// map.
+    pub fn tile_at(&self, pos: Point2<f32>) -> Point2<i32> {
+        match self.projection {
+            TileProjection::Orthogonal => Point2::new(
+                (pos.x / self.tile_size.x).floor() as i32,
+                (pos.y / self.tile_size.y).floor() as i32,
+            ),
+            TileProjection::Isometric => isometric_tile_at(pos, self.tile_size),
+            TileProjection::Staggered => {
+                // Every staggered tile is also a tile of an isometric map,
+                // just with different coordinates.
+                let iso = isometric_tile_at(pos, self.tile_size);
+                let y = iso.x + iso.y;
+
+                Point2::new((iso.x - iso.y - (y & 1)) / 2, y)
+            }
+        }
+    }
+
+    /// Returns the tile that is `offset` tiles away from `tile` along the X
+    /// and Y axes of the map, as they are described in the module
+    /// documentation.
+    ///
+    /// For orthogonal and isometric maps, this simply adds the offset. In
+    /// staggered maps, the axes run diagonally through the rows, so that the
+    /// neighbors of a tile are the same as in an isometric map.
+    pub fn neighbor(&self, tile: Point2<i32>, offset: Vector2<i32>) -> Point2<i32> {
+        match self.projection {
+            TileProjection::Orthogonal | TileProjection::Isometric => tile + offset,
+            TileProjection::Staggered => {
+                let odd = tile.y & 1;
+                let iso =
+                    Point2::new(tile.x + (tile.y + odd) / 2, (tile.y - odd) / 2 - tile.x) + offset;
+                let y = iso.x + iso.y;
+
+                Point2::new((iso.x - iso.y - (y & 1)) / 2, y)
+            }
+        }
+    }
+
+    /// Returns the outline of `tile`: a rect for orthogonal maps and a
+    /// diamond otherwise, e.g. for highlighting the tile under the cursor.
+    ///
+    /// The corners of diamonds are given in the order top, left, bottom,
+    /// right, matching the orientation of the corners of axis-aligned quads.
+    pub fn tile_outline(&self, tile: Point2<i32>) -> Quad {
+        let center = self.tile_center(tile);
+        let half = self.tile_size / 2.0;
+
+        match self.projection {
+            TileProjection::Orthogonal => Quad::axis_aligned(center, self.tile_size),
+            TileProjection::Isometric | TileProjection::Staggered => Quad {
+                corners: [
+                    center - Vector2::new(0.0, half.y),
+                    center - Vector2::new(half.x, 0.0),
+                    center + Vector2::new(0.0, half.y),
+                    center + Vector2::new(half.x, 0.0),
+                ],
+            },
+        }
+    }
+
+    /// Returns the key by which tiles need to be sorted so that tiles in
+    /// front are drawn later. This is the Y coordinate of the tile's center,
+    /// so it can be mixed with the keys of sprites that are given by
+    /// `Batch::set_y_sort_key`.
+    pub fn depth_key(&self, tile: Point2<i32>) -> f32 {
+        self.tile_center(tile).y
+    }
+
+    /// Returns the smallest and largest tile coordinates of tiles that may
+    /// intersect `rect`.
+    pub(crate) fn tile_range(&self, rect: &AaRect) -> (Point2<i32>, Point2<i32>) {
+        let (min, max) = (rect.min(), rect.max());
+
+        match self.projection {
+            TileProjection::Orthogonal => (self.tile_at(min), self.tile_at(max)),
+            TileProjection::Isometric => {
+                let corners = [
+                    self.tile_at(min),
+                    self.tile_at(Point2::new(max.x, min.y)),
+                    self.tile_at(max),
+                    self.tile_at(Point2::new(min.x, max.y)),
+                ];
+                let range_min = corners
+                    .iter()
+                    .fold(corners[0], |a, b| Point2::new(a.x.min(b.x), a.y.min(b.y)));
+                let range_max = corners
+                    .iter()
+                    .fold(corners[0], |a, b| Point2::new(a.x.max(b.x), a.y.max(b.y)));
+
+                (range_min, range_max)
+            }
+            TileProjection::Staggered => {
+                let (range_min, range_max) = (self.tile_at(min), self.tile_at(max));
+
+                // Rows overlap, so one more tile may be visible on each side.
+                (
+                    range_min - Vector2::new(1, 1),
+                    range_max + Vector2::new(1, 1),
+                )
+            }
+        }
+    }
+}
+
+fn isometric_tile_at(pos: Point2<f32>, tile_size: Vector2<f32>) -> Point2<i32> {
+    // In isometric coordinates, each diamond becomes a unit square that is
+    // centered at the tile's coordinates.
+    let a = pos.x / (tile_size.x / 2.0);
+    let b = pos.y / (tile_size.y / 2.0);
+
+    Point2::new(
+        ((a + b) / 2.0 + 0.5).floor() as i32,
+        ((b - a) / 2.0 + 0.5).floor() as i32,
+    )
+}
+
+/// A rectangular map of tiles, each given by the index of a frame in a
+/// `SpriteSheet`.
+#[derive(Debug, Clone)]
+pub struct Tilemap {
+    layout: TileLayout,
+    size: Vector2<u32>,
+    tiles: Vec<Option<usize>>,
+}
+
+impl Tilemap {
+    /// Creates an empty map of `size` tiles.
+    pub fn new(layout: TileLayout, size: Vector2<u32>) -> Self {
+        Self {
+            layout,
+            size,
+            tiles: vec![None; size.x as usize * size.y as usize],
+        }
+    }
+
+    pub fn layout(&self) -> &TileLayout {
+        &self.layout
+    }
+
+    pub fn size(&self) -> Vector2<u32> {
+        self.size
+    }
+
+    pub fn contains(&self, tile: Point2<i32>) -> bool {
+        tile.x >= 0 && tile.y >= 0 && (tile.x as u32) < self.size.x && (tile.y as u32) < self.size.y
+    }
+
+    pub fn get(&self, tile: Point2<i32>) -> Option<usize> {
+        if self.contains(tile) {
+            self.tiles[self.index(tile)]
+        } else {
+            None
+        }
+    }
+
+    /// Sets the frame of `tile`. Tiles outside of the map are ignored.
+    pub fn set(&mut self, tile: Point2<i32>, frame: Option<usize>) {
+        if self.contains(tile) {
+            let index = self.index(tile);
+            self.tiles[index] = frame;
+        }
+    }
+
+    /// Returns the tile of the map that contains the world position `pos`,
+    /// if any.
+    pub fn tile_at(&self, pos: Point2<f32>) -> Option<Point2<i32>> {
+        Some(self.layout.tile_at(pos)).filter(|tile| self.contains(*tile))
+    }
+
+    /// Returns the non-empty tiles that intersect `visible_rect`, which
+    /// usually is given by `Camera::visible_rect`, in the order in which
+    /// they need to be drawn.
+    ///
+    /// Tiles whose frames extend beyond their outline may be culled too
+    /// early; enlarge `visible_rect` to account for that.
+    pub fn visible_tiles(&self, visible_rect: &AaRect) -> Vec<Point2<i32>> {
+        let (range_min, range_max) = self.layout.tile_range(visible_rect);
+        let mut tiles = Vec::new();
+
+        for y in range_min.y.max(0)..=range_max.y.min(self.size.y as i32 - 1) {
+            for x in range_min.x.max(0)..=range_max.x.min(self.size.x as i32 - 1) {
+                let tile = Point2::new(x, y);
+
+                if self.tiles[self.index(tile)].is_some()
+                    && visible_rect.intersects(&self.layout.tile_outline(tile).bounding_rect())
+                {
+                    tiles.push(tile);
+                }
+            }
+        }
+
+        // `sort_by` is stable, so tiles at the same depth stay in row order.
+        tiles.sort_by(|a, b| {
+            self.layout
+                .depth_key(*a)
+                .total_cmp(&self.layout.depth_key(*b))
+        });
+
+        tiles
+    }
+
+    fn index(&self, tile: Point2<i32>) -> usize {
+        tile.y as usize * self.size.x as usize + tile.x as usize
+    }
+}
+
+impl TriBatch<TexColVertex> {
+    /// Pushes the tiles of `map` that are visible in `visible_rect`, back to
+    /// front. The frames of `sheet` are placed with their pivot at the
+    /// center of the tile and drawn at their size in pixels.
+    ///
+    /// If the Y axis of the batch points up, the rows of the map appear in
+    /// the opposite order on screen, so the tiles are pushed in reverse.
+    pub fn push_tilemap(
+        &mut self,
+        map: &Tilemap,
+        sheet: &SpriteSheet,
+        visible_rect: &AaRect,
+        z: f32,
+        color: Color4,
+    ) {
+        let mut tiles = map.visible_tiles(visible_rect);
+        if self.y_axis() == YAxis::Up {
+            tiles.reverse();
+        }
+
+        for tile in tiles {
+            if let Some(frame) = map.get(tile) {
+                self.push_sprite(
+                    sheet,
+                    frame,
+                    map.layout().tile_center(tile),
+                    Vector2::new(1.0, 1.0),
+                    z,
+                    color,
+                );
+            }
+        }
+    }
+}
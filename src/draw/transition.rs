@@ -0,0 +1,309 @@
+//! Transition effects between two scenes, such as fades and wipes.
+//!
+//! While a transition runs, both scenes are rendered into screen-sized
+//! targets every frame, so that animated scenes keep moving, and the
+//! targets are then combined on the canvas by the effect's shader.
+
+use std::time::Duration;
+
+use golem::{
+    Attribute, AttributeType, Dimension, NumberType, ShaderDescription, TextureFilter, Uniform,
+    UniformType, UniformValue,
+};
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::{
+        shader_cache::set_uniform_if_used, RenderTarget, SharedShader, TargetScope, TexVertex,
+        TriBatch, Vertex,
+    },
+    AaRect, Canvas, Color4, Error,
+};
+
+#[derive(Debug, Clone)]
+pub enum TransitionEffect {
+    /// Fades the old scene out to a color, and then fades the new scene in.
+    Fade(Color4),
+
+    /// Blends the old scene into the new one.
+    CrossFade,
+
+    /// Reveals the new scene in a circle that grows from the center of the
+    /// screen.
+    CircleWipe,
+
+    /// Pixelates the old scene more and more, switches to the new scene
+    /// halfway through, and then pixelates it less and less.
+    Pixelate {
+        /// The size of the pixels halfway through, in physical pixels.
+        max_pixel_size: f32,
+    },
+
+    /// A custom effect, given as GLSL source that defines the function
+    /// `vec4 transition(vec2 uv)`.
+    ///
+    /// The function is called for each fragment of the screen, and can use
+    /// the samplers `from_tex` and `to_tex`, which hold the old and the new
+    /// scene, the float `progress`, which goes from zero to one, and the
+    /// `vec2` `screen_size` in physical pixels.
+    Custom(String),
+}
+
+impl TransitionEffect {
+    fn glsl(&self) -> &str {
+        match self {
+            TransitionEffect::Fade(_) => {
+                r#"
+                vec4 transition(vec2 uv) {
+                    if (progress < 0.5) {
+                        return mix(texture(from_tex, uv), fade_color, progress * 2.0);
+                    } else {
+                        return mix(fade_color, texture(to_tex, uv), progress * 2.0 - 1.0);
+                    }
+                }
+                "#
+            }
+            TransitionEffect::CrossFade => {
+                r#"
+                vec4 transition(vec2 uv) {
+                    return mix(texture(from_tex, uv), texture(to_tex, uv), progress);
+                }
+                "#
+            }
+            TransitionEffect::CircleWipe => {
+                r#"
+                vec4 transition(vec2 uv) {
+                    vec2 p = (uv - 0.5) * screen_size;
+                    float radius = progress * length(screen_size * 0.5);
+
+                    // Antialias the edge over about a pixel.
+                    float t = clamp(radius - length(p) + 0.5, 0.0, 1.0);
+
+                    return mix(texture(from_tex, uv), texture(to_tex, uv), t);
+                }
+                "#
+            }
+            TransitionEffect::Pixelate { .. } => {
+                r#"
+                vec4 transition(vec2 uv) {
+                    float pixel_size = mix(1.0, max_pixel_size, 1.0 - abs(progress * 2.0 - 1.0));
+                    vec2 cell = (floor(uv * screen_size / pixel_size) + 0.5) * pixel_size;
+                    vec2 snapped_uv = cell / screen_size;
+
+                    if (progress < 0.5) {
+                        return texture(from_tex, snapped_uv);
+                    } else {
+                        return texture(to_tex, snapped_uv);
+                    }
+                }
+                "#
+            }
+            TransitionEffect::Custom(glsl) => glsl,
+        }
+    }
+}
+
+pub struct Transition {
+    effect: TransitionEffect,
+    duration: Duration,
+    elapsed: Duration,
+
+    from: RenderTarget,
+    to: RenderTarget,
+
+    shader: SharedShader,
+    batch: TriBatch<TexVertex>,
+}
+
+impl Transition {
+    /// Creates a transition that runs for `duration`, which is advanced with
+    /// `update`.
+    pub fn new(ctx: &Canvas, effect: TransitionEffect, duration: Duration) -> Result<Self, Error> {
+        let from = Self::new_target(ctx, "transition from")?;
+        let to = Self::new_target(ctx, "transition to")?;
+
+        let fragment_shader = format!(
+            "{}{}",
+            effect.glsl(),
+            r#"
+            void main() {
+                gl_FragColor = transition(v_tex_coords);
+            }
+            "#
+        );
+
+        let shader = ctx.shader_cache().get_or_compile(
+            ctx.golem_ctx(),
+            "Transition",
+            ShaderDescription {
+                vertex_input: &TexVertex::attributes(),
+                fragment_input: &[Attribute::new(
+                    "v_tex_coords",
+                    AttributeType::Vector(Dimension::D2),
+                )],
+                uniforms: &[
+                    Uniform::new("from_tex", UniformType::Sampler2D),
+                    Uniform::new("to_tex", UniformType::Sampler2D),
+                    Uniform::new("progress", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new(
+                        "screen_size",
+                        UniformType::Vector(NumberType::Float, Dimension::D2),
+                    ),
+                    Uniform::new(
+                        "fade_color",
+                        UniformType::Vector(NumberType::Float, Dimension::D4),
+                    ),
+                    Uniform::new("max_pixel_size", UniformType::Scalar(NumberType::Float)),
+                ],
+                vertex_shader: r#"
+                void main() {
+                    gl_Position = vec4(a_world_pos.xy, 0.0, 1.0);
+                    v_tex_coords = a_tex_coords;
+                }
+                "#,
+                fragment_shader: &fragment_shader,
+            },
+            &[],
+        )?;
+
+        // A quad that covers the whole viewport in normalized device
+        // coordinates.
+        let mut batch = TriBatch::new(ctx)?;
+        batch.push_quad(
+            &AaRect::from_top_left(Point2::new(-1.0, -1.0), Vector2::new(2.0, 2.0)).into(),
+            0.0,
+            AaRect::zero_to_one(),
+        );
+
+        Ok(Self {
+            effect,
+            duration,
+            elapsed: Duration::from_secs(0),
+            from,
+            to,
+            shader,
+            batch,
+        })
+    }
+
+    fn new_target(ctx: &Canvas, label: &str) -> Result<RenderTarget, Error> {
+        RenderTarget::with_filters(
+            ctx,
+            ctx.screen_geom().size,
+            TextureFilter::Nearest,
+            TextureFilter::Nearest,
+            label,
+        )
+    }
+
+    pub fn effect(&self) -> &TransitionEffect {
+        &self.effect
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns how far the transition has progressed, from zero to one.
+    pub fn progress(&self) -> f32 {
+        if self.duration == Duration::from_secs(0) {
+            1.0
+        } else {
+            (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Advances the transition by `dt`, which usually is the game time of
+    /// the frame, see `GameClock::dt`, so that the transition stands still
+    /// while the game is paused.
+    pub fn update(&mut self, dt: Duration) {
+        self.elapsed = (self.elapsed + dt).min(self.duration);
+    }
+
+    /// Starts the transition again from the beginning.
+    pub fn restart(&mut self) {
+        self.elapsed = Duration::from_secs(0);
+    }
+
+    /// Draws the current state of the transition onto the canvas.
+    ///
+    /// `draw_from` and `draw_to` draw the old and the new scene, each into a
+    /// target that has been cleared to `clear_color`. Once the transition
+    /// has finished, only `draw_to` is called, drawing directly onto the
+    /// canvas.
+    pub fn draw(
+        &mut self,
+        ctx: &Canvas,
+        clear_color: Color4,
+        draw_from: impl FnOnce(&Canvas) -> Result<(), Error>,
+        draw_to: impl FnOnce(&Canvas) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.is_finished() {
+            return draw_to(ctx);
+        }
+
+        let size = ctx.screen_geom().size;
+        if self.from.size() != size {
+            self.from = Self::new_target(ctx, "transition from")?;
+            self.to = Self::new_target(ctx, "transition to")?;
+        }
+
+        {
+            let _target = TargetScope::new(ctx, &self.from);
+            ctx.clear(clear_color);
+            draw_from(ctx)?;
+        }
+        {
+            let _target = TargetScope::new(ctx, &self.to);
+            ctx.clear(clear_color);
+            draw_to(ctx)?;
+        }
+
+        // The targets have been unbound when their scopes ended.
+        self.from
+            .with_texture(|texture| texture.set_active(std::num::NonZeroU32::new(1).unwrap()));
+        self.to
+            .with_texture(|texture| texture.set_active(std::num::NonZeroU32::new(2).unwrap()));
+
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+
+        // Depending on the effect, some of the uniforms are unused.
+        let fade_color = match &self.effect {
+            TransitionEffect::Fade(color) => *color,
+            _ => Color4::new(0.0, 0.0, 0.0, 1.0),
+        };
+        let max_pixel_size = match &self.effect {
+            TransitionEffect::Pixelate { max_pixel_size } => *max_pixel_size,
+            _ => 1.0,
+        };
+        set_uniform_if_used(&mut shader, "from_tex", UniformValue::Int(1))?;
+        set_uniform_if_used(&mut shader, "to_tex", UniformValue::Int(2))?;
+        set_uniform_if_used(
+            &mut shader,
+            "progress",
+            UniformValue::Float(self.progress()),
+        )?;
+        set_uniform_if_used(
+            &mut shader,
+            "screen_size",
+            UniformValue::Vector2([size.x as f32, size.y as f32]),
+        )?;
+        set_uniform_if_used(
+            &mut shader,
+            "fade_color",
+            UniformValue::Vector4(fade_color.into()),
+        )?;
+        set_uniform_if_used(
+            &mut shader,
+            "max_pixel_size",
+            UniformValue::Float(max_pixel_size),
+        )?;
+
+        self.batch.draw_unit().draw(&shader)
+    }
+}
@@ -0,0 +1,139 @@
+//! Visibility polygons for fog of war and stealth vision.
+//!
+//! The visibility polygon of a point contains everything that can be seen
+//! from the point when looking in all directions, given line segments that
+//! block the view. It is computed with an angular sweep: rays are cast
+//! towards every segment endpoint, and slightly to either side of it, and
+//! the nearest hits are connected in the order of their angles.
+
+use nalgebra::{Point2, Point3, Vector2};
+
+use crate::{
+    draw::{ColVertex, TriBatch},
+    AaRect, Color4,
+};
+
+/// Angle by which the additional rays are rotated to either side of each
+/// endpoint, so that they pass the corner and hit what is behind it.
+const CORNER_EPSILON: f32 = 0.0001;
+
+/// Computes the visibility polygon of `origin`, given the segments that
+/// block the view.
+///
+/// The view is limited to `bounds`, which needs to contain `origin`. The
+/// returned points are sorted by their angle around `origin`, so that they
+/// form a fan around it.
+///
+/// This casts three rays per endpoint against all segments, so it takes
+/// quadratic time in the number of segments. For large maps, only pass the
+/// segments that are near `origin`.
+pub fn visibility_polygon(
+    origin: Point2<f32>,
+    segments: &[(Point2<f32>, Point2<f32>)],
+    bounds: AaRect,
+) -> Vec<Point2<f32>> {
+    let min = bounds.min();
+    let max = bounds.max();
+    let corners = [
+        min,
+        Point2::new(max.x, min.y),
+        max,
+        Point2::new(min.x, max.y),
+    ];
+
+    let mut all_segments: Vec<(Point2<f32>, Point2<f32>)> = segments.to_vec();
+    for i in 0..4 {
+        all_segments.push((corners[i], corners[(i + 1) % 4]));
+    }
+
+    let mut angles: Vec<f32> = all_segments
+        .iter()
+        .flat_map(|&(a, b)| vec![a, b])
+        .filter(|p| bounds.contains_point(*p))
+        .map(|p| (p.y - origin.y).atan2(p.x - origin.x))
+        .flat_map(|angle| vec![angle - CORNER_EPSILON, angle, angle + CORNER_EPSILON])
+        .collect();
+    angles.sort_by(|a, b| a.total_cmp(b));
+    angles.dedup();
+
+    angles
+        .into_iter()
+        .filter_map(|angle| {
+            let dir = Vector2::new(angle.cos(), angle.sin());
+
+            all_segments
+                .iter()
+                .filter_map(|&(a, b)| ray_segment_intersection(origin, dir, a, b))
+                .min_by(|s, t| s.total_cmp(t))
+                .map(|t| origin + dir * t)
+        })
+        .collect()
+}
+
+/// Returns the distance along the ray at which it hits the segment from `a`
+/// to `b`, if it does.
+fn ray_segment_intersection(
+    origin: Point2<f32>,
+    dir: Vector2<f32>,
+    a: Point2<f32>,
+    b: Point2<f32>,
+) -> Option<f32> {
+    let edge = b - a;
+    let denom = dir.perp(&edge);
+    if denom.abs() < std::f32::EPSILON {
+        // The ray is parallel to the segment.
+        return None;
+    }
+
+    let to_a = a - origin;
+    let t = to_a.perp(&edge) / denom;
+    let u = to_a.perp(&dir) / denom;
+
+    if t >= 0.0 && (0.0..=1.0).contains(&u) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+impl TriBatch<ColVertex> {
+    /// Pushes a triangle fan around `origin` that covers `polygon`, which is
+    /// usually given by `visibility_polygon`.
+    ///
+    /// The colors are interpolated from `center_color` at `origin` to
+    /// `edge_color` at the points of the polygon. For fog of war, the fan
+    /// can be drawn into a mask that is then used to reveal the map; for
+    /// stealth vision, it can be drawn additively on a lights layer.
+    pub fn push_visibility_fan(
+        &mut self,
+        origin: Point2<f32>,
+        polygon: &[Point2<f32>],
+        z: f32,
+        center_color: Color4,
+        edge_color: Color4,
+    ) {
+        if polygon.len() < 2 {
+            return;
+        }
+
+        let center_idx = self.next_index();
+        self.push_vertex(&ColVertex {
+            world_pos: Point3::new(origin.x, origin.y, z),
+            color: center_color,
+        });
+
+        for point in polygon {
+            self.push_vertex(&ColVertex {
+                world_pos: Point3::new(point.x, point.y, z),
+                color: edge_color,
+            });
+        }
+
+        let num_points = polygon.len() as u32;
+        for i in 0..num_points {
+            self.push_element(center_idx);
+            self.push_element(center_idx + 1 + i);
+            self.push_element(center_idx + 1 + (i + 1) % num_points);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! A water surface that reflects what is drawn above it.
+//!
+//! The scene is first rendered into a reflection target with
+//! `WaterEffect::render_reflection`. When the water is drawn, each fragment
+//! samples the reflection target at the position mirrored about the water
+//! line, distorted by animated ripples.
+
+use std::time::Duration;
+
+use golem::{
+    blend::BlendMode, Attribute, AttributeType, Dimension, NumberType, ShaderDescription,
+    TextureFilter, Uniform, UniformType, UniformValue,
+};
+use nalgebra::{Matrix3, Point3};
+
+use crate::{
+    draw::{BlendScope, ColVertex, RenderTarget, SharedShader, TargetScope, TriBatch, Vertex},
+    geom::matrix3_to_flat_array,
+    AaRect, Canvas, Color4, Error,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct WaterParams {
+    /// How strongly the ripples displace the reflection, as a fraction of
+    /// the screen size.
+    pub ripple_amplitude: f32,
+
+    /// Number of ripples per world unit, times two pi.
+    pub ripple_frequency: f32,
+
+    /// Speed of the ripples in radians per second.
+    pub ripple_speed: f32,
+
+    /// How much of the reflection is mixed into the water color, between 0
+    /// and 1.
+    pub reflectivity: f32,
+}
+
+impl Default for WaterParams {
+    fn default() -> Self {
+        Self {
+            ripple_amplitude: 0.004,
+            ripple_frequency: 0.05,
+            ripple_speed: 2.0,
+            reflectivity: 0.6,
+        }
+    }
+}
+
+pub struct WaterEffect {
+    reflection: RenderTarget,
+    shader: SharedShader,
+    batch: TriBatch<ColVertex>,
+    params: WaterParams,
+}
+
+impl WaterEffect {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        let reflection = Self::new_reflection(ctx)?;
+
+        let shader = ctx.shader_cache().get_or_compile(
+            ctx.golem_ctx(),
+            "WaterEffect",
+            ShaderDescription {
+                vertex_input: &ColVertex::attributes(),
+                fragment_input: &[
+                    Attribute::new("v_color", AttributeType::Vector(Dimension::D4)),
+                    Attribute::new("v_screen_uv", AttributeType::Vector(Dimension::D2)),
+                    Attribute::new("v_world_pos", AttributeType::Vector(Dimension::D2)),
+                ],
+                uniforms: &[
+                    Uniform::new("mat_projection_view", UniformType::Matrix(Dimension::D3)),
+                    Uniform::new("reflection", UniformType::Sampler2D),
+                    Uniform::new("water_line", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("time", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("ripple_amplitude", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("ripple_frequency", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("ripple_speed", UniformType::Scalar(NumberType::Float)),
+                    Uniform::new("reflectivity", UniformType::Scalar(NumberType::Float)),
+                ],
+                vertex_shader: r#"
+                void main() {
+                    vec3 p = mat_projection_view * vec3(a_world_pos.xy, 1.0);
+                    gl_Position = vec4(p.xy, a_world_pos.z * p.z, p.z);
+                    v_color = a_color;
+                    v_screen_uv = p.xy / p.z * 0.5 + 0.5;
+                    v_world_pos = a_world_pos.xy;
+                }
+                "#,
+                fragment_shader: r#"
+                void main() {
+                    float phase = time * ripple_speed;
+                    vec2 ripple = ripple_amplitude * vec2(
+                        sin(v_world_pos.y * ripple_frequency + phase),
+                        0.5 * sin(v_world_pos.x * ripple_frequency * 0.7 - phase * 1.3)
+                    );
+
+                    // Mirror about the water line, which is given in screen
+                    // UV coordinates.
+                    vec2 uv = vec2(v_screen_uv.x, 2.0 * water_line - v_screen_uv.y);
+                    vec4 reflected = texture(reflection, uv + ripple);
+
+                    gl_FragColor = vec4(
+                        mix(v_color.rgb, reflected.rgb, reflectivity * reflected.a),
+                        v_color.a
+                    );
+                }
+                "#,
+            },
+            &[],
+        )?;
+
+        Ok(Self {
+            reflection,
+            shader,
+            batch: TriBatch::new(ctx)?,
+            params: WaterParams::default(),
+        })
+    }
+
+    fn new_reflection(ctx: &Canvas) -> Result<RenderTarget, Error> {
+        RenderTarget::with_filters(
+            ctx,
+            ctx.screen_geom().size,
+            TextureFilter::Linear,
+            TextureFilter::Linear,
+            "water reflection",
+        )
+    }
+
+    pub fn params(&self) -> &WaterParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: WaterParams) {
+        self.params = params;
+    }
+
+    /// Renders the scene that is to be reflected into the reflection target.
+    ///
+    /// `f` should draw the scene with the same transform that is later used
+    /// for drawing the water. The target is cleared to transparent black
+    /// beforehand, so parts of the water that reflect nothing show only the
+    /// water color.
+    pub fn render_reflection(
+        &mut self,
+        ctx: &Canvas,
+        f: impl FnOnce(&Canvas) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if self.reflection.size() != ctx.screen_geom().size {
+            self.reflection = Self::new_reflection(ctx)?;
+        }
+
+        let _target = TargetScope::new(ctx, &self.reflection);
+        ctx.clear(Color4::new(0.0, 0.0, 0.0, 0.0));
+
+        f(ctx)
+    }
+
+    /// Draws a rectangle of water, whose top edge is the water line.
+    ///
+    /// `rect` is given in world space, and `time` drives the ripple
+    /// animation.
+    pub fn draw(
+        &mut self,
+        ctx: &Canvas,
+        transform: &Matrix3<f32>,
+        rect: AaRect,
+        z: f32,
+        color: Color4,
+        time: Duration,
+    ) -> Result<(), Error> {
+        let water_line = {
+            let p = transform * Point3::new(rect.center.x, rect.min().y, 1.0);
+            p.y / p.z * 0.5 + 0.5
+        };
+
+        self.batch.clear();
+        self.batch.push_quad(&rect.into(), z, color);
+
+        let _blend = BlendScope::new(ctx.golem_ctx(), BlendMode::default());
+
+        // The reflection target is not bound while drawing the water, since
+        // `render_reflection` unbinds it when its scope ends.
+        self.reflection
+            .with_texture(|texture| texture.set_active(std::num::NonZeroU32::new(1).unwrap()));
+
+        let mut shader = self.shader.borrow_mut();
+        shader.bind();
+        shader.set_uniform(
+            "mat_projection_view",
+            UniformValue::Matrix3(matrix3_to_flat_array(transform)),
+        )?;
+        shader.set_uniform("reflection", UniformValue::Int(1))?;
+        shader.set_uniform("water_line", UniformValue::Float(water_line))?;
+        shader.set_uniform("time", UniformValue::Float(time.as_secs_f32()))?;
+        shader.set_uniform(
+            "ripple_amplitude",
+            UniformValue::Float(self.params.ripple_amplitude),
+        )?;
+        shader.set_uniform(
+            "ripple_frequency",
+            UniformValue::Float(self.params.ripple_frequency),
+        )?;
+        shader.set_uniform(
+            "ripple_speed",
+            UniformValue::Float(self.params.ripple_speed),
+        )?;
+        shader.set_uniform(
+            "reflectivity",
+            UniformValue::Float(self.params.reflectivity),
+        )?;
+
+        self.batch.draw_unit().draw(&shader)
+    }
+}
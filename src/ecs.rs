@@ -0,0 +1,348 @@
+//! Optional adapter for drawing the entities of a `hecs` world, enabled with
+//! the `hecs` feature.
+//!
+//! Entities with a `Transform2D` and a `SpriteComp` are drawn as quads, and
+//! entities with a `Transform2D` and a `TextComp` are drawn as text. The
+//! view is taken from the first entity that has a `Transform2D` and an
+//! active `CameraComp`. For top-down games, sprites can be drawn in the
+//! order of their baseline with `EcsRenderer::set_y_sort`.
+
+use std::rc::Rc;
+
+use golem::blend::BlendMode;
+use nalgebra::{Matrix3, Point2, Point3, Vector2};
+
+use crate::{
+    draw::{
+        shadow::Light, BlendScope, ColPass, ColVertex, Font, Quad, TexColPass, TexColVertex,
+        TextBatch, TextureRegion, TriBatch,
+    },
+    geom::scale_rotate_translate,
+    Camera, Canvas, Color3, Color4, Error, Texture, YAxis,
+};
+
+/// The placement of an entity in world coordinates.
+#[derive(Debug, Clone, Copy)]
+pub struct Transform2D {
+    pub pos: Point2<f32>,
+
+    /// Rotation around `pos` in radians.
+    pub angle: f32,
+
+    pub scale: Vector2<f32>,
+
+    /// Depth used for depth testing.
+    pub z: f32,
+}
+
+impl Default for Transform2D {
+    fn default() -> Self {
+        Self {
+            pos: Point2::origin(),
+            angle: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+            z: 0.0,
+        }
+    }
+}
+
+impl Transform2D {
+    pub fn at(pos: Point2<f32>) -> Self {
+        Self {
+            pos,
+            ..Default::default()
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix3<f32> {
+        scale_rotate_translate(self.scale, self.angle, self.pos.coords)
+    }
+}
+
+/// Identifies a texture region that has been registered with
+/// `EcsRenderer::add_region`.
+///
+/// Components need to be `Send` and `Sync`, so they cannot hold regions
+/// directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionId(usize);
+
+/// A rectangle that is centered at the entity's position.
+#[derive(Debug, Clone)]
+pub struct SpriteComp {
+    pub size: Vector2<f32>,
+    pub color: Color4,
+
+    /// If set, the sprite shows the region, multiplied with `color`.
+    pub region: Option<RegionId>,
+}
+
+impl SpriteComp {
+    pub fn colored(size: Vector2<f32>, color: Color4) -> Self {
+        Self {
+            size,
+            color,
+            region: None,
+        }
+    }
+
+    pub fn textured(size: Vector2<f32>, region: RegionId) -> Self {
+        Self {
+            size,
+            color: Color4::new(1.0, 1.0, 1.0, 1.0),
+            region: Some(region),
+        }
+    }
+}
+
+/// Shifts the sort key of a sprite when the renderer sorts sprites by their
+/// baseline, see `EcsRenderer::set_y_sort`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct YSortBias(pub f32);
+
+/// Text whose top left corner is at the entity's position. Rotation and
+/// scaling are ignored.
+#[derive(Debug, Clone)]
+pub struct TextComp {
+    pub text: String,
+    pub size: f32,
+    pub color: Color4,
+}
+
+/// A light source at the entity's position, pointing in the direction of the
+/// entity's angle. Use `to_light` to pass it to a `ShadowMap`.
+#[derive(Debug, Clone, Copy)]
+pub struct LightComp {
+    pub radius: f32,
+
+    /// The size of the light cone in radians.
+    pub angle_size: f32,
+
+    pub color: Color3,
+}
+
+impl LightComp {
+    pub fn to_light(&self, transform: &Transform2D) -> Light {
+        Light {
+            world_pos: transform.pos,
+            radius: self.radius,
+            angle: transform.angle,
+            angle_size: self.angle_size,
+            color: self.color,
+        }
+    }
+}
+
+/// Makes the entity's transform determine the view.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraComp {
+    pub zoom: f32,
+    pub active: bool,
+
+    /// The direction of the world's Y axis on screen. Sprites and text stay
+    /// upright either way.
+    pub y_axis: YAxis,
+}
+
+impl Default for CameraComp {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            active: true,
+            y_axis: YAxis::Down,
+        }
+    }
+}
+
+/// Draws the entities of a `hecs::World`.
+///
+/// The renderer keeps its batches between frames, so that their buffers
+/// can be reused.
+pub struct EcsRenderer {
+    color_batch: TriBatch<ColVertex>,
+    tex_batches: Vec<(Rc<Texture>, TriBatch<TexColVertex>)>,
+    text_batch: TextBatch,
+
+    /// The registered regions, together with the index of the batch of
+    /// their texture.
+    regions: Vec<(TextureRegion, usize)>,
+
+    color_pass: ColPass,
+    tex_pass: TexColPass,
+
+    y_sort: bool,
+}
+
+impl EcsRenderer {
+    pub fn new(ctx: &Canvas) -> Result<Self, Error> {
+        Ok(Self {
+            color_batch: TriBatch::new(ctx)?,
+            tex_batches: Vec::new(),
+            text_batch: TextBatch::new(ctx)?,
+            regions: Vec::new(),
+            color_pass: ColPass::new(ctx)?,
+            tex_pass: TexColPass::new(ctx)?,
+            y_sort: false,
+        })
+    }
+
+    /// Registers a texture region that can then be shown by sprites.
+    ///
+    /// Sprites whose regions share a texture are drawn with one call.
+    pub fn add_region(&mut self, ctx: &Canvas, region: TextureRegion) -> Result<RegionId, Error> {
+        let batch_index = match self
+            .tex_batches
+            .iter()
+            .position(|(texture, _)| Rc::ptr_eq(texture, &region.texture))
+        {
+            Some(index) => index,
+            None => {
+                self.tex_batches
+                    .push((region.texture.clone(), TriBatch::new(ctx)?));
+                self.tex_batches.len() - 1
+            }
+        };
+        self.regions.push((region, batch_index));
+
+        Ok(RegionId(self.regions.len() - 1))
+    }
+
+    pub fn region(&self, id: RegionId) -> &TextureRegion {
+        &self.regions[id.0].0
+    }
+
+    /// Enables drawing sprites in the order of their baseline, i.e. the
+    /// bottom of their bounding rect, plus their `YSortBias`, as needed for
+    /// top-down games. Disabled by default.
+    ///
+    /// Sprites are only sorted among the sprites that end up in the same
+    /// batch, i.e. the colored sprites and the sprites of each texture, so
+    /// sprites that need to be sorted against each other should share an
+    /// atlas texture.
+    pub fn set_y_sort(&mut self, y_sort: bool) {
+        self.y_sort = y_sort;
+    }
+
+    /// Returns the camera of the first entity with an active `CameraComp`,
+    /// or a camera centered at the origin if there is none.
+    pub fn camera(world: &hecs::World) -> Camera {
+        world
+            .query::<(&Transform2D, &CameraComp)>()
+            .iter()
+            .find(|(_, (_, camera))| camera.active)
+            .map_or(Camera::default(), |(_, (transform, camera))| Camera {
+                center: transform.pos,
+                zoom: camera.zoom,
+                angle: transform.angle,
+                y_axis: camera.y_axis,
+                ..Camera::default()
+            })
+    }
+
+    /// Draws all sprites and texts of `world`, with texts drawn on top.
+    pub fn draw(
+        &mut self,
+        ctx: &Canvas,
+        world: &hecs::World,
+        font: &mut Font,
+    ) -> Result<(), Error> {
+        let screen_geom = ctx.screen_geom();
+        let camera = Self::camera(world);
+        let transform = screen_geom.orthographic_projection() * camera.to_matrix(&screen_geom);
+        let visible_rect = camera.visible_rect(&screen_geom);
+
+        self.color_batch.clear();
+        for (_, batch) in self.tex_batches.iter_mut() {
+            batch.clear();
+        }
+        self.text_batch.clear();
+        self.text_batch.set_y_axis(camera.y_axis);
+
+        for (_, (entity_transform, sprite, y_sort_bias)) in world
+            .query::<(&Transform2D, &SpriteComp, Option<&YSortBias>)>()
+            .iter()
+        {
+            let quad = Quad::new(
+                &(entity_transform.to_matrix() * Matrix3::new_nonuniform_scaling(&sprite.size)),
+            );
+            let y_sort_key = if self.y_sort {
+                // The baseline is at the bottom on screen, and sprites
+                // further down are drawn later.
+                let bounds = quad.bounding_rect();
+                let baseline_y = match camera.y_axis {
+                    YAxis::Down => bounds.max().y,
+                    YAxis::Up => -bounds.min().y,
+                };
+
+                Some((baseline_y, y_sort_bias.map_or(0.0, |bias| bias.0)))
+            } else {
+                None
+            };
+            match sprite.region {
+                Some(id) => {
+                    let (region, batch_index) = &self.regions[id.0];
+                    let uv_rect = match camera.y_axis {
+                        YAxis::Down => region.uv_rect,
+                        YAxis::Up => region.uv_rect.flip_y(),
+                    };
+
+                    let batch = &mut self.tex_batches[*batch_index].1;
+                    if let Some((baseline_y, bias)) = y_sort_key {
+                        batch.set_y_sort_key(baseline_y, bias);
+                    }
+                    batch.push_quad_if_visible(
+                        &visible_rect,
+                        &quad,
+                        entity_transform.z,
+                        uv_rect,
+                        sprite.color,
+                    );
+                }
+                None => {
+                    if let Some((baseline_y, bias)) = y_sort_key {
+                        self.color_batch.set_y_sort_key(baseline_y, bias);
+                    }
+                    self.color_batch.push_quad_if_visible(
+                        &visible_rect,
+                        &quad,
+                        entity_transform.z,
+                        sprite.color,
+                    );
+                }
+            }
+        }
+
+        // Rasterize glyphs at their zoomed size, so that text stays sharp.
+        let raster_scale = font.raster_scale();
+        font.set_raster_scale(camera.zoom.abs().max(f32::EPSILON));
+
+        for (_, (entity_transform, text)) in world.query::<(&Transform2D, &TextComp)>().iter() {
+            font.write(
+                text.size,
+                Point3::new(
+                    entity_transform.pos.x,
+                    entity_transform.pos.y,
+                    entity_transform.z,
+                ),
+                text.color,
+                &text.text,
+                &mut self.text_batch,
+            );
+        }
+
+        font.set_raster_scale(raster_scale);
+
+        self.color_pass
+            .draw(&transform, &self.color_batch.draw_unit())?;
+
+        {
+            let _blend = BlendScope::new(ctx.golem_ctx(), BlendMode::default());
+            for (texture, batch) in self.tex_batches.iter_mut() {
+                self.tex_pass
+                    .draw(&transform, texture, &batch.draw_unit())?;
+            }
+        }
+
+        font.draw(ctx, &transform, &self.text_batch.draw_unit())
+    }
+}
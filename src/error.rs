@@ -27,4 +27,49 @@ pub enum Error {
 
     #[error("Failed to load font")]
     Font(String),
+
+    #[error("failed to load image: {0}")]
+    Image(String),
+
+    #[error("failed to load scene: {0}")]
+    Scene(String),
+
+    #[error("hot reload error: {0}")]
+    HotReload(String),
+
+    #[error("DOM error: {0}")]
+    Dom(String),
+
+    #[error("WebXR error: {0}")]
+    Xr(String),
+
+    #[error("render graph error: {0}")]
+    RenderGraph(String),
+
+    #[error("texture error: {0}")]
+    Texture(String),
+
+    #[error("failed to load sprite sheet: {0}")]
+    SpriteSheet(String),
+
+    #[error("audio error: {0}")]
+    Audio(String),
+
+    #[error("shortcut error: {0}")]
+    Shortcut(String),
+
+    #[error("recording error: {0}")]
+    Recording(String),
+
+    #[error("instancing error: {0}")]
+    Instancing(String),
+
+    #[error("shader error: {0}")]
+    Shader(String),
+
+    #[error("locale error: {0}")]
+    Locale(String),
+
+    #[error("tilemap error: {0}")]
+    Tilemap(String),
 }
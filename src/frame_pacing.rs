@@ -0,0 +1,154 @@
+//! Measuring how regularly frames are delivered.
+//!
+//! CPU profilers show how long our own code takes, but stutter is often
+//! caused by the browser delivering `requestAnimationFrame` callbacks late.
+//! `FramePacing` keeps statistics about the delta times that the main loop
+//! passes to its callback.
+
+use std::{collections::VecDeque, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct FramePacingConfig {
+    /// The number of most recent frames over which statistics are computed.
+    pub window_size: usize,
+
+    /// Frames that take at least this long are considered long frames.
+    pub long_frame_threshold: Duration,
+
+    /// If true, a warning is logged for each long frame.
+    pub log_long_frames: bool,
+}
+
+impl Default for FramePacingConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 120,
+            long_frame_threshold: Duration::from_millis(50),
+            log_long_frames: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FramePacingStats {
+    /// The number of frames in the window.
+    pub num_frames: usize,
+
+    /// Mean time between frames.
+    pub mean_interval: Duration,
+
+    /// Standard deviation of the time between frames.
+    pub jitter: Duration,
+
+    /// The longest time between two frames in the window.
+    pub longest_interval: Duration,
+
+    /// Estimated interval at which the display refreshes, taken to be the
+    /// shortest frame interval in the window.
+    pub refresh_interval: Duration,
+
+    /// Estimated number of display refreshes in the window for which no frame
+    /// was delivered.
+    pub missed_vsyncs: usize,
+
+    /// The number of frames in the window that took at least
+    /// `long_frame_threshold`.
+    pub num_long_frames: usize,
+}
+
+pub struct FramePacing {
+    config: FramePacingConfig,
+    intervals: VecDeque<f64>,
+}
+
+impl FramePacing {
+    pub fn new(config: FramePacingConfig) -> Self {
+        assert!(config.window_size > 0);
+
+        Self {
+            intervals: VecDeque::with_capacity(config.window_size),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &FramePacingConfig {
+        &self.config
+    }
+
+    /// Records a frame, given the delta time that was passed to the main
+    /// loop callback.
+    ///
+    /// Frames with zero delta time (such as the very first frame) are
+    /// ignored.
+    pub fn record(&mut self, dt: Duration) {
+        if dt == Duration::from_secs(0) {
+            return;
+        }
+
+        if self.config.log_long_frames && dt >= self.config.long_frame_threshold {
+            log::warn!(
+                "Long frame: {:.1}ms (threshold {:.1}ms)",
+                dt.as_secs_f64() * 1000.0,
+                self.config.long_frame_threshold.as_secs_f64() * 1000.0,
+            );
+        }
+
+        if self.intervals.len() == self.config.window_size {
+            self.intervals.pop_front();
+        }
+        self.intervals.push_back(dt.as_secs_f64());
+    }
+
+    pub fn clear(&mut self) {
+        self.intervals.clear();
+    }
+
+    pub fn stats(&self) -> FramePacingStats {
+        if self.intervals.is_empty() {
+            return FramePacingStats::default();
+        }
+
+        let n = self.intervals.len() as f64;
+        let mean = self.intervals.iter().sum::<f64>() / n;
+        let variance = self
+            .intervals
+            .iter()
+            .map(|dt| (dt - mean) * (dt - mean))
+            .sum::<f64>()
+            / n;
+        let longest = self.intervals.iter().cloned().fold(0.0, f64::max);
+        let shortest = self
+            .intervals
+            .iter()
+            .cloned()
+            .fold(std::f64::INFINITY, f64::min);
+
+        let missed_vsyncs = self
+            .intervals
+            .iter()
+            .map(|dt| ((dt / shortest).round() as usize).saturating_sub(1))
+            .sum();
+        let long_threshold = self.config.long_frame_threshold.as_secs_f64();
+        let num_long_frames = self
+            .intervals
+            .iter()
+            .filter(|dt| **dt >= long_threshold)
+            .count();
+
+        FramePacingStats {
+            num_frames: self.intervals.len(),
+            mean_interval: Duration::from_secs_f64(mean),
+            jitter: Duration::from_secs_f64(variance.sqrt()),
+            longest_interval: Duration::from_secs_f64(longest),
+            refresh_interval: Duration::from_secs_f64(shortest),
+            missed_vsyncs,
+            num_long_frames,
+        }
+    }
+}
+
+impl Default for FramePacing {
+    fn default() -> Self {
+        Self::new(FramePacingConfig::default())
+    }
+}
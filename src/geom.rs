@@ -1,4 +1,4 @@
-use nalgebra::{Matrix3, Point2, Vector2, Vector3};
+use nalgebra::{Matrix3, Point2, Point3, Vector2, Vector3};
 
 #[derive(Debug, Clone, Copy)]
 pub struct AaRect {
@@ -24,6 +24,72 @@ impl AaRect {
             size,
         }
     }
+
+    pub fn from_min_max(min: Point2<f32>, max: Point2<f32>) -> Self {
+        Self {
+            center: nalgebra::center(&min, &max),
+            size: max - min,
+        }
+    }
+
+    /// Returns the smallest rect that contains all of the given points.
+    pub fn bounding(points: &[Point2<f32>]) -> Self {
+        let mut min = Point2::new(std::f32::INFINITY, std::f32::INFINITY);
+        let mut max = Point2::new(std::f32::NEG_INFINITY, std::f32::NEG_INFINITY);
+
+        for p in points {
+            min = Point2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Point2::new(max.x.max(p.x), max.y.max(p.y));
+        }
+
+        Self::from_min_max(min, max)
+    }
+
+    /// Returns the rect with its top and bottom edges swapped, i.e. with a
+    /// negative height.
+    ///
+    /// Quads that are textured with a flipped UV rect show the texture upside
+    /// down. This keeps textures upright in worlds whose Y axis points up;
+    /// see `YAxis`.
+    pub fn flip_y(&self) -> Self {
+        Self {
+            center: self.center,
+            size: Vector2::new(self.size.x, -self.size.y),
+        }
+    }
+
+    pub fn min(&self) -> Point2<f32> {
+        self.center - self.size / 2.0
+    }
+
+    pub fn max(&self) -> Point2<f32> {
+        self.center + self.size / 2.0
+    }
+
+    pub fn contains_point(&self, p: Point2<f32>) -> bool {
+        let (min, max) = (self.min(), self.max());
+
+        p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y
+    }
+
+    /// Returns the intersection of two rects. If they don't intersect, the
+    /// result has zero size.
+    pub fn intersection(&self, other: &AaRect) -> AaRect {
+        let (min_a, max_a) = (self.min(), self.max());
+        let (min_b, max_b) = (other.min(), other.max());
+
+        let min = Point2::new(min_a.x.max(min_b.x), min_a.y.max(min_b.y));
+        let max = Point2::new(max_a.x.min(max_b.x), max_a.y.min(max_b.y));
+
+        AaRect::from_min_max(min, Point2::new(max.x.max(min.x), max.y.max(min.y)))
+    }
+
+    pub fn intersects(&self, other: &AaRect) -> bool {
+        let delta = other.center - self.center;
+        let extent = (self.size + other.size) / 2.0;
+
+        delta.x.abs() <= extent.x && delta.y.abs() <= extent.y
+    }
 }
 
 pub fn matrix3_to_flat_array(m: &Matrix3<f32>) -> [f32; 9] {
@@ -53,6 +119,28 @@ pub fn scale_translate3(scale: Vector2<f32>, offset: Vector3<f32>) -> Matrix3<f3
     transform
 }
 
+/// Returns the transform that maps `rect` to the whole render target, i.e.
+/// to normalized device coordinates, e.g. for rendering a part of the world
+/// into a texture.
+///
+/// The top of the rect, i.e. its minimum Y for `YAxis::Down` and its
+/// maximum Y for `YAxis::Up`, ends up at `v = 0` of the target's texture.
+pub fn rect_to_target(rect: &AaRect, y_axis: YAxis) -> Matrix3<f32> {
+    let scale = Vector2::new(2.0 / rect.size.x, 2.0 / rect.size.y);
+    let (min, max) = (rect.min(), rect.max());
+
+    match y_axis {
+        YAxis::Down => scale_translate3(
+            scale,
+            Vector3::new(-1.0 - min.x * scale.x, -1.0 - min.y * scale.y, 1.0),
+        ),
+        YAxis::Up => scale_translate3(
+            Vector2::new(scale.x, -scale.y),
+            Vector3::new(-1.0 - min.x * scale.x, -1.0 + max.y * scale.y, 1.0),
+        ),
+    }
+}
+
 /// Build a 3x3 matrix that applies transformations in this order: first
 /// scaling, then translation
 pub fn scale_translate(scale: Vector2<f32>, offset: Vector2<f32>) -> Matrix3<f32> {
@@ -87,7 +175,14 @@ pub fn scale_rotate_translate(
         * Matrix3::new_nonuniform_scaling(&scale)
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Returns the factor by which a 2D affine transform scales lengths, i.e.
+/// the square root of the area scaling. For transforms that scale both axes
+/// the same, this is independent of rotation and flipping.
+pub fn transform_scale(m: &Matrix3<f32>) -> f32 {
+    (m[(0, 0)] * m[(1, 1)] - m[(0, 1)] * m[(1, 0)]).abs().sqrt()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ScreenGeom {
     /// The screen size in pixels.
     pub size: Vector2<u32>,
@@ -120,6 +215,74 @@ impl ScreenGeom {
     pub fn aa_rect(&self) -> AaRect {
         AaRect::from_top_left(Point2::origin(), nalgebra::convert(self.size))
     }
+
+    /// Returns the screen size in logical pixels, i.e. in the coordinates
+    /// that are used by `orthographic_projection`.
+    pub fn logical_size(&self) -> Vector2<f32> {
+        Vector2::new(self.size.x as f32, self.size.y as f32) / self.device_pixel_ratio as f32
+    }
+
+    /// Returns the screen rect in logical pixels. This is the parent rect for
+    /// laying out screen-space user interfaces.
+    pub fn logical_rect(&self) -> AaRect {
+        AaRect::from_top_left(Point2::origin(), self.logical_size())
+    }
+}
+
+/// The direction in which the Y axis of world space points on screen.
+///
+/// Screen space, i.e. logical pixels on the canvas as used by UI, scissor
+/// rects and mouse positions, always has its origin at the top left with Y
+/// pointing down. The camera maps world space to screen space, so with
+/// `YAxis::Up`, world positions are flipped vertically, and
+/// `Camera::screen_to_world` gives mouse positions in Y-up coordinates.
+///
+/// Anything that is textured is flipped as well, so textures need to be
+/// pushed with flipped UV rects (see `AaRect::flip_y`) to stay upright.
+/// Things that are built when they are drawn, such as `SpriteBatch`,
+/// `InstancedSpritePass` and `EcsRenderer`, take the axis from the camera or
+/// transform that they are drawn with. Text, sprite sheets and tilemaps are
+/// pushed into a batch before the transform is known, so they use the axis
+/// of the batch, see `Batch::set_y_axis`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YAxis {
+    /// Y grows downwards, like in screen space.
+    Down,
+
+    /// Y grows upwards, like in mathematics and most physics engines.
+    Up,
+}
+
+impl Default for YAxis {
+    fn default() -> Self {
+        YAxis::Down
+    }
+}
+
+impl YAxis {
+    /// Returns the direction of the Y axis of the space that `transform`
+    /// maps to normalized device coordinates, e.g. of
+    /// `Camera::projection_view`.
+    pub fn of_transform(transform: &Matrix3<f32>) -> Self {
+        // Normalized device coordinates have Y pointing up, so transforms of
+        // Y-down spaces mirror, which makes their determinant negative.
+        let det = transform[(0, 0)] * transform[(1, 1)] - transform[(0, 1)] * transform[(1, 0)];
+
+        if det < 0.0 {
+            YAxis::Down
+        } else {
+            YAxis::Up
+        }
+    }
+
+    /// Returns 1 for `YAxis::Down` and -1 for `YAxis::Up`, i.e. the factor
+    /// that turns offsets with Y pointing down into this axis.
+    pub fn sign(self) -> f32 {
+        match self {
+            YAxis::Down => 1.0,
+            YAxis::Up => -1.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -131,14 +294,48 @@ pub struct Camera {
     /// Zoom factor. For values larger than one, things will look larger, while
     /// for values smaller than one they will look smaller.
     /// Using nonpositive values will probably lead to funny (or frustrating)
-    /// results.
+    /// results. Values closer to zero than `Camera::MIN_ZOOM` are treated as
+    /// `MIN_ZOOM`, so that the camera's matrix can always be inverted.
     pub zoom: f32,
 
     /// Angle of rotation around the center in radians.
     pub angle: f32,
+
+    /// The part of the canvas that the camera shows its view in, given in
+    /// logical pixels, e.g. for split-screen games. If this is `None`, the
+    /// camera uses the whole canvas.
+    ///
+    /// The camera's matrices place the view in the viewport, but they do not
+    /// clip to it, so drawing should happen within
+    /// `Canvas::scissor_scope(&camera.viewport_rect(screen))`.
+    pub viewport: Option<AaRect>,
+
+    /// The direction of the world's Y axis on screen.
+    pub y_axis: YAxis,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(Point2::origin())
+    }
 }
 
 impl Camera {
+    /// The smallest magnitude of the zoom that the camera uses, see `zoom`.
+    pub const MIN_ZOOM: f32 = 1e-6;
+
+    /// Creates an unrotated camera without zoom that looks at `center`
+    /// through the whole canvas.
+    pub fn new(center: Point2<f32>) -> Self {
+        Self {
+            center,
+            zoom: 1.0,
+            angle: 0.0,
+            viewport: None,
+            y_axis: YAxis::Down,
+        }
+    }
+
     pub fn screen_view_matrix(screen_geom: &ScreenGeom) -> Matrix3<f32> {
         Self {
             center: Point2::new(
@@ -147,12 +344,21 @@ impl Camera {
             ),
             angle: 0.0,
             zoom: 1.0,
+            viewport: None,
+            y_axis: YAxis::Down,
         }
         .to_matrix(&screen_geom)
     }
 
+    /// Returns the rect that the camera shows its view in, in logical
+    /// pixels.
+    pub fn viewport_rect(&self, screen: &ScreenGeom) -> AaRect {
+        self.viewport.unwrap_or_else(|| screen.logical_rect())
+    }
+
     /// Build a 3x3 matrix with homogeneous coordinates to represent the
-    /// transformation from world space to camera space.
+    /// transformation from world space to camera space, i.e. logical pixels
+    /// on the canvas.
     pub fn to_matrix(&self, screen: &ScreenGeom) -> Matrix3<f32> {
         // It's a bit easier to first consider the camera space -> world space
         // transformation C2W and then take the inverse to get W2C. For C2W, we
@@ -166,14 +372,80 @@ impl Camera {
         // (Using S(x)^-1 = S(1/x),
         //        R(x)^-1 = R(-x),
         //        T(x)^-1 = T(-x).)
+        //
+        // For worlds with the Y axis pointing up, the scaling also flips Y.
 
-        Matrix3::new_translation(&Vector2::new(
-            screen.size.x as f32 / (2.0 * screen.device_pixel_ratio as f32),
-            screen.size.y as f32 / (2.0 * screen.device_pixel_ratio as f32),
-        )) * translate_rotate_scale(
-            -self.center.coords,
-            -self.angle,
-            Vector2::new(self.zoom, self.zoom),
+        Matrix3::new_translation(&self.viewport_rect(screen).center.coords)
+            * translate_rotate_scale(
+                -self.center.coords,
+                -self.angle,
+                Vector2::new(
+                    self.clamped_zoom(),
+                    self.y_axis.sign() * self.clamped_zoom(),
+                ),
+            )
+    }
+
+    /// Returns the smallest axis-aligned rect in world space that contains
+    /// everything that is visible through the camera.
+    ///
+    /// If the camera is rotated, the rect is larger than the actual visible
+    /// area. This is meant for cheaply culling objects before tessellation.
+    pub fn visible_rect(&self, screen: &ScreenGeom) -> AaRect {
+        let camera_to_world = self.inverse_matrix(screen);
+        let viewport = self.viewport_rect(screen);
+        let (min, max) = (viewport.min(), viewport.max());
+
+        let corners = [
+            Point3::new(min.x, min.y, 1.0),
+            Point3::new(max.x, min.y, 1.0),
+            Point3::new(min.x, max.y, 1.0),
+            Point3::new(max.x, max.y, 1.0),
+        ];
+
+        AaRect::bounding(
+            &corners
+                .iter()
+                .map(|p| (camera_to_world * p).xy())
+                .collect::<Vec<_>>(),
         )
     }
+
+    /// Returns the transform from world space to normalized device
+    /// coordinates, which is what the draw passes expect.
+    pub fn projection_view(&self, screen: &ScreenGeom) -> Matrix3<f32> {
+        screen.orthographic_projection() * self.to_matrix(screen)
+    }
+
+    /// Maps a point in world space to logical pixels on the canvas, e.g. for
+    /// placing UI next to an object.
+    pub fn world_to_screen(&self, screen: &ScreenGeom, p: Point2<f32>) -> Point2<f32> {
+        (self.to_matrix(screen) * Point3::new(p.x, p.y, 1.0)).xy()
+    }
+
+    /// Maps a point in logical pixels on the canvas to world space, e.g. for
+    /// finding out what `InputState::mouse_pos` points at.
+    pub fn screen_to_world(&self, screen: &ScreenGeom, p: Point2<f32>) -> Point2<f32> {
+        (self.inverse_matrix(screen) * Point3::new(p.x, p.y, 1.0)).xy()
+    }
+
+    /// Returns the zoom with its magnitude clamped to at least `MIN_ZOOM`,
+    /// keeping its sign. This is the zoom that the camera's matrices use.
+    pub fn clamped_zoom(&self) -> f32 {
+        if self.zoom.abs() >= Self::MIN_ZOOM {
+            self.zoom
+        } else if self.zoom < 0.0 {
+            -Self::MIN_ZOOM
+        } else {
+            Self::MIN_ZOOM
+        }
+    }
+
+    fn inverse_matrix(&self, screen: &ScreenGeom) -> Matrix3<f32> {
+        // With the zoom clamped, this only fails for non-finite parameters,
+        // in which case there is no meaningful inverse anyway.
+        self.to_matrix(screen)
+            .try_inverse()
+            .unwrap_or_else(Matrix3::identity)
+    }
 }
@@ -0,0 +1,151 @@
+//! Bookkeeping of GPU memory allocated by `malen`.
+//!
+//! WebGL does not let us query how much memory our resources use, so we
+//! estimate it from the sizes of the data we upload. Every resource that is
+//! tracked holds a `GpuAllocation`, which removes itself from the books when
+//! it is dropped.
+//!
+//! Since WebAssembly is single-threaded for our purposes, the books are kept
+//! in a thread-local registry that is shared by all canvases.
+
+use std::{cell::RefCell, collections::BTreeMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GpuResourceKind {
+    Texture,
+    VertexBuffer,
+    ElementBuffer,
+}
+
+#[derive(Debug, Clone)]
+pub struct GpuResourceInfo {
+    pub kind: GpuResourceKind,
+    pub label: String,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GpuMemoryReport {
+    pub texture_bytes: usize,
+    pub buffer_bytes: usize,
+
+    /// All tracked resources, sorted by decreasing size.
+    pub resources: Vec<GpuResourceInfo>,
+}
+
+impl GpuMemoryReport {
+    pub fn total_bytes(&self) -> usize {
+        self.texture_bytes + self.buffer_bytes
+    }
+
+    /// Logs a summary of the report, including the `max_resources` largest
+    /// resources.
+    pub fn log(&self, max_resources: usize) {
+        log::info!(
+            "GPU memory: {:.2} MiB in textures, {:.2} MiB in buffers",
+            self.texture_bytes as f64 / (1024.0 * 1024.0),
+            self.buffer_bytes as f64 / (1024.0 * 1024.0),
+        );
+
+        for resource in self.resources.iter().take(max_resources) {
+            log::info!(
+                "    {:>10} bytes: {:?} {}",
+                resource.bytes,
+                resource.kind,
+                resource.label
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    resources: BTreeMap<u64, GpuResourceInfo>,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+/// A handle to a tracked GPU resource.
+///
+/// Resources created by `malen` track themselves. If you create your own
+/// textures or buffers through `golem`, you can keep a `GpuAllocation` next
+/// to them so that they show up in the report.
+#[derive(Debug)]
+pub struct GpuAllocation {
+    id: u64,
+}
+
+impl GpuAllocation {
+    pub fn new(kind: GpuResourceKind, label: impl Into<String>, bytes: usize) -> Self {
+        let info = GpuResourceInfo {
+            kind,
+            label: label.into(),
+            bytes,
+        };
+
+        REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            let id = registry.next_id;
+            registry.next_id += 1;
+            registry.resources.insert(id, info);
+
+            Self { id }
+        })
+    }
+
+    pub fn texture(label: impl Into<String>, width: u32, height: u32) -> Self {
+        // We always use RGBA textures with one byte per channel.
+        Self::new(
+            GpuResourceKind::Texture,
+            label,
+            width as usize * height as usize * 4,
+        )
+    }
+
+    pub fn bytes(&self) -> usize {
+        REGISTRY.with(|registry| registry.borrow().resources[&self.id].bytes)
+    }
+
+    pub fn set_bytes(&self, bytes: usize) {
+        REGISTRY.with(|registry| {
+            if let Some(info) = registry.borrow_mut().resources.get_mut(&self.id) {
+                info.bytes = bytes;
+            }
+        });
+    }
+}
+
+impl Drop for GpuAllocation {
+    fn drop(&mut self) {
+        REGISTRY.with(|registry| {
+            registry.borrow_mut().resources.remove(&self.id);
+        });
+    }
+}
+
+/// Returns a report of all currently tracked GPU resources.
+pub fn gpu_memory_report() -> GpuMemoryReport {
+    REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+
+        let mut report = GpuMemoryReport::default();
+        for info in registry.resources.values() {
+            match info.kind {
+                GpuResourceKind::Texture => report.texture_bytes += info.bytes,
+                GpuResourceKind::VertexBuffer | GpuResourceKind::ElementBuffer => {
+                    report.buffer_bytes += info.bytes
+                }
+            }
+            report.resources.push(info.clone());
+        }
+
+        report
+            .resources
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.label.cmp(&b.label)));
+
+        report
+    })
+}
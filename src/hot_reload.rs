@@ -0,0 +1,179 @@
+//! Reloading assets during development when their files change.
+//!
+//! A dev server can notify us about changed files over a websocket by sending
+//! the path of each changed file as a text message. Without such a server,
+//! watched URLs can be polled instead, using HEAD requests that compare the
+//! `ETag` and `Last-Modified` headers.
+//!
+//! `HotReload` only finds out which files changed. Textures that are kept in
+//! a `TextureLoader` can be replaced automatically with `reload_textures`;
+//! other assets, such as fonts and maps, need to be reloaded by the game.
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{MessageEvent, RequestCache, RequestInit, Response, WebSocket};
+
+use crate::{draw::TextureLoader, Error};
+
+struct Watched {
+    url: String,
+
+    /// The `ETag` or `Last-Modified` header from the last poll.
+    version: Option<String>,
+
+    /// Set while a poll request is in flight, so that slow servers don't
+    /// pile up requests.
+    polling: bool,
+}
+
+pub struct HotReload {
+    changed: Rc<RefCell<Vec<String>>>,
+    socket: Option<(WebSocket, Closure<dyn FnMut(MessageEvent)>)>,
+
+    poll_interval: Option<Duration>,
+    last_poll_ms: f64,
+    watched: Vec<Rc<RefCell<Watched>>>,
+}
+
+impl HotReload {
+    pub fn new() -> Self {
+        Self {
+            changed: Rc::new(RefCell::new(Vec::new())),
+            socket: None,
+            poll_interval: None,
+            last_poll_ms: 0.0,
+            watched: Vec::new(),
+        }
+    }
+
+    /// Connects to a dev server at the websocket `url`, which sends the paths
+    /// of changed files as text messages.
+    pub fn connect(&mut self, url: &str) -> Result<(), Error> {
+        let socket = WebSocket::new(url).map_err(|e| Error::HotReload(format!("{:?}", e)))?;
+
+        let on_message = Closure::wrap(Box::new({
+            let changed = self.changed.clone();
+            move |event: MessageEvent| {
+                if let Some(path) = event.data().as_string() {
+                    log::info!("Hot reload: `{}` changed", path);
+                    changed.borrow_mut().push(path);
+                }
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        self.socket = Some((socket, on_message));
+
+        Ok(())
+    }
+
+    /// Enables polling of the watched URLs at the given interval, or disables
+    /// polling if `None` is given.
+    pub fn set_poll_interval(&mut self, poll_interval: Option<Duration>) {
+        self.poll_interval = poll_interval;
+    }
+
+    /// Adds a URL to poll for changes.
+    pub fn watch(&mut self, url: &str) {
+        self.watched.push(Rc::new(RefCell::new(Watched {
+            url: url.to_string(),
+            version: None,
+            polling: false,
+        })));
+    }
+
+    /// Starts poll requests if they are due, and returns the paths of files
+    /// that have changed since the last call. This should be called once
+    /// per frame.
+    pub fn poll(&mut self) -> Vec<String> {
+        if let Some(poll_interval) = self.poll_interval {
+            let now_ms = js_sys::Date::now();
+
+            if now_ms - self.last_poll_ms >= poll_interval.as_secs_f64() * 1000.0 {
+                self.last_poll_ms = now_ms;
+
+                for watched in &self.watched {
+                    if !watched.borrow().polling {
+                        watched.borrow_mut().polling = true;
+                        wasm_bindgen_futures::spawn_local(poll_watched(
+                            watched.clone(),
+                            self.changed.clone(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        std::mem::replace(&mut *self.changed.borrow_mut(), Vec::new())
+    }
+
+    /// Like `poll`, but also starts reloading the textures in `loader` whose
+    /// files have changed. The new textures replace the old ones in the
+    /// loader once they have been uploaded.
+    pub fn reload_textures(&mut self, loader: &mut TextureLoader) -> Vec<String> {
+        let changed = self.poll();
+
+        for path in &changed {
+            loader.reload(path);
+        }
+
+        changed
+    }
+}
+
+impl Default for HotReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HotReload {
+    fn drop(&mut self) {
+        if let Some((socket, _)) = self.socket.take() {
+            socket.set_onmessage(None);
+            let _ = socket.close();
+        }
+    }
+}
+
+async fn poll_watched(watched: Rc<RefCell<Watched>>, changed: Rc<RefCell<Vec<String>>>) {
+    let url = watched.borrow().url.clone();
+    let result = fetch_version(&url).await;
+
+    let mut watched = watched.borrow_mut();
+    watched.polling = false;
+
+    match result {
+        Ok(version) => {
+            // The first poll only establishes the version.
+            if watched.version.is_some() && version != watched.version {
+                log::info!("Hot reload: `{}` changed", url);
+                changed.borrow_mut().push(url);
+            }
+            watched.version = version;
+        }
+        Err(e) => {
+            log::warn!("Hot reload: failed to poll `{}`: {:?}", url, e);
+        }
+    }
+}
+
+async fn fetch_version(url: &str) -> Result<Option<String>, JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+
+    let mut init = RequestInit::new();
+    init.method("HEAD");
+    init.cache(RequestCache::NoCache);
+
+    let response: Response = JsFuture::from(window.fetch_with_str_and_init(url, &init))
+        .await?
+        .dyn_into()?;
+    let headers = response.headers();
+
+    Ok(match headers.get("ETag")? {
+        Some(etag) => Some(etag),
+        None => headers.get("Last-Modified")?,
+    })
+}
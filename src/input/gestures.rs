@@ -0,0 +1,211 @@
+//! Recognizing taps, pans and pinches from touch input.
+//!
+//! `GestureRecognizer` is fed with the events that are popped from the
+//! `Canvas`, and turns the pointer events of fingers into gestures. It is
+//! independent of the rest of the input handling, so games can use the
+//! gestures e.g. for moving and zooming the camera, while still handling
+//! the raw touch events for anything else.
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{util, Event, PointerKind};
+
+#[derive(Debug, Clone)]
+pub struct GestureConfig {
+    /// The distance in logical pixels that a finger may move before a touch
+    /// becomes a pan instead of a tap.
+    pub tap_slop: f32,
+
+    /// The maximal duration of a tap in milliseconds.
+    pub tap_max_duration_ms: f64,
+
+    /// If true, the mouse is treated like a finger while a button is
+    /// pressed, e.g. for testing on the desktop.
+    pub include_mouse: bool,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            tap_slop: 10.0,
+            tap_max_duration_ms: 300.0,
+            include_mouse: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    /// A single finger touched the canvas briefly without moving.
+    Tap(Point2<f32>),
+
+    /// A single finger moved by `delta` to `pos`.
+    Pan {
+        pos: Point2<f32>,
+        delta: Vector2<f32>,
+    },
+
+    /// Two fingers moved.
+    ///
+    /// `center` is the midpoint between the fingers, and `delta` is how far
+    /// it moved. `scale` is the ratio of the distance between the fingers to
+    /// the distance before the move, so multiplying a camera's zoom by it
+    /// keeps the content under the fingers.
+    Pinch {
+        center: Point2<f32>,
+        delta: Vector2<f32>,
+        scale: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Touch {
+    id: i32,
+    start: Point2<f32>,
+    pos: Point2<f32>,
+    start_ms: f64,
+}
+
+/// Turns touch events into gestures. Only the first two fingers take part in
+/// pinches; further fingers are ignored.
+pub struct GestureRecognizer {
+    config: GestureConfig,
+    touches: Vec<Touch>,
+
+    /// True once the current interaction can no longer be a tap, because a
+    /// finger left the tap slop, or a second finger touched.
+    moved: bool,
+}
+
+impl GestureRecognizer {
+    pub fn new(config: GestureConfig) -> Self {
+        Self {
+            config,
+            touches: Vec::new(),
+            moved: false,
+        }
+    }
+
+    pub fn config(&self) -> &GestureConfig {
+        &self.config
+    }
+
+    /// Returns the number of fingers that are currently tracked.
+    pub fn num_touches(&self) -> usize {
+        self.touches.len()
+    }
+
+    /// Updates the recognizer with `event`, returning the gesture that it
+    /// completes or continues, if any.
+    pub fn handle_event(&mut self, event: &Event) -> Option<Gesture> {
+        let pointer = match event {
+            Event::Unfocused => {
+                self.touches.clear();
+                return None;
+            }
+            Event::PointerPressed(pointer)
+            | Event::PointerMoved(pointer)
+            | Event::PointerReleased(pointer)
+            | Event::PointerCancelled(pointer) => pointer,
+            _ => return None,
+        };
+
+        let accepted = match pointer.kind {
+            PointerKind::Touch => true,
+            PointerKind::Mouse => self.config.include_mouse,
+            _ => false,
+        };
+        if !accepted {
+            return None;
+        }
+
+        match event {
+            Event::PointerPressed(_) => {
+                self.moved = !self.touches.is_empty();
+                self.touches.push(Touch {
+                    id: pointer.id,
+                    start: pointer.pos,
+                    pos: pointer.pos,
+                    start_ms: util::now_ms(),
+                });
+
+                None
+            }
+            Event::PointerMoved(_) => {
+                let index = self.index(pointer.id)?;
+                let before = self.pinch_state();
+                let prev_pos = std::mem::replace(&mut self.touches[index].pos, pointer.pos);
+
+                if self.touches.len() == 1 {
+                    let start = self.touches[index].start;
+                    if (pointer.pos - start).norm() > self.config.tap_slop {
+                        self.moved = true;
+                    }
+
+                    if self.moved {
+                        Some(Gesture::Pan {
+                            pos: pointer.pos,
+                            delta: pointer.pos - prev_pos,
+                        })
+                    } else {
+                        None
+                    }
+                } else if index < 2 {
+                    let (center_before, distance_before) = before?;
+                    let (center, distance) = self.pinch_state()?;
+
+                    Some(Gesture::Pinch {
+                        center,
+                        delta: center - center_before,
+                        scale: if distance_before > 0.0 {
+                            distance / distance_before
+                        } else {
+                            1.0
+                        },
+                    })
+                } else {
+                    None
+                }
+            }
+            Event::PointerReleased(_) => {
+                let touch = self.touches.remove(self.index(pointer.id)?);
+                let duration_ms = util::now_ms() - touch.start_ms;
+
+                if self.touches.is_empty()
+                    && !self.moved
+                    && duration_ms <= self.config.tap_max_duration_ms
+                {
+                    Some(Gesture::Tap(touch.pos))
+                } else {
+                    None
+                }
+            }
+            Event::PointerCancelled(_) => {
+                let index = self.index(pointer.id)?;
+                self.touches.remove(index);
+                self.moved = true;
+
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn index(&self, id: i32) -> Option<usize> {
+        self.touches.iter().position(|touch| touch.id == id)
+    }
+
+    /// Returns the midpoint of the first two fingers and their distance.
+    fn pinch_state(&self) -> Option<(Point2<f32>, f32)> {
+        match &self.touches[..] {
+            [a, b, ..] => Some((nalgebra::center(&a.pos, &b.pos), (b.pos - a.pos).norm())),
+            _ => None,
+        }
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        Self::new(GestureConfig::default())
+    }
+}
@@ -0,0 +1,1337 @@
+pub mod gestures;
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    rc::Rc,
+};
+
+use nalgebra::{Point2, Vector2};
+use wasm_bindgen::{closure::Closure, convert::FromWasmAbi, JsCast};
+use web_sys::{
+    ClipboardEvent, FocusEvent, Gamepad, GamepadButton, GamepadEvent, GamepadMappingType,
+    HtmlCanvasElement, KeyboardEvent, MouseEvent, PointerEvent, WheelEvent,
+};
+
+use crate::{main_loop::RedrawTrigger, util, Error, ScreenGeom};
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Focused,
+    Unfocused,
+    KeyPressed(Key),
+    KeyReleased(Key),
+
+    /// The mouse moved to a new position, given in logical pixels relative
+    /// to the top left of the canvas. Positions are corrected for canvases
+    /// that are scaled by CSS.
+    MouseMoved(Point2<f32>),
+    MouseButtonPressed(MouseButton),
+    MouseButtonReleased(MouseButton),
+
+    /// A pointer (mouse, finger or pen) started touching the canvas, or a
+    /// mouse button was pressed.
+    ///
+    /// Pointer events are emitted in addition to the raw mouse events, so
+    /// that code which does not care about the kind of pointing device can
+    /// handle all of them in the same way.
+    PointerPressed(Pointer),
+
+    /// A pointer moved. For mice, this is emitted even if no button is
+    /// pressed.
+    PointerMoved(Pointer),
+
+    /// A pointer stopped touching the canvas, or a mouse button was
+    /// released.
+    PointerReleased(Pointer),
+
+    /// The browser took over the pointer, e.g. to start scrolling, and no
+    /// more events will be emitted for it.
+    PointerCancelled(Pointer),
+
+    /// A finger started touching the canvas, given by its pointer id and
+    /// position in logical pixels.
+    ///
+    /// Touch events are emitted in addition to the pointer events of
+    /// fingers, for code that only handles touch screens.
+    TouchStarted(i32, Point2<f32>),
+
+    TouchMoved(i32, Point2<f32>),
+
+    /// A finger stopped touching the canvas, or the browser took over the
+    /// touch.
+    TouchEnded(i32, Point2<f32>),
+
+    /// The mouse wheel was scrolled by the given amount in logical pixels.
+    /// Positive values scroll down or to the right.
+    MouseWheel(Vector2<f32>),
+
+    /// Text that has been typed, usually a single character. This is
+    /// separate from `KeyPressed`, since it takes the keyboard layout and
+    /// modifiers into account.
+    Text(String),
+
+    /// Text was pasted from the clipboard.
+    Paste(String),
+
+    /// The user requested to copy. If text has been set with
+    /// `Canvas::set_copy_text`, it has already been put into the clipboard.
+    Copy,
+
+    /// Like `Copy`, but the copied text should also be removed.
+    Cut,
+
+    /// The canvas size or the device pixel ratio changed. The GL viewport
+    /// has already been updated to cover the whole canvas.
+    ///
+    /// `ScreenGeom::size` is the size of the backing store in physical
+    /// pixels, while `ScreenGeom::logical_size` is the CSS size.
+    WindowResized(ScreenGeom),
+
+    /// The device was rotated, or the window's aspect ratio changed such
+    /// that its longer side changed.
+    OrientationChanged(Orientation),
+
+    /// A gamepad was connected. Note that browsers usually only expose
+    /// gamepads after a button has been pressed on them.
+    GamepadConnected(GamepadInfo),
+
+    GamepadDisconnected(GamepadInfo),
+
+    /// An XR input source started its primary action, e.g. a trigger was
+    /// pulled.
+    #[cfg(feature = "xr")]
+    XrSelectStarted(crate::xr::XrInput),
+
+    #[cfg(feature = "xr")]
+    XrSelectEnded(crate::xr::XrInput),
+
+    /// The XR session was ended, either by us or by the user.
+    #[cfg(feature = "xr")]
+    XrSessionEnded,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+impl Orientation {
+    pub fn from_size(width: f64, height: f64) -> Self {
+        if width >= height {
+            Orientation::Landscape
+        } else {
+            Orientation::Portrait
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum PointerKind {
+    Mouse,
+    Touch,
+    Pen,
+    Other,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Pointer {
+    /// Identifies the pointer while it is active. Ids may be reused once a
+    /// pointer has been released.
+    pub id: i32,
+
+    /// The position in logical pixels relative to the top left of the
+    /// canvas.
+    pub pos: Point2<f32>,
+
+    /// Pressure in `[0, 1]`. Devices that do not support pressure report
+    /// 0.5 while pressed and 0 otherwise.
+    pub pressure: f32,
+
+    pub kind: PointerKind,
+
+    /// True for the mouse, the first finger of a multi-touch interaction,
+    /// and the pen.
+    pub is_primary: bool,
+}
+
+impl Pointer {
+    /// Converts a pointer event that was received by `canvas`.
+    pub fn from_pointer_event(canvas: &HtmlCanvasElement, event: &PointerEvent) -> Self {
+        let kind = match &event.pointer_type()[..] {
+            "mouse" => PointerKind::Mouse,
+            "touch" => PointerKind::Touch,
+            "pen" => PointerKind::Pen,
+            _ => PointerKind::Other,
+        };
+
+        Self {
+            id: event.pointer_id(),
+            pos: canvas_pos(canvas, event),
+            pressure: event.pressure(),
+            kind,
+            is_primary: event.is_primary(),
+        }
+    }
+}
+
+/// Pushes a pointer event, followed by the matching touch event if the
+/// pointer is a finger.
+fn push_pointer_event(state: &mut SharedState, event: Event, pointer_event: &PointerEvent) {
+    let touch = match &event {
+        Event::PointerPressed(pointer) => Some(Event::TouchStarted(pointer.id, pointer.pos)),
+        Event::PointerMoved(pointer) => Some(Event::TouchMoved(pointer.id, pointer.pos)),
+        Event::PointerReleased(pointer) | Event::PointerCancelled(pointer) => {
+            Some(Event::TouchEnded(pointer.id, pointer.pos))
+        }
+        _ => None,
+    };
+    let is_touch = pointer_event.pointer_type() == "touch";
+
+    state.push_event_at(event, pointer_event.time_stamp());
+    if let Some(touch) = touch.filter(|_| is_touch) {
+        state.push_event_at(touch, pointer_event.time_stamp());
+    }
+}
+
+/// Returns the position of a mouse event in logical pixels relative to the
+/// top left of `canvas`.
+///
+/// `offsetX` and `offsetY` are in CSS pixels, so they do not match the
+/// logical pixels of the canvas when its CSS size differs from its size
+/// divided by the device pixel ratio, e.g. when it is stretched by a style.
+/// We scale the position within the canvas's content box accordingly.
+fn canvas_pos(canvas: &HtmlCanvasElement, event: &MouseEvent) -> Point2<f32> {
+    let rect = canvas.get_bounding_client_rect();
+    let ratio = util::device_pixel_ratio();
+
+    let css_pos = Vector2::new(
+        event.client_x() as f64 - rect.left() - canvas.client_left() as f64,
+        event.client_y() as f64 - rect.top() - canvas.client_top() as f64,
+    );
+    let scale = Vector2::new(
+        canvas.width() as f64 / ratio / (canvas.client_width() as f64).max(1.0),
+        canvas.height() as f64 / ratio / (canvas.client_height() as f64).max(1.0),
+    );
+
+    Point2::new((css_pos.x * scale.x) as f32, (css_pos.y * scale.y) as f32)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GamepadMapping {
+    /// The gamepad's buttons and axes follow the W3C standard gamepad
+    /// layout, so e.g. button 0 is the bottom face button ("A" on Xbox
+    /// controllers).
+    Standard,
+
+    /// The browser does not know how to map the gamepad's buttons and axes.
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GamepadInfo {
+    /// The index of the gamepad, which stays the same while it is connected.
+    pub index: u32,
+
+    /// A browser-specific description of the device, usually containing the
+    /// name and the USB vendor and product ids.
+    pub id: String,
+
+    pub mapping: GamepadMapping,
+}
+
+impl GamepadInfo {
+    pub fn from_gamepad_event(event: &GamepadEvent) -> Option<Self> {
+        let gamepad = event.gamepad()?;
+        let mapping = match gamepad.mapping() {
+            GamepadMappingType::Standard => GamepadMapping::Standard,
+            _ => GamepadMapping::Unknown,
+        };
+
+        Some(Self {
+            index: gamepad.index(),
+            id: gamepad.id(),
+            mapping,
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct GamepadButtonState {
+    pub pressed: bool,
+
+    /// How far the button is pressed in `[0, 1]`. Digital buttons report
+    /// either 0 or 1, while triggers report values in between.
+    pub value: f32,
+}
+
+/// The buttons and axes of a gamepad, as polled from the browser. For
+/// gamepads with `GamepadMapping::Standard`, the indices follow the W3C
+/// standard gamepad layout.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GamepadState {
+    pub buttons: Vec<GamepadButtonState>,
+
+    /// Raw axis values in `[-1, 1]`, without the dead zone applied.
+    pub axes: Vec<f32>,
+}
+
+impl GamepadState {
+    pub fn from_gamepad(gamepad: &Gamepad) -> Self {
+        let buttons = gamepad
+            .buttons()
+            .iter()
+            .map(|button| {
+                button
+                    .dyn_into::<GamepadButton>()
+                    .map_or(GamepadButtonState::default(), |button| GamepadButtonState {
+                        pressed: button.pressed(),
+                        value: button.value() as f32,
+                    })
+            })
+            .collect();
+        let axes = gamepad
+            .axes()
+            .iter()
+            .map(|axis| axis.as_f64().unwrap_or(0.0) as f32)
+            .collect();
+
+        Self { buttons, axes }
+    }
+}
+
+/// Maps `value` in `[-1, 1]` to zero within `dead_zone` of zero, and
+/// rescales the remaining range so that the output still starts at zero.
+fn apply_dead_zone(value: f32, dead_zone: f32) -> f32 {
+    if value.abs() <= dead_zone {
+        0.0
+    } else {
+        value.signum() * ((value.abs() - dead_zone) / (1.0 - dead_zone)).min(1.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Other(i16),
+}
+
+impl MouseButton {
+    pub fn from_mouse_event(event: &MouseEvent) -> Self {
+        match event.button() {
+            0 => MouseButton::Left,
+            1 => MouseButton::Middle,
+            2 => MouseButton::Right,
+            n => MouseButton::Other(n),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputState {
+    pressed_keys: BTreeSet<Key>,
+    pressed_mouse_buttons: BTreeSet<MouseButton>,
+    mouse_pos: Point2<f32>,
+    pointers: BTreeMap<i32, Pointer>,
+    gamepads: BTreeMap<u32, (GamepadInfo, GamepadState)>,
+    gamepad_dead_zone: f32,
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            pressed_keys: BTreeSet::new(),
+            pressed_mouse_buttons: BTreeSet::new(),
+            mouse_pos: Point2::origin(),
+            pointers: BTreeMap::new(),
+            gamepads: BTreeMap::new(),
+            gamepad_dead_zone: 0.1,
+        }
+    }
+}
+
+impl InputState {
+    /// Updates the state of the connected gamepads. Browsers do not emit
+    /// events for gamepad buttons and axes, so they need to be polled.
+    pub(crate) fn poll_gamepads(&mut self) {
+        if self.gamepads.is_empty() {
+            return;
+        }
+
+        let gamepads = match web_sys::window().map(|window| window.navigator().get_gamepads()) {
+            Some(Ok(gamepads)) => gamepads,
+            _ => return,
+        };
+
+        for gamepad in gamepads.iter() {
+            if let Ok(gamepad) = gamepad.dyn_into::<Gamepad>() {
+                if let Some((_, state)) = self.gamepads.get_mut(&gamepad.index()) {
+                    *state = GamepadState::from_gamepad(&gamepad);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn on_event(&mut self, event: &Event) {
+        match event {
+            Event::Unfocused => {
+                self.pressed_keys.clear();
+                self.pressed_mouse_buttons.clear();
+                self.pointers.clear();
+            }
+            Event::KeyPressed(key) => {
+                self.pressed_keys.insert(*key);
+            }
+            Event::KeyReleased(key) => {
+                self.pressed_keys.remove(key);
+            }
+            Event::MouseMoved(pos) => {
+                self.mouse_pos = *pos;
+            }
+            Event::MouseButtonPressed(button) => {
+                self.pressed_mouse_buttons.insert(*button);
+            }
+            Event::MouseButtonReleased(button) => {
+                self.pressed_mouse_buttons.remove(button);
+            }
+            Event::PointerPressed(pointer) => {
+                self.pointers.insert(pointer.id, *pointer);
+            }
+            Event::PointerMoved(pointer) => {
+                if let Some(active) = self.pointers.get_mut(&pointer.id) {
+                    *active = *pointer;
+                }
+            }
+            Event::PointerReleased(pointer) | Event::PointerCancelled(pointer) => {
+                self.pointers.remove(&pointer.id);
+            }
+            Event::GamepadConnected(info) => {
+                self.gamepads
+                    .insert(info.index, (info.clone(), GamepadState::default()));
+            }
+            Event::GamepadDisconnected(info) => {
+                self.gamepads.remove(&info.index);
+            }
+            _ => (),
+        }
+    }
+
+    pub fn key(&self, key: Key) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    pub fn pressed_keys(&self) -> &BTreeSet<Key> {
+        &self.pressed_keys
+    }
+
+    pub fn mouse_button(&self, button: MouseButton) -> bool {
+        self.pressed_mouse_buttons.contains(&button)
+    }
+
+    pub fn pressed_mouse_buttons(&self) -> &BTreeSet<MouseButton> {
+        &self.pressed_mouse_buttons
+    }
+
+    /// Returns the last known mouse position in logical pixels relative to
+    /// the top left of the canvas.
+    pub fn mouse_pos(&self) -> Point2<f32> {
+        self.mouse_pos
+    }
+
+    /// Returns the pointers that are currently pressed, i.e. touching
+    /// fingers, pens in contact and mice with a pressed button.
+    pub fn pointers(&self) -> impl Iterator<Item = &Pointer> {
+        self.pointers.values()
+    }
+
+    /// Returns the fingers that are currently touching the canvas.
+    pub fn touches(&self) -> impl Iterator<Item = &Pointer> {
+        self.pointers
+            .values()
+            .filter(|pointer| pointer.kind == PointerKind::Touch)
+    }
+
+    pub fn pointer(&self, id: i32) -> Option<&Pointer> {
+        self.pointers.get(&id)
+    }
+
+    /// Returns the currently connected gamepads, ordered by index.
+    pub fn gamepads(&self) -> impl Iterator<Item = &GamepadInfo> {
+        self.gamepads.values().map(|(info, _)| info)
+    }
+
+    pub fn gamepad(&self, index: u32) -> Option<&GamepadInfo> {
+        self.gamepads.get(&index).map(|(info, _)| info)
+    }
+
+    /// Returns the buttons and axes of a connected gamepad.
+    ///
+    /// The state is polled once per frame, when the event queue has been
+    /// emptied with `Canvas::pop_event`.
+    pub fn gamepad_state(&self, index: u32) -> Option<&GamepadState> {
+        self.gamepads.get(&index).map(|(_, state)| state)
+    }
+
+    /// Returns whether a button of a gamepad is pressed. Unknown gamepads
+    /// and buttons are reported as released.
+    pub fn gamepad_button(&self, index: u32, button: usize) -> bool {
+        self.gamepad_state(index)
+            .and_then(|state| state.buttons.get(button))
+            .map_or(false, |button| button.pressed)
+    }
+
+    /// Returns how far a button of a gamepad is pressed in `[0, 1]`.
+    pub fn gamepad_button_value(&self, index: u32, button: usize) -> f32 {
+        self.gamepad_state(index)
+            .and_then(|state| state.buttons.get(button))
+            .map_or(0.0, |button| button.value)
+    }
+
+    /// Returns an axis of a gamepad in `[-1, 1]`, with the dead zone
+    /// applied. Unknown gamepads and axes are reported as zero.
+    pub fn gamepad_axis(&self, index: u32, axis: usize) -> f32 {
+        self.gamepad_state(index)
+            .and_then(|state| state.axes.get(axis))
+            .map_or(0.0, |value| apply_dead_zone(*value, self.gamepad_dead_zone))
+    }
+
+    /// Returns the position of an analog stick of a gamepad, given by the
+    /// pair of axes `2 * stick` and `2 * stick + 1`. In the standard layout,
+    /// stick 0 is the left and stick 1 the right stick, with Y pointing
+    /// down.
+    ///
+    /// The dead zone is applied to the length of the vector rather than to
+    /// each axis, so that diagonal directions are not snapped to the axes.
+    pub fn gamepad_stick(&self, index: u32, stick: usize) -> Vector2<f32> {
+        let axes = match self.gamepad_state(index) {
+            Some(state) => &state.axes,
+            None => return Vector2::zeros(),
+        };
+        let raw = Vector2::new(
+            axes.get(2 * stick).copied().unwrap_or(0.0),
+            axes.get(2 * stick + 1).copied().unwrap_or(0.0),
+        );
+
+        let length = raw.norm();
+        if length <= self.gamepad_dead_zone {
+            Vector2::zeros()
+        } else {
+            raw * (apply_dead_zone(length, self.gamepad_dead_zone) / length)
+        }
+    }
+
+    pub fn gamepad_dead_zone(&self) -> f32 {
+        self.gamepad_dead_zone
+    }
+
+    /// Sets the distance from the center within which axes and sticks are
+    /// reported as zero, compensating for sticks that do not quite return
+    /// to the center. Defaults to 0.1.
+    pub fn set_gamepad_dead_zone(&mut self, dead_zone: f32) {
+        assert!(
+            (0.0..1.0).contains(&dead_zone),
+            "dead zone must be in [0, 1)"
+        );
+
+        self.gamepad_dead_zone = dead_zone;
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+struct SharedState {
+    /// Pending events, each with the time at which it occurred.
+    events: VecDeque<(Event, f64)>,
+    redraw: RedrawTrigger,
+
+    /// Text to put into the clipboard when the user copies or cuts.
+    copy_text: Option<String>,
+
+    /// Set when the browser window has been resized or rotated since the
+    /// flag was last taken.
+    window_resized: bool,
+
+    /// If true, the browser's context menu is not shown when right-clicking
+    /// the canvas.
+    suppress_context_menu: bool,
+}
+
+impl SharedState {
+    fn push_event(&mut self, event: Event) {
+        self.push_event_at(event, util::now_ms());
+    }
+
+    /// Pushes an event with the time at which it occurred, e.g. the
+    /// `timeStamp` of the DOM event, which uses the same clock as
+    /// `util::now_ms`.
+    fn push_event_at(&mut self, event: Event, time_ms: f64) {
+        self.events.push_back((event, time_ms));
+        self.redraw.request();
+    }
+
+    fn set_clipboard_data(&self, event: &ClipboardEvent) {
+        if let (Some(text), Some(data)) = (self.copy_text.as_ref(), event.clipboard_data()) {
+            if data.set_data("text/plain", text).is_ok() {
+                event.prevent_default();
+            }
+        }
+    }
+}
+
+/// A handle for pushing events from listeners that are registered outside of
+/// `EventHandlers`.
+#[cfg(feature = "xr")]
+#[derive(Clone)]
+pub(crate) struct EventQueue(Rc<RefCell<SharedState>>);
+
+#[cfg(feature = "xr")]
+impl EventQueue {
+    pub fn push(&self, event: Event) {
+        self.0.borrow_mut().push_event(event);
+    }
+}
+
+pub struct EventHandlers {
+    state: Rc<RefCell<SharedState>>,
+
+    _on_focus: EventListener<FocusEvent>,
+    _on_blur: EventListener<FocusEvent>,
+    _on_key_down: EventListener<KeyboardEvent>,
+    _on_key_release: EventListener<KeyboardEvent>,
+    _on_mouse_move: EventListener<MouseEvent>,
+    _on_mouse_down: EventListener<MouseEvent>,
+    _on_mouse_up: EventListener<MouseEvent>,
+    _on_wheel: EventListener<WheelEvent>,
+    _on_context_menu: EventListener<MouseEvent>,
+    _on_pointer_down: EventListener<PointerEvent>,
+    _on_pointer_move: EventListener<PointerEvent>,
+    _on_pointer_up: EventListener<PointerEvent>,
+    _on_pointer_cancel: EventListener<PointerEvent>,
+    _on_window_resize: EventListener<web_sys::Event>,
+    _on_orientation_change: EventListener<web_sys::Event>,
+    _on_gamepad_connected: EventListener<GamepadEvent>,
+    _on_gamepad_disconnected: EventListener<GamepadEvent>,
+    _on_paste: EventListener<ClipboardEvent>,
+    _on_copy: EventListener<ClipboardEvent>,
+    _on_cut: EventListener<ClipboardEvent>,
+}
+
+impl EventHandlers {
+    pub fn new(canvas: HtmlCanvasElement, redraw: RedrawTrigger) -> Result<Self, Error> {
+        let state = Rc::new(RefCell::new(SharedState {
+            events: VecDeque::new(),
+            redraw,
+            copy_text: None,
+            window_resized: false,
+            suppress_context_menu: true,
+        }));
+
+        let on_focus = EventListener::new_consume(&canvas, "focus", {
+            let state = state.clone();
+            move |_: FocusEvent| {
+                state.borrow_mut().push_event(Event::Focused);
+            }
+        });
+
+        let on_blur = EventListener::new_consume(&canvas, "blur", {
+            let state = state.clone();
+            move |_: FocusEvent| {
+                state.borrow_mut().push_event(Event::Unfocused);
+            }
+        });
+
+        let on_key_down = EventListener::new_consume(&canvas, "keydown", {
+            let state = state.clone();
+            move |event: KeyboardEvent| {
+                let mut state = state.borrow_mut();
+
+                if let Some(key) = Key::from_keyboard_event(&event) {
+                    state.push_event_at(Event::KeyPressed(key), event.time_stamp());
+                }
+
+                // Named keys such as "Enter" have multi-character values, so
+                // this only lets through printable characters.
+                let text = event.key();
+                if text.chars().count() == 1 && !event.ctrl_key() && !event.meta_key() {
+                    state.push_event_at(Event::Text(text), event.time_stamp());
+                }
+            }
+        });
+
+        let on_key_release = EventListener::new_consume(&canvas, "keyup", {
+            let state = state.clone();
+            move |event: KeyboardEvent| {
+                if let Some(key) = Key::from_keyboard_event(&event) {
+                    state
+                        .borrow_mut()
+                        .push_event_at(Event::KeyReleased(key), event.time_stamp());
+                }
+            }
+        });
+
+        let on_mouse_move = EventListener::new_consume(&canvas, "mousemove", {
+            let state = state.clone();
+            let canvas = canvas.clone();
+            move |event: MouseEvent| {
+                let pos = canvas_pos(&canvas, &event);
+                state
+                    .borrow_mut()
+                    .push_event_at(Event::MouseMoved(pos), event.time_stamp());
+            }
+        });
+
+        let on_mouse_down = EventListener::new_consume(&canvas, "mousedown", {
+            let state = state.clone();
+            move |event: MouseEvent| {
+                let button = MouseButton::from_mouse_event(&event);
+                state
+                    .borrow_mut()
+                    .push_event_at(Event::MouseButtonPressed(button), event.time_stamp());
+            }
+        });
+
+        let on_mouse_up = EventListener::new_consume(&canvas, "mouseup", {
+            let state = state.clone();
+            move |event: MouseEvent| {
+                let button = MouseButton::from_mouse_event(&event);
+                state
+                    .borrow_mut()
+                    .push_event_at(Event::MouseButtonReleased(button), event.time_stamp());
+            }
+        });
+
+        let on_wheel = EventListener::new_consume(&canvas, "wheel", {
+            let state = state.clone();
+            move |event: WheelEvent| {
+                // Don't scroll the page while the mouse is over the canvas.
+                event.prevent_default();
+
+                // Convert lines and pages to pixels, using the same rough
+                // estimates as most browsers.
+                let scale = match event.delta_mode() {
+                    WheelEvent::DOM_DELTA_LINE => 16.0,
+                    WheelEvent::DOM_DELTA_PAGE => 800.0,
+                    _ => 1.0,
+                };
+                let delta = Vector2::new(event.delta_x() as f32, event.delta_y() as f32) * scale;
+
+                state
+                    .borrow_mut()
+                    .push_event_at(Event::MouseWheel(delta), event.time_stamp());
+            }
+        });
+
+        let on_context_menu = EventListener::new_consume(&canvas, "contextmenu", {
+            let state = state.clone();
+            move |event: MouseEvent| {
+                if state.borrow().suppress_context_menu {
+                    event.prevent_default();
+                }
+            }
+        });
+
+        let on_pointer_down = EventListener::new_consume(&canvas, "pointerdown", {
+            let state = state.clone();
+            let canvas = canvas.clone();
+            move |event: PointerEvent| {
+                // Keep receiving events for this pointer if it leaves the
+                // canvas while pressed.
+                let _ = canvas.set_pointer_capture(event.pointer_id());
+
+                let pointer = Pointer::from_pointer_event(&canvas, &event);
+                push_pointer_event(
+                    &mut state.borrow_mut(),
+                    Event::PointerPressed(pointer),
+                    &event,
+                );
+            }
+        });
+
+        let on_pointer_move = EventListener::new_consume(&canvas, "pointermove", {
+            let state = state.clone();
+            let canvas = canvas.clone();
+            move |event: PointerEvent| {
+                let pointer = Pointer::from_pointer_event(&canvas, &event);
+                push_pointer_event(
+                    &mut state.borrow_mut(),
+                    Event::PointerMoved(pointer),
+                    &event,
+                );
+            }
+        });
+
+        let on_pointer_up = EventListener::new_consume(&canvas, "pointerup", {
+            let state = state.clone();
+            let canvas = canvas.clone();
+            move |event: PointerEvent| {
+                let pointer = Pointer::from_pointer_event(&canvas, &event);
+                push_pointer_event(
+                    &mut state.borrow_mut(),
+                    Event::PointerReleased(pointer),
+                    &event,
+                );
+            }
+        });
+
+        let on_pointer_cancel = EventListener::new_consume(&canvas, "pointercancel", {
+            let state = state.clone();
+            let canvas = canvas.clone();
+            move |event: PointerEvent| {
+                let pointer = Pointer::from_pointer_event(&canvas, &event);
+                push_pointer_event(
+                    &mut state.borrow_mut(),
+                    Event::PointerCancelled(pointer),
+                    &event,
+                );
+            }
+        });
+
+        // Note that changes of the device pixel ratio (e.g. through browser
+        // zoom) also cause resize events.
+        let window = web_sys::window().ok_or(Error::NoWindow)?;
+        let on_window_resize = EventListener::new(&window, "resize", {
+            let state = state.clone();
+            move |_: web_sys::Event| {
+                let mut state = state.borrow_mut();
+                state.window_resized = true;
+                state.redraw.request();
+            }
+        });
+
+        let on_orientation_change = EventListener::new(&window, "orientationchange", {
+            let state = state.clone();
+            move |_: web_sys::Event| {
+                let mut state = state.borrow_mut();
+                state.window_resized = true;
+                state.redraw.request();
+            }
+        });
+
+        let on_gamepad_connected = EventListener::new(&window, "gamepadconnected", {
+            let state = state.clone();
+            move |event: GamepadEvent| {
+                if let Some(info) = GamepadInfo::from_gamepad_event(&event) {
+                    state
+                        .borrow_mut()
+                        .push_event_at(Event::GamepadConnected(info), event.time_stamp());
+                }
+            }
+        });
+
+        let on_gamepad_disconnected = EventListener::new(&window, "gamepaddisconnected", {
+            let state = state.clone();
+            move |event: GamepadEvent| {
+                if let Some(info) = GamepadInfo::from_gamepad_event(&event) {
+                    state
+                        .borrow_mut()
+                        .push_event_at(Event::GamepadDisconnected(info), event.time_stamp());
+                }
+            }
+        });
+
+        let on_paste = EventListener::new_consume(&canvas, "paste", {
+            let state = state.clone();
+            move |event: ClipboardEvent| {
+                if let Some(text) = event
+                    .clipboard_data()
+                    .and_then(|data| data.get_data("text/plain").ok())
+                {
+                    event.prevent_default();
+                    state
+                        .borrow_mut()
+                        .push_event_at(Event::Paste(text), event.time_stamp());
+                }
+            }
+        });
+
+        let on_copy = EventListener::new_consume(&canvas, "copy", {
+            let state = state.clone();
+            move |event: ClipboardEvent| {
+                let mut state = state.borrow_mut();
+                state.set_clipboard_data(&event);
+                state.push_event_at(Event::Copy, event.time_stamp());
+            }
+        });
+
+        let on_cut = EventListener::new_consume(&canvas, "cut", {
+            let state = state.clone();
+            move |event: ClipboardEvent| {
+                let mut state = state.borrow_mut();
+                state.set_clipboard_data(&event);
+                state.push_event_at(Event::Cut, event.time_stamp());
+            }
+        });
+
+        Ok(Self {
+            state,
+            _on_focus: on_focus,
+            _on_blur: on_blur,
+            _on_key_down: on_key_down,
+            _on_key_release: on_key_release,
+            _on_mouse_move: on_mouse_move,
+            _on_mouse_down: on_mouse_down,
+            _on_mouse_up: on_mouse_up,
+            _on_wheel: on_wheel,
+            _on_context_menu: on_context_menu,
+            _on_pointer_down: on_pointer_down,
+            _on_pointer_move: on_pointer_move,
+            _on_pointer_up: on_pointer_up,
+            _on_pointer_cancel: on_pointer_cancel,
+            _on_window_resize: on_window_resize,
+            _on_orientation_change: on_orientation_change,
+            _on_gamepad_connected: on_gamepad_connected,
+            _on_gamepad_disconnected: on_gamepad_disconnected,
+            _on_paste: on_paste,
+            _on_copy: on_copy,
+            _on_cut: on_cut,
+        })
+    }
+
+    pub fn pop_event(&mut self) -> Option<(Event, f64)> {
+        self.state.borrow_mut().events.pop_front()
+    }
+
+    pub fn push_event(&self, event: Event) {
+        self.state.borrow_mut().push_event(event);
+    }
+
+    #[cfg(feature = "xr")]
+    pub(crate) fn queue(&self) -> EventQueue {
+        EventQueue(self.state.clone())
+    }
+
+    pub fn take_window_resized(&self) -> bool {
+        std::mem::replace(&mut self.state.borrow_mut().window_resized, false)
+    }
+
+    pub fn set_copy_text(&mut self, text: Option<String>) {
+        self.state.borrow_mut().copy_text = text;
+    }
+
+    pub fn set_suppress_context_menu(&mut self, suppress: bool) {
+        self.state.borrow_mut().suppress_context_menu = suppress;
+    }
+}
+
+/// Event handlers without automatic clean up, inspired by
+/// <https://github.com/rustwasm/gloo/issues/30>.
+pub(crate) struct EventListener<T> {
+    element: web_sys::EventTarget,
+    kind: &'static str,
+    callback: Closure<dyn FnMut(T)>,
+}
+
+impl<T> EventListener<T>
+where
+    T: 'static + AsRef<web_sys::Event> + FromWasmAbi,
+{
+    pub fn new<F>(element: &web_sys::EventTarget, kind: &'static str, f: F) -> Self
+    where
+        F: 'static + FnMut(T),
+    {
+        let callback = Closure::wrap(Box::new(f) as Box<dyn FnMut(T)>);
+
+        element
+            .add_event_listener_with_callback(kind, &callback.as_ref().unchecked_ref())
+            .expect(&format!("Failed to add event listener for kind {}", kind));
+
+        Self {
+            element: element.clone(),
+            kind,
+            callback,
+        }
+    }
+
+    pub fn new_consume<F>(element: &web_sys::EventTarget, kind: &'static str, mut f: F) -> Self
+    where
+        F: 'static + FnMut(T),
+    {
+        Self::new(element, kind, move |event| {
+            {
+                let event_ref = event.as_ref();
+                event_ref.stop_propagation();
+                event_ref.cancel_bubble();
+            }
+
+            f(event);
+        })
+    }
+}
+
+impl<T> Drop for EventListener<T> {
+    fn drop(&mut self) {
+        self.element
+            .remove_event_listener_with_callback(self.kind, self.callback.as_ref().unchecked_ref())
+            .expect(&format!(
+                "Failed to remove event listener for kind {}",
+                self.kind
+            ));
+    }
+}
+
+/// A key that can be pressed.
+///
+/// This enum has been copied almost exactly from winit.
+/// Source: https://github.com/rust-windowing/winit/blob/a2db4c0a320aafc10d240c432fe5ef4e4d84629d/src/event.rs#L774
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    /// The '1' key over the letters.
+    Key1,
+    /// The '2' key over the letters.
+    Key2,
+    /// The '3' key over the letters.
+    Key3,
+    /// The '4' key over the letters.
+    Key4,
+    /// The '5' key over the letters.
+    Key5,
+    /// The '6' key over the letters.
+    Key6,
+    /// The '7' key over the letters.
+    Key7,
+    /// The '8' key over the letters.
+    Key8,
+    /// The '9' key over the letters.
+    Key9,
+    /// The '0' key over the 'O' and 'P' keys.
+    Key0,
+
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+
+    /// The Escape key, next to F1.
+    Escape,
+
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+    F21,
+    F22,
+    F23,
+    F24,
+
+    /// Print Screen/SysRq.
+    Snapshot,
+    /// Scroll Lock.
+    Scroll,
+    /// Pause/Break key, next to Scroll lock.
+    Pause,
+
+    /// `Insert`, next to Backspace.
+    Insert,
+    Home,
+    Delete,
+    End,
+    PageDown,
+    PageUp,
+
+    Left,
+    Up,
+    Right,
+    Down,
+
+    /// The Backspace key, right over Enter.
+    Backspace,
+    /// The Enter key.
+    Return,
+    /// The space bar.
+    Space,
+
+    /// The "Compose" key on Linux.
+    Compose,
+
+    Caret,
+
+    Numlock,
+    Numpad0,
+    Numpad1,
+    Numpad2,
+    Numpad3,
+    Numpad4,
+    Numpad5,
+    Numpad6,
+    Numpad7,
+    Numpad8,
+    Numpad9,
+
+    AbntC1,
+    AbntC2,
+    Add,
+    Apostrophe,
+    Apps,
+    At,
+    Ax,
+    Backslash,
+    Calculator,
+    Capital,
+    Colon,
+    Comma,
+    Convert,
+    Decimal,
+    Divide,
+    Equals,
+    Grave,
+    Kana,
+    Kanji,
+    LAlt,
+    LBracket,
+    LControl,
+    LShift,
+    LWin,
+    Mail,
+    MediaSelect,
+    MediaStop,
+    Minus,
+    Multiply,
+    Mute,
+    MyComputer,
+    NavigateForward,  // also called "Prior"
+    NavigateBackward, // also called "Next"
+    NextTrack,
+    NoConvert,
+    NumpadComma,
+    NumpadEnter,
+    NumpadEquals,
+    OEM102,
+    Period,
+    PlayPause,
+    Power,
+    PrevTrack,
+    RAlt,
+    RBracket,
+    RControl,
+    RShift,
+    RWin,
+    Semicolon,
+    Slash,
+    Sleep,
+    Stop,
+    Subtract,
+    Sysrq,
+    Tab,
+    Underline,
+    Unlabeled,
+    VolumeDown,
+    VolumeUp,
+    Wake,
+    WebBack,
+    WebFavorites,
+    WebForward,
+    WebHome,
+    WebRefresh,
+    WebSearch,
+    WebStop,
+    Yen,
+    Copy,
+    Paste,
+    Cut,
+}
+
+impl Key {
+    pub fn from_keyboard_event(event: &KeyboardEvent) -> Option<Self> {
+        // Source:
+        // https://github.com/rust-windowing/winit/blob/e4754999b7e7f27786092a62eda5275672d74130/src/platform_impl/web/web_sys/event.rs#L64
+        Some(match &event.code()[..] {
+            "Digit1" => Key::Key1,
+            "Digit2" => Key::Key2,
+            "Digit3" => Key::Key3,
+            "Digit4" => Key::Key4,
+            "Digit5" => Key::Key5,
+            "Digit6" => Key::Key6,
+            "Digit7" => Key::Key7,
+            "Digit8" => Key::Key8,
+            "Digit9" => Key::Key9,
+            "Digit0" => Key::Key0,
+            "KeyA" => Key::A,
+            "KeyB" => Key::B,
+            "KeyC" => Key::C,
+            "KeyD" => Key::D,
+            "KeyE" => Key::E,
+            "KeyF" => Key::F,
+            "KeyG" => Key::G,
+            "KeyH" => Key::H,
+            "KeyI" => Key::I,
+            "KeyJ" => Key::J,
+            "KeyK" => Key::K,
+            "KeyL" => Key::L,
+            "KeyM" => Key::M,
+            "KeyN" => Key::N,
+            "KeyO" => Key::O,
+            "KeyP" => Key::P,
+            "KeyQ" => Key::Q,
+            "KeyR" => Key::R,
+            "KeyS" => Key::S,
+            "KeyT" => Key::T,
+            "KeyU" => Key::U,
+            "KeyV" => Key::V,
+            "KeyW" => Key::W,
+            "KeyX" => Key::X,
+            "KeyY" => Key::Y,
+            "KeyZ" => Key::Z,
+            "Escape" => Key::Escape,
+            "F1" => Key::F1,
+            "F2" => Key::F2,
+            "F3" => Key::F3,
+            "F4" => Key::F4,
+            "F5" => Key::F5,
+            "F6" => Key::F6,
+            "F7" => Key::F7,
+            "F8" => Key::F8,
+            "F9" => Key::F9,
+            "F10" => Key::F10,
+            "F11" => Key::F11,
+            "F12" => Key::F12,
+            "F13" => Key::F13,
+            "F14" => Key::F14,
+            "F15" => Key::F15,
+            "F16" => Key::F16,
+            "F17" => Key::F17,
+            "F18" => Key::F18,
+            "F19" => Key::F19,
+            "F20" => Key::F20,
+            "F21" => Key::F21,
+            "F22" => Key::F22,
+            "F23" => Key::F23,
+            "F24" => Key::F24,
+            "PrintScreen" => Key::Snapshot,
+            "ScrollLock" => Key::Scroll,
+            "Pause" => Key::Pause,
+            "Insert" => Key::Insert,
+            "Home" => Key::Home,
+            "Delete" => Key::Delete,
+            "End" => Key::End,
+            "PageDown" => Key::PageDown,
+            "PageUp" => Key::PageUp,
+            "ArrowLeft" => Key::Left,
+            "ArrowUp" => Key::Up,
+            "ArrowRight" => Key::Right,
+            "ArrowDown" => Key::Down,
+            "Backspace" => Key::Backspace,
+            "Enter" => Key::Return,
+            "Space" => Key::Space,
+            "Compose" => Key::Compose,
+            "Caret" => Key::Caret,
+            "NumLock" => Key::Numlock,
+            "Numpad0" => Key::Numpad0,
+            "Numpad1" => Key::Numpad1,
+            "Numpad2" => Key::Numpad2,
+            "Numpad3" => Key::Numpad3,
+            "Numpad4" => Key::Numpad4,
+            "Numpad5" => Key::Numpad5,
+            "Numpad6" => Key::Numpad6,
+            "Numpad7" => Key::Numpad7,
+            "Numpad8" => Key::Numpad8,
+            "Numpad9" => Key::Numpad9,
+            "AbntC1" => Key::AbntC1,
+            "AbntC2" => Key::AbntC2,
+            "NumpadAdd" => Key::Add,
+            "Quote" => Key::Apostrophe,
+            "Apps" => Key::Apps,
+            "At" => Key::At,
+            "Ax" => Key::Ax,
+            "Backslash" => Key::Backslash,
+            "Calculator" => Key::Calculator,
+            "Capital" => Key::Capital,
+            "Semicolon" => Key::Semicolon,
+            "Comma" => Key::Comma,
+            "Convert" => Key::Convert,
+            "NumpadDecimal" => Key::Decimal,
+            "NumpadDivide" => Key::Divide,
+            "Equal" => Key::Equals,
+            "Backquote" => Key::Grave,
+            "Kana" => Key::Kana,
+            "Kanji" => Key::Kanji,
+            "AltLeft" => Key::LAlt,
+            "BracketLeft" => Key::LBracket,
+            "ControlLeft" => Key::LControl,
+            "ShiftLeft" => Key::LShift,
+            "MetaLeft" => Key::LWin,
+            "Mail" => Key::Mail,
+            "MediaSelect" => Key::MediaSelect,
+            "MediaStop" => Key::MediaStop,
+            "Minus" => Key::Minus,
+            "NumpadMultiply" => Key::Multiply,
+            "Mute" => Key::Mute,
+            "LaunchMyComputer" => Key::MyComputer,
+            "NavigateForward" => Key::NavigateForward,
+            "NavigateBackward" => Key::NavigateBackward,
+            "NextTrack" => Key::NextTrack,
+            "NoConvert" => Key::NoConvert,
+            "NumpadComma" => Key::NumpadComma,
+            "NumpadEnter" => Key::NumpadEnter,
+            "NumpadEquals" => Key::NumpadEquals,
+            "OEM102" => Key::OEM102,
+            "Period" => Key::Period,
+            "PlayPause" => Key::PlayPause,
+            "Power" => Key::Power,
+            "PrevTrack" => Key::PrevTrack,
+            "AltRight" => Key::RAlt,
+            "BracketRight" => Key::RBracket,
+            "ControlRight" => Key::RControl,
+            "ShiftRight" => Key::RShift,
+            "MetaRight" => Key::RWin,
+            "Slash" => Key::Slash,
+            "Sleep" => Key::Sleep,
+            "Stop" => Key::Stop,
+            "Sysrq" => Key::Sysrq,
+            "Tab" => Key::Tab,
+            "Underline" => Key::Underline,
+            "Unlabeled" => Key::Unlabeled,
+            "AudioVolumeDown" => Key::VolumeDown,
+            "AudioVolumeUp" => Key::VolumeUp,
+            "Wake" => Key::Wake,
+            "WebBack" => Key::WebBack,
+            "WebFavorites" => Key::WebFavorites,
+            "WebForward" => Key::WebForward,
+            "WebHome" => Key::WebHome,
+            "WebRefresh" => Key::WebRefresh,
+            "WebSearch" => Key::WebSearch,
+            "WebStop" => Key::WebStop,
+            "Yen" => Key::Yen,
+            _ => return None,
+        })
+    }
+}
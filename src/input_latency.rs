@@ -0,0 +1,201 @@
+//! Measuring the latency from input events to the frame that shows them.
+//!
+//! For competitive games, it matters how long it takes until the effect of
+//! an input is on screen. `InputLatency` records the timestamps of the
+//! events that have been handled in a frame, estimates when the frame is
+//! presented, and keeps statistics about the differences.
+//!
+//! Browsers do not tell when a frame is presented, so it is estimated in one
+//! of two ways; see `PresentEstimate`. Both include the time that the event
+//! waited for the frame, the time spent in the frame, and the time that the
+//! browser needs to render it, but not the latency of the display itself.
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc, time::Duration};
+
+use wasm_bindgen::{closure::Closure, JsCast};
+
+use crate::util;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentEstimate {
+    /// Takes the `requestAnimationFrame` timestamp of the next frame, which
+    /// is the time of the vsync after which the current frame is shown.
+    NextFrame,
+
+    /// Takes the time at which a timeout that has been set at the end of the
+    /// frame runs. This is the pattern commonly used to emulate the proposed
+    /// `requestPostAnimationFrame`: the timeout only runs once the browser
+    /// has rendered the frame, which gives an earlier and often more
+    /// accurate estimate on fast machines.
+    PostAnimationFrame,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputLatencyConfig {
+    /// The number of most recent latencies over which statistics are
+    /// computed.
+    pub window_size: usize,
+
+    pub present_estimate: PresentEstimate,
+}
+
+impl Default for InputLatencyConfig {
+    fn default() -> Self {
+        Self {
+            window_size: 500,
+            present_estimate: PresentEstimate::NextFrame,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InputLatencyStats {
+    /// The number of measured events in the window.
+    pub num_samples: usize,
+
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl InputLatencyStats {
+    pub fn log(&self) {
+        log::info!(
+            "Input latency over {} events: {:.1}ms mean, {:.1}ms p50, {:.1}ms p90, \
+             {:.1}ms p99, {:.1}ms max",
+            self.num_samples,
+            self.mean.as_secs_f64() * 1000.0,
+            self.p50.as_secs_f64() * 1000.0,
+            self.p90.as_secs_f64() * 1000.0,
+            self.p99.as_secs_f64() * 1000.0,
+            self.max.as_secs_f64() * 1000.0,
+        );
+    }
+}
+
+/// Measures input latency, once enabled.
+///
+/// In each frame, pass the timestamps of the handled events from
+/// `Canvas::pop_timed_event` to `record_input`, and call `end_frame` after
+/// drawing, from within the main loop callback.
+pub struct InputLatency {
+    config: InputLatencyConfig,
+    enabled: bool,
+    pending: Vec<f64>,
+    samples: Rc<RefCell<VecDeque<f64>>>,
+}
+
+impl InputLatency {
+    /// Creates a disabled latency monitor.
+    pub fn new(config: InputLatencyConfig) -> Self {
+        Self {
+            config,
+            enabled: false,
+            pending: Vec::new(),
+            samples: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    pub fn config(&self) -> &InputLatencyConfig {
+        &self.config
+    }
+
+    /// Enables or disables measuring. While disabled, the calls do nothing,
+    /// so that they can stay in place in release builds.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.pending.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Records that an event with the timestamp `time_ms` has been handled
+    /// in the current frame.
+    pub fn record_input(&mut self, time_ms: f64) {
+        if self.enabled {
+            self.pending.push(time_ms);
+        }
+    }
+
+    /// Ends the current frame, measuring the latency of its inputs once the
+    /// frame is estimated to have been presented.
+    pub fn end_frame(&mut self) {
+        if !self.enabled || self.pending.is_empty() {
+            return;
+        }
+
+        let inputs = std::mem::take(&mut self.pending);
+        let samples = self.samples.clone();
+        let window_size = self.config.window_size;
+        let on_present = move |present_ms: f64| {
+            let mut samples = samples.borrow_mut();
+            for input_ms in inputs {
+                samples.push_back((present_ms - input_ms).max(0.0));
+            }
+            while samples.len() > window_size {
+                samples.pop_front();
+            }
+        };
+
+        let window = match web_sys::window() {
+            Some(window) => window,
+            None => return,
+        };
+        let result = match self.config.present_estimate {
+            PresentEstimate::NextFrame => {
+                let callback = Closure::once_into_js(on_present);
+                window
+                    .request_animation_frame(callback.unchecked_ref())
+                    .map(|_| ())
+            }
+            PresentEstimate::PostAnimationFrame => {
+                let callback = Closure::once_into_js(move || on_present(util::now_ms()));
+                window
+                    .set_timeout_with_callback_and_timeout_and_arguments_0(
+                        callback.unchecked_ref(),
+                        0,
+                    )
+                    .map(|_| ())
+            }
+        };
+
+        if let Err(e) = result {
+            log::warn!("Failed to schedule input latency measurement: {:?}", e);
+        }
+    }
+
+    /// Removes all measurements.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.samples.borrow_mut().clear();
+    }
+
+    /// Computes statistics over the latencies in the window.
+    pub fn stats(&self) -> InputLatencyStats {
+        let mut samples: Vec<f64> = self.samples.borrow().iter().copied().collect();
+        if samples.is_empty() {
+            return InputLatencyStats::default();
+        }
+
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let to_duration = |ms: f64| Duration::from_secs_f64(ms / 1000.0);
+        let percentile = |p: f64| {
+            let rank = ((p * samples.len() as f64).ceil() as usize).max(1);
+            to_duration(samples[rank.min(samples.len()) - 1])
+        };
+
+        InputLatencyStats {
+            num_samples: samples.len(),
+            mean: to_duration(samples.iter().sum::<f64>() / samples.len() as f64),
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: to_duration(*samples.last().unwrap()),
+        }
+    }
+}
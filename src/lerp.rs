@@ -0,0 +1,92 @@
+//! Linear interpolation, e.g. for smoothing a fixed-timestep simulation
+//! between two updates.
+
+use nalgebra::{Point2, Point3, Vector2, Vector3};
+
+use crate::{AaRect, Camera, Color3, Color4};
+
+/// Types that can be linearly interpolated.
+pub trait Lerp {
+    /// Returns `self` for `t = 0` and `other` for `t = 1`.
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vector2<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vector3<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Point2<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Point3<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Color3 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Color3::new(
+            self.r.lerp(&other.r, t),
+            self.g.lerp(&other.g, t),
+            self.b.lerp(&other.b, t),
+        )
+    }
+}
+
+impl Lerp for Color4 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Color4::new(
+            self.r.lerp(&other.r, t),
+            self.g.lerp(&other.g, t),
+            self.b.lerp(&other.b, t),
+            self.a.lerp(&other.a, t),
+        )
+    }
+}
+
+impl Lerp for Camera {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Camera {
+            center: self.center.lerp(&other.center, t),
+            zoom: self.zoom.lerp(&other.zoom, t),
+            angle: lerp_angle(self.angle, other.angle, t),
+            viewport: match (self.viewport, other.viewport) {
+                (Some(a), Some(b)) => Some(AaRect {
+                    center: a.center.lerp(&b.center, t),
+                    size: a.size.lerp(&b.size, t),
+                }),
+                _ if t < 0.5 => self.viewport,
+                _ => other.viewport,
+            },
+            y_axis: if t < 0.5 { self.y_axis } else { other.y_axis },
+        }
+    }
+}
+
+/// Interpolates between two angles in radians along the shorter arc, so
+/// that e.g. interpolating from 350 to 10 degrees passes through 0 degrees.
+pub fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    use std::f32::consts::PI;
+
+    let delta = (b - a + PI).rem_euclid(2.0 * PI) - PI;
+
+    a + delta * t
+}
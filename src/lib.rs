@@ -4,23 +4,68 @@
 mod canvas;
 mod color;
 mod error;
+mod frame_pacing;
+mod game_clock;
+mod gpu_memory;
+mod hot_reload;
 mod input;
+mod input_latency;
+mod lerp;
+mod locale;
 mod main_loop;
+mod profiler;
+mod scene_stack;
+mod shortcuts;
 
 pub(crate) mod util;
 
+pub mod audio;
 pub mod draw;
 pub mod geom;
+pub mod ui;
+
+#[cfg(feature = "hecs")]
+pub mod ecs;
+#[cfg(feature = "scene")]
+pub mod scene;
+#[cfg(feature = "xr")]
+pub mod xr;
 
 // Re-export dependencies that occur in our public API.
+//
+// TODO: These golem types are still part of our API and need wrappers of
+// their own before golem can become an implementation detail:
+// - `Texture`, re-exported by `draw`
+// - `ShaderProgram`, through `DrawUnit::draw`, `Batch::draw`,
+//   `InstancedBatch::draw`, `ScreenQuad::draw` and `SharedShader`
+// - `UniformValue`, `UniformType` and `AttributeType` in `CustomPass`
+// - `Context`, through `Canvas::golem_ctx` and the `new_golem`
+//   constructors
 pub use golem;
 pub use golem::glow;
 pub use nalgebra;
 
-pub use canvas::Canvas;
+pub use canvas::{Canvas, ClearOptions};
 pub use color::{Color3, Color4};
-pub use draw::{Batch, Font, TextBatch, Texture};
+pub use draw::{Batch, Font, TextBatch, Texture, TextureExt};
 pub use error::Error;
-pub use geom::{AaRect, Camera, ScreenGeom};
-pub use input::{Event, InputState, Key};
-pub use main_loop::main_loop;
+pub use frame_pacing::{FramePacing, FramePacingConfig, FramePacingStats};
+pub use game_clock::GameClock;
+pub use geom::{AaRect, Camera, ScreenGeom, YAxis};
+pub use gpu_memory::{GpuAllocation, GpuMemoryReport, GpuResourceInfo, GpuResourceKind};
+pub use hot_reload::HotReload;
+pub use input::{
+    gestures::{Gesture, GestureConfig, GestureRecognizer},
+    Event, GamepadButtonState, GamepadInfo, GamepadMapping, GamepadState, InputState, Key,
+    MouseButton, Orientation, Pointer, PointerKind,
+};
+pub use input_latency::{InputLatency, InputLatencyConfig, InputLatencyStats, PresentEstimate};
+pub use lerp::{lerp_angle, Lerp};
+pub use locale::LocaleFormat;
+pub use main_loop::{
+    main_loop, main_loop_fixed, main_loop_on_demand, main_loop_with_clock, FixedStep,
+    FixedTimestep, RedrawTrigger,
+};
+pub use profiler::{ProfileScope, Profiler, ProfilerReport, ScopeStats};
+pub use scene_stack::{Scene, SceneCommand, SceneStack};
+pub use shortcuts::{KeyChord, Modifiers, Platform, Shortcuts};
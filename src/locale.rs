@@ -0,0 +1,223 @@
+//! Formatting numbers, dates and times for the user's locale.
+//!
+//! The formatting is done by the browser's `Intl` API, so no locale data
+//! needs to be shipped with the game. The resulting strings can be written
+//! with a `Font` like any other text, e.g. for scores in a HUD.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use js_sys::{Array, Function, Intl, Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::Error;
+
+/// The largest number of fraction digits that all browsers support.
+const MAX_FRACTION_DIGITS: u32 = 20;
+
+/// Formats values according to a locale.
+///
+/// Creating `Intl` formatters is comparatively expensive, so they are
+/// created once and reused. A `LocaleFormat` should therefore be kept
+/// around rather than created for each string.
+pub struct LocaleFormat {
+    locales: Array,
+    integer: Intl::NumberFormat,
+    percent: Intl::NumberFormat,
+    date: Intl::DateTimeFormat,
+    time: Intl::DateTimeFormat,
+    date_time: Intl::DateTimeFormat,
+    decimals: RefCell<HashMap<u32, Intl::NumberFormat>>,
+}
+
+impl LocaleFormat {
+    /// Formats for the locale that the browser is set to.
+    pub fn current() -> Self {
+        Self::with_locales(Array::new()).expect("formatters for the default locale are valid")
+    }
+
+    /// Formats for a locale given as BCP 47 language tag, e.g. `"de-DE"`.
+    /// Unsupported locales fall back to the browser's locale, while
+    /// malformed tags, e.g. `"de_DE"`, are an error.
+    pub fn new(locale: &str) -> Result<Self, Error> {
+        Self::with_locales(Array::of1(&JsValue::from_str(locale)))
+    }
+
+    fn with_locales(locales: Array) -> Result<Self, Error> {
+        let integer = construct(
+            "NumberFormat",
+            &locales,
+            &options(&[("maximumFractionDigits", JsValue::from_f64(0.0))]),
+        )?;
+        let percent = construct(
+            "NumberFormat",
+            &locales,
+            &options(&[("style", JsValue::from_str("percent"))]),
+        )?;
+        let date = construct(
+            "DateTimeFormat",
+            &locales,
+            &options(&[("dateStyle", JsValue::from_str("medium"))]),
+        )?;
+        let time = construct(
+            "DateTimeFormat",
+            &locales,
+            &options(&[("timeStyle", JsValue::from_str("short"))]),
+        )?;
+        let date_time = construct(
+            "DateTimeFormat",
+            &locales,
+            &options(&[
+                ("dateStyle", JsValue::from_str("medium")),
+                ("timeStyle", JsValue::from_str("short")),
+            ]),
+        )?;
+
+        Ok(Self {
+            locales,
+            integer,
+            percent,
+            date,
+            time,
+            date_time,
+            decimals: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the locale that is actually used, e.g. `"en-US"`.
+    pub fn locale(&self) -> String {
+        Reflect::get(
+            &self.integer.resolved_options(),
+            &JsValue::from_str("locale"),
+        )
+        .ok()
+        .and_then(|locale| locale.as_string())
+        .unwrap_or_default()
+    }
+
+    /// Formats an integer with thousands separators, e.g. "1,234,567" in
+    /// English and "1.234.567" in German.
+    pub fn integer(&self, value: i64) -> String {
+        format_number(&self.integer, value as f64)
+    }
+
+    /// Formats a number with exactly `fraction_digits` digits after the
+    /// decimal separator. At most 20 digits are supported; larger values are
+    /// clamped.
+    pub fn decimal(&self, value: f64, fraction_digits: u32) -> String {
+        let fraction_digits = fraction_digits.min(MAX_FRACTION_DIGITS);
+
+        let mut decimals = self.decimals.borrow_mut();
+        if !decimals.contains_key(&fraction_digits) {
+            let digits = JsValue::from_f64(fraction_digits as f64);
+            let format = construct(
+                "NumberFormat",
+                &self.locales,
+                &options(&[
+                    ("minimumFractionDigits", digits.clone()),
+                    ("maximumFractionDigits", digits),
+                ]),
+            );
+
+            match format {
+                Ok(format) => {
+                    decimals.insert(fraction_digits, format);
+                }
+                Err(e) => {
+                    log::warn!("Failed to create decimal format: {}", e);
+                    return format!("{:.*}", fraction_digits as usize, value);
+                }
+            }
+        }
+
+        format_number(&decimals[&fraction_digits], value)
+    }
+
+    /// Formats a fraction as percentage, e.g. 0.25 as "25%" in English and
+    /// "25 %" in German.
+    pub fn percent(&self, fraction: f64) -> String {
+        format_number(&self.percent, fraction)
+    }
+
+    /// Formats the date of a timestamp, given in milliseconds since the Unix
+    /// epoch as returned by `js_sys::Date::now`.
+    ///
+    /// Timestamps that do not form a valid date, e.g. NaN, are formatted as
+    /// "Invalid Date", like JavaScript does.
+    pub fn date(&self, time_ms: f64) -> String {
+        format_date(&self.date, time_ms)
+    }
+
+    /// Formats the time of day of a timestamp, without seconds.
+    pub fn time(&self, time_ms: f64) -> String {
+        format_date(&self.time, time_ms)
+    }
+
+    /// Formats the date and the time of day of a timestamp.
+    pub fn date_time(&self, time_ms: f64) -> String {
+        format_date(&self.date_time, time_ms)
+    }
+}
+
+/// What dates are formatted as if their timestamp is invalid.
+const INVALID_DATE: &str = "Invalid Date";
+
+/// Creates an `Intl` formatter by calling the constructor `Intl.<name>`,
+/// returning the `RangeError` that it throws for malformed locales or
+/// options as an error.
+fn construct<T: JsCast>(name: &str, locales: &Array, options: &Object) -> Result<T, Error> {
+    let intl = Reflect::get(&js_sys::global(), &JsValue::from_str("Intl")).map_err(locale_error)?;
+    let constructor: Function = Reflect::get(&intl, &JsValue::from_str(name))
+        .map_err(locale_error)?
+        .dyn_into()
+        .map_err(|_| Error::Locale(format!("`Intl.{}` is not supported", name)))?;
+
+    Reflect::construct(&constructor, &Array::of2(locales, options))
+        .map(JsCast::unchecked_into)
+        .map_err(locale_error)
+}
+
+fn locale_error(e: JsValue) -> Error {
+    let message = e
+        .dyn_ref::<js_sys::Error>()
+        .map(|e| String::from(e.message()))
+        .or_else(|| e.as_string())
+        .unwrap_or_else(|| format!("{:?}", e));
+
+    Error::Locale(message)
+}
+
+fn options(entries: &[(&str, JsValue)]) -> Object {
+    let options = Object::new();
+    for (key, value) in entries {
+        // Setting properties on a fresh object cannot fail.
+        let _ = Reflect::set(&options, &JsValue::from_str(key), value);
+    }
+
+    options
+}
+
+fn format_number(format: &Intl::NumberFormat, value: f64) -> String {
+    format
+        .format()
+        .call1(&JsValue::UNDEFINED, &JsValue::from_f64(value))
+        .ok()
+        .and_then(|s| s.as_string())
+        .unwrap_or_else(|| value.to_string())
+}
+
+fn format_date(format: &Intl::DateTimeFormat, time_ms: f64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(time_ms));
+
+    // Both `format` and `toISOString` throw a `RangeError` for timestamps
+    // that are NaN or out of range.
+    if date.get_time().is_nan() {
+        return String::from(INVALID_DATE);
+    }
+
+    format
+        .format()
+        .call1(&JsValue::UNDEFINED, &date)
+        .ok()
+        .and_then(|s| s.as_string())
+        .unwrap_or_else(|| String::from(date.to_iso_string()))
+}
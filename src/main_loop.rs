@@ -1,8 +1,65 @@
-use std::{cell::RefCell, rc::Rc, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    fmt,
+    rc::Rc,
+    time::Duration,
+};
 
 use wasm_bindgen::{closure::Closure, JsCast};
 
-use crate::Error;
+use crate::{Error, GameClock, Profiler};
+
+/// A handle for requesting that a frame is rendered when running
+/// `main_loop_on_demand`.
+///
+/// Every `Canvas` owns a trigger that is set whenever an input event arrives.
+/// Clones of the trigger can be kept around to request redraws, e.g. from
+/// asynchronous callbacks.
+#[derive(Clone, Default)]
+pub struct RedrawTrigger(Rc<TriggerState>);
+
+#[derive(Default)]
+struct TriggerState {
+    requested: Cell<bool>,
+
+    /// Set while `main_loop_on_demand` has an animation frame scheduled.
+    frame_pending: Cell<bool>,
+
+    /// Schedules an animation frame. This is set while `main_loop_on_demand`
+    /// runs, so that a request wakes up the idle loop.
+    wake: RefCell<Option<Rc<dyn Fn()>>>,
+}
+
+impl RedrawTrigger {
+    pub fn request(&self) {
+        self.0.requested.set(true);
+
+        if !self.0.frame_pending.get() {
+            let wake = self.0.wake.borrow().clone();
+            if let Some(wake) = wake {
+                self.0.frame_pending.set(true);
+                wake();
+            }
+        }
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.requested.get()
+    }
+
+    fn take(&self) -> bool {
+        self.0.requested.replace(false)
+    }
+}
+
+impl fmt::Debug for RedrawTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedrawTrigger")
+            .field("requested", &self.0.requested.get())
+            .field("frame_pending", &self.0.frame_pending.get())
+            .finish()
+    }
+}
 
 /// Run the `malen` main loop.
 ///
@@ -22,7 +79,154 @@ use crate::Error;
 ///   [`performance.now()`](https://developer.mozilla.org/en-US/docs/Web/API/Performance/now)
 ///   is limited in resolution to mitigate potential security threats.
 /// - Render the game.
-pub fn main_loop<F>(mut callback: F) -> Result<(), Error>
+pub fn main_loop<F>(callback: F) -> Result<(), Error>
+where
+    F: FnMut(Duration, &mut bool) + 'static,
+{
+    run(None, callback)
+}
+
+/// Run the `malen` main loop, passing game time instead of wall clock time.
+///
+/// In each frame, `clock` is ticked with the time that has elapsed since the
+/// last frame, and then passed to the callback, which can pause the clock or
+/// change its time scale, e.g. when opening a pause menu. Everything that
+/// should follow game time, such as `Transition::update`, should then be
+/// advanced by `GameClock::dt`.
+pub fn main_loop_with_clock<F>(mut clock: GameClock, mut callback: F) -> Result<(), Error>
+where
+    F: FnMut(&mut GameClock, &mut bool) + 'static,
+{
+    main_loop(move |dt, running| {
+        clock.tick(dt);
+        callback(&mut clock, running);
+    })
+}
+
+/// Run the `malen` main loop, only calling the callback for frames in which
+/// a redraw has been requested through `trigger`.
+///
+/// This is meant for tools and visualizations that don't need to be redrawn
+/// at the display's refresh rate, saving battery on mobile devices. Pass the
+/// trigger returned by `Canvas::redraw_trigger`, so that input events cause a
+/// redraw; use `Canvas::request_redraw` when the state changes for other
+/// reasons (e.g. an animation is running). The first frame is always drawn.
+///
+/// Note that resizing the window does not currently cause an event, so it
+/// does not trigger a redraw by itself.
+///
+/// The time passed to the callback is the time since the callback was last
+/// called, which may span many display frames.
+///
+/// While no redraw is requested, no animation frames are scheduled at all,
+/// so that the page can go idle. A request schedules the next frame.
+pub fn main_loop_on_demand<F>(trigger: RedrawTrigger, callback: F) -> Result<(), Error>
+where
+    F: FnMut(Duration, &mut bool) + 'static,
+{
+    trigger.request();
+
+    run(Some(trigger), callback)
+}
+
+/// What the callback of `main_loop_fixed` is asked to do.
+#[derive(Debug, Clone, Copy)]
+pub enum FixedStep {
+    /// Advance the simulation by the given fixed time step.
+    Update(Duration),
+
+    /// Render the game. The value in `[0, 1]` is the fraction of a time step
+    /// that has accumulated since the last update, which can be used to
+    /// interpolate between the previous and the current simulation state
+    /// (see `Lerp`).
+    Render(f32),
+}
+
+/// Accumulates frame delta times into fixed-size simulation steps.
+///
+/// This is what `main_loop_fixed` uses internally; it can also be used
+/// directly within a custom loop.
+#[derive(Debug, Clone)]
+pub struct FixedTimestep {
+    timestep: Duration,
+    max_steps_per_frame: usize,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    pub fn new(timestep: Duration) -> Self {
+        assert!(timestep > Duration::from_secs(0));
+
+        Self {
+            timestep,
+            max_steps_per_frame: 8,
+            accumulator: Duration::from_secs(0),
+        }
+    }
+
+    pub fn timestep(&self) -> Duration {
+        self.timestep
+    }
+
+    /// Sets the maximal number of steps that are taken per frame. If the
+    /// simulation falls further behind, e.g. because updates are too slow,
+    /// the remaining time is dropped rather than accumulated forever.
+    pub fn set_max_steps_per_frame(&mut self, max_steps_per_frame: usize) {
+        assert!(max_steps_per_frame > 0);
+
+        self.max_steps_per_frame = max_steps_per_frame;
+    }
+
+    /// Adds the time that has elapsed in the last frame and returns the
+    /// number of steps that should be taken now.
+    pub fn advance(&mut self, dt: Duration) -> usize {
+        self.accumulator += dt;
+
+        let mut num_steps = 0;
+        while self.accumulator >= self.timestep {
+            self.accumulator -= self.timestep;
+            num_steps += 1;
+        }
+
+        if num_steps > self.max_steps_per_frame {
+            self.accumulator = Duration::from_secs(0);
+            num_steps = self.max_steps_per_frame;
+        }
+
+        num_steps
+    }
+
+    /// Returns the fraction of a time step that has accumulated but not been
+    /// simulated yet.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator.as_secs_f64() / self.timestep.as_secs_f64()) as f32
+    }
+}
+
+/// Run the `malen` main loop with a fixed simulation time step.
+///
+/// In each frame, the callback is first called with `FixedStep::Update` for
+/// every time step that has elapsed, and then once with `FixedStep::Render`.
+/// This decouples the simulation rate from the display's refresh rate, e.g.
+/// for running a 30 Hz simulation smoothly on a 144 Hz display.
+pub fn main_loop_fixed<F>(timestep: Duration, mut callback: F) -> Result<(), Error>
+where
+    F: FnMut(FixedStep, &mut bool) + 'static,
+{
+    let mut fixed_timestep = FixedTimestep::new(timestep);
+
+    main_loop(move |dt, running| {
+        for _ in 0..fixed_timestep.advance(dt) {
+            crate::profile_scope!("update");
+            callback(FixedStep::Update(timestep), running);
+        }
+
+        crate::profile_scope!("render");
+        callback(FixedStep::Render(fixed_timestep.alpha()), running);
+    })
+}
+
+fn run<F>(trigger: Option<RedrawTrigger>, mut callback: F) -> Result<(), Error>
 where
     F: FnMut(Duration, &mut bool) + 'static,
 {
@@ -41,26 +245,58 @@ where
     let mut last_timestamp = None;
     let mut running = true;
 
+    if let Some(trigger) = trigger.as_ref() {
+        let f = f.clone();
+        let wake: Rc<dyn Fn()> = Rc::new(move || {
+            if let Some(f) = f.borrow().as_ref() {
+                request_animation_frame(f);
+            }
+        });
+        *trigger.0.wake.borrow_mut() = Some(wake);
+        trigger.0.frame_pending.set(true);
+    }
+
     *f.borrow_mut() = Some(Closure::wrap(Box::new({
         let f = f.clone();
 
         move |timestamp: f64| {
-            let dt = last_timestamp.map_or(Duration::from_secs(0), |last_timestamp: f64| {
-                let dt_ms = (timestamp - last_timestamp).max(0.0);
-                let dt_secs = dt_ms / 1000.0;
+            if let Some(trigger) = trigger.as_ref() {
+                trigger.0.frame_pending.set(false);
+            }
+
+            let redraw = trigger.as_ref().map_or(true, RedrawTrigger::take);
 
-                Duration::from_secs_f64(dt_secs)
-            });
-            last_timestamp = Some(timestamp);
+            if redraw {
+                let dt = last_timestamp.map_or(Duration::from_secs(0), |last_timestamp: f64| {
+                    let dt_ms = (timestamp - last_timestamp).max(0.0);
+                    let dt_secs = dt_ms / 1000.0;
 
-            callback(dt, &mut running);
+                    Duration::from_secs_f64(dt_secs)
+                });
+                last_timestamp = Some(timestamp);
+
+                {
+                    crate::profile_scope!("frame");
+                    callback(dt, &mut running);
+                }
+                Profiler::end_frame();
+            }
 
             if !running {
+                if let Some(trigger) = trigger.as_ref() {
+                    // Break the reference cycle between the trigger and the
+                    // closure.
+                    trigger.0.wake.borrow_mut().take();
+                }
                 let _ = f.borrow_mut().take();
                 return;
             }
 
-            request_animation_frame(f.borrow().as_ref().unwrap());
+            // When drawing on demand, requests that were made during the
+            // frame have already scheduled the next one; otherwise, go idle.
+            if trigger.is_none() {
+                request_animation_frame(f.borrow().as_ref().unwrap());
+            }
         }
     }) as Box<dyn FnMut(f64)>));
 
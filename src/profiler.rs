@@ -0,0 +1,293 @@
+//! A lightweight CPU profiler for named scopes.
+//!
+//! Scopes are timed with `profile_scope!`, which measures the time until the
+//! end of the enclosing block. Timings are summed up per frame, and
+//! statistics over the most recent frames can be queried with
+//! `Profiler::report`. For offline analysis, the individual scopes can also
+//! be recorded as a trace in the Chrome trace event format, which can be
+//! loaded in `chrome://tracing` or Perfetto.
+//!
+//! Scopes can also be emitted as `performance.mark` and `performance.measure`
+//! entries with `Profiler::set_performance_marks`, so that they show up in
+//! the performance panel of the browser's developer tools, next to the
+//! browser's own work.
+//!
+//! Like the GPU memory books, the profiler is kept in a thread-local, so
+//! that scopes can be timed anywhere without passing a handle around. It is
+//! disabled until `Profiler::set_enabled` is called.
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+
+use crate::util;
+
+/// Times the rest of the enclosing block under the given name.
+///
+/// ```ignore
+/// {
+///     malen::profile_scope!("physics");
+///     world.step();
+/// }
+/// ```
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::ProfileScope::new($name);
+    };
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct ScopeTotal {
+    calls: usize,
+    total_ms: f64,
+}
+
+struct TraceEvent {
+    name: &'static str,
+    start_ms: f64,
+    duration_ms: f64,
+}
+
+struct State {
+    enabled: bool,
+    performance_marks: bool,
+    window_size: usize,
+    current: BTreeMap<&'static str, ScopeTotal>,
+    frames: VecDeque<BTreeMap<&'static str, ScopeTotal>>,
+    trace: Option<Vec<TraceEvent>>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            performance_marks: false,
+            window_size: 120,
+            current: BTreeMap::new(),
+            frames: VecDeque::new(),
+            trace: None,
+        }
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+/// Measures the time from its creation until it is dropped. Usually created
+/// through `profile_scope!`.
+#[must_use]
+pub struct ProfileScope {
+    name: &'static str,
+    start_ms: Option<f64>,
+    start_mark: Option<String>,
+}
+
+impl ProfileScope {
+    pub fn new(name: &'static str) -> Self {
+        let (enabled, performance_marks) = STATE.with(|state| {
+            let state = state.borrow();
+            (state.enabled, state.performance_marks)
+        });
+
+        let start_mark = if enabled && performance_marks {
+            performance().and_then(|performance| {
+                let start_mark = format!("{}:start", name);
+                performance.mark(&start_mark).ok().map(|_| start_mark)
+            })
+        } else {
+            None
+        };
+
+        Self {
+            name,
+            start_ms: if enabled { Some(util::now_ms()) } else { None },
+            start_mark,
+        }
+    }
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        if let Some(start_ms) = self.start_ms {
+            let duration_ms = util::now_ms() - start_ms;
+
+            if let (Some(start_mark), Some(performance)) = (self.start_mark.take(), performance()) {
+                let _ = performance.measure_with_start_mark(self.name, &start_mark);
+                performance.clear_marks_with_mark_name(&start_mark);
+            }
+
+            STATE.with(|state| {
+                let mut state = state.borrow_mut();
+
+                let total = state.current.entry(self.name).or_default();
+                total.calls += 1;
+                total.total_ms += duration_ms;
+
+                if let Some(trace) = state.trace.as_mut() {
+                    trace.push(TraceEvent {
+                        name: self.name,
+                        start_ms,
+                        duration_ms,
+                    });
+                }
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScopeStats {
+    pub name: &'static str,
+
+    /// Mean number of times that the scope was entered per frame.
+    pub mean_calls: f32,
+
+    /// Mean total time spent in the scope per frame.
+    pub mean_time: Duration,
+
+    /// Maximal total time spent in the scope in a single frame.
+    pub max_time: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProfilerReport {
+    /// The number of frames over which the statistics were computed.
+    pub num_frames: usize,
+
+    /// Statistics of all scopes, sorted by decreasing mean time.
+    pub scopes: Vec<ScopeStats>,
+}
+
+impl ProfilerReport {
+    pub fn log(&self) {
+        log::info!("Profile over {} frames:", self.num_frames);
+
+        for scope in self.scopes.iter() {
+            log::info!(
+                "    {:>8.3}ms mean, {:>8.3}ms max, {:>6.1} calls: {}",
+                scope.mean_time.as_secs_f64() * 1000.0,
+                scope.max_time.as_secs_f64() * 1000.0,
+                scope.mean_calls,
+                scope.name,
+            );
+        }
+    }
+}
+
+/// Controls the thread-local profiler.
+pub struct Profiler;
+
+impl Profiler {
+    /// Enables or disables timing of scopes. Disabled by default, so that
+    /// scopes only cost a thread-local lookup unless profiling is wanted.
+    pub fn set_enabled(enabled: bool) {
+        STATE.with(|state| state.borrow_mut().enabled = enabled);
+    }
+
+    /// Enables or disables emitting `performance.mark` and
+    /// `performance.measure` entries for every timed scope. Disabled by
+    /// default, since this allocates for every scope.
+    pub fn set_performance_marks(performance_marks: bool) {
+        STATE.with(|state| state.borrow_mut().performance_marks = performance_marks);
+    }
+
+    /// Sets the number of most recent frames over which statistics are
+    /// computed.
+    pub fn set_window_size(window_size: usize) {
+        STATE.with(|state| state.borrow_mut().window_size = window_size.max(1));
+    }
+
+    /// Finishes the timings of the current frame.
+    ///
+    /// The main loop functions call this after every frame, so this only
+    /// needs to be called when frames are driven in some other way.
+    pub fn end_frame() {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+
+            if state.performance_marks {
+                // The browser has already recorded the measures, so we only
+                // need to keep its buffer from growing.
+                if let Some(performance) = performance() {
+                    performance.clear_measures();
+                }
+            }
+
+            let frame = std::mem::replace(&mut state.current, BTreeMap::new());
+            state.frames.push_back(frame);
+
+            while state.frames.len() > state.window_size {
+                state.frames.pop_front();
+            }
+        });
+    }
+
+    /// Returns statistics over the most recent frames.
+    pub fn report() -> ProfilerReport {
+        STATE.with(|state| {
+            let state = state.borrow();
+            let num_frames = state.frames.len();
+
+            if num_frames == 0 {
+                return ProfilerReport::default();
+            }
+
+            let mut sums: BTreeMap<&'static str, (ScopeTotal, f64)> = BTreeMap::new();
+            for frame in state.frames.iter() {
+                for (name, total) in frame.iter() {
+                    let (sum, max_ms) = sums.entry(*name).or_default();
+                    sum.calls += total.calls;
+                    sum.total_ms += total.total_ms;
+                    *max_ms = max_ms.max(total.total_ms);
+                }
+            }
+
+            let mut scopes: Vec<ScopeStats> = sums
+                .into_iter()
+                .map(|(name, (sum, max_ms))| ScopeStats {
+                    name,
+                    mean_calls: sum.calls as f32 / num_frames as f32,
+                    mean_time: Duration::from_secs_f64(sum.total_ms / num_frames as f64 / 1000.0),
+                    max_time: Duration::from_secs_f64(max_ms / 1000.0),
+                })
+                .collect();
+            scopes.sort_by(|a, b| b.mean_time.cmp(&a.mean_time));
+
+            ProfilerReport { num_frames, scopes }
+        })
+    }
+
+    /// Starts recording every timed scope for a trace. Any previously
+    /// recorded trace is discarded.
+    pub fn start_trace() {
+        STATE.with(|state| state.borrow_mut().trace = Some(Vec::new()));
+    }
+
+    /// Stops recording and returns the trace as JSON in the Chrome trace
+    /// event format, or `None` if no trace was being recorded.
+    pub fn stop_trace() -> Option<String> {
+        let trace = STATE.with(|state| state.borrow_mut().trace.take())?;
+
+        let events: Vec<String> = trace
+            .iter()
+            .map(|event| {
+                format!(
+                    r#"{{"name":"{}","ph":"X","ts":{:.3},"dur":{:.3},"pid":0,"tid":0}}"#,
+                    event.name.replace('\\', "\\\\").replace('"', "\\\""),
+                    event.start_ms * 1000.0,
+                    event.duration_ms * 1000.0,
+                )
+            })
+            .collect();
+
+        Some(format!(r#"{{"traceEvents":[{}]}}"#, events.join(",")))
+    }
+}
+
+fn performance() -> Option<web_sys::Performance> {
+    web_sys::window()?.performance()
+}
@@ -0,0 +1,299 @@
+//! A serializable scene format for data-driven levels, enabled with the
+//! `scene` feature.
+//!
+//! Scenes are stored as JSON. Vectors and colors are stored as arrays, and
+//! texture regions are referenced by name (e.g. a frame of a sprite sheet),
+//! so that scene files do not depend on how textures are loaded. In older
+//! files, the `texture` of a sprite is read as the name of its region, and
+//! its `uv_rect` is ignored.
+
+use nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::Response;
+
+use crate::{draw::shadow::Light, Color3, Color4, Error};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Scene {
+    pub entities: Vec<SceneEntity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneEntity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    #[serde(default)]
+    pub transform: SceneTransform,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sprite: Option<SceneSprite>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<SceneText>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub light: Option<SceneLight>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneTransform {
+    pub pos: [f32; 2],
+    pub angle: f32,
+    pub scale: [f32; 2],
+    pub z: f32,
+}
+
+impl Default for SceneTransform {
+    fn default() -> Self {
+        Self {
+            pos: [0.0, 0.0],
+            angle: 0.0,
+            scale: [1.0, 1.0],
+            z: 0.0,
+        }
+    }
+}
+
+impl SceneTransform {
+    pub fn pos(&self) -> Point2<f32> {
+        Point2::new(self.pos[0], self.pos[1])
+    }
+
+    pub fn scale(&self) -> Vector2<f32> {
+        Vector2::new(self.scale[0], self.scale[1])
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneSprite {
+    pub size: [f32; 2],
+
+    #[serde(default = "white")]
+    pub color: [f32; 4],
+
+    /// The name of the texture region, if the sprite is textured.
+    #[serde(default, alias = "texture", skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+impl SceneSprite {
+    pub fn size(&self) -> Vector2<f32> {
+        Vector2::new(self.size[0], self.size[1])
+    }
+
+    pub fn color(&self) -> Color4 {
+        self.color.into()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneText {
+    pub text: String,
+    pub size: f32,
+
+    #[serde(default = "white")]
+    pub color: [f32; 4],
+}
+
+impl SceneText {
+    pub fn color(&self) -> Color4 {
+        self.color.into()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SceneLight {
+    pub radius: f32,
+
+    /// The size of the light cone in radians. The cone points in the
+    /// direction of the entity's angle.
+    #[serde(default = "full_circle")]
+    pub angle_size: f32,
+
+    pub color: [f32; 3],
+}
+
+impl SceneLight {
+    /// Returns the light placed at the given transform.
+    pub fn to_light(&self, transform: &SceneTransform) -> Light {
+        Light {
+            world_pos: transform.pos(),
+            radius: self.radius,
+            angle: transform.angle,
+            angle_size: self.angle_size,
+            color: Color3::from(self.color),
+        }
+    }
+}
+
+impl Scene {
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        serde_json::from_str(json).map_err(|e| Error::Scene(e.to_string()))
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Scene(e.to_string()))
+    }
+
+    /// Fetches a scene file from `url`.
+    pub async fn load(url: &str) -> Result<Self, Error> {
+        let window = web_sys::window().ok_or(Error::NoWindow)?;
+        let fetch_error = |e: wasm_bindgen::JsValue| Error::Scene(format!("{:?}", e));
+
+        let response: Response = JsFuture::from(window.fetch_with_str(url))
+            .await
+            .map_err(fetch_error)?
+            .dyn_into()
+            .map_err(fetch_error)?;
+        if !response.ok() {
+            return Err(Error::Scene(format!(
+                "failed to fetch `{}`: status {}",
+                url,
+                response.status()
+            )));
+        }
+
+        let text = JsFuture::from(response.text().map_err(fetch_error)?)
+            .await
+            .map_err(fetch_error)?
+            .as_string()
+            .unwrap_or_default();
+
+        Self::from_json(&text)
+    }
+
+    /// Returns the lights of all entities that have one.
+    pub fn lights(&self) -> Vec<Light> {
+        self.entities
+            .iter()
+            .filter_map(|entity| {
+                entity
+                    .light
+                    .as_ref()
+                    .map(|light| light.to_light(&entity.transform))
+            })
+            .collect()
+    }
+
+    /// Spawns the entities of the scene into `world`.
+    ///
+    /// `region_id` maps region names to texture regions that have been
+    /// registered with the renderer. Sprites whose region is not found are
+    /// spawned untextured.
+    #[cfg(feature = "hecs")]
+    pub fn spawn<F>(&self, world: &mut hecs::World, mut region_id: F) -> Vec<hecs::Entity>
+    where
+        F: FnMut(&str) -> Option<crate::ecs::RegionId>,
+    {
+        use crate::ecs::{LightComp, SpriteComp, TextComp, Transform2D};
+
+        self.entities
+            .iter()
+            .map(|entity| {
+                let mut builder = hecs::EntityBuilder::new();
+
+                builder.add(Transform2D {
+                    pos: entity.transform.pos(),
+                    angle: entity.transform.angle,
+                    scale: entity.transform.scale(),
+                    z: entity.transform.z,
+                });
+
+                if let Some(name) = entity.name.as_ref() {
+                    builder.add(SceneName(name.clone()));
+                }
+                if let Some(sprite) = entity.sprite.as_ref() {
+                    builder.add(SpriteComp {
+                        size: sprite.size(),
+                        color: sprite.color(),
+                        region: sprite.region.as_deref().and_then(&mut region_id),
+                    });
+                }
+                if let Some(text) = entity.text.as_ref() {
+                    builder.add(TextComp {
+                        text: text.text.clone(),
+                        size: text.size,
+                        color: text.color(),
+                    });
+                }
+                if let Some(light) = entity.light.as_ref() {
+                    builder.add(LightComp {
+                        radius: light.radius,
+                        angle_size: light.angle_size,
+                        color: Color3::from(light.color),
+                    });
+                }
+
+                world.spawn(builder.build())
+            })
+            .collect()
+    }
+
+    /// Builds a scene from all entities of `world` that have a
+    /// `Transform2D`, e.g. for saving a level from an editor.
+    ///
+    /// `region_name` maps texture regions back to the names under which
+    /// they are stored in the scene file.
+    #[cfg(feature = "hecs")]
+    pub fn from_world<F>(world: &hecs::World, mut region_name: F) -> Self
+    where
+        F: FnMut(crate::ecs::RegionId) -> Option<String>,
+    {
+        use crate::ecs::{LightComp, SpriteComp, TextComp, Transform2D};
+
+        let entities = world
+            .query::<(
+                &Transform2D,
+                Option<&SceneName>,
+                Option<&SpriteComp>,
+                Option<&TextComp>,
+                Option<&LightComp>,
+            )>()
+            .iter()
+            .map(|(_, (transform, name, sprite, text, light))| SceneEntity {
+                name: name.map(|name| name.0.clone()),
+                transform: SceneTransform {
+                    pos: [transform.pos.x, transform.pos.y],
+                    angle: transform.angle,
+                    scale: [transform.scale.x, transform.scale.y],
+                    z: transform.z,
+                },
+                sprite: sprite.map(|sprite| SceneSprite {
+                    size: [sprite.size.x, sprite.size.y],
+                    color: sprite.color.into(),
+                    region: sprite.region.and_then(&mut region_name),
+                }),
+                text: text.map(|text| SceneText {
+                    text: text.text.clone(),
+                    size: text.size,
+                    color: text.color.into(),
+                }),
+                light: light.map(|light| SceneLight {
+                    radius: light.radius,
+                    angle_size: light.angle_size,
+                    color: light.color.into(),
+                }),
+            })
+            .collect();
+
+        Scene { entities }
+    }
+}
+
+/// The name of an entity that has been spawned from a scene, kept so that
+/// the name survives saving the scene again.
+#[cfg(feature = "hecs")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SceneName(pub String);
+
+fn white() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn full_circle() -> f32 {
+    std::f32::consts::PI * 2.0
+}
@@ -0,0 +1,159 @@
+//! A stack of scenes, such as menus, gameplay and pause screens.
+//!
+//! Only the scene on top of the stack receives events and updates. Scenes
+//! can push new scenes on top of themselves, e.g. a pause screen over the
+//! game, pop themselves to return to the scene below, or replace themselves.
+//!
+//! Not to be confused with the data-driven scene files of the `scene`
+//! module.
+
+use std::time::Duration;
+
+use crate::{main_loop, Canvas, Error, Event};
+
+/// What should happen to the scene stack after a scene has handled an event
+/// or an update.
+pub enum SceneCommand {
+    None,
+
+    /// Pushes a scene on top of the current one.
+    Push(Box<dyn Scene>),
+
+    /// Removes the current scene, returning to the one below.
+    Pop,
+
+    /// Replaces the current scene, e.g. to go from the menu to the game.
+    Replace(Box<dyn Scene>),
+
+    /// Removes all scenes, which ends the main loop.
+    Quit,
+}
+
+pub trait Scene {
+    /// Called when the scene is put on the stack.
+    fn enter(&mut self, _canvas: &mut Canvas) {}
+
+    /// Called when the scene is removed from the stack. Scenes that are
+    /// covered by another scene are not exited.
+    fn exit(&mut self, _canvas: &mut Canvas) {}
+
+    fn handle_event(&mut self, _canvas: &mut Canvas, _event: &Event) -> SceneCommand {
+        SceneCommand::None
+    }
+
+    fn update(&mut self, canvas: &mut Canvas, dt: Duration) -> SceneCommand;
+
+    fn draw(&mut self, canvas: &mut Canvas) -> Result<(), Error>;
+
+    /// Whether the scene below is drawn before this one, e.g. for a pause
+    /// screen that shows the paused game in the background.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Default)]
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+}
+
+impl SceneStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    pub fn push(&mut self, canvas: &mut Canvas, mut scene: Box<dyn Scene>) {
+        scene.enter(canvas);
+        self.scenes.push(scene);
+    }
+
+    pub fn pop(&mut self, canvas: &mut Canvas) -> Option<Box<dyn Scene>> {
+        let mut scene = self.scenes.pop()?;
+        scene.exit(canvas);
+
+        Some(scene)
+    }
+
+    /// Pops all scenes, from top to bottom.
+    pub fn clear(&mut self, canvas: &mut Canvas) {
+        while self.pop(canvas).is_some() {}
+    }
+
+    /// Passes the event to the scene on top.
+    pub fn handle_event(&mut self, canvas: &mut Canvas, event: &Event) {
+        if let Some(scene) = self.scenes.last_mut() {
+            let command = scene.handle_event(canvas, event);
+            self.apply(canvas, command);
+        }
+    }
+
+    /// Updates the scene on top.
+    pub fn update(&mut self, canvas: &mut Canvas, dt: Duration) {
+        if let Some(scene) = self.scenes.last_mut() {
+            let command = scene.update(canvas, dt);
+            self.apply(canvas, command);
+        }
+    }
+
+    /// Draws the scene on top, preceded by the scenes below it for as long
+    /// as the scenes above are overlays.
+    pub fn draw(&mut self, canvas: &mut Canvas) -> Result<(), Error> {
+        let first_drawn = self
+            .scenes
+            .iter()
+            .rposition(|scene| !scene.is_overlay())
+            .unwrap_or(0);
+
+        for scene in self.scenes[first_drawn..].iter_mut() {
+            scene.draw(canvas)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the main loop until the stack is empty. In each frame, all
+    /// pending events are handled, and then the stack is updated and drawn.
+    ///
+    /// Errors from drawing are logged and end the main loop.
+    pub fn run(mut self, mut canvas: Canvas) -> Result<(), Error> {
+        main_loop(move |dt, running| {
+            while let Some(event) = canvas.pop_event() {
+                self.handle_event(&mut canvas, &event);
+            }
+
+            self.update(&mut canvas, dt);
+
+            if let Err(e) = self.draw(&mut canvas) {
+                log::error!("Failed to draw scene: {}", e);
+                self.clear(&mut canvas);
+            }
+
+            if self.is_empty() {
+                *running = false;
+            }
+        })
+    }
+
+    fn apply(&mut self, canvas: &mut Canvas, command: SceneCommand) {
+        match command {
+            SceneCommand::None => (),
+            SceneCommand::Push(scene) => self.push(canvas, scene),
+            SceneCommand::Pop => {
+                self.pop(canvas);
+            }
+            SceneCommand::Replace(scene) => {
+                self.pop(canvas);
+                self.push(canvas, scene);
+            }
+            SceneCommand::Quit => self.clear(canvas),
+        }
+    }
+}
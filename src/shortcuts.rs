@@ -0,0 +1,409 @@
+//! Keyboard shortcuts with modifiers, such as Ctrl+Shift+S.
+//!
+//! `Shortcuts` maps key chords to application-defined actions, refuses
+//! chords that are already bound, and formats chords the way the platform
+//! shows them, e.g. "⇧⌘S" on macOS and "Ctrl+Shift+S" elsewhere.
+
+use std::{fmt, str::FromStr};
+
+use crate::{Error, Event, InputState, Key};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Mac,
+    Other,
+}
+
+impl Platform {
+    /// Detects the platform that the browser runs on.
+    pub fn current() -> Self {
+        let platform = web_sys::window()
+            .and_then(|window| window.navigator().platform().ok())
+            .unwrap_or_default();
+
+        if platform.starts_with("Mac") || platform.starts_with("iP") {
+            Platform::Mac
+        } else {
+            Platform::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+
+    /// The Command key on macOS and the Windows key elsewhere.
+    pub meta: bool,
+}
+
+impl Modifiers {
+    /// Returns the modifiers that are currently held.
+    pub fn from_input_state(input: &InputState) -> Self {
+        Self {
+            ctrl: input.key(Key::LControl) || input.key(Key::RControl),
+            shift: input.key(Key::LShift) || input.key(Key::RShift),
+            alt: input.key(Key::LAlt) || input.key(Key::RAlt),
+            meta: input.key(Key::LWin) || input.key(Key::RWin),
+        }
+    }
+}
+
+/// A key together with the modifiers that need to be held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub modifiers: Modifiers,
+    pub key: Key,
+}
+
+impl KeyChord {
+    pub fn new(key: Key) -> Self {
+        Self {
+            modifiers: Modifiers::default(),
+            key,
+        }
+    }
+
+    pub fn ctrl(mut self) -> Self {
+        self.modifiers.ctrl = true;
+        self
+    }
+
+    pub fn shift(mut self) -> Self {
+        self.modifiers.shift = true;
+        self
+    }
+
+    pub fn alt(mut self) -> Self {
+        self.modifiers.alt = true;
+        self
+    }
+
+    pub fn meta(mut self) -> Self {
+        self.modifiers.meta = true;
+        self
+    }
+
+    /// Adds the platform's primary modifier, which is Command on macOS and
+    /// Ctrl elsewhere.
+    pub fn primary(self, platform: Platform) -> Self {
+        match platform {
+            Platform::Mac => self.meta(),
+            Platform::Other => self.ctrl(),
+        }
+    }
+
+    /// Parses a chord such as "Ctrl+Shift+S". Modifiers and key names are
+    /// case-insensitive. "Mod" stands for the primary modifier of
+    /// `platform`; see `primary`.
+    pub fn parse(s: &str, platform: Platform) -> Result<Self, Error> {
+        let parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let (key_name, modifier_names) = parts.split_last().unwrap();
+
+        let key = key_from_name(key_name)
+            .ok_or_else(|| Error::Shortcut(format!("unknown key `{}` in `{}`", key_name, s)))?;
+        let mut chord = KeyChord::new(key);
+
+        for name in modifier_names {
+            chord = match name.to_lowercase().as_str() {
+                "ctrl" | "control" => chord.ctrl(),
+                "shift" => chord.shift(),
+                "alt" | "option" => chord.alt(),
+                "meta" | "cmd" | "command" | "super" | "win" => chord.meta(),
+                "mod" => chord.primary(platform),
+                _ => {
+                    return Err(Error::Shortcut(format!(
+                        "unknown modifier `{}` in `{}`",
+                        name, s
+                    )))
+                }
+            };
+        }
+
+        Ok(chord)
+    }
+
+    /// Formats the chord the way the platform usually shows shortcuts in
+    /// menus, e.g. "⇧⌘S" on macOS and "Ctrl+Shift+S" elsewhere.
+    pub fn display_string(&self, platform: Platform) -> String {
+        let key = key_name(self.key);
+
+        match platform {
+            Platform::Mac => {
+                let mut s = String::new();
+                for (held, symbol) in &[
+                    (self.modifiers.ctrl, "⌃"),
+                    (self.modifiers.alt, "⌥"),
+                    (self.modifiers.shift, "⇧"),
+                    (self.modifiers.meta, "⌘"),
+                ] {
+                    if *held {
+                        s.push_str(symbol);
+                    }
+                }
+                s.push_str(&key);
+                s
+            }
+            Platform::Other => {
+                let mut parts = Vec::new();
+                for (held, name) in &[
+                    (self.modifiers.ctrl, "Ctrl"),
+                    (self.modifiers.alt, "Alt"),
+                    (self.modifiers.shift, "Shift"),
+                    (self.modifiers.meta, "Meta"),
+                ] {
+                    if *held {
+                        parts.push(name.to_string());
+                    }
+                }
+                parts.push(key);
+                parts.join("+")
+            }
+        }
+    }
+}
+
+impl fmt::Display for KeyChord {
+    /// Formats the chord like `display_string` does for `Platform::Other`,
+    /// which `parse` accepts on all platforms.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.display_string(Platform::Other))
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        KeyChord::parse(s, Platform::current())
+    }
+}
+
+/// A registry of keyboard shortcuts that trigger actions of type `A`.
+pub struct Shortcuts<A> {
+    platform: Platform,
+    bindings: Vec<(KeyChord, A)>,
+}
+
+impl<A> Default for Shortcuts<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> Shortcuts<A> {
+    /// Creates an empty registry for the current platform.
+    pub fn new() -> Self {
+        Self {
+            platform: Platform::current(),
+            bindings: Vec::new(),
+        }
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// Binds `chord` to `action`. Fails if the chord is already bound.
+    pub fn register(&mut self, chord: KeyChord, action: A) -> Result<(), Error> {
+        if self.action(chord).is_some() {
+            return Err(Error::Shortcut(format!(
+                "`{}` is already bound",
+                chord.display_string(self.platform)
+            )));
+        }
+
+        self.bindings.push((chord, action));
+        Ok(())
+    }
+
+    /// Parses `chord` with `KeyChord::parse` and binds it to `action`.
+    pub fn register_str(&mut self, chord: &str, action: A) -> Result<(), Error> {
+        let chord = KeyChord::parse(chord, self.platform)?;
+        self.register(chord, action)
+    }
+
+    /// Removes the binding of `chord`, returning its action.
+    pub fn unregister(&mut self, chord: KeyChord) -> Option<A> {
+        let index = self
+            .bindings
+            .iter()
+            .position(|(bound, _)| *bound == chord)?;
+
+        Some(self.bindings.remove(index).1)
+    }
+
+    pub fn bindings(&self) -> impl Iterator<Item = &(KeyChord, A)> {
+        self.bindings.iter()
+    }
+
+    /// Returns the action that is bound to `chord`.
+    pub fn action(&self, chord: KeyChord) -> Option<&A> {
+        self.bindings
+            .iter()
+            .find(|(bound, _)| *bound == chord)
+            .map(|(_, action)| action)
+    }
+
+    /// Returns the action whose shortcut has been pressed, if any.
+    ///
+    /// Modifiers need to match exactly, so that e.g. Ctrl+Shift+S does not
+    /// trigger Ctrl+S. `input` needs to include the event, which is the case
+    /// for events popped from the `Canvas`.
+    pub fn handle_event(&self, event: &Event, input: &InputState) -> Option<&A> {
+        match event {
+            Event::KeyPressed(key) => self.action(KeyChord {
+                modifiers: Modifiers::from_input_state(input),
+                key: *key,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<A: PartialEq> Shortcuts<A> {
+    /// Returns the first chord that is bound to `action`.
+    pub fn chord(&self, action: &A) -> Option<KeyChord> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| bound == action)
+            .map(|(chord, _)| *chord)
+    }
+
+    /// Returns the display string of the chord that is bound to `action`,
+    /// e.g. to show it next to a menu item.
+    pub fn display_string(&self, action: &A) -> Option<String> {
+        self.chord(action)
+            .map(|chord| chord.display_string(self.platform))
+    }
+}
+
+/// Names of keys that are not letters, digits or function keys.
+const KEY_NAMES: &[(Key, &str)] = &[
+    (Key::Escape, "Esc"),
+    (Key::Return, "Enter"),
+    (Key::Space, "Space"),
+    (Key::Tab, "Tab"),
+    (Key::Backspace, "Backspace"),
+    (Key::Delete, "Delete"),
+    (Key::Insert, "Insert"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::Up, "Up"),
+    (Key::Down, "Down"),
+    (Key::Left, "Left"),
+    (Key::Right, "Right"),
+    (Key::Comma, ","),
+    (Key::Period, "."),
+    (Key::Slash, "/"),
+    (Key::Backslash, "\\"),
+    (Key::Semicolon, ";"),
+    (Key::Apostrophe, "'"),
+    (Key::Grave, "`"),
+    (Key::Minus, "-"),
+    (Key::Equals, "="),
+    (Key::LBracket, "["),
+    (Key::RBracket, "]"),
+];
+
+const LETTERS: [Key; 26] = [
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+];
+
+const DIGITS: [Key; 10] = [
+    Key::Key0,
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+];
+
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
+fn key_name(key: Key) -> String {
+    if let Some(i) = LETTERS.iter().position(|k| *k == key) {
+        ((b'A' + i as u8) as char).to_string()
+    } else if let Some(i) = DIGITS.iter().position(|k| *k == key) {
+        i.to_string()
+    } else if let Some(i) = FUNCTION_KEYS.iter().position(|k| *k == key) {
+        format!("F{}", i + 1)
+    } else if let Some((_, name)) = KEY_NAMES.iter().find(|(k, _)| *k == key) {
+        name.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+fn key_from_name(name: &str) -> Option<Key> {
+    let upper = name.to_uppercase();
+    let mut chars = upper.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c @ 'A'..='Z'), None) => return Some(LETTERS[(c as u8 - b'A') as usize]),
+        (Some(c @ '0'..='9'), None) => return Some(DIGITS[(c as u8 - b'0') as usize]),
+        (Some('F'), Some(_)) => {
+            if let Ok(i) = upper[1..].parse::<usize>() {
+                return FUNCTION_KEYS.get(i.wrapping_sub(1)).copied();
+            }
+        }
+        _ => (),
+    }
+
+    match upper.as_str() {
+        "ESCAPE" => return Some(Key::Escape),
+        "RETURN" => return Some(Key::Return),
+        _ => (),
+    }
+
+    KEY_NAMES
+        .iter()
+        .find(|(_, key_name)| key_name.to_uppercase() == upper)
+        .map(|(key, _)| *key)
+}
@@ -0,0 +1,375 @@
+//! Keyboard and gamepad navigation between widgets.
+//!
+//! `FocusNav` keeps track of which of a set of rects has the focus, and moves
+//! the focus to the nearest rect in a direction. Inputs from the keyboard
+//! are mapped with `NavInput::from_event`, and inputs from gamepads are read
+//! from the `InputState` with `GamepadNav`, so that menus can be used
+//! without a mouse.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{ui::UiPainter, AaRect, Event, GamepadMapping, InputState, Key};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NavDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl NavDirection {
+    /// Returns the direction as a unit vector in screen coordinates, where
+    /// Y points down.
+    pub fn to_vector(self) -> Vector2<f32> {
+        match self {
+            NavDirection::Up => Vector2::new(0.0, -1.0),
+            NavDirection::Down => Vector2::new(0.0, 1.0),
+            NavDirection::Left => Vector2::new(-1.0, 0.0),
+            NavDirection::Right => Vector2::new(1.0, 0.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NavInput {
+    /// Move the focus in a direction.
+    Move(NavDirection),
+
+    /// Activate the focused widget, like clicking it.
+    Activate,
+
+    /// Leave the menu, e.g. to go back to the previous screen.
+    Cancel,
+}
+
+impl NavInput {
+    /// Maps the arrow keys to `Move`, Enter and Space to `Activate`, and
+    /// Escape to `Cancel`.
+    pub fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::KeyPressed(key) => match key {
+                Key::Up => Some(NavInput::Move(NavDirection::Up)),
+                Key::Down => Some(NavInput::Move(NavDirection::Down)),
+                Key::Left => Some(NavInput::Move(NavDirection::Left)),
+                Key::Right => Some(NavInput::Move(NavDirection::Right)),
+                Key::Return | Key::Space => Some(NavInput::Activate),
+                Key::Escape => Some(NavInput::Cancel),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Tracks the focus among a set of rects, such as the buttons of a menu.
+///
+/// Rects are identified by the index in which they have been added. The
+/// application decides what activating a rect means, so this works with
+/// any kind of widget.
+#[derive(Debug, Clone, Default)]
+pub struct FocusNav {
+    rects: Vec<AaRect>,
+    focused: Option<usize>,
+}
+
+impl FocusNav {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a focusable rect and returns its index.
+    pub fn add(&mut self, rect: AaRect) -> usize {
+        self.rects.push(rect);
+        self.rects.len() - 1
+    }
+
+    /// Moves the rect at `index`, e.g. after the layout has changed.
+    pub fn set_rect(&mut self, index: usize, rect: AaRect) {
+        self.rects[index] = rect;
+    }
+
+    pub fn rect(&self, index: usize) -> AaRect {
+        self.rects[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    /// Removes all rects and the focus.
+    pub fn clear(&mut self) {
+        self.rects.clear();
+        self.focused = None;
+    }
+
+    pub fn focused(&self) -> Option<usize> {
+        self.focused
+    }
+
+    pub fn is_focused(&self, index: usize) -> bool {
+        self.focused == Some(index)
+    }
+
+    pub fn set_focused(&mut self, focused: Option<usize>) {
+        assert!(focused.map_or(true, |index| index < self.rects.len()));
+        self.focused = focused;
+    }
+
+    /// Focuses the rect that contains `pos`, if any, so that the mouse and
+    /// the keyboard can be used together. Returns true if the focus
+    /// changed.
+    pub fn focus_at(&mut self, pos: Point2<f32>) -> bool {
+        match self.rects.iter().position(|rect| rect.contains_point(pos)) {
+            Some(index) if self.focused != Some(index) => {
+                self.focused = Some(index);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Moves the focus to the nearest rect in `direction`. If nothing is
+    /// focused yet, the first rect is focused. Returns true if the focus
+    /// changed.
+    ///
+    /// Rects that are close to the line through the focused rect in
+    /// `direction` are preferred over rects that are closer but further to
+    /// the side.
+    pub fn navigate(&mut self, direction: NavDirection) -> bool {
+        let focused = match self.focused {
+            Some(focused) => focused,
+            None => {
+                self.focused = if self.rects.is_empty() { None } else { Some(0) };
+                return self.focused.is_some();
+            }
+        };
+
+        let dir = direction.to_vector();
+        let from = self.rects[focused].center;
+
+        let best = self
+            .rects
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != focused)
+            .filter_map(|(index, rect)| {
+                let delta = rect.center - from;
+                let along = delta.dot(&dir);
+                let across = (delta - dir * along).norm();
+
+                if along > 0.0 {
+                    Some((index, along + 2.0 * across))
+                } else {
+                    None
+                }
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((index, _)) = best {
+            self.focused = Some(index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Handles a navigation input. Returns the index of the focused rect if
+    /// it was activated.
+    pub fn handle_input(&mut self, input: NavInput) -> Option<usize> {
+        match input {
+            NavInput::Move(direction) => {
+                self.navigate(direction);
+                None
+            }
+            NavInput::Activate => self.focused,
+            NavInput::Cancel => None,
+        }
+    }
+
+    /// Pushes an outline around the focused rect.
+    pub fn push_highlight(&self, painter: &mut UiPainter) {
+        if let Some(focused) = self.focused {
+            let rect = self.rects[focused];
+            let width = painter.style.focus_width;
+            let color = painter.style.focus;
+
+            let (min, size) = (rect.min(), rect.size);
+            let edges = [
+                AaRect::from_top_left(min, Vector2::new(size.x, width)),
+                AaRect::from_top_left(
+                    min + Vector2::new(0.0, size.y - width),
+                    Vector2::new(size.x, width),
+                ),
+                AaRect::from_top_left(min, Vector2::new(width, size.y)),
+                AaRect::from_top_left(
+                    min + Vector2::new(size.x - width, 0.0),
+                    Vector2::new(width, size.y),
+                ),
+            ];
+            for edge in &edges {
+                painter.fill_rect(edge, color);
+            }
+        }
+    }
+}
+
+/// Buttons of the W3C standard gamepad layout.
+const BUTTON_A: usize = 0;
+const BUTTON_B: usize = 1;
+const DPAD_UP: usize = 12;
+const DPAD_DOWN: usize = 13;
+const DPAD_LEFT: usize = 14;
+const DPAD_RIGHT: usize = 15;
+
+/// Reads navigation inputs from the gamepads of an `InputState`, using the
+/// d-pad and the left stick for moving, the bottom face button for
+/// activating and the right face button for cancelling.
+///
+/// The left stick is read with the dead zone of the `InputState`, and only
+/// moves along the axis in which it is pushed further.
+///
+/// Holding a direction repeats it, like holding an arrow key.
+#[derive(Debug, Clone)]
+pub struct GamepadNav {
+    /// How long a direction needs to be held before it starts repeating.
+    pub repeat_delay: Duration,
+
+    /// The time between repeats.
+    pub repeat_interval: Duration,
+
+    held: BTreeMap<(u32, NavKey), Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NavKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    Activate,
+    Cancel,
+}
+
+impl NavKey {
+    fn to_input(self) -> NavInput {
+        match self {
+            NavKey::Up => NavInput::Move(NavDirection::Up),
+            NavKey::Down => NavInput::Move(NavDirection::Down),
+            NavKey::Left => NavInput::Move(NavDirection::Left),
+            NavKey::Right => NavInput::Move(NavDirection::Right),
+            NavKey::Activate => NavInput::Activate,
+            NavKey::Cancel => NavInput::Cancel,
+        }
+    }
+}
+
+impl Default for GamepadNav {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GamepadNav {
+    pub fn new() -> Self {
+        Self {
+            repeat_delay: Duration::from_millis(400),
+            repeat_interval: Duration::from_millis(120),
+            held: BTreeMap::new(),
+        }
+    }
+
+    /// Reads all gamepads with the standard mapping and returns the inputs
+    /// that happened since the last call, which should be one frame of `dt`
+    /// ago.
+    pub fn poll(&mut self, input: &InputState, dt: Duration) -> Vec<NavInput> {
+        let mut pressed = Vec::new();
+        for gamepad in input.gamepads() {
+            if gamepad.mapping != GamepadMapping::Standard {
+                continue;
+            }
+
+            for key in pressed_keys(input, gamepad.index) {
+                pressed.push((gamepad.index, key));
+            }
+        }
+
+        self.held.retain(|held, _| pressed.contains(held));
+
+        let mut inputs = Vec::new();
+        for held in pressed {
+            match self.held.get_mut(&held) {
+                None => {
+                    inputs.push(held.1.to_input());
+                    self.held.insert(held, Duration::from_secs(0));
+                }
+                Some(time) => {
+                    let old_time = *time;
+                    *time += dt;
+
+                    // Only directions repeat.
+                    if let NavInput::Move(_) = held.1.to_input() {
+                        if repeats_between(old_time, *time, self.repeat_delay, self.repeat_interval)
+                        {
+                            inputs.push(held.1.to_input());
+                        }
+                    }
+                }
+            }
+        }
+
+        inputs
+    }
+}
+
+/// Returns true if a repeat happens in the interval `(from, to]` of holding
+/// a key.
+fn repeats_between(from: Duration, to: Duration, delay: Duration, interval: Duration) -> bool {
+    let count = |time: Duration| {
+        if time < delay {
+            0
+        } else {
+            1 + ((time - delay).as_secs_f64() / interval.as_secs_f64().max(0.001)) as u64
+        }
+    };
+
+    count(to) > count(from)
+}
+
+fn pressed_keys(input: &InputState, index: u32) -> Vec<NavKey> {
+    let button = |button: usize| input.gamepad_button(index, button);
+    let stick = input.gamepad_stick(index, 0);
+    let (horizontal, vertical) = if stick.x.abs() > stick.y.abs() {
+        (stick.x, 0.0)
+    } else {
+        (0.0, stick.y)
+    };
+
+    let mut keys = Vec::new();
+    if button(DPAD_UP) || vertical < 0.0 {
+        keys.push(NavKey::Up);
+    }
+    if button(DPAD_DOWN) || vertical > 0.0 {
+        keys.push(NavKey::Down);
+    }
+    if button(DPAD_LEFT) || horizontal < 0.0 {
+        keys.push(NavKey::Left);
+    }
+    if button(DPAD_RIGHT) || horizontal > 0.0 {
+        keys.push(NavKey::Right);
+    }
+    if button(BUTTON_A) {
+        keys.push(NavKey::Activate);
+    }
+    if button(BUTTON_B) {
+        keys.push(NavKey::Cancel);
+    }
+
+    keys
+}
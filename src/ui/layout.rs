@@ -0,0 +1,193 @@
+//! Positioning rects relative to a parent rect.
+//!
+//! Placements are given relative to a parent, which usually is the screen as
+//! given by `ScreenGeom::logical_rect`. Recomputing the placement whenever
+//! the parent changes keeps e.g. a HUD in place when the window is resized.
+
+use nalgebra::{Point2, Vector2};
+
+use crate::AaRect;
+
+/// A point of reference on a rect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Returns the anchor position relative to a rect, where `(0, 0)` is the
+    /// top left and `(1, 1)` the bottom right corner.
+    pub fn factors(self) -> Vector2<f32> {
+        match self {
+            Anchor::TopLeft => Vector2::new(0.0, 0.0),
+            Anchor::Top => Vector2::new(0.5, 0.0),
+            Anchor::TopRight => Vector2::new(1.0, 0.0),
+            Anchor::Left => Vector2::new(0.0, 0.5),
+            Anchor::Center => Vector2::new(0.5, 0.5),
+            Anchor::Right => Vector2::new(1.0, 0.5),
+            Anchor::BottomLeft => Vector2::new(0.0, 1.0),
+            Anchor::Bottom => Vector2::new(0.5, 1.0),
+            Anchor::BottomRight => Vector2::new(1.0, 1.0),
+        }
+    }
+
+    /// Returns the position of this anchor on `rect`.
+    pub fn point(self, rect: &AaRect) -> Point2<f32> {
+        rect.min() + rect.size.component_mul(&self.factors())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Margins {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Margins {
+    pub fn uniform(margin: f32) -> Self {
+        Self {
+            left: margin,
+            top: margin,
+            right: margin,
+            bottom: margin,
+        }
+    }
+
+    /// Shrinks `rect` by the margins.
+    pub fn shrink(&self, rect: &AaRect) -> AaRect {
+        let min = rect.min() + Vector2::new(self.left, self.top);
+        let max = rect.max() - Vector2::new(self.right, self.bottom);
+
+        AaRect::from_min_max(min, Point2::new(max.x.max(min.x), max.y.max(min.y)))
+    }
+}
+
+/// Describes where to place a rect inside of its parent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Placement {
+    /// A rect of fixed size, whose `anchor` point is placed at the same
+    /// anchor point of the parent, shifted by `offset`.
+    ///
+    /// For example, `Anchor::BottomRight` with an offset of `(-10, -10)`
+    /// places the rect in the bottom right corner with a distance of ten
+    /// units to the parent's edges.
+    Anchored {
+        anchor: Anchor,
+        offset: Vector2<f32>,
+        size: Vector2<f32>,
+    },
+
+    /// A rect that fills the parent, except for the margins.
+    Stretch { margins: Margins },
+
+    /// A rect whose size is a fraction of the parent's size, anchored like
+    /// in `Placement::Anchored`.
+    Relative {
+        anchor: Anchor,
+        offset: Vector2<f32>,
+        fraction: Vector2<f32>,
+    },
+}
+
+impl Placement {
+    pub fn anchored(anchor: Anchor, offset: Vector2<f32>, size: Vector2<f32>) -> Self {
+        Placement::Anchored {
+            anchor,
+            offset,
+            size,
+        }
+    }
+
+    pub fn stretch(margins: Margins) -> Self {
+        Placement::Stretch { margins }
+    }
+
+    /// Computes the placed rect inside of `parent`.
+    pub fn place(&self, parent: &AaRect) -> AaRect {
+        match *self {
+            Placement::Anchored {
+                anchor,
+                offset,
+                size,
+            } => anchored_rect(parent, anchor, offset, size),
+            Placement::Stretch { margins } => margins.shrink(parent),
+            Placement::Relative {
+                anchor,
+                offset,
+                fraction,
+            } => anchored_rect(parent, anchor, offset, parent.size.component_mul(&fraction)),
+        }
+    }
+}
+
+fn anchored_rect(
+    parent: &AaRect,
+    anchor: Anchor,
+    offset: Vector2<f32>,
+    size: Vector2<f32>,
+) -> AaRect {
+    let anchor_point = anchor.point(parent) + offset;
+    let top_left = anchor_point - size.component_mul(&anchor.factors());
+
+    AaRect::from_top_left(top_left, size)
+}
+
+/// A placement together with its most recently computed rect.
+///
+/// Call `update` once per frame (or when handling a resize) with the current
+/// parent rect; the rect is only recomputed if the parent changed.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    placement: Placement,
+    parent: Option<AaRect>,
+    rect: AaRect,
+}
+
+impl Layout {
+    pub fn new(placement: Placement) -> Self {
+        Self {
+            placement,
+            parent: None,
+            rect: AaRect::from_top_left(Point2::origin(), Vector2::zeros()),
+        }
+    }
+
+    pub fn placement(&self) -> &Placement {
+        &self.placement
+    }
+
+    pub fn set_placement(&mut self, placement: Placement) {
+        self.placement = placement;
+        self.parent = None;
+    }
+
+    /// Updates the rect for the given parent. Returns true if the rect was
+    /// recomputed.
+    pub fn update(&mut self, parent: &AaRect) -> bool {
+        let changed = self.parent.map_or(true, |old| {
+            old.center != parent.center || old.size != parent.size
+        });
+
+        if changed {
+            self.rect = self.placement.place(parent);
+            self.parent = Some(*parent);
+        }
+
+        changed
+    }
+
+    /// Returns the rect as of the last call to `update`.
+    pub fn rect(&self) -> &AaRect {
+        &self.rect
+    }
+}
@@ -0,0 +1,15 @@
+//! Building blocks for simple user interfaces.
+
+mod focus;
+mod layout;
+mod scroll_area;
+mod text_edit;
+mod touch_controls;
+mod widgets;
+
+pub use focus::{FocusNav, GamepadNav, NavDirection, NavInput};
+pub use layout::{Anchor, Layout, Margins, Placement};
+pub use scroll_area::ScrollArea;
+pub use text_edit::TextEdit;
+pub use touch_controls::{JoystickKeys, TouchControls, VirtualButton, VirtualJoystick};
+pub use widgets::{Button, Checkbox, Label, Slider, Style, UiPainter, WidgetState};
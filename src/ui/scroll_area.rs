@@ -0,0 +1,134 @@
+//! A rect that shows a scrollable, clipped part of larger content.
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{draw::ScissorScope, AaRect, Canvas, Error, Event, InputState, MouseButton};
+
+/// Displays content that is larger than the area's rect, such as an
+/// inventory or a chat log.
+///
+/// The content is scrolled by the mouse wheel or by dragging. Lay out the
+/// content relative to `content_origin`, tell the area how large the content
+/// is with `set_content_size` or `measure`, and draw the content inside of
+/// `clip`, so that nothing outside of the rect is visible.
+#[derive(Debug, Clone)]
+pub struct ScrollArea {
+    pub rect: AaRect,
+
+    /// Whether the content can be dragged with the left mouse button.
+    pub drag_to_scroll: bool,
+
+    content_size: Vector2<f32>,
+    offset: Vector2<f32>,
+    drag_pos: Option<Point2<f32>>,
+}
+
+impl ScrollArea {
+    pub fn new(rect: AaRect) -> Self {
+        Self {
+            rect,
+            drag_to_scroll: true,
+            content_size: Vector2::zeros(),
+            offset: Vector2::zeros(),
+            drag_pos: None,
+        }
+    }
+
+    pub fn content_size(&self) -> Vector2<f32> {
+        self.content_size
+    }
+
+    pub fn set_content_size(&mut self, content_size: Vector2<f32>) {
+        self.content_size = content_size;
+        self.clamp_offset();
+    }
+
+    /// Sets the content size to contain all of the given rects, which are
+    /// relative to `content_origin`.
+    pub fn measure<'a>(&mut self, rects: impl IntoIterator<Item = &'a AaRect>) {
+        let origin = self.content_origin();
+        let size = rects.into_iter().fold(Vector2::zeros(), |size, rect| {
+            let max = rect.max() - origin;
+            Vector2::new(size.x.max(max.x), size.y.max(max.y))
+        });
+
+        self.set_content_size(size);
+    }
+
+    /// Returns the scroll offset, i.e. the position of the top left of the
+    /// rect within the content.
+    pub fn offset(&self) -> Vector2<f32> {
+        self.offset
+    }
+
+    pub fn set_offset(&mut self, offset: Vector2<f32>) {
+        self.offset = offset;
+        self.clamp_offset();
+    }
+
+    /// Scrolls so that the bottom of the content is visible, e.g. for chat
+    /// logs.
+    pub fn scroll_to_bottom(&mut self) {
+        self.set_offset(Vector2::new(self.offset.x, std::f32::INFINITY));
+    }
+
+    /// Returns the position at which the top left of the content is to be
+    /// drawn.
+    pub fn content_origin(&self) -> Point2<f32> {
+        self.rect.min() - self.offset
+    }
+
+    pub fn max_offset(&self) -> Vector2<f32> {
+        let max = self.content_size - self.rect.size;
+        Vector2::new(max.x.max(0.0), max.y.max(0.0))
+    }
+
+    /// Handles an event. Returns true if the area was scrolled.
+    pub fn handle_event(&mut self, event: &Event, input: &InputState) -> bool {
+        let old_offset = self.offset;
+
+        match event {
+            Event::MouseWheel(delta) if self.rect.contains_point(input.mouse_pos()) => {
+                self.offset += delta;
+            }
+            Event::MouseButtonPressed(MouseButton::Left)
+                if self.drag_to_scroll && self.rect.contains_point(input.mouse_pos()) =>
+            {
+                self.drag_pos = Some(input.mouse_pos());
+            }
+            Event::MouseMoved(pos) => {
+                if let Some(drag_pos) = self.drag_pos {
+                    self.offset -= pos - drag_pos;
+                    self.drag_pos = Some(*pos);
+                }
+            }
+            Event::MouseButtonReleased(MouseButton::Left) | Event::Unfocused => {
+                self.drag_pos = None;
+            }
+            _ => (),
+        }
+
+        self.clamp_offset();
+
+        self.offset != old_offset
+    }
+
+    /// Calls `f` with drawing restricted to the area's rect.
+    pub fn clip<R>(
+        &self,
+        canvas: &mut Canvas,
+        f: impl FnOnce(&mut Canvas) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let mut scope = ScissorScope::new(canvas, &self.rect);
+
+        f(&mut scope)
+    }
+
+    fn clamp_offset(&mut self) {
+        let max = self.max_offset();
+        self.offset = Vector2::new(
+            self.offset.x.max(0.0).min(max.x),
+            self.offset.y.max(0.0).min(max.y),
+        );
+    }
+}
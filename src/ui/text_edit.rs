@@ -0,0 +1,318 @@
+//! A single-line text field.
+
+use std::ops::Range;
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    draw::Font,
+    ui::{Anchor, Margins, Style, UiPainter},
+    AaRect, Color4, Event, InputState, Key, MouseButton,
+};
+
+/// A single-line text field with a caret, mouse and keyboard selection, and
+/// clipboard support.
+///
+/// Feed every event to `handle_event`. To support copying, call
+/// `Canvas::set_copy_text(edit.selected_text())` once per frame.
+///
+/// Note that IME composition is not supported yet, since the canvas is not an
+/// editable element and thus does not receive composition events.
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub rect: AaRect,
+
+    /// The maximal number of characters, if any.
+    pub max_len: Option<usize>,
+
+    text: String,
+
+    /// Caret position as a character index.
+    caret: usize,
+
+    /// The other end of the selection, if any, as a character index.
+    selection_anchor: Option<usize>,
+
+    focused: bool,
+    dragging: bool,
+}
+
+impl TextEdit {
+    pub fn new(rect: AaRect, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let caret = text.chars().count();
+
+        Self {
+            rect,
+            max_len: None,
+            text,
+            caret,
+            selection_anchor: None,
+            focused: false,
+            dragging: false,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: impl Into<String>) {
+        self.text = text.into();
+        self.caret = self.len();
+        self.selection_anchor = None;
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+        if !focused {
+            self.selection_anchor = None;
+            self.dragging = false;
+        }
+    }
+
+    /// Returns the caret position as a character index.
+    pub fn caret(&self) -> usize {
+        self.caret
+    }
+
+    /// Returns the selected range of character indices, if it is not empty.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        self.selection_anchor.and_then(|anchor| {
+            if anchor == self.caret {
+                None
+            } else {
+                Some(anchor.min(self.caret)..anchor.max(self.caret))
+            }
+        })
+    }
+
+    pub fn selected_text(&self) -> Option<String> {
+        if !self.focused {
+            return None;
+        }
+
+        self.selection().map(|range| {
+            self.text[self.byte_index(range.start)..self.byte_index(range.end)].to_string()
+        })
+    }
+
+    pub fn select_all(&mut self) {
+        self.selection_anchor = Some(0);
+        self.caret = self.len();
+    }
+
+    /// Handles an event. Returns true if the text changed.
+    pub fn handle_event(
+        &mut self,
+        event: &Event,
+        input: &InputState,
+        font: &Font,
+        style: &Style,
+    ) -> bool {
+        match event {
+            Event::MouseButtonPressed(MouseButton::Left) => {
+                let pos = input.mouse_pos();
+                if self.rect.contains_point(pos) {
+                    let caret = self.hit_test(pos, font, style);
+                    let shift = input.key(Key::LShift) || input.key(Key::RShift);
+
+                    if !(shift && self.focused) {
+                        self.selection_anchor = Some(caret);
+                    }
+                    self.caret = caret;
+                    self.focused = true;
+                    self.dragging = true;
+                } else {
+                    self.set_focused(false);
+                }
+
+                false
+            }
+            Event::MouseMoved(pos) if self.dragging => {
+                self.caret = self.hit_test(*pos, font, style);
+                false
+            }
+            Event::MouseButtonReleased(MouseButton::Left) => {
+                self.dragging = false;
+                false
+            }
+            Event::Unfocused => {
+                self.set_focused(false);
+                false
+            }
+            _ if !self.focused => false,
+            Event::KeyPressed(key) => self.handle_key(*key, input),
+            Event::Text(text) => {
+                let text: String = text.chars().filter(|c| !c.is_control()).collect();
+                self.insert(&text)
+            }
+            Event::Paste(text) => {
+                let line = text.lines().next().unwrap_or("");
+                self.insert(line)
+            }
+            Event::Cut => self.delete_selection(),
+            _ => false,
+        }
+    }
+
+    pub fn push(&self, painter: &mut UiPainter) {
+        let background = if self.focused {
+            painter.style.hovered
+        } else {
+            painter.style.background
+        };
+        painter.fill_rect(&self.rect, background);
+
+        let inner = Margins::uniform(painter.style.padding).shrink(&self.rect);
+        let offsets = painter
+            .font
+            .caret_offsets(painter.style.font_size, &self.text);
+
+        if let (true, Some(selection)) = (self.focused, self.selection()) {
+            let accent = painter.style.accent;
+            let selection_rect = AaRect::from_min_max(
+                Point2::new(inner.min().x + offsets[selection.start], inner.min().y),
+                Point2::new(inner.min().x + offsets[selection.end], inner.max().y),
+            );
+            painter.fill_rect(
+                &selection_rect,
+                Color4::new(accent.r, accent.g, accent.b, 0.5 * accent.a),
+            );
+        }
+
+        let text_color = painter.style.text_color;
+        painter.text(&self.rect, Anchor::Left, &self.text, text_color);
+
+        if self.focused {
+            let caret_rect = AaRect::from_top_left(
+                Point2::new(inner.min().x + offsets[self.caret], inner.min().y),
+                Vector2::new(1.5, inner.size.y),
+            );
+            painter.fill_rect(&caret_rect, text_color);
+        }
+    }
+
+    fn handle_key(&mut self, key: Key, input: &InputState) -> bool {
+        let shift = input.key(Key::LShift) || input.key(Key::RShift);
+        let ctrl = input.key(Key::LControl)
+            || input.key(Key::RControl)
+            || input.key(Key::LWin)
+            || input.key(Key::RWin);
+
+        match key {
+            Key::Left => {
+                let target = match (shift, self.selection()) {
+                    (false, Some(selection)) => selection.start,
+                    _ => self.caret.saturating_sub(1),
+                };
+                self.move_caret(target, shift);
+                false
+            }
+            Key::Right => {
+                let target = match (shift, self.selection()) {
+                    (false, Some(selection)) => selection.end,
+                    _ => (self.caret + 1).min(self.len()),
+                };
+                self.move_caret(target, shift);
+                false
+            }
+            Key::Home => {
+                self.move_caret(0, shift);
+                false
+            }
+            Key::End => {
+                self.move_caret(self.len(), shift);
+                false
+            }
+            Key::A if ctrl => {
+                self.select_all();
+                false
+            }
+            Key::Backspace => {
+                if self.selection().is_none() && self.caret > 0 {
+                    self.selection_anchor = Some(self.caret - 1);
+                }
+                self.delete_selection()
+            }
+            Key::Delete => {
+                if self.selection().is_none() && self.caret < self.len() {
+                    self.selection_anchor = Some(self.caret + 1);
+                }
+                self.delete_selection()
+            }
+            Key::Escape => {
+                self.set_focused(false);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn move_caret(&mut self, target: usize, extend_selection: bool) {
+        if extend_selection {
+            if self.selection_anchor.is_none() {
+                self.selection_anchor = Some(self.caret);
+            }
+        } else {
+            self.selection_anchor = None;
+        }
+
+        self.caret = target;
+    }
+
+    fn insert(&mut self, text: &str) -> bool {
+        let deleted = self.delete_selection();
+
+        let num_chars = match self.max_len {
+            Some(max_len) => text.chars().count().min(max_len.saturating_sub(self.len())),
+            None => text.chars().count(),
+        };
+        if num_chars == 0 {
+            return deleted;
+        }
+
+        let text: String = text.chars().take(num_chars).collect();
+        let index = self.byte_index(self.caret);
+        self.text.insert_str(index, &text);
+        self.caret += num_chars;
+
+        true
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.selection_anchor = None;
+
+        if let Some(selection) = selection {
+            let range = self.byte_index(selection.start)..self.byte_index(selection.end);
+            self.text.replace_range(range, "");
+            self.caret = selection.start;
+
+            true
+        } else {
+            false
+        }
+    }
+
+    fn hit_test(&self, pos: Point2<f32>, font: &Font, style: &Style) -> usize {
+        let x = pos.x - (self.rect.min().x + style.padding);
+
+        font.hit_test(style.font_size, &self.text, x)
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn byte_index(&self, char_index: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(char_index)
+            .map_or(self.text.len(), |(index, _)| index)
+    }
+}
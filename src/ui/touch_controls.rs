@@ -0,0 +1,343 @@
+//! Virtual joysticks and buttons for playing keyboard games on touch screens.
+//!
+//! The controls are fed with pointer events and emulate the keyboard by
+//! pushing `KeyPressed` and `KeyReleased` events to the `Canvas`. These are
+//! handled like real key presses once they are popped, so the `InputState`
+//! and the game's own event handling work unchanged.
+
+use nalgebra::{Point2, Vector2};
+
+use crate::{
+    ui::{Anchor, Placement, UiPainter},
+    AaRect, Canvas, Color4, Event, Key, Pointer, PointerKind,
+};
+
+/// The keys that a `VirtualJoystick` presses for its four directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoystickKeys {
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+}
+
+impl JoystickKeys {
+    pub fn arrows() -> Self {
+        Self {
+            up: Key::Up,
+            down: Key::Down,
+            left: Key::Left,
+            right: Key::Right,
+        }
+    }
+
+    pub fn wasd() -> Self {
+        Self {
+            up: Key::W,
+            down: Key::S,
+            left: Key::A,
+            right: Key::D,
+        }
+    }
+}
+
+/// A circular stick that presses the keys of the directions in which it is
+/// pushed, including two keys at once for diagonals.
+#[derive(Debug, Clone)]
+pub struct VirtualJoystick {
+    /// The placement of the joystick within the screen. The stick is a
+    /// circle inscribed in the placed rect.
+    pub placement: Placement,
+    pub keys: JoystickKeys,
+
+    /// How far the stick needs to be pushed in a direction to press its key,
+    /// as a fraction of the radius.
+    pub dead_zone: f32,
+
+    pointer: Option<i32>,
+    offset: Vector2<f32>,
+    pressed: Vec<Key>,
+}
+
+impl VirtualJoystick {
+    pub fn new(placement: Placement, keys: JoystickKeys) -> Self {
+        Self {
+            placement,
+            keys,
+            dead_zone: 0.35,
+            pointer: None,
+            offset: Vector2::zeros(),
+            pressed: Vec::new(),
+        }
+    }
+
+    /// Returns how far the stick is pushed, with a length of up to one.
+    pub fn offset(&self) -> Vector2<f32> {
+        self.offset
+    }
+
+    fn circle(&self, screen: &AaRect) -> (Point2<f32>, f32) {
+        let rect = self.placement.place(screen);
+
+        (rect.center, rect.size.x.min(rect.size.y) / 2.0)
+    }
+
+    fn update_keys(&mut self, canvas: &Canvas) {
+        let mut pressed = Vec::new();
+        if self.offset.y < -self.dead_zone {
+            pressed.push(self.keys.up);
+        }
+        if self.offset.y > self.dead_zone {
+            pressed.push(self.keys.down);
+        }
+        if self.offset.x < -self.dead_zone {
+            pressed.push(self.keys.left);
+        }
+        if self.offset.x > self.dead_zone {
+            pressed.push(self.keys.right);
+        }
+
+        emit_changes(canvas, &self.pressed, &pressed);
+        self.pressed = pressed;
+    }
+
+    fn handle_pointer(&mut self, canvas: &Canvas, screen: &AaRect, event: &Event) -> bool {
+        let (center, radius) = self.circle(screen);
+
+        match event {
+            Event::PointerPressed(pointer)
+                if self.pointer.is_none() && (pointer.pos - center).norm() <= radius =>
+            {
+                self.pointer = Some(pointer.id);
+            }
+            Event::PointerMoved(pointer) if self.pointer == Some(pointer.id) => (),
+            Event::PointerReleased(pointer) | Event::PointerCancelled(pointer)
+                if self.pointer == Some(pointer.id) =>
+            {
+                self.release(canvas);
+                return true;
+            }
+            _ => return false,
+        }
+
+        let pos = pointer_of(event).unwrap().pos;
+        let offset = (pos - center) / radius.max(1.0);
+        self.offset = if offset.norm() > 1.0 {
+            offset.normalize()
+        } else {
+            offset
+        };
+        self.update_keys(canvas);
+
+        true
+    }
+
+    fn release(&mut self, canvas: &Canvas) {
+        self.pointer = None;
+        self.offset = Vector2::zeros();
+        self.update_keys(canvas);
+    }
+
+    fn reset(&mut self) {
+        self.pointer = None;
+        self.offset = Vector2::zeros();
+        self.pressed.clear();
+    }
+
+    fn push(&self, screen: &AaRect, painter: &mut UiPainter) {
+        let (center, radius) = self.circle(screen);
+        let background = with_alpha(painter.style.background, 0.4);
+        let knob = with_alpha(
+            if self.pointer.is_some() {
+                painter.style.pressed
+            } else {
+                painter.style.hovered
+            },
+            0.7,
+        );
+
+        painter.fill_circle(center, radius, background);
+        painter.fill_circle(center + self.offset * radius * 0.6, radius * 0.4, knob);
+    }
+}
+
+/// A round button that presses a key while it is touched.
+#[derive(Debug, Clone)]
+pub struct VirtualButton {
+    /// The placement of the button within the screen. The button is a circle
+    /// inscribed in the placed rect.
+    pub placement: Placement,
+    pub key: Key,
+    pub label: String,
+
+    pointer: Option<i32>,
+}
+
+impl VirtualButton {
+    pub fn new(placement: Placement, key: Key, label: impl Into<String>) -> Self {
+        Self {
+            placement,
+            key,
+            label: label.into(),
+            pointer: None,
+        }
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pointer.is_some()
+    }
+
+    fn handle_pointer(&mut self, canvas: &Canvas, screen: &AaRect, event: &Event) -> bool {
+        let rect = self.placement.place(screen);
+        let radius = rect.size.x.min(rect.size.y) / 2.0;
+
+        match event {
+            Event::PointerPressed(pointer)
+                if self.pointer.is_none() && (pointer.pos - rect.center).norm() <= radius =>
+            {
+                self.pointer = Some(pointer.id);
+                canvas.push_event(Event::KeyPressed(self.key));
+                true
+            }
+            Event::PointerMoved(pointer) => self.pointer == Some(pointer.id),
+            Event::PointerReleased(pointer) | Event::PointerCancelled(pointer)
+                if self.pointer == Some(pointer.id) =>
+            {
+                self.pointer = None;
+                canvas.push_event(Event::KeyReleased(self.key));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn push(&self, screen: &AaRect, painter: &mut UiPainter) {
+        let rect = self.placement.place(screen);
+        let radius = rect.size.x.min(rect.size.y) / 2.0;
+        let color = with_alpha(
+            if self.is_pressed() {
+                painter.style.pressed
+            } else {
+                painter.style.background
+            },
+            0.6,
+        );
+
+        painter.fill_circle(rect.center, radius, color);
+
+        let text_color = painter.style.text_color;
+        painter.text(&rect, Anchor::Center, &self.label, text_color);
+    }
+}
+
+/// A set of virtual joysticks and buttons.
+///
+/// Pass all events to `handle_event` before handling them in the game, and
+/// push the controls into a `UiPainter` after the rest of the user
+/// interface. The controls only react to touch and pen input, and they stay
+/// hidden until the screen has been touched for the first time, so that
+/// they do not get in the way on desktop.
+#[derive(Debug, Clone, Default)]
+pub struct TouchControls {
+    pub joysticks: Vec<VirtualJoystick>,
+    pub buttons: Vec<VirtualButton>,
+
+    visible: bool,
+}
+
+impl TouchControls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_joystick(mut self, joystick: VirtualJoystick) -> Self {
+        self.joysticks.push(joystick);
+        self
+    }
+
+    pub fn with_button(mut self, button: VirtualButton) -> Self {
+        self.buttons.push(button);
+        self
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Shows or hides the controls. Hidden controls are shown again when the
+    /// screen is touched.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Handles pointer events, pushing key events to `canvas` for the keys
+    /// that are pressed or released by the controls. Returns true if the
+    /// event was consumed by a control, in which case the game should ignore
+    /// it.
+    pub fn handle_event(&mut self, canvas: &Canvas, event: &Event) -> bool {
+        if let Event::Unfocused = event {
+            // The `InputState` releases all keys by itself.
+            self.joysticks.iter_mut().for_each(VirtualJoystick::reset);
+            self.buttons
+                .iter_mut()
+                .for_each(|button| button.pointer = None);
+            return false;
+        }
+
+        match pointer_of(event) {
+            Some(pointer)
+                if pointer.kind == PointerKind::Touch || pointer.kind == PointerKind::Pen =>
+            {
+                self.visible = true;
+            }
+            _ => return false,
+        }
+
+        let screen = canvas.screen_geom().logical_rect();
+        self.joysticks
+            .iter_mut()
+            .any(|joystick| joystick.handle_pointer(canvas, &screen, event))
+            || self
+                .buttons
+                .iter_mut()
+                .any(|button| button.handle_pointer(canvas, &screen, event))
+    }
+
+    /// Pushes the controls, if they are visible, laid out within `screen`,
+    /// which should be the logical rect of the screen.
+    pub fn push(&self, screen: &AaRect, painter: &mut UiPainter) {
+        if !self.visible {
+            return;
+        }
+
+        for joystick in &self.joysticks {
+            joystick.push(screen, painter);
+        }
+        for button in &self.buttons {
+            button.push(screen, painter);
+        }
+    }
+}
+
+fn pointer_of(event: &Event) -> Option<&Pointer> {
+    match event {
+        Event::PointerPressed(pointer)
+        | Event::PointerMoved(pointer)
+        | Event::PointerReleased(pointer)
+        | Event::PointerCancelled(pointer) => Some(pointer),
+        _ => None,
+    }
+}
+
+fn emit_changes(canvas: &Canvas, old: &[Key], new: &[Key]) {
+    for key in old.iter().filter(|key| !new.contains(key)) {
+        canvas.push_event(Event::KeyReleased(*key));
+    }
+    for key in new.iter().filter(|key| !old.contains(key)) {
+        canvas.push_event(Event::KeyPressed(*key));
+    }
+}
+
+fn with_alpha(color: Color4, alpha: f32) -> Color4 {
+    Color4::new(color.r, color.g, color.b, color.a * alpha)
+}
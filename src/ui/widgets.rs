@@ -0,0 +1,349 @@
+//! A minimal set of retained widgets for menus and settings screens.
+//!
+//! Widgets keep their own state and are updated once per frame from the
+//! `InputState`, after events have been popped from the `Canvas`. They are
+//! rendered into a `DrawList` through a `UiPainter`, so they can be drawn
+//! together with the rest of the user interface.
+
+use nalgebra::{Point2, Point3, Vector2};
+
+use crate::{
+    draw::{DrawList, Font, TextureRegion},
+    ui::{Anchor, Margins, Placement},
+    AaRect, Color4, InputState, MouseButton, YAxis,
+};
+
+#[derive(Debug, Clone)]
+pub struct Style {
+    pub font_size: f32,
+    pub padding: f32,
+    pub text_color: Color4,
+    pub background: Color4,
+    pub hovered: Color4,
+    pub pressed: Color4,
+
+    /// Color of checkbox ticks and of the filled part of sliders.
+    pub accent: Color4,
+
+    /// Color and width of the outline around the widget that has the
+    /// keyboard or gamepad focus.
+    pub focus: Color4,
+    pub focus_width: f32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            font_size: 20.0,
+            padding: 6.0,
+            text_color: Color4::new(1.0, 1.0, 1.0, 1.0),
+            background: Color4::new(0.2, 0.2, 0.25, 1.0),
+            hovered: Color4::new(0.3, 0.3, 0.38, 1.0),
+            pressed: Color4::new(0.15, 0.15, 0.2, 1.0),
+            accent: Color4::new(0.3, 0.6, 0.9, 1.0),
+            focus: Color4::new(1.0, 0.8, 0.3, 1.0),
+            focus_width: 2.0,
+        }
+    }
+}
+
+/// Collects the geometry of widgets into a draw list.
+///
+/// After pushing all widgets, draw the list using the screen's orthographic
+/// projection. Since the list keeps the order of shapes and text, widgets
+/// may overlap each other. The list needs to use the atlas of `font`.
+pub struct UiPainter<'a> {
+    pub list: &'a mut DrawList,
+    pub font: &'a mut Font,
+    pub style: &'a Style,
+    pub z: f32,
+}
+
+impl<'a> UiPainter<'a> {
+    pub fn fill_rect(&mut self, rect: &AaRect, color: Color4) {
+        self.list.shapes().push_quad(&(*rect).into(), self.z, color);
+    }
+
+    pub fn fill_circle(&mut self, center: Point2<f32>, radius: f32, color: Color4) {
+        self.list
+            .shapes()
+            .push_circle(center, radius, 32, self.z, color);
+    }
+
+    /// Draws `region` stretched over `rect`, with its colors multiplied by
+    /// `color`.
+    pub fn image(&mut self, rect: &AaRect, region: &TextureRegion, color: Color4) {
+        let batch = self.list.textured(&region.texture);
+        let uv_rect = match batch.y_axis() {
+            YAxis::Down => region.uv_rect,
+            YAxis::Up => region.uv_rect.flip_y(),
+        };
+
+        batch.push_quad(&(*rect).into(), self.z, uv_rect, color);
+    }
+
+    /// Writes `text` inside of `rect`, aligned at the given anchor and
+    /// respecting the style's padding.
+    pub fn text(&mut self, rect: &AaRect, anchor: Anchor, text: &str, color: Color4) {
+        let size = self.font.text_size(self.style.font_size, text);
+        let inner = Margins::uniform(self.style.padding).shrink(rect);
+        let text_rect = Placement::anchored(anchor, Vector2::zeros(), size).place(&inner);
+        let top_left = text_rect.min();
+
+        self.font.write(
+            self.style.font_size,
+            Point3::new(top_left.x, top_left.y, self.z),
+            color,
+            text,
+            self.list.text(),
+        );
+    }
+
+    fn state_color(&self, state: WidgetState) -> Color4 {
+        match state {
+            WidgetState::Idle => self.style.background,
+            WidgetState::Hovered => self.style.hovered,
+            WidgetState::Pressed => self.style.pressed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WidgetState {
+    Idle,
+    Hovered,
+    Pressed,
+}
+
+impl Default for WidgetState {
+    fn default() -> Self {
+        WidgetState::Idle
+    }
+}
+
+/// Tracks the left mouse button across frames for one widget.
+#[derive(Debug, Clone, Default)]
+struct Interaction {
+    was_down: bool,
+    pressed_inside: bool,
+}
+
+impl Interaction {
+    /// Returns the widget's new state, and whether it was clicked.
+    fn update(&mut self, rect: &AaRect, input: &InputState) -> (WidgetState, bool) {
+        let down = input.mouse_button(MouseButton::Left);
+        let hovered = rect.contains_point(input.mouse_pos());
+
+        if down && !self.was_down && hovered {
+            self.pressed_inside = true;
+        }
+
+        let clicked = !down && self.was_down && self.pressed_inside && hovered;
+        if !down {
+            self.pressed_inside = false;
+        }
+        self.was_down = down;
+
+        let state = if self.pressed_inside {
+            WidgetState::Pressed
+        } else if hovered {
+            WidgetState::Hovered
+        } else {
+            WidgetState::Idle
+        };
+
+        (state, clicked)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub rect: AaRect,
+    pub text: String,
+    pub anchor: Anchor,
+}
+
+impl Label {
+    pub fn new(rect: AaRect, text: impl Into<String>) -> Self {
+        Self {
+            rect,
+            text: text.into(),
+            anchor: Anchor::Left,
+        }
+    }
+
+    pub fn push(&self, painter: &mut UiPainter) {
+        let color = painter.style.text_color;
+        painter.text(&self.rect, self.anchor, &self.text, color);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Button {
+    pub rect: AaRect,
+    pub label: String,
+    state: WidgetState,
+    interaction: Interaction,
+}
+
+impl Button {
+    pub fn new(rect: AaRect, label: impl Into<String>) -> Self {
+        Self {
+            rect,
+            label: label.into(),
+            state: WidgetState::Idle,
+            interaction: Interaction::default(),
+        }
+    }
+
+    pub fn state(&self) -> WidgetState {
+        self.state
+    }
+
+    /// Updates the button's state. Returns true if the button was clicked,
+    /// i.e. the mouse button was pressed and released on top of it.
+    pub fn update(&mut self, input: &InputState) -> bool {
+        let (state, clicked) = self.interaction.update(&self.rect, input);
+        self.state = state;
+
+        clicked
+    }
+
+    pub fn push(&self, painter: &mut UiPainter) {
+        let color = painter.state_color(self.state);
+        painter.fill_rect(&self.rect, color);
+
+        let text_color = painter.style.text_color;
+        painter.text(&self.rect, Anchor::Center, &self.label, text_color);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Checkbox {
+    /// The rect containing both the box and the label.
+    pub rect: AaRect,
+    pub label: String,
+    pub checked: bool,
+    state: WidgetState,
+    interaction: Interaction,
+}
+
+impl Checkbox {
+    pub fn new(rect: AaRect, label: impl Into<String>, checked: bool) -> Self {
+        Self {
+            rect,
+            label: label.into(),
+            checked,
+            state: WidgetState::Idle,
+            interaction: Interaction::default(),
+        }
+    }
+
+    pub fn state(&self) -> WidgetState {
+        self.state
+    }
+
+    /// Updates the checkbox's state. Returns true if `checked` was toggled.
+    pub fn update(&mut self, input: &InputState) -> bool {
+        let (state, clicked) = self.interaction.update(&self.rect, input);
+        self.state = state;
+
+        if clicked {
+            self.checked = !self.checked;
+        }
+
+        clicked
+    }
+
+    pub fn push(&self, painter: &mut UiPainter) {
+        let box_size = Vector2::new(self.rect.size.y, self.rect.size.y);
+        let box_rect = AaRect::from_top_left(self.rect.min(), box_size);
+
+        let color = painter.state_color(self.state);
+        painter.fill_rect(&box_rect, color);
+
+        if self.checked {
+            let tick_rect = Margins::uniform(box_size.y * 0.25).shrink(&box_rect);
+            let accent = painter.style.accent;
+            painter.fill_rect(&tick_rect, accent);
+        }
+
+        let label_rect = AaRect::from_top_left(
+            self.rect.min() + Vector2::new(box_size.x, 0.0),
+            Vector2::new((self.rect.size.x - box_size.x).max(0.0), self.rect.size.y),
+        );
+        let text_color = painter.style.text_color;
+        painter.text(&label_rect, Anchor::Left, &self.label, text_color);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Slider {
+    pub rect: AaRect,
+    pub min: f32,
+    pub max: f32,
+    pub value: f32,
+    state: WidgetState,
+    interaction: Interaction,
+}
+
+impl Slider {
+    pub fn new(rect: AaRect, min: f32, max: f32, value: f32) -> Self {
+        Self {
+            rect,
+            min,
+            max,
+            value,
+            state: WidgetState::Idle,
+            interaction: Interaction::default(),
+        }
+    }
+
+    pub fn state(&self) -> WidgetState {
+        self.state
+    }
+
+    /// Returns the value's position between `min` and `max`, in the range
+    /// `[0..1]`.
+    pub fn fraction(&self) -> f32 {
+        if self.max > self.min {
+            ((self.value - self.min) / (self.max - self.min))
+                .max(0.0)
+                .min(1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// Updates the slider's state, moving the value while the slider is
+    /// dragged. Returns true if the value changed.
+    pub fn update(&mut self, input: &InputState) -> bool {
+        let (state, _) = self.interaction.update(&self.rect, input);
+        self.state = state;
+
+        if state != WidgetState::Pressed || self.rect.size.x <= 0.0 {
+            return false;
+        }
+
+        let fraction = ((input.mouse_pos().x - self.rect.min().x) / self.rect.size.x)
+            .max(0.0)
+            .min(1.0);
+        let value = self.min + fraction * (self.max - self.min);
+        let changed = value != self.value;
+        self.value = value;
+
+        changed
+    }
+
+    pub fn push(&self, painter: &mut UiPainter) {
+        let color = painter.state_color(self.state);
+        painter.fill_rect(&self.rect, color);
+
+        let filled_rect = AaRect::from_top_left(
+            self.rect.min(),
+            Vector2::new(self.rect.size.x * self.fraction(), self.rect.size.y),
+        );
+        let accent = painter.style.accent;
+        painter.fill_rect(&filled_rect, accent);
+    }
+}
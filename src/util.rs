@@ -7,6 +7,22 @@ pub fn device_pixel_ratio() -> f64 {
     window.device_pixel_ratio()
 }
 
+/// Returns a high-resolution timestamp in milliseconds.
+pub fn now_ms() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or_else(js_sys::Date::now, |performance| performance.now())
+}
+
+/// Returns the inner size of the browser window in logical pixels.
+pub fn window_inner_size() -> Option<(f64, f64)> {
+    let window = web_sys::window()?;
+    let width = window.inner_width().ok()?.as_f64()?;
+    let height = window.inner_height().ok()?.as_f64()?;
+
+    Some((width, height))
+}
+
 pub fn set_canvas_size(canvas: &HtmlCanvasElement, logical_size: Vector2<u32>) {
     let scale_factor = device_pixel_ratio();
 
@@ -0,0 +1,386 @@
+//! Optional WebXR support, enabled with the `xr` feature.
+//!
+//! The WebXR bindings of `web-sys` are unstable, so this also requires
+//! building with `RUSTFLAGS=--cfg=web_sys_unstable_apis`.
+//!
+//! Since our passes work in 2D, XR content is drawn onto planes that are
+//! placed in the XR reference space, using the transforms given by
+//! `XrView::plane_transform`. Only `ColPass` and `TexColPass` support the
+//! resulting perspective transforms.
+
+use std::{cell::RefCell, future::Future, rc::Rc, time::Duration};
+
+use nalgebra::{Matrix3, Matrix4, Point2, Vector2};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    WebGlRenderingContext, XrHandedness, XrInputSourceEvent, XrReferenceSpace,
+    XrReferenceSpaceType, XrRenderStateInit, XrSessionMode, XrWebGlLayer,
+};
+
+use crate::{
+    input::{EventListener, EventQueue},
+    Canvas, Error, Event,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum XrMode {
+    /// Exclusive access to a headset, rendering one view per eye.
+    ImmersiveVr,
+
+    /// Rendering into the canvas on the page, with a single view.
+    Inline,
+}
+
+impl XrMode {
+    fn session_mode(self) -> XrSessionMode {
+        match self {
+            XrMode::ImmersiveVr => XrSessionMode::ImmersiveVr,
+            XrMode::Inline => XrSessionMode::Inline,
+        }
+    }
+
+    fn reference_space_type(self) -> XrReferenceSpaceType {
+        match self {
+            XrMode::ImmersiveVr => XrReferenceSpaceType::Local,
+            XrMode::Inline => XrReferenceSpaceType::Viewer,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum XrEye {
+    Left,
+    Right,
+    None,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum XrHand {
+    Left,
+    Right,
+    None,
+}
+
+/// The state of an XR input source, such as a tracked controller.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XrInput {
+    pub hand: XrHand,
+
+    /// Transform from the space of the input's target ray into the reference
+    /// space. The ray starts at the origin and points along negative Z.
+    pub target_ray: Option<Matrix4<f32>>,
+
+    /// Transform from the space of the input's grip into the reference
+    /// space, if the input is held in the hand.
+    pub grip: Option<Matrix4<f32>>,
+}
+
+impl XrInput {
+    fn new(
+        source: &web_sys::XrInputSource,
+        frame: &web_sys::XrFrame,
+        reference_space: &XrReferenceSpace,
+    ) -> Self {
+        let pose_matrix = |space: &web_sys::XrSpace| {
+            frame
+                .get_pose(space, reference_space)
+                .map(|pose| Matrix4::from_column_slice(&pose.transform().matrix()))
+        };
+
+        let hand = match source.handedness() {
+            XrHandedness::Left => XrHand::Left,
+            XrHandedness::Right => XrHand::Right,
+            _ => XrHand::None,
+        };
+
+        Self {
+            hand,
+            target_ray: pose_matrix(&source.target_ray_space()),
+            grip: source.grip_space().and_then(|space| pose_matrix(&space)),
+        }
+    }
+
+    fn from_event(event: &XrInputSourceEvent, reference_space: &XrReferenceSpace) -> Self {
+        Self::new(&event.input_source(), &event.frame(), reference_space)
+    }
+}
+
+/// One view that needs to be rendered in an XR frame, e.g. for one eye.
+#[derive(Debug, Clone)]
+pub struct XrView {
+    pub eye: XrEye,
+    pub projection: Matrix4<f32>,
+
+    /// Transform from the reference space into the view space.
+    pub view: Matrix4<f32>,
+
+    /// The lower left corner of the viewport in the XR framebuffer, in
+    /// physical pixels.
+    pub viewport_pos: Point2<i32>,
+    pub viewport_size: Vector2<i32>,
+}
+
+impl XrView {
+    fn new(view: &web_sys::XrView, layer: &XrWebGlLayer) -> Option<Self> {
+        let viewport = layer.get_viewport(view)?;
+
+        let eye = match view.eye() {
+            web_sys::XrEye::Left => XrEye::Left,
+            web_sys::XrEye::Right => XrEye::Right,
+            _ => XrEye::None,
+        };
+
+        Some(Self {
+            eye,
+            projection: Matrix4::from_column_slice(&view.projection_matrix()),
+            view: Matrix4::from_column_slice(&view.transform().inverse().matrix()),
+            viewport_pos: Point2::new(viewport.x(), viewport.y()),
+            viewport_size: Vector2::new(viewport.width(), viewport.height()),
+        })
+    }
+
+    /// Returns a transform that can be given to our passes for drawing onto
+    /// the plane z = 0 of `plane`, which maps from plane coordinates into
+    /// the reference space.
+    ///
+    /// The Z coordinates of vertices are still only used for depth testing
+    /// within the plane.
+    pub fn plane_transform(&self, plane: &Matrix4<f32>) -> Matrix3<f32> {
+        // Points on the plane have z = 0, so the third column drops out. The
+        // shaders use the third component of the result as the clip space W.
+        let m = self.projection * self.view * plane;
+
+        Matrix3::new(
+            m[(0, 0)],
+            m[(0, 1)],
+            m[(0, 3)],
+            m[(1, 0)],
+            m[(1, 1)],
+            m[(1, 3)],
+            m[(3, 0)],
+            m[(3, 1)],
+            m[(3, 3)],
+        )
+    }
+}
+
+/// The data of one XR frame, given to the callback of `XrSession::run`.
+pub struct XrFrame {
+    webgl_ctx: WebGlRenderingContext,
+    layer: XrWebGlLayer,
+    views: Vec<XrView>,
+    inputs: Vec<XrInput>,
+}
+
+impl XrFrame {
+    pub fn views(&self) -> &[XrView] {
+        &self.views
+    }
+
+    pub fn inputs(&self) -> &[XrInput] {
+        &self.inputs
+    }
+
+    /// Binds the XR framebuffer and sets the viewport for drawing `view`.
+    ///
+    /// Drawing into render targets, e.g. with `TargetScope` or `ShadowMap`,
+    /// restores the XR framebuffer and viewport afterwards. Binding a
+    /// `golem` surface directly does not, in which case this needs to be
+    /// called again.
+    pub fn bind_view(&self, view: &XrView) {
+        self.webgl_ctx.bind_framebuffer(
+            WebGlRenderingContext::FRAMEBUFFER,
+            self.layer.framebuffer().as_ref(),
+        );
+        self.webgl_ctx.viewport(
+            view.viewport_pos.x,
+            view.viewport_pos.y,
+            view.viewport_size.x,
+            view.viewport_size.y,
+        );
+    }
+}
+
+pub struct XrSession {
+    session: web_sys::XrSession,
+    layer: XrWebGlLayer,
+    reference_space: XrReferenceSpace,
+    webgl_ctx: WebGlRenderingContext,
+
+    _on_select_start: EventListener<XrInputSourceEvent>,
+    _on_select_end: EventListener<XrInputSourceEvent>,
+    _on_end: EventListener<web_sys::Event>,
+}
+
+impl XrSession {
+    /// Checks whether the browser and the connected devices support the
+    /// given mode.
+    pub async fn is_supported(mode: XrMode) -> bool {
+        let xr = match web_sys::window() {
+            Some(window) => window.navigator().xr(),
+            None => return false,
+        };
+
+        JsFuture::from(xr.is_session_supported(mode.session_mode()))
+            .await
+            .ok()
+            .and_then(|supported| supported.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Starts an XR session that renders with the WebGL context of `canvas`.
+    ///
+    /// Browsers only allow starting immersive sessions shortly after user
+    /// interaction, e.g. in the frame after a button has been clicked.
+    ///
+    /// While the session is running, selection by XR input sources is
+    /// reported through `Canvas::pop_event`.
+    pub fn request(canvas: &Canvas, mode: XrMode) -> impl Future<Output = Result<Self, Error>> {
+        let webgl_ctx = canvas.webgl_ctx().clone();
+        let events = canvas.event_queue();
+
+        async move {
+            let xr = web_sys::window().ok_or(Error::NoWindow)?.navigator().xr();
+
+            let session: web_sys::XrSession =
+                JsFuture::from(xr.request_session(mode.session_mode()))
+                    .await
+                    .map_err(xr_error)?
+                    .unchecked_into();
+
+            JsFuture::from(webgl_ctx.make_xr_compatible())
+                .await
+                .map_err(xr_error)?;
+
+            let layer = XrWebGlLayer::new_with_web_gl_rendering_context(&session, &webgl_ctx)
+                .map_err(xr_error)?;
+            let mut render_state = XrRenderStateInit::new();
+            render_state.base_layer(Some(&layer));
+            session.update_render_state_with_state(&render_state);
+
+            let reference_space: XrReferenceSpace =
+                JsFuture::from(session.request_reference_space(mode.reference_space_type()))
+                    .await
+                    .map_err(xr_error)?
+                    .unchecked_into();
+
+            let on_select_start = EventListener::new(&session, "selectstart", {
+                let events = events.clone();
+                let reference_space = reference_space.clone();
+                move |event: XrInputSourceEvent| {
+                    let input = XrInput::from_event(&event, &reference_space);
+                    events.push(Event::XrSelectStarted(input));
+                }
+            });
+
+            let on_select_end = EventListener::new(&session, "selectend", {
+                let events = events.clone();
+                let reference_space = reference_space.clone();
+                move |event: XrInputSourceEvent| {
+                    let input = XrInput::from_event(&event, &reference_space);
+                    events.push(Event::XrSelectEnded(input));
+                }
+            });
+
+            let on_end = EventListener::new(&session, "end", {
+                let events = events.clone();
+                move |_: web_sys::Event| {
+                    events.push(Event::XrSessionEnded);
+                }
+            });
+
+            Ok(Self {
+                session,
+                layer,
+                reference_space,
+                webgl_ctx,
+                _on_select_start: on_select_start,
+                _on_select_end: on_select_end,
+                _on_end: on_end,
+            })
+        }
+    }
+
+    /// Runs the XR frame loop. This works like `main_loop`, except that the
+    /// callback is called at the refresh rate of the XR device and is given
+    /// the views to render.
+    ///
+    /// Setting the boolean to true ends the session. Note that the regular
+    /// `main_loop` is not called by browsers while an immersive session is
+    /// running.
+    pub fn run<F>(self, mut callback: F)
+    where
+        F: FnMut(Duration, &XrFrame, &mut bool) + 'static,
+    {
+        fn request_animation_frame(
+            session: &web_sys::XrSession,
+            f: &Closure<dyn FnMut(f64, web_sys::XrFrame)>,
+        ) {
+            session.request_animation_frame(f.as_ref().unchecked_ref());
+        }
+
+        let session = self.session.clone();
+        let f = Rc::new(RefCell::new(None));
+
+        let mut last_timestamp = None;
+        let mut running = true;
+
+        *f.borrow_mut() = Some(Closure::wrap(Box::new({
+            let f = f.clone();
+
+            move |timestamp: f64, frame: web_sys::XrFrame| {
+                let dt = last_timestamp.map_or(Duration::from_secs(0), |last_timestamp: f64| {
+                    let dt_ms = (timestamp - last_timestamp).max(0.0);
+                    Duration::from_secs_f64(dt_ms / 1000.0)
+                });
+                last_timestamp = Some(timestamp);
+
+                // The viewer pose can be missing temporarily, e.g. when
+                // tracking is lost.
+                if let Some(frame) = self.frame(&frame) {
+                    callback(dt, &frame, &mut running);
+                }
+
+                if !running {
+                    let _ = self.session.end();
+                    let _ = f.borrow_mut().take();
+                    return;
+                }
+
+                request_animation_frame(&self.session, f.borrow().as_ref().unwrap());
+            }
+        })
+            as Box<dyn FnMut(f64, web_sys::XrFrame)>));
+
+        request_animation_frame(&session, f.borrow().as_ref().unwrap());
+    }
+
+    fn frame(&self, frame: &web_sys::XrFrame) -> Option<XrFrame> {
+        let pose = frame.get_viewer_pose(&self.reference_space)?;
+
+        let views = pose
+            .views()
+            .iter()
+            .filter_map(|view| view.dyn_into::<web_sys::XrView>().ok())
+            .filter_map(|view| XrView::new(&view, &self.layer))
+            .collect();
+
+        let sources = self.session.input_sources();
+        let inputs = (0..sources.length())
+            .filter_map(|i| sources.get(i))
+            .map(|source| XrInput::new(&source, frame, &self.reference_space))
+            .collect();
+
+        Some(XrFrame {
+            webgl_ctx: self.webgl_ctx.clone(),
+            layer: self.layer.clone(),
+            views,
+            inputs,
+        })
+    }
+}
+
+fn xr_error(e: JsValue) -> Error {
+    Error::Xr(format!("{:?}", e))
+}